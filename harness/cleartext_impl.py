@@ -20,7 +20,7 @@ from utils import parse_submission_arguments
 
 def main():
 
-    __, params, __, __, __, __ = parse_submission_arguments('Generate dataset for FHE benchmark.')
+    __, params, __, __, __, __, __ = parse_submission_arguments('Generate dataset for FHE benchmark.')
     DATASET_ENC_PATH = params.datadir() / f"db.hex"
     AES_KEY_PATH = params.datadir() / f"aes_key.hex"
     IV_PATH = params.datadir() / f"aes_iv.hex"
@@ -23,7 +23,7 @@ def main():
     
     # 0. Prepare running
     # Get the arguments
-    size, params, seed, num_runs, clrtxt, mini_workload = utils.parse_submission_arguments('Run the add-two-values FHE benchmark.')
+    size, params, seed, num_runs, clrtxt, mini_workload, download_flags = utils.parse_submission_arguments('Run the add-two-values FHE benchmark.')
     test = instance_name(size)
     print(f"\n[harness] Running submission for {test} dataset")
 
@@ -89,17 +89,18 @@ def main():
     utils.log_step(6, "(Encrypted) dataset preprocessing")    
 
     # 7. Server side: Run aes_decryption 
-    subprocess.run([exec_dir/"server_encrypted_aes_decryption", str(size)], check=True)
+    subprocess.run([exec_dir/"server_encrypted_aes_decryption", str(size), *download_flags], check=True)
     utils.log_step(7, "Encrypted aes decryption")
     utils.log_size(io_dir / "ciphertext_aes_download", "Encrypted results")
 
     # 8. Server side: Run the encrypted processing run exec_dir/server_encrypted_compute
-    subprocess.run([exec_dir/"server_encrypted_compute", str(size)], check=True)
+    subprocess.run([exec_dir/"server_encrypted_compute", str(size),
+                    "--mini_workload", str(mini_workload), *download_flags], check=True)
     utils.log_step(8, "Encrypted computation of mini workload")
     utils.log_size(io_dir / "ciphertexts_download", "Encrypted results")
 
     # 9. Client-side: decrypt
-    subprocess.run([exec_dir/"client_decrypt_decode_aes_decryption", str(size)], check=True)
+    subprocess.run([exec_dir/"client_decrypt_decode_aes_decryption", str(size), *download_flags], check=True)
     utils.log_step(9, "Result decryption")
 
     # 10. Client-side: post-process
@@ -107,7 +108,7 @@ def main():
     utils.log_step(10, "Result postprocessing")
 
     # 11. Client-side: decrypt
-    subprocess.run([exec_dir/"client_decrypt_decode", str(size)], check=True)
+    subprocess.run([exec_dir/"client_decrypt_decode", str(size), *download_flags], check=True)
     utils.log_step(11, "Result decryption")
 
     # 12. Client-side: post-process
@@ -17,7 +17,7 @@ import json
 from datetime import datetime
 from pathlib import Path
 from params import InstanceParams, TOY, MEDIUM
-from typing import Tuple
+from typing import List, Tuple
 
 # Global variable to track the last timestamp
 _last_timestamp: datetime = None
@@ -27,7 +27,7 @@ _timestampsStr = {}
 # Global variable to store measured sizes
 _bandwidth = {}
 
-def parse_submission_arguments(workload: str) -> Tuple[int, InstanceParams, int, int, int, int]:
+def parse_submission_arguments(workload: str) -> Tuple[int, InstanceParams, int, int, int, int, List[str]]:
     """
     Get the arguments of the submission. Populate arguments as needed for the workload.
     """
@@ -43,6 +43,11 @@ def parse_submission_arguments(workload: str) -> Tuple[int, InstanceParams, int,
                         help='Specify with 1 if to rerun the cleartext computation')
     parser.add_argument('--mini_workload', type=int, default=0,
                         help='Specify 0 for mini workload = max and 1 for mini workload = dot product.')
+    download = parser.add_mutually_exclusive_group()
+    download.add_argument('--pack_glwe', action='store_true',
+                          help='Download the results packed into GLWE ciphertexts')
+    download.add_argument('--compress', action='store_true',
+                          help='Download the results modulus switched and bit packed')
 
     args = parser.parse_args()
     size = args.size
@@ -50,10 +55,17 @@ def parse_submission_arguments(workload: str) -> Tuple[int, InstanceParams, int,
     num_runs = args.num_runs
     clrtxt = args.clrtxt
     mini_workload = args.mini_workload
+    # Flags selecting the download form, passed to every binary that writes
+    # or reads a downloaded result
+    download_flags = []
+    if args.pack_glwe:
+        download_flags.append('--pack_glwe')
+    if args.compress:
+        download_flags.append('--compress')
 
     # Use params.py to get instance parameters
     params = InstanceParams(size)
-    return size, params, seed, num_runs, clrtxt, mini_workload
+    return size, params, seed, num_runs, clrtxt, mini_workload, download_flags
 
 def ensure_directories(rootdir: Path):
     """ Check that the current directory has sub-directories
@@ -9,14 +9,15 @@ use tfhe::{
                 bootstrap::FourierLweBootstrapKeyView,
                 ggsw::{
                     FourierGgswCiphertextListView,
-                    FourierGgswCiphertextListMutView,
                 },
             },
         },
     },
 };
 use std::collections::HashMap;
-use crate::{aes_ref::*, convert_lwe_to_glwe_const, ggsw_conv::*, lwe_preprocessing, trace_assign, utils::*, AutomorphKey};
+#[cfg(feature = "multithread")]
+use rayon::prelude::*;
+use crate::{aes_ref::*, convert_lwe_to_glwe_const, error::*, ggsw_conv::*, keyswitch_lwe_ciphertext_list_by_glwe_keyswitch, lwe_preprocessing, check_trace_auto_keys, trace_assign, utils::*, AutomorphKey, FourierGlweKeyswitchKey};
 
 #[inline]
 pub fn he_add_round_key<Scalar, StateCont, RkCont>(
@@ -36,28 +37,21 @@ pub fn he_sub_bytes_by_patched_wwlp_cbs<Scalar, InputCont, OutputCont>(
     fourier_bsk: FourierLweBootstrapKeyView,
     auto_keys: &HashMap<usize, AutomorphKey<ABox<[c64]>>>,
     ss_key: FourierGgswCiphertextListView,
-    ggsw_base_log: DecompositionBaseLog,
-    ggsw_level: DecompositionLevelCount,
-    log_lut_count: LutCountLog,
-) where
+    workers: &mut [ByteCbsWorker<Scalar>],
+) -> Result<(), Error> where
     Scalar: UnsignedTorus + CastInto<usize> + CastFrom<usize>,
     InputCont: Container<Element = Scalar>,
     OutputCont: ContainerMut<Element = Scalar>,
 {
-    for (input_byte, mut output_byte) in he_state_input.chunks_exact(BYTESIZE)
-        .zip(he_state_output.chunks_exact_mut(BYTESIZE))
-    {
-        he_sbox_eval_by_patched_wwlp_cbs(
-            &input_byte,
-            &mut output_byte,
-            fourier_bsk,
-            auto_keys,
-            ss_key,
-            ggsw_base_log,
-            ggsw_level,
-            log_lut_count,
-        );
-    }
+    he_eval_byte_luts_by_patched_wwlp_cbs(
+        he_state_input,
+        std::slice::from_mut(he_state_output),
+        &[&AES128_SBOX],
+        fourier_bsk,
+        auto_keys,
+        ss_key,
+        workers,
+    )
 }
 
 
@@ -65,31 +59,33 @@ pub fn known_rotate_keyed_lut<Scalar, AccCont, OutputCont>(
     input_cleartext: [u8; BLOCKSIZE_IN_BYTE],
     vec_keyed_sbox: &Vec<GlweCiphertextList<AccCont>>,
     lwe_state_output: &mut LweCiphertextList<OutputCont>,
-) where
+) -> Result<(), Error> where
     Scalar: UnsignedTorus + CastFrom<usize>,
     AccCont: Container<Element=Scalar>,
     OutputCont: ContainerMut<Element=Scalar>,
 {
-    let polynomial_size = vec_keyed_sbox.get(0).unwrap().polynomial_size();
+    check_dimension("keyed LUT byte count", BLOCKSIZE_IN_BYTE, vec_keyed_sbox.len())?;
+    check_dimension("state bit count", BLOCKSIZE_IN_BIT, lwe_state_output.lwe_ciphertext_count().0)?;
+    let layout = LutLayout::new(vec_keyed_sbox[0].polynomial_size())?;
+    for keyed_acc_list in vec_keyed_sbox {
+        check_dimension("keyed LUT accumulator count", layout.accumulator_count(1), keyed_acc_list.glwe_ciphertext_count().0)?;
+    }
 
-    let num_par_lut = polynomial_size.0 / (1 << BYTESIZE);
     for i in 0..BLOCKSIZE_IN_BIT {
         let mut lwe_bit = lwe_state_output.get_mut(i);
         let byte_idx = i / BYTESIZE;
         let bit_idx = i % BYTESIZE;
 
         let keyed_acc_list = vec_keyed_sbox.get(byte_idx).unwrap();
-
-        let acc_idx = bit_idx / num_par_lut;
-        let lut_idx = bit_idx % num_par_lut;
-        let deg = lut_idx * (1 << BYTESIZE) + input_cleartext[byte_idx] as usize;
+        let (acc_idx, deg) = layout.position(bit_idx, input_cleartext[byte_idx]);
 
         extract_lwe_sample_from_glwe_ciphertext(
             &keyed_acc_list.get(acc_idx),
             &mut lwe_bit,
-            MonomialDegree(deg),
+            deg,
         );
     }
+    Ok(())
 }
 
 
@@ -97,59 +93,71 @@ pub fn known_rotate_keyed_lut_for_half_cbs<Scalar, AccCont, OutputCont>(
     input_cleartext: [u8; BLOCKSIZE_IN_BYTE],
     vec_keyed_sbox_glev: &Vec<Vec<GlweCiphertextList<AccCont>>>,
     lev_state_output: &mut Vec<LweCiphertextList<OutputCont>>,
-) where
+) -> Result<(), Error> where
     Scalar: UnsignedTorus + CastFrom<usize>,
     AccCont: Container<Element=Scalar>,
     OutputCont: ContainerMut<Element=Scalar>,
 {
-    let polynomial_size = vec_keyed_sbox_glev.get(0).unwrap().get(0).unwrap().polynomial_size();
+    check_dimension("state bit count", BLOCKSIZE_IN_BIT, lev_state_output.len())?;
+    for vec_keyed_sbox_acc_list in vec_keyed_sbox_glev.iter() {
+        check_dimension("keyed LUT byte count", BLOCKSIZE_IN_BYTE, vec_keyed_sbox_acc_list.len())?;
+    }
+    for lev_bit in lev_state_output.iter() {
+        check_dimension("Lev level count", vec_keyed_sbox_glev.len(), lev_bit.lwe_ciphertext_count().0)?;
+    }
+    let first_acc_list = vec_keyed_sbox_glev.first()
+        .ok_or_else(|| Error::IncompatibleParameters("the GLev keyed LUTs have no level".to_owned()))?;
+    let layout = LutLayout::new(first_acc_list[0].polynomial_size())?;
+    for keyed_acc_list in vec_keyed_sbox_glev.iter().flatten() {
+        check_dimension("keyed LUT accumulator count", layout.accumulator_count(1), keyed_acc_list.glwe_ciphertext_count().0)?;
+    }
 
     for (level_minus_one, vec_keyed_sbox_acc_list) in vec_keyed_sbox_glev.iter().enumerate() {
-        let num_par_lut = polynomial_size.0 / (1 << BYTESIZE);
         for i in 0..BLOCKSIZE_IN_BIT {
             let mut lwe_bit = lev_state_output.get_mut(i).unwrap().get_mut(level_minus_one);
             let byte_idx = i / BYTESIZE;
             let bit_idx = i % BYTESIZE;
 
             let keyed_acc_list = vec_keyed_sbox_acc_list.get(byte_idx).unwrap();
-
-            let acc_idx = bit_idx / num_par_lut;
-            let lut_idx = bit_idx % num_par_lut;
-            let deg = lut_idx * (1 << BYTESIZE) + input_cleartext[byte_idx] as usize;
+            let (acc_idx, deg) = layout.position(bit_idx, input_cleartext[byte_idx]);
 
             extract_lwe_sample_from_glwe_ciphertext(
                 &keyed_acc_list.get(acc_idx),
                 &mut lwe_bit,
-                MonomialDegree(deg),
+                deg,
             );
         }
     }
+    Ok(())
 }
 
 
 pub fn convert_lev_state_to_ggsw<Scalar, InputCont, OutputCont>(
-    lev_state: &Vec<LweCiphertextList<InputCont>>,
+    lev_state: &[LweCiphertextList<InputCont>],
     ggsw_out: &mut GgswCiphertextList<OutputCont>,
     auto_keys: &HashMap<usize, AutomorphKey<ABox<[c64]>>>,
     ss_key: FourierGgswCiphertextListView,
-) where
+) -> Result<(), Error> where
     Scalar: UnsignedTorus,
     InputCont: Container<Element=Scalar>,
     OutputCont: ContainerMut<Element=Scalar>,
 {
-    let lev_lwe_size = lev_state.get(0).unwrap().lwe_size();
     let glwe_size = ggsw_out.glwe_size();
     let polynomial_size = ggsw_out.polynomial_size();
     let ciphertext_modulus = ggsw_out.ciphertext_modulus();
-
     let ggsw_level = ggsw_out.decomposition_level_count();
+    let lev_lwe_size = LweSize(glwe_size.to_glwe_dimension().0 * polynomial_size.0 + 1);
 
-    assert_eq!(lev_state.get(0).unwrap().lwe_ciphertext_count().0, ggsw_level.0);
-    assert_eq!(lev_state.get(0).unwrap().ciphertext_modulus(), ciphertext_modulus);
-    assert_eq!(lev_lwe_size.to_lwe_dimension().0, glwe_size.to_glwe_dimension().0 * polynomial_size.0);
-
-    let state_size = lev_state.len();
-    assert_eq!(ggsw_out.ggsw_ciphertext_count().0, state_size);
+    check_dimension("GGSW count", lev_state.len(), ggsw_out.ggsw_ciphertext_count().0)?;
+    for lev in lev_state {
+        if lev.ciphertext_modulus() != ciphertext_modulus {
+            return Err(Error::CiphertextModulusMismatch);
+        }
+        check_dimension("Lev level count", ggsw_level.0, lev.lwe_ciphertext_count().0)?;
+        check_dimension("Lev LWE size", lev_lwe_size.0, lev.lwe_size().0)?;
+    }
+    check_trace_auto_keys(auto_keys, glwe_size, polynomial_size, 1)?;
+    check_scheme_switching_key(ss_key, glwe_size, polynomial_size)?;
 
     let mut buf_lwe = LweCiphertext::new(
         Scalar::ZERO,
@@ -173,112 +181,74 @@ pub fn convert_lev_state_to_ggsw<Scalar, InputCont, OutputCont>(
 
         switch_scheme(&glev, &mut ggsw, ss_key);
     }
+    Ok(())
 }
 
 
+/// Keyed S-boxes of a forward round: every byte of `he_state_input` is
+/// circuit bootstrapped once, and the accumulators of byte `i` in
+/// `vec_keyed_sbox_acc` and its ×2 and ×3 counterparts are rotated by it, as
+/// in [`he_sub_bytes_8_to_24_by_patched_wwlp_cbs`] with the round key folded
+/// into the LUTs.
 pub fn blind_rotate_keyed_sboxes<Scalar, InputCont, AccCont, OutputCont>(
-    ggsw_list: &GgswCiphertextList<InputCont>,
-    vec_keyed_sbox_acc: &Vec<GlweCiphertextList<AccCont>>,
-    vec_keyed_sbox_mult_by_2_acc: &Vec<GlweCiphertextList<AccCont>>,
-    vec_keyed_sbox_mult_by_3_acc: &Vec<GlweCiphertextList<AccCont>>,
+    he_state_input: &LweCiphertextList<InputCont>,
+    vec_keyed_sbox_acc: &[GlweCiphertextList<AccCont>],
+    vec_keyed_sbox_mult_by_2_acc: &[GlweCiphertextList<AccCont>],
+    vec_keyed_sbox_mult_by_3_acc: &[GlweCiphertextList<AccCont>],
     lwe_state_output: &mut LweCiphertextList<OutputCont>,
     lwe_state_output_mult_by_2: &mut LweCiphertextList<OutputCont>,
     lwe_state_output_mult_by_3: &mut LweCiphertextList<OutputCont>,
-) where
-    Scalar: UnsignedTorus + CastFrom<usize>,
+    fourier_bsk: FourierLweBootstrapKeyView,
+    auto_keys: &HashMap<usize, AutomorphKey<ABox<[c64]>>>,
+    ss_key: FourierGgswCiphertextListView,
+    workers: &mut [ByteCbsWorker<Scalar>],
+) -> Result<(), Error> where
+    Scalar: UnsignedTorus + CastInto<usize> + CastFrom<usize>,
     InputCont: Container<Element=Scalar>,
-    AccCont: Container<Element=Scalar>,
+    AccCont: Container<Element=Scalar> + Sync,
     OutputCont: ContainerMut<Element=Scalar>,
 {
-    let glwe_size = ggsw_list.glwe_size();
-    let polynomial_size = ggsw_list.polynomial_size();
-    let ciphertext_modulus = lwe_state_output.ciphertext_modulus();
-    let cbs_base_log = ggsw_list.decomposition_base_log();
-    let cbs_level = ggsw_list.decomposition_level_count();
-
-    let num_par_lut = polynomial_size.0 / (1 << BYTESIZE);
-    for (((ggsw_chunk,
-        (keyed_sbox_acc_list, mut output_chunk)),
-        (keyed_sbox_mult_by_2_acc_list, mut output_mult_by_2_chunk)),
-        (keyed_sbox_mult_by_3_acc_list, mut output_mult_by_3_chunk),
+    he_eval_keyed_byte_luts_by_patched_wwlp_cbs(
+        he_state_input,
+        &mut [
+            lwe_state_output.as_mut_view(),
+            lwe_state_output_mult_by_2.as_mut_view(),
+            lwe_state_output_mult_by_3.as_mut_view(),
+        ],
+        &[vec_keyed_sbox_acc, vec_keyed_sbox_mult_by_2_acc, vec_keyed_sbox_mult_by_3_acc],
+        fourier_bsk,
+        auto_keys,
+        ss_key,
+        workers,
     )
-        in ggsw_list.chunks_exact(BYTESIZE)
-        .zip(
-            vec_keyed_sbox_acc.iter()
-            .zip(lwe_state_output.chunks_exact_mut(BYTESIZE))
-        )
-        .zip(
-            vec_keyed_sbox_mult_by_2_acc.iter()
-            .zip(lwe_state_output_mult_by_2.chunks_exact_mut(BYTESIZE))
-        )
-        .zip(
-            vec_keyed_sbox_mult_by_3_acc.iter()
-            .zip(lwe_state_output_mult_by_3.chunks_exact_mut(BYTESIZE))
-        )
-    {
-        let mut fourier_ggsw_chunk = FourierGgswCiphertextList::new(
-            vec![c64::default();
-            BYTESIZE * polynomial_size.to_fourier_polynomial_size().0
-                * glwe_size.0
-                * glwe_size.0
-                * cbs_level.0
-            ],
-            BYTESIZE,
-            glwe_size,
-            polynomial_size,
-            cbs_base_log,
-            cbs_level,
-        );
-
-        for (mut fourier_ggsw, ggsw) in fourier_ggsw_chunk.as_mut_view().into_ggsw_iter().zip(ggsw_chunk.iter()) {
-            convert_standard_ggsw_ciphertext_to_fourier(&ggsw, &mut fourier_ggsw);
-        }
-
-        for (acc_idx, ((keyed_acc, keyed_acc_mult_by_2), keyed_acc_mult_by_3))
-            in keyed_sbox_acc_list.iter()
-            .zip(keyed_sbox_mult_by_2_acc_list.iter())
-            .zip(keyed_sbox_mult_by_3_acc_list.iter())
-            .enumerate()
-        {
-            let mut acc = GlweCiphertext::new(Scalar::ZERO, glwe_size, polynomial_size, ciphertext_modulus);
-            acc.as_mut().clone_from_slice(keyed_acc.as_ref());
-
-            let mut acc_mult_by_2 = GlweCiphertext::new(Scalar::ZERO, glwe_size, polynomial_size, ciphertext_modulus);
-            acc_mult_by_2.as_mut().clone_from_slice(keyed_acc_mult_by_2.as_ref());
-
-            let mut acc_mult_by_3 = GlweCiphertext::new(Scalar::ZERO, glwe_size, polynomial_size, ciphertext_modulus);
-            acc_mult_by_3.as_mut().clone_from_slice(keyed_acc_mult_by_3.as_ref());
-
-            for (i, fourier_ggsw_bit) in fourier_ggsw_chunk.as_view().into_ggsw_iter().enumerate() {
-                let mut buf = acc.clone();
-                glwe_ciphertext_monic_monomial_div_assign(&mut buf, MonomialDegree(1 << i));
-                glwe_ciphertext_sub_assign(&mut buf, &acc);
-                add_external_product_assign(&mut acc, &fourier_ggsw_bit, &buf);
-
-                let mut buf2 = acc_mult_by_2.clone();
-                glwe_ciphertext_monic_monomial_div_assign(&mut buf2, MonomialDegree(1 << i));
-                glwe_ciphertext_sub_assign(&mut buf2, &acc_mult_by_2);
-                add_external_product_assign(&mut acc_mult_by_2, &fourier_ggsw_bit, &buf2);
-
-                let mut buf3 = acc_mult_by_3.clone();
-                glwe_ciphertext_monic_monomial_div_assign(&mut buf3, MonomialDegree(1 << i));
-                glwe_ciphertext_sub_assign(&mut buf3, &acc_mult_by_3);
-                add_external_product_assign(&mut acc_mult_by_3, &fourier_ggsw_bit, &buf3);
-            }
-
-            for i in 0..num_par_lut {
-                let bit_idx = acc_idx * num_par_lut + i;
-                let mut lwe_out = output_chunk.get_mut(bit_idx);
-                extract_lwe_sample_from_glwe_ciphertext(&acc, &mut lwe_out, MonomialDegree(i * (1 << BYTESIZE)));
+}
 
-                let mut lwe_out2 = output_mult_by_2_chunk.get_mut(bit_idx);
-                extract_lwe_sample_from_glwe_ciphertext(&acc_mult_by_2, &mut lwe_out2, MonomialDegree(i * (1 << BYTESIZE)));
 
-                let mut lwe_out3 = output_mult_by_3_chunk.get_mut(bit_idx);
-                extract_lwe_sample_from_glwe_ciphertext(&acc_mult_by_3, &mut lwe_out3, MonomialDegree(i * (1 << BYTESIZE)));
-            }
-        }
-    }
+/// Keyed S-box alone, as in the last forward round, see
+/// [`blind_rotate_keyed_sboxes`].
+pub fn blind_rotate_keyed_sbox<Scalar, InputCont, AccCont, OutputCont>(
+    he_state_input: &LweCiphertextList<InputCont>,
+    vec_keyed_sbox_acc: &[GlweCiphertextList<AccCont>],
+    lwe_state_output: &mut LweCiphertextList<OutputCont>,
+    fourier_bsk: FourierLweBootstrapKeyView,
+    auto_keys: &HashMap<usize, AutomorphKey<ABox<[c64]>>>,
+    ss_key: FourierGgswCiphertextListView,
+    workers: &mut [ByteCbsWorker<Scalar>],
+) -> Result<(), Error> where
+    Scalar: UnsignedTorus + CastInto<usize> + CastFrom<usize>,
+    InputCont: Container<Element=Scalar>,
+    AccCont: Container<Element=Scalar> + Sync,
+    OutputCont: ContainerMut<Element=Scalar>,
+{
+    he_eval_keyed_byte_luts_by_patched_wwlp_cbs(
+        he_state_input,
+        std::slice::from_mut(lwe_state_output),
+        &[vec_keyed_sbox_acc],
+        fourier_bsk,
+        auto_keys,
+        ss_key,
+        workers,
+    )
 }
 
 
@@ -290,33 +260,25 @@ pub fn he_sub_bytes_8_to_24_by_patched_wwlp_cbs<Scalar, InputCont, OutputCont>(
     fourier_bsk: FourierLweBootstrapKeyView,
     auto_keys: &HashMap<usize, AutomorphKey<ABox<[c64]>>>,
     ss_key: FourierGgswCiphertextListView,
-    ggsw_base_log: DecompositionBaseLog,
-    ggsw_level: DecompositionLevelCount,
-    log_lut_count: LutCountLog,
-) where
+    workers: &mut [ByteCbsWorker<Scalar>],
+) -> Result<(), Error> where
     Scalar: UnsignedTorus + CastInto<usize> + CastFrom<usize>,
     InputCont: Container<Element=Scalar>,
     OutputCont: ContainerMut<Element=Scalar>,
 {
-    for (((input, mut output), mut output_mult_by_2), mut output_mult_by_3)
-        in he_state_input.chunks_exact(BYTESIZE)
-        .zip(he_state_output.chunks_exact_mut(BYTESIZE))
-        .zip(he_state_output_mult_by_2.chunks_exact_mut(BYTESIZE))
-        .zip(he_state_output_mult_by_3.chunks_exact_mut(BYTESIZE))
-    {
-        he_sbox_8_to_24_eval_by_patched_wwlp_cbs(
-            &input,
-            &mut output,
-            &mut output_mult_by_2,
-            &mut output_mult_by_3,
-            fourier_bsk,
-            auto_keys,
-            ss_key,
-            ggsw_base_log,
-            ggsw_level,
-            log_lut_count,
-        );
-    }
+    he_eval_byte_luts_by_patched_wwlp_cbs(
+        he_state_input,
+        &mut [
+            he_state_output.as_mut_view(),
+            he_state_output_mult_by_2.as_mut_view(),
+            he_state_output_mult_by_3.as_mut_view(),
+        ],
+        &[&AES128_SBOX, &AES128_SBOX_MULT_BY_2, &AES128_SBOX_MULT_BY_3],
+        fourier_bsk,
+        auto_keys,
+        ss_key,
+        workers,
+    )
 }
 
 pub fn get_he_state_byte<Scalar, Cont>(
@@ -577,260 +539,735 @@ where
     he_byte.as_mut().clone_from_slice(buf.as_ref());
 }
 
-fn he_sbox_eval_by_patched_wwlp_cbs<Scalar, InCont, OutCont>(
-    input: &LweCiphertextList<InCont>,
-    output: &mut LweCiphertextList<OutCont>,
+pub fn he_sbox_8_to_24_eval_by_patched_wwlp_cbs<Scalar, InputCont, OutputCont>(
+    input: &LweCiphertextList<InputCont>,
+    output: &mut LweCiphertextList<OutputCont>,
+    output_mult_by_2: &mut LweCiphertextList<OutputCont>,
+    output_mult_by_3: &mut LweCiphertextList<OutputCont>,
     fourier_bsk: FourierLweBootstrapKeyView,
     auto_keys: &HashMap<usize, AutomorphKey<ABox<[c64]>>>,
     ss_key: FourierGgswCiphertextListView,
-    ggsw_base_log: DecompositionBaseLog,
-    ggsw_level: DecompositionLevelCount,
-    log_lut_count: LutCountLog,
-) where
+    worker: &mut ByteCbsWorker<Scalar>,
+) -> Result<(), Error> where
     Scalar: UnsignedTorus + CastInto<usize> + CastFrom<usize>,
-    InCont: Container<Element=Scalar>,
-    OutCont: ContainerMut<Element=Scalar>,
+    InputCont: Container<Element=Scalar>,
+    OutputCont: ContainerMut<Element=Scalar>,
 {
-    let glwe_size = fourier_bsk.glwe_size();
-    let polynomial_size = fourier_bsk.polynomial_size();
-    let ciphertext_modulus = output.ciphertext_modulus();
+    let accumulators = trivial_packed_lut_accumulators(
+        &[&AES128_SBOX, &AES128_SBOX_MULT_BY_2, &AES128_SBOX_MULT_BY_3],
+        Scalar::BITS - 1,
+        fourier_bsk.glwe_size(),
+        fourier_bsk.polynomial_size(),
+        output.ciphertext_modulus(),
+    )?;
+    worker.eval_packed_byte_luts(
+        input,
+        &accumulators,
+        &mut [
+            output.as_mut_view(),
+            output_mult_by_2.as_mut_view(),
+            output_mult_by_3.as_mut_view(),
+        ],
+        fourier_bsk,
+        auto_keys,
+        ss_key,
+    )
+}
 
-    let mut vec_glev = vec![
-        GlweCiphertextList::new(
-            Scalar::ZERO,
-            glwe_size,
-            polynomial_size,
-            GlweCiphertextCount(ggsw_level.0),
-            ciphertext_modulus,
-        ); BYTESIZE];
-    for (input_bit, glev) in input.iter().zip(vec_glev.iter_mut()) {
-        let glev_mut_view = GlweCiphertextListMutView::from_container(
-            glev.as_mut(),
-            glwe_size,
-            polynomial_size,
-            ciphertext_modulus,
-        );
 
-        lwe_msb_bit_to_glev_by_trace_with_preprocessing(
-            input_bit.as_view(),
-            glev_mut_view,
-            fourier_bsk,
-            auto_keys,
-            ggsw_base_log,
-            ggsw_level,
-            log_lut_count,
-        );
+
+
+/// Layout of 8-to-8 LUTs in GLWE accumulators, shared by the client code that
+/// encrypts keyed LUTs and the server code that rotates and extracts them.
+///
+/// Each output bit of a LUT is a table of 256 coefficients, and an accumulator
+/// holds `polynomial_size / 256` of them side by side. The output bits of
+/// several LUTs are numbered one after the other, bit `b` of LUT `j` being
+/// packed bit `j * BYTESIZE + b`, so LUTs share an accumulator whenever there
+/// is room left after the bits of the previous one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LutLayout {
+    polynomial_size: PolynomialSize,
+}
+
+impl LutLayout {
+    /// Fails unless `polynomial_size` is a multiple of 256.
+    pub fn new(polynomial_size: PolynomialSize) -> Result<Self, Error> {
+        if polynomial_size.0 < 1 << BYTESIZE || polynomial_size.0 % (1 << BYTESIZE) != 0 {
+            return Err(Error::IncompatibleParameters(format!(
+                "a polynomial of size {} cannot hold 8-to-8 LUTs",
+                polynomial_size.0,
+            )));
+        }
+        Ok(LutLayout { polynomial_size })
     }
 
-    let mut ggsw_bit_list = GgswCiphertextList::new(
-        Scalar::ZERO,
-        glwe_size,
-        polynomial_size,
-        ggsw_base_log,
-        ggsw_level,
-        GgswCiphertextCount(vec_glev.len()),
-        ciphertext_modulus,
-    );
-    for (mut ggsw, glev) in ggsw_bit_list.iter_mut().zip(vec_glev.iter()) {
-        switch_scheme(&glev, &mut ggsw, ss_key);
+    pub fn polynomial_size(&self) -> PolynomialSize {
+        self.polynomial_size
     }
 
-    let mut fourier_ggsw_bit_list = FourierGgswCiphertextList::new(
-        vec![c64::default();
-        BYTESIZE * polynomial_size.to_fourier_polynomial_size().0
-            * glwe_size.0
-            * glwe_size.0
-            * ggsw_level.0
-        ],
-        BYTESIZE,
-        glwe_size,
-        polynomial_size,
-        ggsw_base_log,
-        ggsw_level,
-    );
-    for (mut fourier_ggsw, ggsw) in fourier_ggsw_bit_list.as_mut_view().into_ggsw_iter().zip(ggsw_bit_list.iter()) {
-        convert_standard_ggsw_ciphertext_to_fourier(&ggsw, &mut fourier_ggsw);
+    /// Number of output bits one accumulator holds.
+    pub fn bits_per_accumulator(&self) -> usize {
+        self.polynomial_size.0 / (1 << BYTESIZE)
     }
 
-    let num_par_lut = polynomial_size.0 / (1 << BYTESIZE);
-    let num_accumulator = if BYTESIZE % num_par_lut == 0 {
-        BYTESIZE / num_par_lut
-    } else {
-        BYTESIZE / num_par_lut + 1
-    };
+    /// Number of accumulators holding `lut_count` packed LUTs.
+    pub fn accumulator_count(&self, lut_count: usize) -> usize {
+        (lut_count * BYTESIZE).div_ceil(self.bits_per_accumulator())
+    }
 
-    for acc_idx in 0..num_accumulator {
-        let accumulator = (0..polynomial_size.0).map(|i| {
-            let lut_idx = acc_idx * num_par_lut + i / (1 << BYTESIZE);
-            (((AES128_SBOX[i % (1 << BYTESIZE)] & (1 << lut_idx)) as usize) << ((Scalar::BITS - 1) - lut_idx)).cast_into()
-        }).collect::<Vec<Scalar>>();
-        let accumulator_plaintext = PlaintextList::from_container(accumulator);
-        let mut accumulator = allocate_and_trivially_encrypt_new_glwe_ciphertext(glwe_size, &accumulator_plaintext, ciphertext_modulus);
-
-        for (i, fourier_ggsw_bit) in fourier_ggsw_bit_list.as_view().into_ggsw_iter().into_iter().enumerate() {
-            let mut buf = accumulator.clone();
-            glwe_ciphertext_monic_monomial_div_assign(&mut buf, MonomialDegree(1 << i));
-            glwe_ciphertext_sub_assign(&mut buf, &accumulator);
-            add_external_product_assign(&mut accumulator, &fourier_ggsw_bit, &buf);
+    /// Accumulator and coefficient holding packed bit `packed_bit_idx` of the
+    /// LUTs evaluated on `input`.
+    pub fn position(&self, packed_bit_idx: usize, input: u8) -> (usize, MonomialDegree) {
+        let bits_per_acc = self.bits_per_accumulator();
+        let slot = packed_bit_idx % bits_per_acc;
+        (packed_bit_idx / bits_per_acc, MonomialDegree(slot * (1 << BYTESIZE) + input as usize))
+    }
+
+    /// Packed bits below `bit_count` held by accumulator `acc_idx`, each with
+    /// the coefficient it sits at once the accumulator is rotated by the input.
+    pub fn accumulator_bits(&self, acc_idx: usize, bit_count: usize) -> impl Iterator<Item = (usize, MonomialDegree)> {
+        let bits_per_acc = self.bits_per_accumulator();
+        (0..bits_per_acc)
+            .map(move |slot| (acc_idx * bits_per_acc + slot, MonomialDegree(slot * (1 << BYTESIZE))))
+            .take_while(move |&(packed_bit_idx, _)| packed_bit_idx < bit_count)
+    }
+
+    /// Plaintext of the `acc_idx`-th accumulator packing the output bits of
+    /// `luts`, each scaled by `2^log_scale`. Positions past the last bit are
+    /// zero.
+    pub fn accumulator_plaintext<Scalar>(
+        &self,
+        luts: &[&[u8; 1 << BYTESIZE]],
+        acc_idx: usize,
+        log_scale: usize,
+    ) -> Vec<Scalar>
+    where
+        Scalar: UnsignedTorus + CastFrom<usize>,
+    {
+        let mut accumulator = vec![Scalar::ZERO; self.polynomial_size.0];
+        for (packed_bit_idx, MonomialDegree(offset)) in self.accumulator_bits(acc_idx, luts.len() * BYTESIZE) {
+            let lut = luts[packed_bit_idx / BYTESIZE];
+            for (input, coef) in accumulator[offset..offset + (1 << BYTESIZE)].iter_mut().enumerate() {
+                let bit = (lut[input] >> (packed_bit_idx % BYTESIZE)) & 1;
+                *coef = ((bit as usize) << log_scale).cast_into();
+            }
         }
+        accumulator
+    }
+}
 
-        for i in 0..num_par_lut {
-            let bit_idx = acc_idx * num_par_lut + i;
-            let mut lwe_out = output.get_mut(bit_idx);
-            extract_lwe_sample_from_glwe_ciphertext(&accumulator, &mut lwe_out, MonomialDegree(i * (1 << BYTESIZE)));
+/// Trivially encrypted accumulators packing the public `luts`.
+pub fn trivial_packed_lut_accumulators<Scalar>(
+    luts: &[&[u8; 1 << BYTESIZE]],
+    log_scale: usize,
+    glwe_size: GlweSize,
+    polynomial_size: PolynomialSize,
+    ciphertext_modulus: CiphertextModulus<Scalar>,
+) -> Result<Vec<GlweCiphertextOwned<Scalar>>, Error>
+where
+    Scalar: UnsignedTorus + CastFrom<usize>,
+{
+    let layout = LutLayout::new(polynomial_size)?;
+    Ok((0..layout.accumulator_count(luts.len())).map(|acc_idx| {
+        let accumulator = layout.accumulator_plaintext(luts, acc_idx, log_scale);
+        allocate_and_trivially_encrypt_new_glwe_ciphertext(
+            glwe_size,
+            &PlaintextList::from_container(accumulator),
+            ciphertext_modulus,
+        )
+    }).collect())
+}
+
+/// GLWE buffers of [`evaluate_packed_byte_luts`]: the running accumulator and
+/// the CMUX buffer of each CMUX tree, so that the trees can run side by side
+/// and no ciphertext is allocated per byte.
+pub struct PackedLutScratch<Scalar: UnsignedInteger> {
+    accumulators: Vec<GlweCiphertextOwned<Scalar>>,
+    bufs: Vec<GlweCiphertextOwned<Scalar>>,
+}
+
+impl<Scalar: UnsignedInteger> PackedLutScratch<Scalar> {
+    /// Allocates the buffers for evaluating up to `lut_count` packed LUTs.
+    pub fn new(
+        lut_count: usize,
+        glwe_size: GlweSize,
+        polynomial_size: PolynomialSize,
+        ciphertext_modulus: CiphertextModulus<Scalar>,
+    ) -> Result<Self, Error> {
+        let acc_count = LutLayout::new(polynomial_size)?.accumulator_count(lut_count);
+        let new_glwe = || GlweCiphertext::new(Scalar::ZERO, glwe_size, polynomial_size, ciphertext_modulus);
+        Ok(PackedLutScratch {
+            accumulators: (0..acc_count).map(|_| new_glwe()).collect(),
+            bufs: (0..acc_count).map(|_| new_glwe()).collect(),
+        })
+    }
+}
+
+/// Evaluates several 8-to-8 LUTs on the byte encrypted bit by bit in
+/// `fourier_ggsw_bit_list`, LSB first, by vertical packing: one CMUX tree per
+/// accumulator of `packed_luts`, laid out as in
+/// [`LutLayout`], followed by one sample extraction per
+/// output bit. Bit `b` of LUT `j` goes to `outputs[j].get(b)`.
+///
+/// The accumulators may be encrypted, as for keyed LUTs, or trivial.
+pub fn evaluate_packed_byte_luts<Scalar, AccCont, OutputCont>(
+    fourier_ggsw_bit_list: FourierGgswCiphertextListView,
+    packed_luts: &[GlweCiphertext<AccCont>],
+    outputs: &mut [LweCiphertextList<OutputCont>],
+    scratch: &mut PackedLutScratch<Scalar>,
+) -> Result<(), Error> where
+    Scalar: UnsignedTorus + Sync + Send,
+    AccCont: Container<Element=Scalar> + Sync,
+    OutputCont: ContainerMut<Element=Scalar>,
+{
+    let layout = LutLayout::new(fourier_ggsw_bit_list.polynomial_size())?;
+    let acc_count = layout.accumulator_count(outputs.len());
+    check_dimension("packed LUT accumulator count", acc_count, packed_luts.len())?;
+    if scratch.accumulators.len() < acc_count {
+        return Err(Error::IncompatibleParameters(format!(
+            "the LUT scratch holds {} accumulators, {} LUTs need {}",
+            scratch.accumulators.len(), outputs.len(), acc_count,
+        )));
+    }
+    check_dimension("GGSW bit count", BYTESIZE, fourier_ggsw_bit_list.count())?;
+    for output in outputs.iter() {
+        check_dimension("output bit count", BYTESIZE, output.lwe_ciphertext_count().0)?;
+    }
+
+    let cmux_tree = |((packed_lut, accumulator), buf): ((&GlweCiphertext<AccCont>, &mut GlweCiphertextOwned<Scalar>), &mut GlweCiphertextOwned<Scalar>)| {
+        accumulator.as_mut().copy_from_slice(packed_lut.as_ref());
+
+        for (i, fourier_ggsw_bit) in fourier_ggsw_bit_list.as_view().into_ggsw_iter().enumerate() {
+            buf.as_mut().copy_from_slice(accumulator.as_ref());
+            glwe_ciphertext_monic_monomial_div_assign(buf, MonomialDegree(1 << i));
+            glwe_ciphertext_sub_assign(buf, accumulator);
+            add_external_product_assign(accumulator, &fourier_ggsw_bit, buf);
+        }
+    };
+    #[cfg(not(feature = "multithread"))]
+    packed_luts.iter()
+        .zip(scratch.accumulators.iter_mut())
+        .zip(scratch.bufs.iter_mut())
+        .for_each(cmux_tree);
+    #[cfg(feature = "multithread")]
+    packed_luts.par_iter()
+        .zip(scratch.accumulators.par_iter_mut())
+        .zip(scratch.bufs.par_iter_mut())
+        .for_each(cmux_tree);
+
+    let bit_count = outputs.len() * BYTESIZE;
+    for (acc_idx, accumulator) in scratch.accumulators.iter().take(acc_count).enumerate() {
+        for (packed_bit_idx, degree) in layout.accumulator_bits(acc_idx, bit_count) {
+            let mut lwe_out = outputs[packed_bit_idx / BYTESIZE].get_mut(packed_bit_idx % BYTESIZE);
+            extract_lwe_sample_from_glwe_ciphertext(accumulator, &mut lwe_out, degree);
         }
     }
+    Ok(())
 }
 
 
-pub fn he_sbox_8_to_24_eval_by_patched_wwlp_cbs<Scalar, InputCont, OutputCont>(
-    input: &LweCiphertextList<InputCont>,
-    output: &mut LweCiphertextList<OutputCont>,
-    output_mult_by_2: &mut LweCiphertextList<OutputCont>,
-    output_mult_by_3: &mut LweCiphertextList<OutputCont>,
+/// Number of workers [`new_byte_cbs_workers`] allocates: one, or one per byte
+/// of an AES state under the `multithread` feature.
+#[cfg(not(feature = "multithread"))]
+pub const BYTE_CBS_WORKER_COUNT: usize = 1;
+#[cfg(feature = "multithread")]
+pub const BYTE_CBS_WORKER_COUNT: usize = BLOCKSIZE_IN_BYTE;
+
+/// Scratch space of one worker of the byte-wise LUT evaluations: a
+/// [`CbsContext`] with one lane per bit of a byte, and the buffers of
+/// [`evaluate_packed_byte_luts`]. Callers allocate their workers once, with
+/// [`new_byte_cbs_workers`], and hand them to every evaluation under the same
+/// keys, so no round allocates its own.
+pub struct ByteCbsWorker<Scalar: UnsignedTorus> {
+    cbs_context: CbsContext<Scalar>,
+    lut_scratch: PackedLutScratch<Scalar>,
+}
+
+impl<Scalar> ByteCbsWorker<Scalar>
+where
+    Scalar: UnsignedTorus + CastInto<usize> + CastFrom<usize>,
+{
+    /// Allocates a worker circuit bootstrapping into GGSW ciphertexts of the
+    /// given decomposition and evaluating up to `max_lut_count` LUTs at once.
+    pub fn new(
+        fourier_bsk: FourierLweBootstrapKeyView,
+        auto_keys: &HashMap<usize, AutomorphKey<ABox<[c64]>>>,
+        ggsw_base_log: DecompositionBaseLog,
+        ggsw_level: DecompositionLevelCount,
+        log_lut_count: LutCountLog,
+        ciphertext_modulus: CiphertextModulus<Scalar>,
+        max_lut_count: usize,
+    ) -> Result<Self, Error> {
+        Ok(ByteCbsWorker {
+            cbs_context: CbsContext::new(
+                fourier_bsk,
+                auto_keys,
+                ggsw_base_log,
+                ggsw_level,
+                log_lut_count,
+                ciphertext_modulus,
+                BYTESIZE,
+            )?,
+            lut_scratch: PackedLutScratch::new(
+                max_lut_count,
+                fourier_bsk.glwe_size(),
+                fourier_bsk.polynomial_size(),
+                ciphertext_modulus,
+            )?,
+        })
+    }
+
+    pub fn cbs_context(&mut self) -> &mut CbsContext<Scalar> {
+        &mut self.cbs_context
+    }
+
+    /// Circuit bootstraps the byte `input_byte`, LSB first, and evaluates
+    /// `packed_luts` on it as [`evaluate_packed_byte_luts`] does.
+    pub fn eval_packed_byte_luts<InputCont, AccCont, OutputCont>(
+        &mut self,
+        input_byte: &LweCiphertextList<InputCont>,
+        packed_luts: &[GlweCiphertext<AccCont>],
+        outputs: &mut [LweCiphertextList<OutputCont>],
+        fourier_bsk: FourierLweBootstrapKeyView,
+        auto_keys: &HashMap<usize, AutomorphKey<ABox<[c64]>>>,
+        ss_key: FourierGgswCiphertextListView,
+    ) -> Result<(), Error>
+    where
+        InputCont: Container<Element=Scalar>,
+        AccCont: Container<Element=Scalar> + Sync,
+        OutputCont: ContainerMut<Element=Scalar>,
+    {
+        let fourier_ggsw_bit_list = self.cbs_context.circuit_bootstrap_lwe_ciphertext_list(input_byte, fourier_bsk, auto_keys, ss_key)?;
+        evaluate_packed_byte_luts(fourier_ggsw_bit_list, packed_luts, outputs, &mut self.lut_scratch)
+    }
+
+    /// Circuit bootstraps the byte `input_byte` once and evaluates each of
+    /// `keyed_luts`, the accumulators of a single LUT, into the matching entry
+    /// of `outputs`.
+    pub fn eval_keyed_byte_luts<InputCont, AccCont, OutputCont>(
+        &mut self,
+        input_byte: &LweCiphertextList<InputCont>,
+        keyed_luts: &[&GlweCiphertextList<AccCont>],
+        outputs: &mut [LweCiphertextList<OutputCont>],
+        fourier_bsk: FourierLweBootstrapKeyView,
+        auto_keys: &HashMap<usize, AutomorphKey<ABox<[c64]>>>,
+        ss_key: FourierGgswCiphertextListView,
+    ) -> Result<(), Error>
+    where
+        InputCont: Container<Element=Scalar>,
+        AccCont: Container<Element=Scalar> + Sync,
+        OutputCont: ContainerMut<Element=Scalar>,
+    {
+        check_dimension("output count", keyed_luts.len(), outputs.len())?;
+        let fourier_ggsw_bit_list = self.cbs_context.circuit_bootstrap_lwe_ciphertext_list(input_byte, fourier_bsk, auto_keys, ss_key)?;
+        for (keyed_lut, output) in keyed_luts.iter().zip(outputs.iter_mut()) {
+            let accumulators = keyed_lut.iter().collect::<Vec<_>>();
+            evaluate_packed_byte_luts(fourier_ggsw_bit_list.as_view(), &accumulators, std::slice::from_mut(output), &mut self.lut_scratch)?;
+        }
+        Ok(())
+    }
+}
+
+/// Allocates [`BYTE_CBS_WORKER_COUNT`] workers, see [`ByteCbsWorker::new`].
+pub fn new_byte_cbs_workers<Scalar>(
     fourier_bsk: FourierLweBootstrapKeyView,
     auto_keys: &HashMap<usize, AutomorphKey<ABox<[c64]>>>,
-    ss_key: FourierGgswCiphertextListView,
     ggsw_base_log: DecompositionBaseLog,
     ggsw_level: DecompositionLevelCount,
     log_lut_count: LutCountLog,
-) where
+    ciphertext_modulus: CiphertextModulus<Scalar>,
+    max_lut_count: usize,
+) -> Result<Vec<ByteCbsWorker<Scalar>>, Error>
+where
     Scalar: UnsignedTorus + CastInto<usize> + CastFrom<usize>,
-    InputCont: Container<Element=Scalar>,
-    OutputCont: ContainerMut<Element=Scalar>,
 {
-    let glwe_size = fourier_bsk.glwe_size();
-    let polynomial_size = fourier_bsk.polynomial_size();
-    let ciphertext_modulus = output.ciphertext_modulus();
-
-    let mut vec_glev = vec![
-        GlweCiphertextList::new(
-            Scalar::ZERO,
-            glwe_size,
-            polynomial_size,
-            GlweCiphertextCount(ggsw_level.0),
-            ciphertext_modulus,
-        ); BYTESIZE];
-    for (input_bit, glev) in input.iter().zip(vec_glev.iter_mut()) {
-        let glev_mut_view = GlweCiphertextListMutView::from_container(
-            glev.as_mut(),
-            glwe_size,
-            polynomial_size,
-            ciphertext_modulus,
-        );
-
-        lwe_msb_bit_to_glev_by_trace_with_preprocessing(
-            input_bit.as_view(),
-            glev_mut_view,
+    (0..BYTE_CBS_WORKER_COUNT)
+        .map(|_| ByteCbsWorker::new(
             fourier_bsk,
             auto_keys,
             ggsw_base_log,
             ggsw_level,
             log_lut_count,
-        );
+            ciphertext_modulus,
+            max_lut_count,
+        ))
+        .collect()
+}
+
+/// Runs `job` on every item of `jobs`, the items being split into contiguous
+/// batches, one per worker. Under the `multithread` feature the workers run
+/// in parallel.
+pub fn run_on_byte_cbs_workers<Scalar, T, F>(
+    workers: &mut [ByteCbsWorker<Scalar>],
+    jobs: Vec<T>,
+    job: F,
+) -> Result<(), Error>
+where
+    Scalar: UnsignedTorus,
+    T: Send,
+    F: Fn(&mut ByteCbsWorker<Scalar>, T) -> Result<(), Error> + Sync,
+{
+    if workers.is_empty() {
+        return Err(Error::IncompatibleParameters("no circuit bootstrapping worker to run on".to_owned()));
     }
+    let batch_size = jobs.len().div_ceil(workers.len()).max(1);
+    let mut jobs = jobs.into_iter();
+    let batches = workers.iter()
+        .map(|_| jobs.by_ref().take(batch_size).collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+
+    let run_batch = |(worker, batch): (&mut ByteCbsWorker<Scalar>, Vec<T>)| {
+        batch.into_iter().try_for_each(|item| job(worker, item))
+    };
+    #[cfg(not(feature = "multithread"))]
+    workers.iter_mut().zip(batches).try_for_each(run_batch)?;
+    #[cfg(feature = "multithread")]
+    workers.par_iter_mut().zip(batches).try_for_each(run_batch)?;
+    Ok(())
+}
 
-    let mut ggsw_bit_list = GgswCiphertextList::new(
-        Scalar::ZERO,
-        glwe_size,
-        polynomial_size,
-        ggsw_base_log,
-        ggsw_level,
-        GgswCiphertextCount(vec_glev.len()),
-        ciphertext_modulus,
-    );
-    for (mut ggsw, glev) in ggsw_bit_list.iter_mut().zip(vec_glev.iter()) {
-        switch_scheme(&glev, &mut ggsw, ss_key);
+/// Plaintext of a keyed LUT entry table: one polynomial per input byte, the
+/// polynomial of entry `x` holding bit `b` of `luts[j][x]` at coefficient
+/// `j * BYTESIZE + b`, scaled by `2^(Scalar::BITS - 1)`.
+pub fn lut_entry_table_plaintext<Scalar>(
+    luts: &[&[u8; 1 << BYTESIZE]],
+    polynomial_size: PolynomialSize,
+) -> Vec<Scalar>
+where
+    Scalar: UnsignedTorus + CastFrom<usize>,
+{
+    assert!(luts.len() * BYTESIZE <= polynomial_size.0);
+
+    let mut table = vec![Scalar::ZERO; (1 << BYTESIZE) * polynomial_size.0];
+    for (entry, poly) in table.chunks_exact_mut(polynomial_size.0).enumerate() {
+        for (j, lut) in luts.iter().enumerate() {
+            for b in 0..BYTESIZE {
+                let bit = (lut[entry] >> b) & 1;
+                poly[j * BYTESIZE + b] = ((bit as usize) << (Scalar::BITS - 1)).cast_into();
+            }
+        }
     }
+    table
+}
 
-    let mut fourier_ggsw_bit_list = FourierGgswCiphertextList::new(
-        vec![c64::default();
-            BYTESIZE * polynomial_size.to_fourier_polynomial_size().0
-                * glwe_size.0
-                * glwe_size.0
-                * ggsw_level.0
-        ],
-        BYTESIZE,
-        glwe_size,
-        polynomial_size,
-        ggsw_base_log,
-        ggsw_level,
-    );
-    for (mut fourier_ggsw, ggsw) in fourier_ggsw_bit_list.as_mut_view().into_ggsw_iter().zip(ggsw_bit_list.iter()) {
-        convert_standard_ggsw_ciphertext_to_fourier(&ggsw, &mut fourier_ggsw);
+/// GLWE buffers of [`evaluate_lut_entry_table_on_xored_bytes`]: a copy of the
+/// entry table folded in place and the two CMUX buffers.
+pub struct LutEntryTableScratch<Scalar: UnsignedInteger> {
+    entries: GlweCiphertextListOwned<Scalar>,
+    diff: GlweCiphertextOwned<Scalar>,
+    product: GlweCiphertextOwned<Scalar>,
+}
+
+impl<Scalar: UnsignedInteger> LutEntryTableScratch<Scalar> {
+    pub fn new(
+        glwe_size: GlweSize,
+        polynomial_size: PolynomialSize,
+        ciphertext_modulus: CiphertextModulus<Scalar>,
+    ) -> Self {
+        LutEntryTableScratch {
+            entries: GlweCiphertextList::new(Scalar::ZERO, glwe_size, polynomial_size, GlweCiphertextCount(1 << BYTESIZE), ciphertext_modulus),
+            diff: GlweCiphertext::new(Scalar::ZERO, glwe_size, polynomial_size, ciphertext_modulus),
+            product: GlweCiphertext::new(Scalar::ZERO, glwe_size, polynomial_size, ciphertext_modulus),
+        }
     }
+}
 
-    evaluate_8_to_8_lut(
-        fourier_ggsw_bit_list.as_mut_view(),
-        output,
-        &AES128_SBOX,
-        Scalar::BITS - 1,
-        ciphertext_modulus,
-    );
+/// Evaluates the LUTs of `entry_table`, laid out as in
+/// [`lut_entry_table_plaintext`], on the XOR of the bytes encrypted bit by bit
+/// in `fourier_ggsw_byte_lists`, LSB first. The XOR itself is never computed:
+/// at each level of the tree, every byte but the last conditionally swaps the
+/// two entries of a pair and the last one selects between them. Bit `b` of LUT
+/// `j` goes to `outputs[j].get(b)`.
+///
+/// Each GGSW only drives external products against encrypted entries, so it
+/// may come out of a noisier conversion than a full circuit bootstrap.
+pub fn evaluate_lut_entry_table_on_xored_bytes<Scalar, TableCont, OutputCont>(
+    fourier_ggsw_byte_lists: &[FourierGgswCiphertextListView],
+    entry_table: &GlweCiphertextList<TableCont>,
+    outputs: &mut [LweCiphertextList<OutputCont>],
+    scratch: &mut LutEntryTableScratch<Scalar>,
+) where
+    Scalar: UnsignedTorus,
+    TableCont: Container<Element=Scalar>,
+    OutputCont: ContainerMut<Element=Scalar>,
+{
+    assert!(!fourier_ggsw_byte_lists.is_empty());
+    assert_eq!(entry_table.glwe_ciphertext_count().0, 1 << BYTESIZE);
+    assert!(outputs.len() * BYTESIZE <= entry_table.polynomial_size().0);
+
+    let polynomial_size = entry_table.polynomial_size();
+    let ciphertext_modulus = entry_table.ciphertext_modulus();
+    let glwe_len = entry_table.glwe_size().0 * polynomial_size.0;
+    let selector_bits = fourier_ggsw_byte_lists.iter()
+        .map(|byte| {
+            assert_eq!(byte.count(), BYTESIZE);
+            byte.into_ggsw_iter().collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+    let last = selector_bits.len() - 1;
+
+    scratch.entries.as_mut().copy_from_slice(entry_table.as_ref());
+    for i in 0..BYTESIZE {
+        let stride = (1 << i) * glwe_len;
+        for pair in scratch.entries.as_mut().chunks_exact_mut(2 * stride) {
+            let (lo, hi) = pair.split_at_mut(stride);
+            let mut entry_0 = GlweCiphertext::from_container(&mut lo[..glwe_len], polynomial_size, ciphertext_modulus);
+            let mut entry_1 = GlweCiphertext::from_container(&mut hi[..glwe_len], polynomial_size, ciphertext_modulus);
+
+            for (k, byte_bits) in selector_bits.iter().enumerate() {
+                scratch.diff.as_mut().copy_from_slice(entry_1.as_ref());
+                glwe_ciphertext_sub_assign(&mut scratch.diff, &entry_0);
+                scratch.product.as_mut().fill(Scalar::ZERO);
+                add_external_product_assign(&mut scratch.product, &byte_bits[i], &scratch.diff);
+                glwe_ciphertext_add_assign(&mut entry_0, &scratch.product);
+                if k < last {
+                    glwe_ciphertext_sub_assign(&mut entry_1, &scratch.product);
+                }
+            }
+        }
+    }
 
-    evaluate_8_to_8_lut(
-        fourier_ggsw_bit_list.as_mut_view(),
-        output_mult_by_2,
-        &AES128_SBOX_MULT_BY_2,
-        Scalar::BITS - 1,
-        ciphertext_modulus,
-    );
+    let result = scratch.entries.get(0);
+    for (j, output) in outputs.iter_mut().enumerate() {
+        for (b, mut lwe_out) in output.iter_mut().enumerate() {
+            extract_lwe_sample_from_glwe_ciphertext(&result, &mut lwe_out, MonomialDegree(j * BYTESIZE + b));
+        }
+    }
+}
 
-    evaluate_8_to_8_lut(
-        fourier_ggsw_bit_list.as_mut_view(),
-        output_mult_by_3,
-        &AES128_SBOX_MULT_BY_3,
-        Scalar::BITS - 1,
-        ciphertext_modulus,
-    );
+
+/// Splits `outputs` into bytes after checking them against `input`, entry `i`
+/// holding byte `i` of every output.
+fn split_output_bytes<'a, Scalar, InputCont, OutputCont>(
+    input: &LweCiphertextList<InputCont>,
+    outputs: &'a mut [LweCiphertextList<OutputCont>],
+    lut_count: usize,
+) -> Result<Vec<Vec<LweCiphertextListMutView<'a, Scalar>>>, Error> where
+    Scalar: UnsignedInteger,
+    InputCont: Container<Element=Scalar>,
+    OutputCont: ContainerMut<Element=Scalar>,
+{
+    check_dimension("output count", lut_count, outputs.len())?;
+    let num_bits = input.lwe_ciphertext_count().0;
+    if num_bits % BYTESIZE != 0 {
+        return Err(Error::IncompatibleParameters(format!("{} input bits do not make whole bytes", num_bits)));
+    }
+    for output in outputs.iter() {
+        check_dimension("output bit count", num_bits, output.lwe_ciphertext_count().0)?;
+    }
+
+    let mut output_bytes: Vec<Vec<LweCiphertextListMutView<Scalar>>> = (0..num_bits / BYTESIZE)
+        .map(|_| Vec::with_capacity(lut_count))
+        .collect();
+    for output in outputs.iter_mut() {
+        for (byte_outputs, output_byte) in output_bytes.iter_mut().zip(output.chunks_exact_mut(BYTESIZE)) {
+            byte_outputs.push(output_byte);
+        }
+    }
+    Ok(output_bytes)
 }
 
+/// Evaluates public 8-to-8 LUTs on every byte of `input`, circuit bootstrapping
+/// each bit once for all of them: `outputs[j]` receives `luts[j]` of each byte.
+/// The bytes are spread over `workers`, whose LUT scratch must hold
+/// `luts.len()` LUTs.
+pub fn he_eval_byte_luts_by_patched_wwlp_cbs<Scalar, InputCont, OutputCont>(
+    input: &LweCiphertextList<InputCont>,
+    outputs: &mut [LweCiphertextList<OutputCont>],
+    luts: &[&[u8; 1 << BYTESIZE]],
+    fourier_bsk: FourierLweBootstrapKeyView,
+    auto_keys: &HashMap<usize, AutomorphKey<ABox<[c64]>>>,
+    ss_key: FourierGgswCiphertextListView,
+    workers: &mut [ByteCbsWorker<Scalar>],
+) -> Result<(), Error> where
+    Scalar: UnsignedTorus + CastInto<usize> + CastFrom<usize>,
+    InputCont: Container<Element=Scalar>,
+    OutputCont: ContainerMut<Element=Scalar>,
+{
+    let output_bytes = split_output_bytes(input, outputs, luts.len())?;
+    let accumulators = trivial_packed_lut_accumulators(
+        luts,
+        Scalar::BITS - 1,
+        fourier_bsk.glwe_size(),
+        fourier_bsk.polynomial_size(),
+        input.ciphertext_modulus(),
+    )?;
+    run_on_byte_cbs_workers(
+        workers,
+        input.chunks_exact(BYTESIZE).zip(output_bytes).collect(),
+        |worker, (input_byte, mut byte_outputs)| {
+            worker.eval_packed_byte_luts(&input_byte, &accumulators, &mut byte_outputs, fourier_bsk, auto_keys, ss_key)
+        },
+    )
+}
 
+/// Keyed counterpart of [`he_eval_byte_luts_by_patched_wwlp_cbs`]: the
+/// accumulators of LUT `j` on byte `i` are `keyed_luts[j][i]`, one LUT per
+/// list.
+pub fn he_eval_keyed_byte_luts_by_patched_wwlp_cbs<Scalar, InputCont, AccCont, OutputCont>(
+    input: &LweCiphertextList<InputCont>,
+    outputs: &mut [LweCiphertextList<OutputCont>],
+    keyed_luts: &[&[GlweCiphertextList<AccCont>]],
+    fourier_bsk: FourierLweBootstrapKeyView,
+    auto_keys: &HashMap<usize, AutomorphKey<ABox<[c64]>>>,
+    ss_key: FourierGgswCiphertextListView,
+    workers: &mut [ByteCbsWorker<Scalar>],
+) -> Result<(), Error> where
+    Scalar: UnsignedTorus + CastInto<usize> + CastFrom<usize>,
+    InputCont: Container<Element=Scalar>,
+    AccCont: Container<Element=Scalar> + Sync,
+    OutputCont: ContainerMut<Element=Scalar>,
+{
+    let output_bytes = split_output_bytes(input, outputs, keyed_luts.len())?;
+    for keyed_lut in keyed_luts {
+        check_dimension("keyed LUT byte count", output_bytes.len(), keyed_lut.len())?;
+    }
+    run_on_byte_cbs_workers(
+        workers,
+        input.chunks_exact(BYTESIZE).zip(output_bytes).enumerate().collect(),
+        |worker, (i, (input_byte, mut byte_outputs))| {
+            let byte_luts = keyed_luts.iter().map(|keyed_lut| &keyed_lut[i]).collect::<Vec<_>>();
+            worker.eval_keyed_byte_luts(&input_byte, &byte_luts, &mut byte_outputs, fourier_bsk, auto_keys, ss_key)
+        },
+    )
+}
 
+/// Keyed counterpart of [`he_eval_byte_luts_by_patched_wwlp_cbs`] on packed
+/// accumulators: `packed_keyed_luts[i]` packs the LUTs of byte `i` as in
+/// [`LutLayout`], and `outputs[j]` receives LUT `j` of each byte.
+pub fn he_eval_packed_keyed_byte_luts_by_patched_wwlp_cbs<Scalar, InputCont, AccCont, OutputCont>(
+    input: &LweCiphertextList<InputCont>,
+    outputs: &mut [LweCiphertextList<OutputCont>],
+    packed_keyed_luts: &[GlweCiphertextList<AccCont>],
+    fourier_bsk: FourierLweBootstrapKeyView,
+    auto_keys: &HashMap<usize, AutomorphKey<ABox<[c64]>>>,
+    ss_key: FourierGgswCiphertextListView,
+    workers: &mut [ByteCbsWorker<Scalar>],
+) -> Result<(), Error> where
+    Scalar: UnsignedTorus + CastInto<usize> + CastFrom<usize>,
+    InputCont: Container<Element=Scalar>,
+    AccCont: Container<Element=Scalar> + Sync,
+    OutputCont: ContainerMut<Element=Scalar>,
+{
+    let lut_count = outputs.len();
+    let output_bytes = split_output_bytes(input, outputs, lut_count)?;
+    check_dimension("keyed LUT byte count", output_bytes.len(), packed_keyed_luts.len())?;
+    run_on_byte_cbs_workers(
+        workers,
+        input.chunks_exact(BYTESIZE).zip(output_bytes).zip(packed_keyed_luts).collect(),
+        |worker, ((input_byte, mut byte_outputs), packed_luts)| {
+            let packed_luts = packed_luts.iter().collect::<Vec<_>>();
+            worker.eval_packed_byte_luts(&input_byte, &packed_luts, &mut byte_outputs, fourier_bsk, auto_keys, ss_key)
+        },
+    )
+}
 
-fn evaluate_8_to_8_lut<Scalar, ContMut>(
-    fourier_ggsw_bit_list: FourierGgswCiphertextListMutView,
-    output: &mut LweCiphertextList<ContMut>,
-    lut: &[u8; 256],
-    log_scale: usize,
-    ciphertext_modulus: CiphertextModulus<Scalar>,
-) where
-    Scalar: UnsignedTorus + CastFrom<usize>,
-    ContMut: ContainerMut<Element=Scalar>,
+/// Runs the AES key schedule on an encrypted 128, 192 or 256-bit key, given
+/// bit by bit in the HE state layout, and returns the round keys 0 to Nr in
+/// that layout.
+///
+/// Each new word is a sum of earlier words, so the words are refreshed through
+/// an identity LUT four at a time; otherwise the noise roughly doubles from
+/// one word to the next.
+pub fn he_key_expansion_by_patched_wwlp_cbs<Scalar, KeyCont, KSKeyCont>(
+    he_key: &LweCiphertextList<KeyCont>,
+    fourier_glwe_ksk: &FourierGlweKeyswitchKey<KSKeyCont>,
+    fourier_bsk: FourierLweBootstrapKeyView,
+    auto_keys: &HashMap<usize, AutomorphKey<ABox<[c64]>>>,
+    ss_key: FourierGgswCiphertextListView,
+    workers: &mut [ByteCbsWorker<Scalar>],
+) -> Result<Vec<LweCiphertextListOwned<Scalar>>, Error>
+where
+    Scalar: UnsignedTorus + CastInto<usize> + CastFrom<usize>,
+    KeyCont: Container<Element=Scalar>,
+    KSKeyCont: Container<Element=c64> + Sync,
 {
-    let glwe_size = fourier_ggsw_bit_list.glwe_size();
-    // println!("glwe_size {}",glwe_size.0);
-    let polynomial_size = fourier_ggsw_bit_list.polynomial_size();
-    let num_par_lut = polynomial_size.0 / (1 << BYTESIZE);
-    let num_accumulator = if BYTESIZE % num_par_lut == 0 {
-        BYTESIZE / num_par_lut
-    } else {
-        BYTESIZE / num_par_lut + 1
-    };
-    // println!("num_accumulator: {}, num_par_lut: {}", num_accumulator,num_par_lut);
-    for acc_idx in 0..num_accumulator {
-        let accumulator = (0..polynomial_size.0).map(|i| {
-            let lut_idx = acc_idx * num_par_lut + i / (1 << BYTESIZE);
-            (((lut[i % (1 << BYTESIZE)] & (1 << lut_idx)) as usize) << (log_scale - lut_idx)).cast_into()
-        }).collect::<Vec<Scalar>>();
-        let accumulator_plaintext = PlaintextList::from_container(accumulator);
-        let mut accumulator: GlweCiphertext<Vec<Scalar>> = allocate_and_trivially_encrypt_new_glwe_ciphertext(glwe_size, &accumulator_plaintext, ciphertext_modulus);
-
-        for (i, fourier_ggsw_bit) in fourier_ggsw_bit_list.as_view().into_ggsw_iter().into_iter().enumerate() {
-            let mut buf = accumulator.clone();
-            glwe_ciphertext_monic_monomial_div_assign(&mut buf, MonomialDegree(1 << i));
-            glwe_ciphertext_sub_assign(&mut buf, &accumulator);
-            add_external_product_assign(&mut accumulator, &fourier_ggsw_bit, &buf);
+    let key_len_in_bit = he_key.lwe_ciphertext_count().0;
+    let key_size = Some(key_len_in_bit)
+        .filter(|len| len % BYTESIZE == 0)
+        .and_then(|len| AesKeySize::from_key_len_in_byte(len / BYTESIZE))
+        .ok_or_else(|| Error::IncompatibleParameters(format!(
+            "the encrypted AES key is {} bits long, expected 128, 192 or 256",
+            key_len_in_bit,
+        )))?;
+
+    let lwe_size = he_key.lwe_size();
+    let ks_lwe_size = fourier_bsk.input_lwe_dimension().to_lwe_size();
+    let ciphertext_modulus = he_key.ciphertext_modulus();
+    let word_size = NUM_ROWS * BYTESIZE;
+    let nk = key_size.num_key_words();
+    let num_words = NUM_COLUMNS * (key_size.num_rounds() + 1);
+    let identity: [u8; 1 << BYTESIZE] = std::array::from_fn(|x| x as u8);
+
+    let sub_word = |word: &LweCiphertextList<&[Scalar]>, rotate: bool, workers: &mut [ByteCbsWorker<Scalar>]| {
+        let mut word_ks = LweCiphertextList::new(Scalar::ZERO, ks_lwe_size, LweCiphertextCount(word_size), ciphertext_modulus);
+        keyswitch_lwe_ciphertext_list_by_glwe_keyswitch(word, &mut word_ks, fourier_glwe_ksk);
+        if rotate {
+            let buf = word_ks.clone();
+            for row in 0..NUM_ROWS {
+                word_ks.get_sub_mut((row * BYTESIZE)..((row + 1) * BYTESIZE)).as_mut()
+                    .clone_from_slice(buf.get_sub((((row + 1) % NUM_ROWS) * BYTESIZE)..(((row + 1) % NUM_ROWS + 1) * BYTESIZE)).as_ref());
+            }
         }
 
-        for i in 0..num_par_lut {
-            let bit_idx = acc_idx * num_par_lut + i;
-            let mut lwe_out = output.get_mut(bit_idx);
-            extract_lwe_sample_from_glwe_ciphertext(&accumulator, &mut lwe_out, MonomialDegree(i * (1 << BYTESIZE)));
+        let mut output = LweCiphertextList::new(Scalar::ZERO, lwe_size, LweCiphertextCount(word_size), ciphertext_modulus);
+        he_eval_byte_luts_by_patched_wwlp_cbs(
+            &word_ks,
+            std::slice::from_mut(&mut output),
+            &[&AES128_SBOX],
+            fourier_bsk,
+            auto_keys,
+            ss_key,
+            workers,
+        )?;
+        Ok(output)
+    };
+
+    let mut words = LweCiphertextList::new(Scalar::ZERO, lwe_size, LweCiphertextCount(num_words * word_size), ciphertext_modulus);
+    words.get_sub_mut(0..(nk * word_size)).as_mut().clone_from_slice(he_key.as_ref());
+
+    let word_range = |i: usize| (i * word_size)..((i + 1) * word_size);
+    for batch_start in (nk..num_words).step_by(NUM_COLUMNS) {
+        let batch_end = std::cmp::min(batch_start + NUM_COLUMNS, num_words);
+        for i in batch_start..batch_end {
+            // w[i] = w[i - Nk] + temp, where temp is w[i - 1] possibly through SubWord
+            let prev_word = words.get_sub(word_range(i - 1));
+            let mut word = if i % nk == 0 {
+                let mut temp = sub_word(&prev_word, true, workers)?;
+                for (bit_idx, mut lwe) in temp.iter_mut().take(BYTESIZE).enumerate() {
+                    if (RCON[i / nk] >> bit_idx) & 1 == 1 {
+                        lwe_ciphertext_plaintext_add_assign(&mut lwe, Plaintext(Scalar::ONE << (Scalar::BITS - 1)));
+                    }
+                }
+                temp
+            } else if nk > 6 && i % nk == 4 {
+                sub_word(&prev_word, false, workers)?
+            } else {
+                LweCiphertextList::from_container(prev_word.as_ref().to_vec(), lwe_size, ciphertext_modulus)
+            };
+            lwe_ciphertext_list_add_assign(&mut word, words.get_sub(word_range(i - nk)));
+            words.get_sub_mut(word_range(i)).as_mut().clone_from_slice(word.as_ref());
         }
+
+        let mut batch = words.get_sub_mut((batch_start * word_size)..(batch_end * word_size));
+        let mut batch_ks = LweCiphertextList::new(Scalar::ZERO, ks_lwe_size, batch.lwe_ciphertext_count(), ciphertext_modulus);
+        keyswitch_lwe_ciphertext_list_by_glwe_keyswitch(&batch, &mut batch_ks, fourier_glwe_ksk);
+        he_eval_byte_luts_by_patched_wwlp_cbs(
+            &batch_ks,
+            std::slice::from_mut(&mut batch),
+            &[&identity],
+            fourier_bsk,
+            auto_keys,
+            ss_key,
+            workers,
+        )?;
     }
-}
 
+    Ok(words.chunks_exact(BLOCKSIZE_IN_BIT)
+        .map(|round_key| LweCiphertextList::from_container(round_key.as_ref().to_vec(), lwe_size, ciphertext_modulus))
+        .collect())
+}
 
 pub fn generate_vec_keyed_lut_accumulator<Scalar, KeyCont, G>(
     keyed_lut_list: [[u8; 1 << BYTESIZE]; BLOCKSIZE_IN_BYTE],
@@ -839,7 +1276,7 @@ pub fn generate_vec_keyed_lut_accumulator<Scalar, KeyCont, G>(
     glwe_modular_std_dev: impl DispersionParameter,
     ciphertext_modulus: CiphertextModulus<Scalar>,
     encryption_generator: &mut EncryptionRandomGenerator<G>,
-) -> Vec::<GlweCiphertextListOwned<Scalar>>
+) -> Result<Vec::<GlweCiphertextListOwned<Scalar>>, Error>
 where
     Scalar: UnsignedTorus + CastFrom<usize>,
     KeyCont: Container<Element=Scalar>,
@@ -850,22 +1287,15 @@ where
 
     let mut vec_keyed_lut_acc = Vec::<GlweCiphertextListOwned<Scalar>>::with_capacity(BLOCKSIZE_IN_BIT);
 
-    let num_par_lut = polynomial_size.0 / (1 << BYTESIZE);
-    let num_acc_per_byte = if BYTESIZE % num_par_lut == 0 {
-        BYTESIZE / num_par_lut
-    } else {
-        BYTESIZE / num_par_lut + 1
-    };
+    let layout = LutLayout::new(polynomial_size)?;
+    let num_acc_per_byte = layout.accumulator_count(1);
 
     for byte_idx in 0..BLOCKSIZE_IN_BYTE {
         let mut keyed_lut_acc_list = GlweCiphertextList::new(Scalar::ZERO, glwe_size, polynomial_size, GlweCiphertextCount(num_acc_per_byte), ciphertext_modulus);
 
         let keyed_lut = keyed_lut_list[byte_idx];
         for (acc_idx, mut keyed_lut_acc) in keyed_lut_acc_list.iter_mut().enumerate() {
-            let acc = (0..polynomial_size.0).map(|i| {
-                let lut_idx = acc_idx * num_par_lut + i / (1 << BYTESIZE);
-                (((keyed_lut[i % (1 << BYTESIZE)] & (1 << lut_idx)) as usize) << (log_scale - lut_idx)).cast_into()
-            }).collect::<Vec<Scalar>>();
+            let acc = layout.accumulator_plaintext(&[&keyed_lut], acc_idx, log_scale);
             let acc = PlaintextList::from_container(acc);
             
             encrypt_glwe_ciphertext(glwe_secret_key, &mut keyed_lut_acc, &acc, glwe_modular_std_dev, encryption_generator);
@@ -873,7 +1303,78 @@ where
         vec_keyed_lut_acc.push(keyed_lut_acc_list);
     }
 
-    vec_keyed_lut_acc
+    Ok(vec_keyed_lut_acc)
+}
+
+/// Seeded variant of [`generate_vec_keyed_lut_accumulator`]. Each byte's
+/// accumulators form one seeded list, to be expanded with
+/// `decompress_into_glwe_ciphertext_list` on the server.
+pub fn generate_vec_seeded_keyed_lut_accumulator<Scalar, KeyCont, NoiseSeeder>(
+    keyed_lut_list: [[u8; 1 << BYTESIZE]; BLOCKSIZE_IN_BYTE],
+    log_scale: usize,
+    glwe_secret_key: &GlweSecretKey<KeyCont>,
+    glwe_modular_std_dev: impl DispersionParameter,
+    ciphertext_modulus: CiphertextModulus<Scalar>,
+    noise_seeder: &mut NoiseSeeder,
+) -> Result<Vec::<SeededGlweCiphertextListOwned<Scalar>>, Error>
+where
+    Scalar: UnsignedTorus + CastFrom<usize>,
+    KeyCont: Container<Element=Scalar>,
+    NoiseSeeder: Seeder + ?Sized,
+{
+    generate_vec_seeded_packed_keyed_lut_accumulator(
+        &[keyed_lut_list],
+        log_scale,
+        glwe_secret_key,
+        glwe_modular_std_dev,
+        ciphertext_modulus,
+        noise_seeder,
+    )
+}
+
+/// Packs, for each byte, the keyed LUTs of every list in `keyed_lut_lists`
+/// into one seeded list of accumulators laid out as in
+/// [`LutLayout`], so that the server evaluates them
+/// together with [`evaluate_packed_byte_luts`].
+pub fn generate_vec_seeded_packed_keyed_lut_accumulator<Scalar, KeyCont, NoiseSeeder>(
+    keyed_lut_lists: &[[[u8; 1 << BYTESIZE]; BLOCKSIZE_IN_BYTE]],
+    log_scale: usize,
+    glwe_secret_key: &GlweSecretKey<KeyCont>,
+    glwe_modular_std_dev: impl DispersionParameter,
+    ciphertext_modulus: CiphertextModulus<Scalar>,
+    noise_seeder: &mut NoiseSeeder,
+) -> Result<Vec::<SeededGlweCiphertextListOwned<Scalar>>, Error>
+where
+    Scalar: UnsignedTorus + CastFrom<usize>,
+    KeyCont: Container<Element=Scalar>,
+    NoiseSeeder: Seeder + ?Sized,
+{
+    let glwe_size = glwe_secret_key.glwe_dimension().to_glwe_size();
+    let polynomial_size = glwe_secret_key.polynomial_size();
+
+    let layout = LutLayout::new(polynomial_size)?;
+    let num_acc_per_byte = layout.accumulator_count(keyed_lut_lists.len());
+
+    Ok((0..BLOCKSIZE_IN_BYTE).map(|byte_idx| {
+        let keyed_luts = keyed_lut_lists.iter()
+            .map(|keyed_lut_list| &keyed_lut_list[byte_idx])
+            .collect::<Vec<_>>();
+        let acc = (0..num_acc_per_byte)
+            .flat_map(|acc_idx| layout.accumulator_plaintext(&keyed_luts, acc_idx, log_scale))
+            .collect::<Vec<Scalar>>();
+        let acc = PlaintextList::from_container(acc);
+
+        let mut keyed_lut_acc_list = SeededGlweCiphertextList::new(
+            Scalar::ZERO,
+            glwe_size,
+            polynomial_size,
+            GlweCiphertextCount(num_acc_per_byte),
+            noise_seeder.seed().into(),
+            ciphertext_modulus,
+        );
+        encrypt_seeded_glwe_ciphertext_list(glwe_secret_key, &mut keyed_lut_acc_list, &acc, glwe_modular_std_dev, noise_seeder);
+        keyed_lut_acc_list
+    }).collect())
 }
 
 
@@ -885,7 +1386,7 @@ pub fn generate_vec_keyed_lut_glev<Scalar, KeyCont, G>(
     glwe_modular_std_dev: impl DispersionParameter,
     ciphertext_modulus: CiphertextModulus<Scalar>,
     encryption_generator: &mut EncryptionRandomGenerator<G>,
-) -> Vec::<Vec<GlweCiphertextListOwned<Scalar>>>
+) -> Result<Vec::<Vec<GlweCiphertextListOwned<Scalar>>>, Error>
 where
     Scalar: UnsignedTorus + CastFrom<usize>,
     KeyCont: Container<Element=Scalar>,
@@ -904,15 +1405,86 @@ where
             glwe_modular_std_dev,
             ciphertext_modulus,
             encryption_generator,
-        );
+        )?;
 
         vec_keyed_lut_glev.push(vec_keyed_lut_acc_list);
     }
 
-    vec_keyed_lut_glev
+    Ok(vec_keyed_lut_glev)
+}
+
+/// Seeded variant of [`generate_vec_keyed_lut_glev`].
+pub fn generate_vec_seeded_keyed_lut_glev<Scalar, KeyCont, NoiseSeeder>(
+    keyed_lut_list: [[u8; 1 << BYTESIZE]; BLOCKSIZE_IN_BYTE],
+    glev_base_log: DecompositionBaseLog,
+    glev_level: DecompositionLevelCount,
+    glwe_secret_key: &GlweSecretKey<KeyCont>,
+    glwe_modular_std_dev: impl DispersionParameter,
+    ciphertext_modulus: CiphertextModulus<Scalar>,
+    noise_seeder: &mut NoiseSeeder,
+) -> Result<Vec::<Vec<SeededGlweCiphertextListOwned<Scalar>>>, Error>
+where
+    Scalar: UnsignedTorus + CastFrom<usize>,
+    KeyCont: Container<Element=Scalar>,
+    NoiseSeeder: Seeder + ?Sized,
+{
+    (1..=glev_level.0).map(|level| {
+        let log_scale = Scalar::BITS - level * glev_base_log.0;
+
+        generate_vec_seeded_keyed_lut_accumulator(
+            keyed_lut_list,
+            log_scale,
+            glwe_secret_key,
+            glwe_modular_std_dev,
+            ciphertext_modulus,
+            noise_seeder,
+        )
+    }).collect()
 }
 
 
+/// Encrypts, for each byte, the entry table of `keyed_lut_lists` laid out as
+/// in [`lut_entry_table_plaintext`] as one seeded list of 256 GLWEs.
+pub fn generate_vec_seeded_keyed_lut_entry_table<Scalar, KeyCont, NoiseSeeder>(
+    keyed_lut_lists: &[[[u8; 1 << BYTESIZE]; BLOCKSIZE_IN_BYTE]],
+    glwe_secret_key: &GlweSecretKey<KeyCont>,
+    glwe_modular_std_dev: impl DispersionParameter,
+    ciphertext_modulus: CiphertextModulus<Scalar>,
+    noise_seeder: &mut NoiseSeeder,
+) -> Vec::<SeededGlweCiphertextListOwned<Scalar>>
+where
+    Scalar: UnsignedTorus + CastFrom<usize>,
+    KeyCont: Container<Element=Scalar>,
+    NoiseSeeder: Seeder + ?Sized,
+{
+    let glwe_size = glwe_secret_key.glwe_dimension().to_glwe_size();
+    let polynomial_size = glwe_secret_key.polynomial_size();
+
+    (0..BLOCKSIZE_IN_BYTE).map(|byte_idx| {
+        let keyed_luts = keyed_lut_lists.iter()
+            .map(|keyed_lut_list| &keyed_lut_list[byte_idx])
+            .collect::<Vec<_>>();
+        let table = PlaintextList::from_container(lut_entry_table_plaintext(&keyed_luts, polynomial_size));
+
+        let mut entry_table = SeededGlweCiphertextList::new(
+            Scalar::ZERO,
+            glwe_size,
+            polynomial_size,
+            GlweCiphertextCount(1 << BYTESIZE),
+            noise_seeder.seed().into(),
+            ciphertext_modulus,
+        );
+        encrypt_seeded_glwe_ciphertext_list(
+            glwe_secret_key,
+            &mut entry_table,
+            &table,
+            glwe_modular_std_dev,
+            noise_seeder,
+        );
+        entry_table
+    }).collect()
+}
+
 pub fn get_he_state_error<Scalar, StateCont, SkCont>(
     he_state: &LweCiphertextList<StateCont>,
     plain_state: StateByteMat,
@@ -1005,3 +1577,139 @@ where
 
     (vec_out, vec_err, max_err)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{aes_instances::AES_TIGHT, gen_all_auto_keys, keygen_pbs_without_ksk};
+    use tfhe::core_crypto::seeders::new_seeder;
+
+    /// Runs the S-box of one block on a single worker, the monothread
+    /// schedule, and on one worker per byte, which run in parallel under the
+    /// `multithread` feature. The evaluation draws no randomness, so both
+    /// must give the same ciphertexts bit for bit.
+    #[test]
+    fn worker_schedules_agree() {
+        let param = &*AES_TIGHT;
+        let ciphertext_modulus = param.ciphertext_modulus();
+        let mut boxed_seeder = new_seeder();
+        let seeder = boxed_seeder.as_mut();
+        let mut secret_generator = SecretRandomGenerator::<ActivatedRandomGenerator>::new(seeder.seed());
+        let mut encryption_generator =
+            EncryptionRandomGenerator::<ActivatedRandomGenerator>::new(seeder.seed(), seeder);
+
+        let (lwe_sk, glwe_sk, lwe_sk_after_ks, fourier_bsk) = keygen_pbs_without_ksk::<u64, _>(
+            param.lwe_dimension(),
+            param.glwe_dimension(),
+            param.polynomial_size(),
+            param.glwe_modular_std_dev(),
+            param.pbs_base_log(),
+            param.pbs_level(),
+            &mut secret_generator,
+            &mut encryption_generator,
+        );
+        let auto_keys = gen_all_auto_keys(
+            param.auto_base_log(),
+            param.auto_level(),
+            param.fft_type_auto(),
+            &glwe_sk,
+            param.glwe_modular_std_dev(),
+            &mut encryption_generator,
+        );
+        let ss_key = generate_scheme_switching_key_fourier(
+            &glwe_sk,
+            param.ss_base_log(),
+            param.ss_level(),
+            param.glwe_modular_std_dev(),
+            ciphertext_modulus,
+            &mut encryption_generator,
+        );
+
+        let block: StateByteArray = std::array::from_fn(|i| (17 * i + 1) as u8);
+        let plaintexts = PlaintextList::from_container(
+            (0..BLOCKSIZE_IN_BIT)
+                .map(|i| (((block[i / BYTESIZE] >> (i % BYTESIZE)) & 1) as u64) << 63)
+                .collect::<Vec<_>>(),
+        );
+        let mut he_state = LweCiphertextList::new(
+            0u64,
+            lwe_sk_after_ks.lwe_dimension().to_lwe_size(),
+            LweCiphertextCount(BLOCKSIZE_IN_BIT),
+            ciphertext_modulus,
+        );
+        encrypt_lwe_ciphertext_list(&lwe_sk_after_ks, &mut he_state, &plaintexts, param.lwe_modular_std_dev(), &mut encryption_generator);
+
+        let sub_bytes = |worker_count: usize| {
+            let mut workers = (0..worker_count)
+                .map(|_| ByteCbsWorker::new(
+                    fourier_bsk.as_view(),
+                    &auto_keys,
+                    param.cbs_base_log(),
+                    param.cbs_level(),
+                    param.log_lut_count(),
+                    ciphertext_modulus,
+                    1,
+                ))
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap();
+            let mut output = LweCiphertextList::new(
+                0u64,
+                lwe_sk.lwe_dimension().to_lwe_size(),
+                LweCiphertextCount(BLOCKSIZE_IN_BIT),
+                ciphertext_modulus,
+            );
+            he_sub_bytes_by_patched_wwlp_cbs(&he_state, &mut output, fourier_bsk.as_view(), &auto_keys, ss_key.as_view(), &mut workers).unwrap();
+            output
+        };
+        let serial = sub_bytes(1);
+        let parallel = sub_bytes(BLOCKSIZE_IN_BYTE);
+        assert!(serial.as_ref() == parallel.as_ref(), "the two schedules gave different ciphertexts");
+
+        for (i, lwe) in serial.iter().enumerate() {
+            let decrypted = decrypt_lwe_ciphertext(&lwe_sk, &lwe).0;
+            let expected = (AES128_SBOX[block[i / BYTESIZE] as usize] >> (i % BYTESIZE)) & 1;
+            assert_eq!(decrypted.wrapping_add(1 << 62) >> 63, expected as u64, "bit {}", i);
+        }
+    }
+
+    #[test]
+    fn lut_layout_packs_bits_by_polynomial_size() {
+        let luts = [&AES128_SBOX, &AES128_SBOX_MULT_BY_2, &AES128_SBOX_MULT_BY_3];
+        let log_scale = 63;
+
+        for polynomial_size in [512, 1024, 2048] {
+            let layout = LutLayout::new(PolynomialSize(polynomial_size)).unwrap();
+            let bits_per_acc = polynomial_size / 256;
+            assert_eq!(layout.bits_per_accumulator(), bits_per_acc);
+            assert_eq!(layout.accumulator_count(luts.len()), luts.len() * BYTESIZE / bits_per_acc);
+
+            assert_eq!(layout.position(0, 0), (0, MonomialDegree(0)));
+            assert_eq!(layout.position(bits_per_acc - 1, 255), (0, MonomialDegree(polynomial_size - 1)));
+            assert_eq!(layout.position(bits_per_acc, 7), (1, MonomialDegree(7)));
+
+            // Bit b of LUT j on input x is read at position(j * BYTESIZE + b, x)
+            let accumulators: Vec<Vec<u64>> = (0..layout.accumulator_count(luts.len()))
+                .map(|acc_idx| layout.accumulator_plaintext(&luts, acc_idx, log_scale))
+                .collect();
+            for (j, lut) in luts.iter().enumerate() {
+                for b in 0..BYTESIZE {
+                    for input in 0..=u8::MAX {
+                        let (acc_idx, MonomialDegree(degree)) = layout.position(j * BYTESIZE + b, input);
+                        let expected = (((lut[input as usize] >> b) & 1) as u64) << log_scale;
+                        assert_eq!(accumulators[acc_idx][degree], expected, "N = {}, LUT {}, bit {}, input {}", polynomial_size, j, b, input);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn lut_layout_rejects_bad_polynomial_size() {
+        for polynomial_size in [128, 300] {
+            assert!(matches!(
+                LutLayout::new(PolynomialSize(polynomial_size)),
+                Err(Error::IncompatibleParameters(_)),
+            ));
+        }
+    }
+}
@@ -125,4 +125,37 @@ lazy_static! {
         DecompositionBaseLog(4), // half_cbs_base_log
         DecompositionLevelCount(6), // half_cbs_level
     );
-}
\ No newline at end of file
+
+    /// [`AES_HALF_CBS`] seen as an [`AesParam`], registered as `half_cbs`.
+    pub static ref AES_HALF_CBS_BASE: AesParam<u64> = AES_HALF_CBS.base_param();
+}
+/// Names of the [`AesParam`] sets that can be selected at runtime, the first
+/// one being the default. `half_cbs` also selects the half-CBS pipeline.
+pub const AES_PARAM_NAMES: [&str; 5] = ["tight", "set_1", "set_2", "set_3", "half_cbs"];
+
+/// The `aes_` prefix and the case are ignored, so `AES_SET_2` selects `set_2`.
+fn canonical_param_name(name: &str) -> String {
+    let name = name.to_ascii_lowercase();
+    name.strip_prefix("aes_").unwrap_or(&name).to_owned()
+}
+
+/// Looks up a parameter set by name, see [`AES_PARAM_NAMES`].
+pub fn aes_param_by_name(name: &str) -> Option<&'static AesParam<u64>> {
+    match canonical_param_name(name).as_str() {
+        "tight" => Some(&AES_TIGHT),
+        "set_1" => Some(&AES_SET_1),
+        "set_2" => Some(&AES_SET_2),
+        "set_3" => Some(&AES_SET_3),
+        "half_cbs" => Some(&AES_HALF_CBS_BASE),
+        _ => None,
+    }
+}
+
+/// Looks up the half-CBS fields of a parameter set, `None` for the sets that
+/// only run the patched WWLp CBS pipeline.
+pub fn aes_half_cbs_param_by_name(name: &str) -> Option<&'static AesHalfCBSParam<u64>> {
+    match canonical_param_name(name).as_str() {
+        "half_cbs" => Some(&AES_HALF_CBS),
+        _ => None,
+    }
+}
@@ -0,0 +1,286 @@
+use std::fmt;
+use tfhe::core_crypto::prelude::*;
+use crate::{aes_params::AesParam, aes_ref::{BLOCKSIZE_IN_BIT, BYTESIZE}};
+
+// Moments of a binary secret key coefficient.
+const KEY_MEAN: f64 = 0.5;
+const KEY_SQUARE_MEAN: f64 = 0.5;
+// Upper bound on the mean of the top digit of tfhe's balanced decomposition,
+// the only digit whose mean is not zero.
+const TOP_DIGIT_MEAN: f64 = 0.5;
+
+/// Predicted noise of the AES evaluation under one [`AesParam`] set, stage by
+/// stage. All variances are torus variances, i.e. relative to `q^2`, and the
+/// stages follow one ECB middle round: the state bits leave the inverse
+/// MixColumns sum, are keyswitched to the small LWE key, circuit bootstrapped
+/// (modulus switch, blind rotation, trace, scheme switch) and fed to the CMUX
+/// tree of a keyed LUT.
+///
+/// The model assumes binary secret keys, independent Gaussian noise in every
+/// key and a uniform rounding error in every gadget decomposition. FFT errors
+/// are not counted.
+#[derive(Clone, Copy, Debug)]
+pub struct AesNoiseEstimate {
+    /// Number of AES rounds the failure probabilities are computed for.
+    pub num_rounds: usize,
+    /// Noise added by the GLWE keyswitch from the large to the small LWE key.
+    pub keyswitch: f64,
+    /// Rounding noise of the modulus switch to `2N / lut_count`.
+    pub modulus_switch: f64,
+    /// Noise of the blind rotation output.
+    pub blind_rotate: f64,
+    /// Noise added by the trace, on every coefficient of the GLev.
+    pub trace: f64,
+    /// Noise added by the external product with the scheme switching key.
+    pub scheme_switch: f64,
+    /// Noise added by one CMUX of the vertical packing tree.
+    pub cmux: f64,
+    /// Noise of an S-box output bit: the keyed LUT accumulator after the
+    /// whole CMUX tree.
+    pub cmux_tree: f64,
+    /// Noise of a state bit after the inverse MixColumns sum.
+    pub mix_columns: f64,
+    /// Noise at the blind rotation input, where a bit is decided.
+    pub cbs_input: f64,
+}
+
+/// Estimates the noise of the AES evaluation with `param` for a cipher of
+/// `num_rounds` rounds (10, 12 or 14).
+pub fn estimate_aes_noise<Scalar: UnsignedInteger>(
+    param: &AesParam<Scalar>,
+    num_rounds: usize,
+) -> AesNoiseEstimate {
+    let n = param.lwe_dimension().0 as f64;
+    let k = param.glwe_dimension().0 as f64;
+    let big_n = param.polynomial_size().0 as f64;
+    let lwe_var = param.lwe_modular_std_dev().get_variance();
+    let glwe_var = param.glwe_modular_std_dev().get_variance();
+
+    let keyswitch = keyswitch_variance(
+        k * big_n,
+        param.glwe_ds_base_log(),
+        param.glwe_ds_level(),
+        lwe_var,
+    );
+
+    let log_modulus = (2 * param.polynomial_size().0).ilog2() as i32 - param.log_lut_count().0 as i32;
+    let modulus_switch = (1.0 + n * KEY_SQUARE_MEAN) * 2f64.powi(-2 * log_modulus) / 12.0;
+
+    let blind_rotate = n * external_product_variance(
+        k,
+        big_n,
+        param.pbs_base_log(),
+        param.pbs_level(),
+        glwe_var,
+        KEY_SQUARE_MEAN,
+    );
+
+    // Each of the log2(N) steps adds an automorphism keyswitch and at most
+    // doubles the amplitude of what is already there.
+    let auto_keyswitch = keyswitch_variance(
+        k * big_n,
+        param.auto_base_log(),
+        param.auto_level(),
+        glwe_var,
+    );
+    let trace = (big_n * big_n - 1.0) / 3.0 * auto_keyswitch;
+
+    // The message of the scheme switching key is a key polynomial, so the
+    // rounding error is multiplied by N key coefficients.
+    let scheme_switch = external_product_variance(
+        k,
+        big_n,
+        param.ss_base_log(),
+        param.ss_level(),
+        glwe_var,
+        big_n * KEY_SQUARE_MEAN,
+    );
+
+    // The blind rotation noise only sits on the constant coefficient of each
+    // GLev row, and scheme switching multiplies it by a key polynomial. The
+    // CMUX therefore sees it through `sum_c d_c * s_c`, whose mean is not zero
+    // because of the top decomposition digit.
+    let cbs_level = param.cbs_level().0 as f64;
+    let digit_square_mean = decomposition_digit_square_mean(param.cbs_base_log());
+    let body_rows = cbs_level * digit_square_mean * (blind_rotate + big_n * trace);
+    let mask_rows_constant = blind_rotate
+        * (cbs_level * k * big_n * digit_square_mean * KEY_SQUARE_MEAN
+            + (k * TOP_DIGIT_MEAN * KEY_MEAN).powi(2) * big_n * big_n / 3.0);
+    let mask_rows_spread = cbs_level * k * big_n * digit_square_mean
+        * (big_n * KEY_SQUARE_MEAN * trace + scheme_switch);
+    let rounding = (1.0 + k * big_n * KEY_SQUARE_MEAN)
+        * decomposition_rounding_variance(param.cbs_base_log(), param.cbs_level())
+        * 0.5;
+    let cmux = body_rows + mask_rows_constant + mask_rows_spread + rounding;
+
+    let cmux_tree = glwe_var + BYTESIZE as f64 * cmux;
+    let mix_columns = 4.0 * cmux_tree;
+    let cbs_input = mix_columns + keyswitch + modulus_switch;
+
+    AesNoiseEstimate {
+        num_rounds,
+        keyswitch,
+        modulus_switch,
+        blind_rotate,
+        trace,
+        scheme_switch,
+        cmux,
+        cmux_tree,
+        mix_columns,
+        cbs_input,
+    }
+}
+
+impl AesNoiseEstimate {
+    /// log2 of the probability that a bit is decided wrongly by its circuit
+    /// bootstrap, i.e. that its noise exceeds 1/4 at the blind rotation input.
+    pub fn log2_cbs_failure(&self) -> f64 {
+        log2_gaussian_tail(self.cbs_input, 0.25)
+    }
+
+    /// log2 of the probability that a bit of the transciphered result is
+    /// decrypted wrongly by the client.
+    pub fn log2_output_failure(&self) -> f64 {
+        log2_gaussian_tail(self.cmux_tree, 0.25)
+    }
+
+    /// log2 of the failure probability of one output bit over a full
+    /// decryption. Every state bit of the Nr - 1 bootstrapped rounds reaches
+    /// every output bit, so this is the union bound over the 128 (Nr - 1)
+    /// bootstraps of the block and the decoding of the bit itself.
+    pub fn log2_decryption_failure(&self) -> f64 {
+        let num_cbs = (BLOCKSIZE_IN_BIT * (self.num_rounds - 1)) as f64;
+        log2_add(num_cbs.log2() + self.log2_cbs_failure(), self.log2_output_failure())
+    }
+}
+
+impl fmt::Display for AesNoiseEstimate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let stages = [
+            ("keyswitch", self.keyswitch),
+            ("modulus switch", self.modulus_switch),
+            ("blind rotate", self.blind_rotate),
+            ("trace", self.trace),
+            ("scheme switch", self.scheme_switch),
+            ("cmux", self.cmux),
+            ("cmux tree", self.cmux_tree),
+            ("mix columns", self.mix_columns),
+            ("cbs input", self.cbs_input),
+        ];
+        for (stage, variance) in stages {
+            writeln!(f, "{:<15} variance 2^{:.2}", stage, variance.log2())?;
+        }
+        writeln!(f, "cbs failure     2^{:.2}", self.log2_cbs_failure())?;
+        writeln!(f, "output failure  2^{:.2}", self.log2_output_failure())?;
+        write!(f, "{}-round failure 2^{:.2} per bit", self.num_rounds, self.log2_decryption_failure())
+    }
+}
+
+/// Mean square of a digit of the balanced decomposition in base `2^base_log`.
+fn decomposition_digit_square_mean(base_log: DecompositionBaseLog) -> f64 {
+    let base = 2f64.powi(base_log.0 as i32);
+    (base * base + 2.0) / 12.0
+}
+
+/// Variance of the rounding to the closest multiple of `q / B^level`.
+fn decomposition_rounding_variance(base_log: DecompositionBaseLog, level: DecompositionLevelCount) -> f64 {
+    2f64.powi(-2 * (base_log.0 * level.0) as i32) / 12.0
+}
+
+/// Noise added by keyswitching `input_dimension` mask coefficients with a key
+/// of variance `key_variance`.
+fn keyswitch_variance(
+    input_dimension: f64,
+    base_log: DecompositionBaseLog,
+    level: DecompositionLevelCount,
+    key_variance: f64,
+) -> f64 {
+    input_dimension * level.0 as f64 * decomposition_digit_square_mean(base_log) * key_variance
+        + input_dimension * KEY_SQUARE_MEAN * decomposition_rounding_variance(base_log, level)
+}
+
+/// Noise added by an external product with a GGSW of variance
+/// `ggsw_variance` whose message has mean square `message_square_mean`.
+fn external_product_variance(
+    glwe_dimension: f64,
+    polynomial_size: f64,
+    base_log: DecompositionBaseLog,
+    level: DecompositionLevelCount,
+    ggsw_variance: f64,
+    message_square_mean: f64,
+) -> f64 {
+    level.0 as f64 * (glwe_dimension + 1.0) * polynomial_size
+        * decomposition_digit_square_mean(base_log) * ggsw_variance
+        + (1.0 + glwe_dimension * polynomial_size * KEY_SQUARE_MEAN)
+            * decomposition_rounding_variance(base_log, level)
+            * message_square_mean
+}
+
+/// log2 of P(|X| >= bound) for X centered Gaussian of variance `variance`,
+/// accurate far into the tail.
+fn log2_gaussian_tail(variance: f64, bound: f64) -> f64 {
+    let z = bound / (2.0 * variance).sqrt();
+    // erfc(z) from Numerical Recipes, with a relative error below 1.2e-7,
+    // taken in log form so that it does not underflow.
+    let t = 1.0 / (1.0 + 0.5 * z);
+    let ln_erfc = t.ln() - z * z - 1.26551223
+        + t * (1.00002368
+            + t * (0.37409196
+                + t * (0.09678418
+                    + t * (-0.18628806
+                        + t * (0.27886807
+                            + t * (-1.13520398
+                                + t * (1.48851587 + t * (-0.82215223 + t * 0.17087277))))))));
+    ln_erfc / std::f64::consts::LN_2
+}
+
+/// log2(2^a + 2^b).
+fn log2_add(a: f64, b: f64) -> f64 {
+    let (hi, lo) = if a > b { (a, b) } else { (b, a) };
+    hi + (1.0 + 2f64.powf(lo - hi)).log2()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aes_instances::{aes_param_by_name, AES_PARAM_NAMES, AES_TIGHT};
+    use crate::aes_ref::AesKeySize;
+
+    /// Per-bit failure probability of an AES-128 decryption, as a power of
+    /// two, that every registered set must stay below.
+    const LOG2_FAILURE_BOUND: f64 = -15.0;
+
+    #[test]
+    fn tight_stays_below_failure_bound() {
+        let estimate = estimate_aes_noise(&*AES_TIGHT, AesKeySize::Aes128.num_rounds());
+        println!("{}", estimate);
+        assert!(estimate.log2_decryption_failure() < LOG2_FAILURE_BOUND);
+    }
+
+    #[test]
+    fn registered_sets_estimates() {
+        for name in AES_PARAM_NAMES {
+            let param = aes_param_by_name(name).unwrap();
+            let estimate = estimate_aes_noise(param, AesKeySize::Aes128.num_rounds());
+            println!("[{}]\n{}", name, estimate);
+
+            for variance in [
+                estimate.keyswitch,
+                estimate.modulus_switch,
+                estimate.blind_rotate,
+                estimate.trace,
+                estimate.scheme_switch,
+                estimate.cmux,
+            ] {
+                assert!(variance.is_finite() && variance > 0.0, "{}", name);
+            }
+            assert!(estimate.cmux_tree < estimate.mix_columns, "{}", name);
+            assert!(estimate.mix_columns < estimate.cbs_input, "{}", name);
+            assert!(estimate.log2_decryption_failure() < LOG2_FAILURE_BOUND, "{}", name);
+
+            // More rounds only add bootstraps
+            let aes_256 = estimate_aes_noise(param, AesKeySize::Aes256.num_rounds());
+            assert!(aes_256.log2_decryption_failure() > estimate.log2_decryption_failure(), "{}", name);
+        }
+    }
+}
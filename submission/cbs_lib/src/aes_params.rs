@@ -1,5 +1,16 @@
-use tfhe::core_crypto::prelude::*;
-use crate::FftType;
+use std::collections::HashMap;
+use aligned_vec::ABox;
+use tfhe::core_crypto::{
+    prelude::*,
+    fft_impl::fft64::{
+        c64,
+        crypto::{bootstrap::FourierLweBootstrapKeyView, ggsw::FourierGgswCiphertextListView},
+    },
+};
+use crate::{
+    check_dimension, check_lwe_keyswitch_key, check_scheme_switching_key, check_trace_auto_keys,
+    AutomorphKey, Error, FftType, FourierGlweKeyswitchKey,
+};
 
 #[derive(Clone, Copy)]
 pub struct AesParam<Scalar: UnsignedInteger> {
@@ -154,6 +165,73 @@ impl<Scalar: UnsignedInteger> AesParam<Scalar> {
     pub fn ciphertext_modulus(&self) -> CiphertextModulus::<Scalar> {
         self.ciphertext_modulus
     }
+
+    /// Checks that the server evaluation keys were generated for this
+    /// parameter set, so that a bad key is reported before any homomorphic
+    /// operation instead of aborting in the middle of the pipeline.
+    pub fn check_evaluation_keys<KSKeyCont>(
+        &self,
+        fourier_bsk: FourierLweBootstrapKeyView,
+        fourier_glwe_ksk: &FourierGlweKeyswitchKey<KSKeyCont>,
+        auto_keys: &HashMap<usize, AutomorphKey<ABox<[c64]>>>,
+        ss_key: FourierGgswCiphertextListView,
+    ) -> Result<(), Error>
+    where
+        KSKeyCont: Container<Element = c64>,
+    {
+        let glwe_size = self.glwe_dimension.to_glwe_size();
+
+        check_dimension("bootstrapping key input LWE dimension", self.lwe_dimension.0, fourier_bsk.input_lwe_dimension().0)?;
+        check_dimension("bootstrapping key GLWE size", glwe_size.0, fourier_bsk.glwe_size().0)?;
+        check_dimension("bootstrapping key polynomial size", self.polynomial_size.0, fourier_bsk.polynomial_size().0)?;
+        check_decomposition(
+            "bootstrapping key",
+            (self.pbs_base_log, self.pbs_level),
+            (fourier_bsk.decomposition_base_log(), fourier_bsk.decomposition_level_count()),
+        )?;
+
+        check_dimension("keyswitching key polynomial size", self.common_polynomial_size.0, fourier_glwe_ksk.polynomial_size().0)?;
+        check_lwe_keyswitch_key(
+            fourier_glwe_ksk,
+            fourier_bsk.output_lwe_dimension().to_lwe_size(),
+            self.lwe_dimension.to_lwe_size(),
+        )?;
+        check_decomposition(
+            "keyswitching key",
+            (self.glwe_ds_base_log, self.glwe_ds_level),
+            (fourier_glwe_ksk.decomp_base_log(), fourier_glwe_ksk.decomp_level_count()),
+        )?;
+
+        check_trace_auto_keys(auto_keys, glwe_size, self.polynomial_size, 1)?;
+        for auto_key in auto_keys.values() {
+            check_decomposition(
+                "automorphism key",
+                (self.auto_base_log, self.auto_level),
+                (auto_key.decomposition_base_log(), auto_key.decomposition_level_count()),
+            )?;
+        }
+
+        check_scheme_switching_key(ss_key, glwe_size, self.polynomial_size)?;
+        check_decomposition(
+            "scheme switching key",
+            (self.ss_base_log, self.ss_level),
+            (ss_key.decomposition_base_log(), ss_key.decomposition_level_count()),
+        )
+    }
+}
+
+fn check_decomposition(
+    key_name: &str,
+    expected: (DecompositionBaseLog, DecompositionLevelCount),
+    found: (DecompositionBaseLog, DecompositionLevelCount),
+) -> Result<(), Error> {
+    if expected != found {
+        return Err(Error::IncompatibleParameters(format!(
+            "{} has decomposition base log {} and level {}, expected base log {} and level {}",
+            key_name, found.0.0, found.1.0, expected.0.0, expected.1.0,
+        )));
+    }
+    Ok(())
 }
 
 #[derive(Clone, Copy)]
@@ -358,4 +436,58 @@ impl<Scalar: UnsignedInteger> AesHalfCBSParam<Scalar> {
     pub fn half_cbs_level(&self) -> DecompositionLevelCount {
         self.half_cbs_level
     }
-}
\ No newline at end of file
+
+    /// The fields shared with [`AesParam`], which the rounds outside the
+    /// half-CBS path and the regular evaluation keys run on.
+    pub fn base_param(&self) -> AesParam<Scalar> {
+        AesParam::new(
+            self.lwe_dimension,
+            self.lwe_modular_std_dev,
+            self.polynomial_size,
+            self.glwe_dimension,
+            self.glwe_modular_std_dev,
+            self.pbs_base_log,
+            self.pbs_level,
+            self.glwe_ds_base_log,
+            self.glwe_ds_level,
+            self.common_polynomial_size,
+            self.fft_type_ds,
+            self.auto_base_log,
+            self.auto_level,
+            self.fft_type_auto,
+            self.ss_base_log,
+            self.ss_level,
+            self.cbs_base_log,
+            self.cbs_level,
+            self.log_lut_count,
+            self.ciphertext_modulus,
+        )
+    }
+
+    /// Checks the automorphism and scheme switching keys that convert Lev
+    /// ciphertexts to GGSW, the counterpart of
+    /// [`AesParam::check_evaluation_keys`] for the `half_cbs_*` fields.
+    pub fn check_half_cbs_keys(
+        &self,
+        auto_keys: &HashMap<usize, AutomorphKey<ABox<[c64]>>>,
+        ss_key: FourierGgswCiphertextListView,
+    ) -> Result<(), Error> {
+        let glwe_size = self.glwe_dimension.to_glwe_size();
+
+        check_trace_auto_keys(auto_keys, glwe_size, self.polynomial_size, 1)?;
+        for auto_key in auto_keys.values() {
+            check_decomposition(
+                "half-CBS automorphism key",
+                (self.half_cbs_auto_base_log, self.half_cbs_auto_level),
+                (auto_key.decomposition_base_log(), auto_key.decomposition_level_count()),
+            )?;
+        }
+
+        check_scheme_switching_key(ss_key, glwe_size, self.polynomial_size)?;
+        check_decomposition(
+            "half-CBS scheme switching key",
+            (self.half_cbs_ss_base_log, self.half_cbs_ss_level),
+            (ss_key.decomposition_base_log(), ss_key.decomposition_level_count()),
+        )
+    }
+}
@@ -159,83 +159,111 @@ pub const BLOCKSIZE_IN_BYTE: usize = 16;
 pub const BLOCKSIZE_IN_BIT: usize = 128;
 pub const NUM_COLUMNS: usize = 4;
 pub const NUM_ROWS: usize = 4;
-pub const NUM_ROUNDS: usize = 10;
 
 pub type StateByteArray = [u8; BLOCKSIZE_IN_BYTE];
 pub type StateByteMat = [[u8; NUM_COLUMNS]; NUM_ROWS];
 pub type StateBitArray = [u8; BLOCKSIZE_IN_BIT];
 
-pub struct Aes128Ref {
-    key: StateByteArray,
-    rk_mat: [StateByteMat; NUM_ROUNDS + 1],
+/// AES key length, which fixes the number of rounds. The block size is 128
+/// bits for all of them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AesKeySize {
+    Aes128,
+    Aes192,
+    Aes256,
 }
 
-impl Aes128Ref {
-    pub fn new(master_key: &StateByteArray) -> Aes128Ref {
-        let mut key = [0u8; BLOCKSIZE_IN_BYTE];
-        for i in 0..BLOCKSIZE_IN_BYTE {
-            key[i] = master_key[i];
+impl AesKeySize {
+    pub fn from_key_len_in_byte(key_len_in_byte: usize) -> Option<AesKeySize> {
+        match key_len_in_byte {
+            16 => Some(AesKeySize::Aes128),
+            24 => Some(AesKeySize::Aes192),
+            32 => Some(AesKeySize::Aes256),
+            _ => None,
         }
-        let rk_mat = [[[0u8; NUM_COLUMNS]; NUM_ROWS]; NUM_ROUNDS + 1];
-
-        let mut aes = Aes128Ref {
-            key: key,
-            rk_mat: rk_mat,
-        };
-        aes.expand_key();
+    }
 
-        aes
+    pub fn key_len_in_byte(&self) -> usize {
+        NUM_ROWS * self.num_key_words()
     }
 
-    fn expand_key(&mut self) {
-        for col in 0..NUM_COLUMNS {
-            for row in 0..NUM_ROWS {
-                self.rk_mat[0][col][row] = self.key[4 * col + row];
-            }
+    /// Number of 32-bit words in the master key, `Nk` in FIPS-197.
+    pub fn num_key_words(&self) -> usize {
+        match self {
+            AesKeySize::Aes128 => 4,
+            AesKeySize::Aes192 => 6,
+            AesKeySize::Aes256 => 8,
         }
+    }
 
-        for r in 1..=NUM_ROUNDS {
-            let cur_key = self.rk_mat[r - 1];
-            self.rk_mat[r] = cur_key;
+    pub fn num_rounds(&self) -> usize {
+        self.num_key_words() + 6
+    }
+}
 
-            let sub_rot_key = [
-                AES128_SBOX[cur_key[3][1] as usize],
-                AES128_SBOX[cur_key[3][2] as usize],
-                AES128_SBOX[cur_key[3][3] as usize],
-                AES128_SBOX[cur_key[3][0] as usize],
-            ];
+/// FIPS-197 key expansion, returning the `num_rounds + 1` round keys as
+/// state matrices.
+pub fn expand_aes_key(master_key: &[u8], key_size: AesKeySize) -> Vec<StateByteMat> {
+    assert_eq!(master_key.len(), key_size.key_len_in_byte());
 
-            for row in 0..NUM_ROWS {
-                self.rk_mat[r][0][row] ^= sub_rot_key[row];
-            }
-            self.rk_mat[r][0][0] ^= RCON[r];
+    let nk = key_size.num_key_words();
+    let num_words = NUM_COLUMNS * (key_size.num_rounds() + 1);
+    let mut words = Vec::<[u8; NUM_ROWS]>::with_capacity(num_words);
+    for word in master_key.chunks_exact(NUM_ROWS) {
+        words.push([word[0], word[1], word[2], word[3]]);
+    }
 
-            for col in 1..NUM_COLUMNS {
-                for row in 0..NUM_ROWS {
-                    self.rk_mat[r][col][row] ^= self.rk_mat[r][col - 1][row];
-                }
-            }
+    for i in nk..num_words {
+        let mut temp = words[i - 1];
+        if i % nk == 0 {
+            temp = [
+                AES128_SBOX[temp[1] as usize],
+                AES128_SBOX[temp[2] as usize],
+                AES128_SBOX[temp[3] as usize],
+                AES128_SBOX[temp[0] as usize],
+            ];
+            temp[0] ^= RCON[i / nk];
+        } else if nk > 6 && i % nk == 4 {
+            temp = temp.map(|b| AES128_SBOX[b as usize]);
         }
+        let prev = words[i - nk];
+        words.push(std::array::from_fn(|row| prev[row] ^ temp[row]));
     }
 
-    pub fn get_round_keys(&self) -> [StateByteArray; NUM_ROUNDS + 1] {
-        let mut round_keys = [[0u8; BLOCKSIZE_IN_BYTE]; NUM_ROUNDS + 1];
-        for r in 0..=NUM_ROUNDS {
-            for col in 0..NUM_COLUMNS {
-                for row in 0..NUM_ROWS {
-                    round_keys[r][4 * col + row] = self.rk_mat[r][col][row]
-                }
-            }
+    words.chunks_exact(NUM_COLUMNS)
+        .map(|rk| [rk[0], rk[1], rk[2], rk[3]])
+        .collect()
+}
+
+pub struct AesRef {
+    key_size: AesKeySize,
+    rk_mat: Vec<StateByteMat>,
+}
+
+impl AesRef {
+    pub fn new(master_key: &[u8]) -> AesRef {
+        let key_size = AesKeySize::from_key_len_in_byte(master_key.len())
+            .expect("AES key must be 16, 24 or 32 bytes long");
+
+        AesRef {
+            key_size,
+            rk_mat: expand_aes_key(master_key, key_size),
         }
+    }
 
-        round_keys
+    pub fn num_rounds(&self) -> usize {
+        self.key_size.num_rounds()
+    }
+
+    pub fn get_round_keys(&self) -> Vec<StateByteArray> {
+        self.rk_mat.iter().map(|rk| byte_mat_to_array(*rk)).collect()
     }
 
     pub fn encrypt_block(&self, message: StateByteArray) -> StateByteArray {
         let mut state = byte_array_to_mat(message);
         self.add_round_key(&mut state, 0);
 
-        for r in 1..NUM_ROUNDS {
+        for r in 1..self.num_rounds() {
             self.sub_bytes(&mut state);
             self.shift_rows(&mut state);
             self.mix_columns(&mut state);
@@ -244,15 +272,15 @@ impl Aes128Ref {
 
         self.sub_bytes(&mut state);
         self.shift_rows(&mut state);
-        self.add_round_key(&mut state, NUM_ROUNDS);
+        self.add_round_key(&mut state, self.num_rounds());
 
         byte_mat_to_array(state)
     }
 
     pub fn decrypt_block(&self, ciphertext: StateByteArray) -> StateByteArray {
         let mut state = byte_array_to_mat(ciphertext);
-        self.add_round_key(&mut state, NUM_ROUNDS);
-        for r in (1..NUM_ROUNDS).rev() {
+        self.add_round_key(&mut state, self.num_rounds());
+        for r in (1..self.num_rounds()).rev() {
             self.inv_shift_rows(&mut state);
             self.inv_sub_bytes(&mut state);
             self.add_round_key(&mut state, r);
@@ -264,6 +292,26 @@ impl Aes128Ref {
         byte_mat_to_array(state)
     }
 
+    /// States of the decryption of `ciphertext`, indexed by round: entry `r`
+    /// is the state right after round key `r` is added, followed by
+    /// InvMixColumns for `0 < r < Nr`. Entry 0 is the plaintext.
+    pub fn decrypt_round_states(&self, ciphertext: StateByteArray) -> Vec<StateByteMat> {
+        let mut states = vec![[[0u8; NUM_COLUMNS]; NUM_ROWS]; self.num_rounds() + 1];
+        let mut state = byte_array_to_mat(ciphertext);
+        self.add_round_key(&mut state, self.num_rounds());
+        states[self.num_rounds()] = state;
+        for r in (0..self.num_rounds()).rev() {
+            self.inv_shift_rows(&mut state);
+            self.inv_sub_bytes(&mut state);
+            self.add_round_key(&mut state, r);
+            if r > 0 {
+                self.inv_mix_columns(&mut state);
+            }
+            states[r] = state;
+        }
+        states
+    }
+
     pub fn add_round_key(&self, state: &mut StateByteMat, round: usize) {
         for col in 0..NUM_COLUMNS {
             for row in 0..NUM_ROWS {
@@ -505,3 +553,42 @@ pub fn mult_a_b(a: u8, b: u8) -> u8 {
     }
     return result;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex_bytes(hex: &str) -> Vec<u8> {
+        (0..hex.len()).step_by(2).map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap()).collect()
+    }
+
+    /// Cipher keys and last round keys of the key expansion examples of
+    /// FIPS-197, Appendix A.
+    #[test]
+    fn key_expansion_matches_fips_197() {
+        let examples = [
+            (
+                "2b7e151628aed2a6abf7158809cf4f3c",
+                "d014f9a8c9ee2589e13f0cc8b6630ca6",
+            ),
+            (
+                "8e73b0f7da0e6452c810f32b809079e562f8ead2522c6b7b",
+                "e98ba06f448c773c8ecc720401002202",
+            ),
+            (
+                "603deb1015ca71be2b73aef0857d77811f352c073b6108d72d9810a30914dff4",
+                "fe4890d1e6188d0b046df344706c631e",
+            ),
+        ];
+        for (master_key, last_round_key) in examples {
+            let master_key = hex_bytes(master_key);
+            let key_size = AesKeySize::from_key_len_in_byte(master_key.len()).unwrap();
+            let round_keys = expand_aes_key(&master_key, key_size);
+
+            assert_eq!(round_keys.len(), key_size.num_rounds() + 1);
+            let first_round_keys = round_keys.iter().flat_map(|rk| byte_mat_to_array(*rk)).take(master_key.len());
+            assert!(first_round_keys.eq(master_key.iter().copied()), "{:?}", key_size);
+            assert_eq!(byte_mat_to_array(*round_keys.last().unwrap()).to_vec(), hex_bytes(last_round_key), "{:?}", key_size);
+        }
+    }
+}
@@ -1,10 +1,11 @@
 use std::collections::HashMap;
-use aligned_vec::ABox;
+use aligned_vec::{ABox, CACHELINE_ALIGN};
+use dyn_stack::{PodStack, ReborrowMut, SizeOverflow, StackReq};
 use tfhe::core_crypto::{
     prelude::*,
-    fft_impl::fft64::c64,
+    fft_impl::fft64::{c64, math::fft::FftView},
 };
-use crate::{utils::*, glwe_keyswitch::*, fourier_glwe_keyswitch::*};
+use crate::{utils::*, error::*, glwe_keyswitch::*, fourier_glwe_keyswitch::*};
 
 // The following codes generalize rlweExpand
 // from https://github.com/KULeuven-COSIC/SortingHat
@@ -72,18 +73,7 @@ impl AutomorphKey<ABox<[c64]>> {
         debug_assert!(self.polynomial_size == before_key.polynomial_size());
         debug_assert!(self.polynomial_size == after_key.polynomial_size());
 
-        let mut before_poly_list = PolynomialList::new(
-            Scalar::ZERO,
-            self.polynomial_size,
-            PolynomialCount(self.glwe_dimension.0),
-        );
-        for (mut before_poly, after_poly) in before_poly_list.iter_mut()
-            .zip(after_key.as_polynomial_list().iter())
-        {
-            let out = eval_x_k(after_poly.as_view(), k);
-            before_poly.as_mut().clone_from_slice(out.as_ref());
-        }
-        *before_key = GlweSecretKey::from_container(before_poly_list.into_container(), self.polynomial_size);
+        *before_key = automorph_secret_key(after_key, k);
 
         self.fill_with_keyswitch_key(before_key, after_key, noise_parameters, generator);
         self.auto_k = k;
@@ -142,11 +132,48 @@ impl AutomorphKey<ABox<[c64]>> {
     {
         let mut before_power = GlweCiphertextOwned::new(Scalar::ZERO, before.glwe_size(), before.polynomial_size(), before.ciphertext_modulus());
         for (mut poly_power, poly) in before_power.as_mut_polynomial_list().iter_mut().zip(before.as_polynomial_list().iter()) {
-            poly_power.as_mut().clone_from_slice(eval_x_k(poly, self.auto_k).as_ref());
+            eval_x_k_in_memory(&mut poly_power, poly, self.auto_k);
         }
 
         self.keyswitch_ciphertext(after, &before_power);
     }
+
+    /// Return the memory required by [`AutomorphKey::auto_mem_optimized`].
+    pub fn auto_mem_optimized_requirement<Scalar>(
+        &self,
+        fft: FftView<'_>,
+    ) -> Result<StackReq, SizeOverflow> {
+        let glwe_size = self.glwe_dimension.to_glwe_size();
+        StackReq::try_new_aligned::<Scalar>(glwe_size.0 * self.polynomial_size.0, CACHELINE_ALIGN)?
+            .try_and(keyswitch_glwe_ciphertext_mem_optimized_requirement::<Scalar>(
+                glwe_size,
+                self.polynomial_size,
+                self.decomp_level_count,
+                self.ksk.fft_type(),
+                fft,
+            )?)
+    }
+
+    /// Memory optimized version of [`AutomorphKey::auto`].
+    pub fn auto_mem_optimized<Scalar, InputCont, OutputCont>(
+        &self,
+        after: &mut GlweCiphertext<OutputCont>,
+        before: &GlweCiphertext<InputCont>,
+        fft: FftView<'_>,
+        stack: PodStack<'_>,
+    ) where
+        Scalar: UnsignedTorus,
+        InputCont: Container<Element=Scalar>,
+        OutputCont: ContainerMut<Element=Scalar>,
+    {
+        let (mut before_power_data, stack) = stack.make_aligned_with(before.as_ref().len(), CACHELINE_ALIGN, |_| Scalar::ZERO);
+        let mut before_power = GlweCiphertextMutView::from_container(&mut *before_power_data, before.polynomial_size(), before.ciphertext_modulus());
+        for (mut poly_power, poly) in before_power.as_mut_polynomial_list().iter_mut().zip(before.as_polynomial_list().iter()) {
+            eval_x_k_in_memory(&mut poly_power, poly, self.auto_k);
+        }
+
+        keyswitch_glwe_ciphertext_mem_optimized(&self.ksk, &before_power, after, fft, stack);
+    }
 }
 
 pub fn gen_all_auto_keys<Scalar, G>(
@@ -177,6 +204,84 @@ where
     hm
 }
 
+/// Returns {S_i(X^k)} for the GLWE secret key {S_i(X)}.
+fn automorph_secret_key<Scalar: UnsignedTorus>(
+    glwe_secret_key: &GlweSecretKeyOwned<Scalar>,
+    k: usize,
+) -> GlweSecretKeyOwned<Scalar> {
+    let polynomial_size = glwe_secret_key.polynomial_size();
+    let mut before_poly_list = PolynomialList::new(
+        Scalar::ZERO,
+        polynomial_size,
+        PolynomialCount(glwe_secret_key.glwe_dimension().0),
+    );
+    for (mut before_poly, after_poly) in before_poly_list.iter_mut()
+        .zip(glwe_secret_key.as_polynomial_list().iter())
+    {
+        let out = eval_x_k(after_poly.as_view(), k);
+        before_poly.as_mut().clone_from_slice(out.as_ref());
+    }
+
+    GlweSecretKey::from_container(before_poly_list.into_container(), polynomial_size)
+}
+
+/// Seeded variant of [`gen_all_auto_keys`], returning the standard-domain
+/// keyswitching key of each automorphism. They are turned into
+/// [`AutomorphKey`]s by [`decompress_seeded_auto_keys`].
+pub fn gen_all_seeded_auto_keys<Scalar, NoiseSeeder>(
+    decomp_base_log: DecompositionBaseLog,
+    decomp_level: DecompositionLevelCount,
+    glwe_secret_key: &GlweSecretKeyOwned<Scalar>,
+    noise_parameters: impl DispersionParameter,
+    noise_seeder: &mut NoiseSeeder,
+) -> HashMap<usize, SeededGlweKeyswitchKey<Scalar>>
+where
+    Scalar: UnsignedTorus,
+    NoiseSeeder: Seeder + ?Sized,
+{
+    let polynomial_size = glwe_secret_key.polynomial_size();
+
+    let mut hm = HashMap::new();
+    for i in 1..=(polynomial_size.0).ilog2() as usize {
+        let k = polynomial_size.0 / (1 << (i - 1)) + 1;
+        let before_key = automorph_secret_key(glwe_secret_key, k);
+        let seeded_ksk = allocate_and_generate_new_seeded_glwe_keyswitch_key(
+            &before_key,
+            glwe_secret_key,
+            decomp_base_log,
+            decomp_level,
+            noise_parameters,
+            CiphertextModulus::new_native(),
+            noise_seeder,
+        );
+        hm.insert(k, seeded_ksk);
+    }
+
+    hm
+}
+
+pub fn decompress_seeded_auto_keys<Scalar: UnsignedTorus>(
+    seeded_auto_keys: HashMap<usize, SeededGlweKeyswitchKey<Scalar>>,
+    fft_type: FftType,
+) -> HashMap<usize, AutomorphKey<ABox<[c64]>>> {
+    seeded_auto_keys
+        .into_iter()
+        .map(|(k, seeded_ksk)| {
+            let mut auto_key = AutomorphKey::allocate(
+                seeded_ksk.decomp_base_log(),
+                seeded_ksk.decomp_level_count(),
+                seeded_ksk.output_glwe_dimension(),
+                seeded_ksk.polynomial_size(),
+                k,
+                fft_type,
+            );
+            let standard_ksk = seeded_ksk.decompress_into_glwe_keyswitch_key();
+            convert_standard_glwe_keyswitch_key_to_fourier(&standard_ksk, &mut auto_key.ksk);
+            (k, auto_key)
+        })
+        .collect()
+}
+
 pub fn trace<Scalar, Cont>(
     glwe_in: &GlweCiphertext<Cont>,
     auto_keys: &HashMap<usize, AutomorphKey<ABox<[c64]>>>,
@@ -209,6 +314,55 @@ pub fn trace_partial_assign<Scalar, Cont>(
 ) where
     Scalar: UnsignedTorus,
     Cont: ContainerMut<Element=Scalar>,
+{
+    let polynomial_size = input.polynomial_size();
+    let fft = Fft::new(polynomial_size);
+    let fft = fft.as_view();
+
+    let mut buffers = ComputationBuffers::new();
+    buffers.resize(
+        trace_partial_assign_mem_optimized_requirement::<Scalar>(auto_keys, input.glwe_size(), polynomial_size, n, fft)
+            .unwrap()
+            .unaligned_bytes_required(),
+    );
+
+    trace_partial_assign_mem_optimized(input, auto_keys, n, fft, buffers.stack());
+}
+
+/// Return the memory required by [`trace_partial_assign_mem_optimized`].
+pub fn trace_partial_assign_mem_optimized_requirement<Scalar>(
+    auto_keys: &HashMap<usize, AutomorphKey<ABox<[c64]>>>,
+    glwe_size: GlweSize,
+    polynomial_size: PolynomialSize,
+    n: usize,
+    fft: FftView<'_>,
+) -> Result<StackReq, SizeOverflow> {
+    let log_polynomial_size = polynomial_size.0.ilog2() as usize;
+    let log_n = n.ilog2() as usize;
+    let auto_req = StackReq::try_any_of(
+        (1..=(log_polynomial_size - log_n))
+            .filter_map(|i| auto_keys.get(&(polynomial_size.0 / (1 << (i - 1)) + 1)))
+            .map(|auto_key| auto_key.auto_mem_optimized_requirement::<Scalar>(fft))
+            .collect::<Result<Vec<_>, _>>()?,
+    )?;
+
+    StackReq::try_all_of([
+        StackReq::try_new_aligned::<Scalar>(glwe_size.0 * polynomial_size.0, CACHELINE_ALIGN)?,
+        StackReq::try_new_aligned::<Scalar>(glwe_size.0 * polynomial_size.0, CACHELINE_ALIGN)?,
+        auto_req,
+    ])
+}
+
+/// Memory optimized version of [`trace_partial_assign`].
+pub fn trace_partial_assign_mem_optimized<Scalar, Cont>(
+    input: &mut GlweCiphertext<Cont>,
+    auto_keys: &HashMap<usize, AutomorphKey<ABox<[c64]>>>,
+    n: usize,
+    fft: FftView<'_>,
+    stack: PodStack<'_>,
+) where
+    Scalar: UnsignedTorus,
+    Cont: ContainerMut<Element=Scalar>,
 {
     let glwe_size = input.glwe_size();
     let polynomial_size = input.polynomial_size();
@@ -216,22 +370,76 @@ pub fn trace_partial_assign<Scalar, Cont>(
 
     assert!(polynomial_size.0 % n == 0);
 
-    let mut buf = GlweCiphertextOwned::new(Scalar::ZERO, glwe_size, polynomial_size, ciphertext_modulus);
-    let mut out: GlweCiphertext<Vec<Scalar>> = GlweCiphertext::new(Scalar::ZERO, glwe_size, polynomial_size, ciphertext_modulus);
-    glwe_ciphertext_clone_from(&mut out, input);
+    let (mut buf_data, stack) = stack.make_aligned_with(glwe_size.0 * polynomial_size.0, CACHELINE_ALIGN, |_| Scalar::ZERO);
+    let (mut out_data, mut stack) = stack.collect_aligned(CACHELINE_ALIGN, input.as_ref().iter().copied());
+    let mut buf = GlweCiphertextMutView::from_container(&mut *buf_data, polynomial_size, ciphertext_modulus);
+    let mut out = GlweCiphertextMutView::from_container(&mut *out_data, polynomial_size, ciphertext_modulus);
 
     let log_polynomial_size = polynomial_size.0.ilog2() as usize;
     let log_n = n.ilog2() as usize;
     for i in 1..=(log_polynomial_size - log_n) {
         let k = polynomial_size.0 / (1 << (i - 1)) + 1;
         let auto_key = auto_keys.get(&k).unwrap();
-        auto_key.auto(&mut buf, &out);
+        auto_key.auto_mem_optimized(&mut buf, &out, fft, stack.rb_mut());
         glwe_ciphertext_add_assign(&mut out, &buf);
     }
 
     glwe_ciphertext_clone_from(input, &out);
 }
 
+/// Fallible version of [`trace_assign`].
+pub fn try_trace_assign<Scalar, ContMut>(
+    glwe_in: &mut GlweCiphertext<ContMut>,
+    auto_keys: &HashMap<usize, AutomorphKey<ABox<[c64]>>>,
+) -> Result<(), Error> where
+    Scalar: UnsignedTorus + Sync + Send,
+    ContMut: ContainerMut<Element=Scalar>,
+{
+    try_trace_partial_assign(glwe_in, auto_keys, 1)
+}
+
+/// Fallible version of [`trace_partial_assign`]: checks that `n` divides the
+/// polynomial size and that every automorphism key the partial trace uses is
+/// present and matches the GLWE ciphertext.
+pub fn try_trace_partial_assign<Scalar, Cont>(
+    input: &mut GlweCiphertext<Cont>,
+    auto_keys: &HashMap<usize, AutomorphKey<ABox<[c64]>>>,
+    n: usize,
+) -> Result<(), Error> where
+    Scalar: UnsignedTorus,
+    Cont: ContainerMut<Element=Scalar>,
+{
+    check_trace_auto_keys(auto_keys, input.glwe_size(), input.polynomial_size(), n)?;
+    trace_partial_assign(input, auto_keys, n);
+    Ok(())
+}
+
+/// Checks that `auto_keys` holds the keys used by a partial trace down to
+/// `n` coefficients of GLWE ciphertexts of the given size.
+pub fn check_trace_auto_keys(
+    auto_keys: &HashMap<usize, AutomorphKey<ABox<[c64]>>>,
+    glwe_size: GlweSize,
+    polynomial_size: PolynomialSize,
+    n: usize,
+) -> Result<(), Error> {
+    if !n.is_power_of_two() || n > polynomial_size.0 {
+        return Err(Error::IncompatibleParameters(format!(
+            "cannot trace a polynomial of size {} down to {} coefficients",
+            polynomial_size.0, n,
+        )));
+    }
+
+    let log_polynomial_size = polynomial_size.0.ilog2() as usize;
+    let log_n = n.ilog2() as usize;
+    for i in 1..=(log_polynomial_size - log_n) {
+        let k = polynomial_size.0 / (1 << (i - 1)) + 1;
+        let auto_key = auto_keys.get(&k).ok_or(Error::MissingAutomorphKey(k))?;
+        check_dimension("automorphism key GLWE dimension", glwe_size.to_glwe_dimension().0, auto_key.glwe_dimension().0)?;
+        check_dimension("automorphism key polynomial size", polynomial_size.0, auto_key.polynomial_size().0)?;
+    }
+    Ok(())
+}
+
 use serde::{Serialize, Deserialize};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -298,4 +506,61 @@ impl AutomorphKey<ABox<[c64]>> {
             auto_k: serializable.auto_k,
         }
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aes_instances::AES_TIGHT;
+    use tfhe::core_crypto::seeders::new_seeder;
+
+    /// Encodes 4-bit messages in the top bits of each coefficient.
+    const MESSAGE_LOG: usize = 4;
+
+    fn decode(plaintext: u64) -> u64 {
+        let rounding = 1 << (u64::BITS as usize - MESSAGE_LOG - 1);
+        plaintext.wrapping_add(rounding) >> (u64::BITS as usize - MESSAGE_LOG)
+    }
+
+    #[test]
+    fn decompressed_seeded_auto_keys_evaluate_automorphisms() {
+        let param = &*AES_TIGHT;
+        let polynomial_size = param.polynomial_size();
+        let ciphertext_modulus = param.ciphertext_modulus();
+        let mut boxed_seeder = new_seeder();
+        let seeder = boxed_seeder.as_mut();
+        let mut secret_generator = SecretRandomGenerator::<ActivatedRandomGenerator>::new(seeder.seed());
+        let mut encryption_generator =
+            EncryptionRandomGenerator::<ActivatedRandomGenerator>::new(seeder.seed(), seeder);
+
+        let glwe_sk = GlweSecretKey::generate_new_binary(param.glwe_dimension(), polynomial_size, &mut secret_generator);
+        let seeded_auto_keys = gen_all_seeded_auto_keys(
+            param.auto_base_log(),
+            param.auto_level(),
+            &glwe_sk,
+            param.glwe_modular_std_dev(),
+            seeder,
+        );
+        let auto_keys = decompress_seeded_auto_keys(seeded_auto_keys, param.fft_type_auto());
+        assert_eq!(auto_keys.len(), polynomial_size.0.ilog2() as usize);
+
+        let messages: Vec<u64> = (0..polynomial_size.0).map(|_| rand::random::<u64>() % (1 << MESSAGE_LOG)).collect();
+        let plaintexts = PlaintextList::from_container(
+            messages.iter().map(|m| m << (u64::BITS as usize - MESSAGE_LOG)).collect::<Vec<_>>()
+        );
+        let mut input = GlweCiphertext::new(0u64, param.glwe_dimension().to_glwe_size(), polynomial_size, ciphertext_modulus);
+        encrypt_glwe_ciphertext(&glwe_sk, &mut input, &plaintexts, param.glwe_modular_std_dev(), &mut encryption_generator);
+
+        for (&k, auto_key) in auto_keys.iter() {
+            let mut output = GlweCiphertext::new(0u64, param.glwe_dimension().to_glwe_size(), polynomial_size, ciphertext_modulus);
+            auto_key.auto(&mut output, &input);
+
+            let mut decrypted = PlaintextList::new(0u64, PlaintextCount(polynomial_size.0));
+            decrypt_glwe_ciphertext(&glwe_sk, &output, &mut decrypted);
+            let decoded: Vec<u64> = decrypted.as_ref().iter().map(|&pt| decode(pt)).collect();
+
+            let expected = eval_x_k(Polynomial::from_container(plaintexts.as_ref()), k);
+            let expected: Vec<u64> = expected.as_ref().iter().map(|&pt| decode(pt)).collect();
+            assert_eq!(decoded, expected, "automorphism X -> X^{}", k);
+        }
+    }
+}
@@ -0,0 +1,51 @@
+use std::fmt;
+
+/// Error returned by the `try_` variants of the conversion and keyswitching
+/// functions, and by the circuit bootstrapping and AES evaluation functions,
+/// which check their inputs instead of panicking.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// A ciphertext or key does not have the size the operation expects.
+    DimensionMismatch {
+        what: &'static str,
+        expected: usize,
+        found: usize,
+    },
+    /// The automorphism key for X -> X^k is not in the key map.
+    MissingAutomorphKey(usize),
+    /// The operands are not under the same ciphertext modulus.
+    CiphertextModulusMismatch,
+    /// The operation only supports the native ciphertext modulus.
+    NonNativeModulus,
+    /// The keys or arguments cannot be used with each other.
+    IncompatibleParameters(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::DimensionMismatch { what, expected, found } => {
+                write!(f, "{} mismatch: expected {}, found {}", what, expected, found)
+            }
+            Error::MissingAutomorphKey(k) => {
+                write!(f, "missing automorphism key for X -> X^{}", k)
+            }
+            Error::CiphertextModulusMismatch => {
+                write!(f, "ciphertexts are under different ciphertext moduli")
+            }
+            Error::NonNativeModulus => {
+                write!(f, "only the native ciphertext modulus is supported")
+            }
+            Error::IncompatibleParameters(msg) => write!(f, "incompatible parameters: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+pub(crate) fn check_dimension(what: &'static str, expected: usize, found: usize) -> Result<(), Error> {
+    if expected != found {
+        return Err(Error::DimensionMismatch { what, expected, found });
+    }
+    Ok(())
+}
@@ -1,12 +1,14 @@
-use aligned_vec::{avec, ABox};
-use dyn_stack::ReborrowMut;
+use aligned_vec::{avec, ABox, CACHELINE_ALIGN};
+use dyn_stack::{PodStack, ReborrowMut, SizeOverflow, StackReq};
+#[cfg(feature = "multithread")]
+use rayon::prelude::*;
 use tfhe::core_crypto::{
     prelude::*,
-    fft_impl::fft64::c64,
+    fft_impl::fft64::{c64, math::fft::FftView},
 };
 
 use crate::{
-    convert_lwe_to_glwe_const, fourier_glev_ciphertext::*, fourier_glwe_ciphertext::*, fourier_poly_mult_and_add, glev_ciphertext::*, GlweKeyswitchKey
+    check_dimension, convert_lwe_to_glwe_const, Error, fourier_glev_ciphertext::*, fourier_glwe_ciphertext::*, fourier_poly_mult_and_add, glev_ciphertext::*, GlweKeyswitchKey
 };
 
 #[derive(Debug, Clone, Copy)]
@@ -219,6 +221,63 @@ pub fn keyswitch_glwe_ciphertext<Scalar, KSKeyCont, InputCont, OutputCont>(
     KSKeyCont: Container<Element=c64>,
     InputCont: Container<Element=Scalar>,
     OutputCont: ContainerMut<Element=Scalar>,
+{
+    let fft = Fft::new(glwe_keyswitch_key.polynomial_size());
+    let fft = fft.as_view();
+
+    let mut buffers = ComputationBuffers::new();
+    buffers.resize(
+        keyswitch_glwe_ciphertext_mem_optimized_requirement::<Scalar>(
+            glwe_keyswitch_key.output_glwe_size(),
+            glwe_keyswitch_key.polynomial_size(),
+            glwe_keyswitch_key.decomp_level_count(),
+            glwe_keyswitch_key.fft_type(),
+            fft,
+        )
+        .unwrap()
+        .unaligned_bytes_required(),
+    );
+
+    keyswitch_glwe_ciphertext_mem_optimized(glwe_keyswitch_key, input, output, fft, buffers.stack());
+}
+
+/// Return the memory required by [`keyswitch_glwe_ciphertext_mem_optimized`]
+/// for a key with the given output GLWE size, decomposition level count and
+/// FFT type.
+pub fn keyswitch_glwe_ciphertext_mem_optimized_requirement<Scalar>(
+    output_glwe_size: GlweSize,
+    polynomial_size: PolynomialSize,
+    decomp_level: DecompositionLevelCount,
+    fft_type: FftType,
+    fft: FftView<'_>,
+) -> Result<StackReq, SizeOverflow> {
+    let fourier_polynomial_size = polynomial_size.to_fourier_polynomial_size().0;
+
+    StackReq::try_all_of([
+        // decomposed input mask polynomial
+        StackReq::try_new_aligned::<Scalar>(decomp_level.0 * polynomial_size.0, CACHELINE_ALIGN)?,
+        StackReq::try_new_aligned::<c64>(decomp_level.0 * fourier_polynomial_size, CACHELINE_ALIGN)?,
+        // one Fourier GLWE accumulator per split
+        StackReq::try_new_aligned::<c64>(fft_type.num_split() * output_glwe_size.0 * fourier_polynomial_size, CACHELINE_ALIGN)?,
+        StackReq::try_new_aligned::<Scalar>(output_glwe_size.0 * polynomial_size.0, CACHELINE_ALIGN)?,
+        fft.forward_scratch()?.try_or(fft.backward_scratch()?)?,
+    ])
+}
+
+/// Memory optimized version of [`keyswitch_glwe_ciphertext`]: every buffer
+/// is taken from `stack`, which must hold at least
+/// [`keyswitch_glwe_ciphertext_mem_optimized_requirement`].
+pub fn keyswitch_glwe_ciphertext_mem_optimized<Scalar, KSKeyCont, InputCont, OutputCont>(
+    glwe_keyswitch_key: &FourierGlweKeyswitchKey<KSKeyCont>,
+    input: &GlweCiphertext<InputCont>,
+    output: &mut GlweCiphertext<OutputCont>,
+    fft: FftView<'_>,
+    stack: PodStack<'_>,
+) where
+    Scalar: UnsignedTorus,
+    KSKeyCont: Container<Element=c64>,
+    InputCont: Container<Element=Scalar>,
+    OutputCont: ContainerMut<Element=Scalar>,
 {
     assert_eq!(
         glwe_keyswitch_key.input_glwe_size(),
@@ -242,22 +301,12 @@ pub fn keyswitch_glwe_ciphertext<Scalar, KSKeyCont, InputCont, OutputCont>(
     );
 
     let polynomial_size = glwe_keyswitch_key.polynomial_size();
+    let fourier_polynomial_size = polynomial_size.to_fourier_polynomial_size().0;
     let output_glwe_size = glwe_keyswitch_key.output_glwe_size();
     let decomp_base_log = glwe_keyswitch_key.decomp_base_log();
     let decomp_level = glwe_keyswitch_key.decomp_level_count();
     let ciphertext_modulus = input.ciphertext_modulus();
 
-    let fft = Fft::new(polynomial_size);
-    let fft = fft.as_view();
-
-    let mut buffers = ComputationBuffers::new();
-    buffers.resize(
-        fft.backward_scratch()
-        .unwrap()
-        .unaligned_bytes_required(),
-    );
-    let mut stack = buffers.stack();
-
     output.as_mut().fill(Scalar::ZERO);
     output.get_mut_body().as_mut().clone_from_slice(input.get_body().as_ref());
 
@@ -266,18 +315,22 @@ pub fn keyswitch_glwe_ciphertext<Scalar, KSKeyCont, InputCont, OutputCont>(
     let num_split = fft_type.num_split();
     let split_base_log = fft_type.split_base_log();
 
-    let mut buffer_fourier_glwe_list = FourierGlweCiphertextList::new(output_glwe_size, polynomial_size, FourierGlweCiphertextCount(num_split));
+    let (mut input_decomp_data, stack) = stack.make_aligned_with(decomp_level.0 * polynomial_size.0, CACHELINE_ALIGN, |_| Scalar::ZERO);
+    let (mut fourier_input_decomp_data, stack) = stack.make_aligned_with(decomp_level.0 * fourier_polynomial_size, CACHELINE_ALIGN, |_| c64::default());
+    let (mut buffer_fourier_glwe_data, stack) = stack.make_aligned_with(num_split * output_glwe_size.0 * fourier_polynomial_size, CACHELINE_ALIGN, |_| c64::default());
+    let (mut buffer_glwe_data, mut stack) = stack.make_aligned_with(output_glwe_size.0 * polynomial_size.0, CACHELINE_ALIGN, |_| Scalar::ZERO);
+
+    let mut input_decomp_poly_list = PolynomialListMutView::from_container(&mut *input_decomp_data, polynomial_size);
+    let mut fourier_input_decomp_poly_list = FourierPolynomialList {
+        data: &mut *fourier_input_decomp_data,
+        polynomial_size: polynomial_size,
+    };
+    let mut buffer_fourier_glwe_list = FourierGlweCiphertextList::from_container(&mut *buffer_fourier_glwe_data, output_glwe_size, polynomial_size);
 
     let input_mask = input.get_mask();
     for (input_mask_poly, fourier_glev_split_list) in input_mask.as_polynomial_list().iter()
         .zip(glwe_keyswitch_key.as_fourier_glev_ciphertext_list().chunks_exact(num_split))
     {
-        let mut input_decomp_poly_list = PolynomialList::new(
-            Scalar::ZERO,
-            polynomial_size,
-            PolynomialCount(decomp_level.0),
-        );
-
         for (i, val) in input_mask_poly.iter().enumerate() {
             let decomposition_iter = decomposer.decompose(*val);
 
@@ -286,15 +339,6 @@ pub fn keyswitch_glwe_ciphertext<Scalar, KSKeyCont, InputCont, OutputCont>(
             }
         }
 
-        let mut fourier_input_decomp_poly_list = FourierPolynomialList {
-            data: avec![
-                c64::default();
-                polynomial_size.to_fourier_polynomial_size().0
-                    * decomp_level.0
-            ].into_boxed_slice(),
-            polynomial_size: polynomial_size,
-        };
-
         for (decomp_poly, mut fourier_decomp_poly) in input_decomp_poly_list.iter()
             .zip(fourier_input_decomp_poly_list.iter_mut())
         {
@@ -320,11 +364,8 @@ pub fn keyswitch_glwe_ciphertext<Scalar, KSKeyCont, InputCont, OutputCont>(
         }
     }
 
-    let mut buffer_glwe_list = GlweCiphertextList::new(Scalar::ZERO, output_glwe_size, polynomial_size, GlweCiphertextCount(num_split), ciphertext_modulus);
-    for (k, (mut buffer_glwe, buffer_fourier_glwe)) in buffer_glwe_list.iter_mut()
-        .zip(buffer_fourier_glwe_list.iter())
-        .enumerate()
-    {
+    let mut buffer_glwe = GlweCiphertextMutView::from_container(&mut *buffer_glwe_data, polynomial_size, ciphertext_modulus);
+    for (k, buffer_fourier_glwe) in buffer_fourier_glwe_list.iter().enumerate() {
         for (mut buffer_poly, buffer_fourier_poly) in buffer_glwe.as_mut_polynomial_list().iter_mut()
             .zip(buffer_fourier_glwe.as_fourier_polynomial_list().iter())
         {
@@ -377,3 +418,109 @@ pub fn keyswitch_lwe_ciphertext_by_glwe_keyswitch<Scalar, InputCont, OutputCont,
     keyswitch_glwe_ciphertext(glwe_keyswitch_key, &input_buf, &mut output_buf);
     extract_lwe_sample_from_glwe_ciphertext(&output_buf, output, MonomialDegree(0));
 }
+
+
+/// Fallible version of [`keyswitch_lwe_ciphertext_by_glwe_keyswitch`].
+pub fn try_keyswitch_lwe_ciphertext_by_glwe_keyswitch<Scalar, InputCont, OutputCont, KSKeyCont>(
+    input: &LweCiphertext<InputCont>,
+    output: &mut LweCiphertext<OutputCont>,
+    glwe_keyswitch_key: &FourierGlweKeyswitchKey<KSKeyCont>
+) -> Result<(), Error> where
+    Scalar: UnsignedTorus,
+    InputCont: Container<Element = Scalar>,
+    OutputCont: ContainerMut<Element = Scalar>,
+    KSKeyCont: Container<Element = c64>,
+{
+    if input.ciphertext_modulus() != output.ciphertext_modulus() {
+        return Err(Error::CiphertextModulusMismatch);
+    }
+    check_lwe_keyswitch_key(glwe_keyswitch_key, input.lwe_size(), output.lwe_size())?;
+
+    keyswitch_lwe_ciphertext_by_glwe_keyswitch(input, output, glwe_keyswitch_key);
+    Ok(())
+}
+
+/// Checks that `glwe_keyswitch_key` switches LWE ciphertexts of size
+/// `input_lwe_size` to LWE ciphertexts of size `output_lwe_size`.
+pub fn check_lwe_keyswitch_key<KSKeyCont>(
+    glwe_keyswitch_key: &FourierGlweKeyswitchKey<KSKeyCont>,
+    input_lwe_size: LweSize,
+    output_lwe_size: LweSize,
+) -> Result<(), Error> where
+    KSKeyCont: Container<Element = c64>,
+{
+    let polynomial_size = glwe_keyswitch_key.polynomial_size();
+    let input_lwe_dimension = input_lwe_size.to_lwe_dimension();
+    let output_lwe_dimension = output_lwe_size.to_lwe_dimension();
+
+    for lwe_dimension in [input_lwe_dimension, output_lwe_dimension] {
+        if lwe_dimension.0 % polynomial_size.0 != 0 {
+            return Err(Error::IncompatibleParameters(format!(
+                "LWE dimension {} is not a multiple of the keyswitching key polynomial size {}",
+                lwe_dimension.0, polynomial_size.0,
+            )));
+        }
+    }
+
+    check_dimension(
+        "keyswitching key input GLWE size",
+        input_lwe_dimension.0 / polynomial_size.0 + 1,
+        glwe_keyswitch_key.input_glwe_size().0,
+    )?;
+    check_dimension(
+        "keyswitching key output GLWE size",
+        output_lwe_dimension.0 / polynomial_size.0 + 1,
+        glwe_keyswitch_key.output_glwe_size().0,
+    )
+}
+
+
+/// Keyswitches every LWE of `input` into the matching LWE of `output`.
+/// With the `multithread` feature the ciphertexts are processed in parallel.
+pub fn keyswitch_lwe_ciphertext_list_by_glwe_keyswitch<Scalar, InputCont, OutputCont, KSKeyCont>(
+    input: &LweCiphertextList<InputCont>,
+    output: &mut LweCiphertextList<OutputCont>,
+    glwe_keyswitch_key: &FourierGlweKeyswitchKey<KSKeyCont>
+) where
+    Scalar: UnsignedTorus,
+    InputCont: Container<Element = Scalar>,
+    OutputCont: ContainerMut<Element = Scalar>,
+    KSKeyCont: Container<Element = c64> + Sync,
+{
+    assert_eq!(input.lwe_ciphertext_count(), output.lwe_ciphertext_count());
+
+    #[cfg(not(feature = "multithread"))]
+    input.iter().zip(output.iter_mut()).for_each(|(lwe_in, mut lwe_out)| {
+        keyswitch_lwe_ciphertext_by_glwe_keyswitch(&lwe_in, &mut lwe_out, glwe_keyswitch_key);
+    });
+    #[cfg(feature = "multithread")]
+    input.par_iter().zip(output.par_iter_mut()).for_each(|(lwe_in, mut lwe_out)| {
+        keyswitch_lwe_ciphertext_by_glwe_keyswitch(&lwe_in, &mut lwe_out, glwe_keyswitch_key);
+    });
+}
+
+
+/// Fallible version of [`keyswitch_lwe_ciphertext_list_by_glwe_keyswitch`].
+pub fn try_keyswitch_lwe_ciphertext_list_by_glwe_keyswitch<Scalar, InputCont, OutputCont, KSKeyCont>(
+    input: &LweCiphertextList<InputCont>,
+    output: &mut LweCiphertextList<OutputCont>,
+    glwe_keyswitch_key: &FourierGlweKeyswitchKey<KSKeyCont>
+) -> Result<(), Error> where
+    Scalar: UnsignedTorus,
+    InputCont: Container<Element = Scalar>,
+    OutputCont: ContainerMut<Element = Scalar>,
+    KSKeyCont: Container<Element = c64> + Sync,
+{
+    check_dimension(
+        "LWE ciphertext count",
+        input.lwe_ciphertext_count().0,
+        output.lwe_ciphertext_count().0,
+    )?;
+    if input.ciphertext_modulus() != output.ciphertext_modulus() {
+        return Err(Error::CiphertextModulusMismatch);
+    }
+    check_lwe_keyswitch_key(glwe_keyswitch_key, input.lwe_size(), output.lwe_size())?;
+
+    keyswitch_lwe_ciphertext_list_by_glwe_keyswitch(input, output, glwe_keyswitch_key);
+    Ok(())
+}
@@ -1,16 +1,21 @@
 use std::collections::HashMap;
 use aligned_vec::{ABox, CACHELINE_ALIGN};
+use dyn_stack::{PodStack, ReborrowMut, SizeOverflow, StackReq};
+#[cfg(feature = "multithread")]
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
 use tfhe::core_crypto::{
     fft_impl::fft64::{
         c64,
         crypto::{
             bootstrap::FourierLweBootstrapKeyView,
-            ggsw::FourierGgswCiphertextListView,
+            ggsw::{FourierGgswCiphertextListView, FourierGgswCiphertextMutView, FourierGgswCiphertextView},
         },
+        math::fft::FftView,
     },
     prelude::{polynomial_algorithms::*, *},
 };
-use crate::{automorphism::*, glwe_conv::*, glwe_preprocessing_assign, keyswitch_glwe_ciphertext, lwe_preprocessing_assign, pbs::*, utils::*, FourierGlweKeyswitchKey};
+use crate::{automorphism::*, error::*, glwe_conv::*, glwe_preprocessing_assign, keyswitch_glwe_ciphertext, lwe_preprocessing_assign, pbs::*, utils::*, FourierGlweKeyswitchKey};
 
 pub fn generate_scheme_switching_key<Scalar, G>(
     glwe_secret_key: &GlweSecretKeyOwned<Scalar>,
@@ -76,6 +81,95 @@ where
     ggsw_key
 }
 
+/// A scheme switching key whose masks are replaced by the seed they are drawn
+/// from. The rows of all GGSWs are stored, in the same order, as one
+/// [`SeededGlweCiphertextList`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SeededSchemeSwitchingKey<Scalar: UnsignedInteger> {
+    glwe_list: SeededGlweCiphertextListOwned<Scalar>,
+    decomp_base_log: DecompositionBaseLog,
+    decomp_level_count: DecompositionLevelCount,
+}
+
+impl<Scalar: UnsignedTorus> SeededSchemeSwitchingKey<Scalar> {
+    pub fn decompress_into_ggsw_ciphertext_list(self) -> GgswCiphertextListOwned<Scalar> {
+        let glwe_size = self.glwe_list.glwe_size();
+        let polynomial_size = self.glwe_list.polynomial_size();
+        let ciphertext_modulus = self.glwe_list.ciphertext_modulus();
+        let glwe_list = self.glwe_list.decompress_into_glwe_ciphertext_list();
+
+        GgswCiphertextList::from_container(
+            glwe_list.into_container(),
+            glwe_size,
+            polynomial_size,
+            self.decomp_base_log,
+            self.decomp_level_count,
+            ciphertext_modulus,
+        )
+    }
+}
+
+/// Seeded variant of [`generate_scheme_switching_key`]. A seeded GLWE cannot
+/// have its mask modified after encryption, so the terms that
+/// [`generate_scheme_switching_key`] adds to mask polynomial `j` are folded
+/// into the body as their phase, `-S_j` times that term.
+pub fn generate_seeded_scheme_switching_key<Scalar, NoiseSeeder>(
+    glwe_secret_key: &GlweSecretKeyOwned<Scalar>,
+    ss_base_log: DecompositionBaseLog,
+    ss_level: DecompositionLevelCount,
+    noise_parameters: impl DispersionParameter,
+    ciphertext_modulus: CiphertextModulus<Scalar>,
+    noise_seeder: &mut NoiseSeeder,
+) -> SeededSchemeSwitchingKey<Scalar>
+where
+    Scalar: UnsignedTorus,
+    NoiseSeeder: Seeder + ?Sized,
+{
+    let glwe_dimension = glwe_secret_key.glwe_dimension();
+    let glwe_size = glwe_dimension.to_glwe_size();
+    let polynomial_size = glwe_secret_key.polynomial_size();
+    let glwe_sk_poly_list = glwe_secret_key.as_polynomial_list();
+    let ggsw_size = ss_level.0 * glwe_size.0 * polynomial_size.0;
+
+    let mut encoded = PlaintextList::new(Scalar::ZERO, PlaintextCount(glwe_dimension.0 * ggsw_size));
+    for (i, ggsw_pt) in encoded.as_mut().chunks_exact_mut(ggsw_size).enumerate() {
+        let glwe_sk_poly_i = glwe_sk_poly_list.get(i);
+        for (row, glwe_pt) in ggsw_pt.chunks_exact_mut(polynomial_size.0).enumerate() {
+            let k = row / glwe_size.0;
+            let log_scale = Scalar::BITS - (k + 1) * ss_base_log.0;
+
+            let mut buf = Polynomial::new(Scalar::ZERO, polynomial_size);
+            for (elem, sk) in buf.iter_mut().zip(glwe_sk_poly_i.iter()) {
+                *elem = (*sk).wrapping_neg() << log_scale;
+            }
+
+            let col = row % glwe_size.0;
+            let mut glwe_pt = Polynomial::from_container(glwe_pt);
+            if col < glwe_dimension.0 {
+                polynomial_wrapping_sub_mul_assign(&mut glwe_pt, &buf, &glwe_sk_poly_list.get(col));
+            } else {
+                polynomial_wrapping_add_assign(&mut glwe_pt, &buf);
+            }
+        }
+    }
+
+    let mut glwe_list = SeededGlweCiphertextList::new(
+        Scalar::ZERO,
+        glwe_size,
+        polynomial_size,
+        GlweCiphertextCount(glwe_dimension.0 * ss_level.0 * glwe_size.0),
+        noise_seeder.seed().into(),
+        ciphertext_modulus,
+    );
+    encrypt_seeded_glwe_ciphertext_list(glwe_secret_key, &mut glwe_list, &encoded, noise_parameters, noise_seeder);
+
+    SeededSchemeSwitchingKey {
+        glwe_list,
+        decomp_base_log: ss_base_log,
+        decomp_level_count: ss_level,
+    }
+}
+
 pub fn generate_scheme_switching_key_fourier<Scalar, G>(
     glwe_secret_key: &GlweSecretKeyOwned<Scalar>,
     ss_base_log: DecompositionBaseLog,
@@ -168,6 +262,40 @@ pub fn switch_scheme<Scalar, InputCont, OutputCont>(
     Scalar: UnsignedTorus,
     InputCont: Container<Element=Scalar>,
     OutputCont: ContainerMut<Element=Scalar>,
+{
+    let fft = Fft::new(glev.polynomial_size());
+    let fft = fft.as_view();
+
+    let mut buffers = ComputationBuffers::new();
+    buffers.resize(
+        switch_scheme_mem_optimized_requirement::<Scalar>(glev.glwe_size(), glev.polynomial_size(), fft)
+            .unwrap()
+            .unaligned_bytes_required(),
+    );
+
+    switch_scheme_mem_optimized(glev, ggsw, ss_key, fft, buffers.stack());
+}
+
+/// Return the memory required by [`switch_scheme_mem_optimized`].
+pub fn switch_scheme_mem_optimized_requirement<Scalar>(
+    glwe_size: GlweSize,
+    polynomial_size: PolynomialSize,
+    fft: FftView<'_>,
+) -> Result<StackReq, SizeOverflow> {
+    add_external_product_assign_mem_optimized_requirement::<Scalar>(glwe_size, polynomial_size, fft)
+}
+
+/// Memory optimized version of [`switch_scheme`].
+pub fn switch_scheme_mem_optimized<Scalar, InputCont, OutputCont>(
+    glev: &GlweCiphertextList<InputCont>,
+    ggsw: &mut GgswCiphertext<OutputCont>,
+    ss_key: FourierGgswCiphertextListView,
+    fft: FftView<'_>,
+    mut stack: PodStack<'_>,
+) where
+    Scalar: UnsignedTorus,
+    InputCont: Container<Element=Scalar>,
+    OutputCont: ContainerMut<Element=Scalar>,
 {
     assert_eq!(glev.ciphertext_modulus(), ggsw.ciphertext_modulus());
     assert_eq!(glev.polynomial_size(), ggsw.polynomial_size());
@@ -186,12 +314,46 @@ pub fn switch_scheme<Scalar, InputCont, OutputCont>(
         let (mut glwe_mask_list, mut glwe_body_list) = glwe_list.split_at_mut(glwe_dimension.0);
 
         for (mut glwe_mask, ss_key_ggsw) in glwe_mask_list.iter_mut().zip(ss_key.into_ggsw_iter()) {
-            add_external_product_assign(&mut glwe_mask, &ss_key_ggsw, &glwe_bit);
+            add_external_product_assign_mem_optimized(&mut glwe_mask, &ss_key_ggsw, &glwe_bit, fft, stack.rb_mut());
         }
         glwe_ciphertext_clone_from(&mut glwe_body_list.get_mut(0), &glwe_bit);
     }
 }
 
+/// Fallible version of [`switch_scheme`].
+pub fn try_switch_scheme<Scalar, InputCont, OutputCont>(
+    glev: &GlweCiphertextList<InputCont>,
+    ggsw: &mut GgswCiphertext<OutputCont>,
+    ss_key: FourierGgswCiphertextListView,
+) -> Result<(), Error> where
+    Scalar: UnsignedTorus,
+    InputCont: Container<Element=Scalar>,
+    OutputCont: ContainerMut<Element=Scalar>,
+{
+    if glev.ciphertext_modulus() != ggsw.ciphertext_modulus() {
+        return Err(Error::CiphertextModulusMismatch);
+    }
+    check_dimension("GGSW polynomial size", glev.polynomial_size().0, ggsw.polynomial_size().0)?;
+    check_dimension("GGSW GLWE size", glev.glwe_size().0, ggsw.glwe_size().0)?;
+    check_dimension("GGSW level count", glev.glwe_ciphertext_count().0, ggsw.decomposition_level_count().0)?;
+    check_scheme_switching_key(ss_key, glev.glwe_size(), glev.polynomial_size())?;
+
+    switch_scheme(glev, ggsw, ss_key);
+    Ok(())
+}
+
+/// Checks that `ss_key` holds one GGSW ciphertext per GLWE secret key
+/// polynomial for GLWE ciphertexts of the given size.
+pub fn check_scheme_switching_key(
+    ss_key: FourierGgswCiphertextListView,
+    glwe_size: GlweSize,
+    polynomial_size: PolynomialSize,
+) -> Result<(), Error> {
+    check_dimension("scheme switching key polynomial size", polynomial_size.0, ss_key.polynomial_size().0)?;
+    check_dimension("scheme switching key GLWE size", glwe_size.0, ss_key.glwe_size().0)?;
+    check_dimension("scheme switching key GGSW count", glwe_size.to_glwe_dimension().0, ss_key.count())
+}
+
 pub fn lwe_msb_bit_to_ggsw_by_pfpks<Scalar, InputCont, OutputCont, KeyCont>(
     input: &LweCiphertext<InputCont>,
     output: &mut GgswCiphertext<OutputCont>,
@@ -229,6 +391,69 @@ pub fn lwe_msb_bit_to_ggsw_by_pfpks<Scalar, InputCont, OutputCont, KeyCont>(
 }
 
 pub fn lwe_msb_bit_to_glev_by_trace_with_preprocessing<Scalar>(
+    lwe_in: LweCiphertextView<Scalar>,
+    glev: GlweCiphertextListMutView<Scalar>,
+    fourier_bsk: FourierLweBootstrapKeyView,
+    auto_keys: &HashMap<usize, AutomorphKey<ABox<[c64]>>>,
+    glev_base_log: DecompositionBaseLog,
+    glev_level: DecompositionLevelCount,
+    log_lut_count: LutCountLog,
+) where
+    Scalar: UnsignedTorus + CastInto<usize> + CastFrom<u128>,
+{
+    let fft = Fft::new(fourier_bsk.polynomial_size());
+    let fft = fft.as_view();
+
+    let mut buffers = ComputationBuffers::new();
+    buffers.resize(
+        lwe_msb_bit_to_glev_by_trace_with_preprocessing_mem_optimized_requirement::<Scalar>(
+            fourier_bsk.glwe_size(),
+            fourier_bsk.polynomial_size(),
+            auto_keys,
+            fft,
+        )
+        .unwrap()
+        .unaligned_bytes_required(),
+    );
+
+    lwe_msb_bit_to_glev_by_trace_with_preprocessing_mem_optimized(
+        lwe_in,
+        glev,
+        fourier_bsk,
+        auto_keys,
+        glev_base_log,
+        glev_level,
+        log_lut_count,
+        fft,
+        buffers.stack(),
+    );
+}
+
+/// Return the memory required by
+/// [`lwe_msb_bit_to_glev_by_trace_with_preprocessing_mem_optimized`].
+pub fn lwe_msb_bit_to_glev_by_trace_with_preprocessing_mem_optimized_requirement<Scalar>(
+    glwe_size: GlweSize,
+    polynomial_size: PolynomialSize,
+    auto_keys: &HashMap<usize, AutomorphKey<ABox<[c64]>>>,
+    fft: FftView<'_>,
+) -> Result<StackReq, SizeOverflow> {
+    let glwe = StackReq::try_new_aligned::<Scalar>(glwe_size.0 * polynomial_size.0, CACHELINE_ALIGN)?;
+    let lwe = StackReq::try_new_aligned::<Scalar>(glwe_size.to_glwe_dimension().0 * polynomial_size.0 + 1, CACHELINE_ALIGN)?;
+    let level_extraction = StackReq::try_all_of([
+        glwe,
+        lwe,
+        trace_partial_assign_mem_optimized_requirement::<Scalar>(auto_keys, glwe_size, polynomial_size, 1, fft)?,
+    ])?;
+
+    // The blind rotated accumulator is kept while the levels are extracted
+    glwe.try_and(
+        blind_rotate_assign_mem_optimized_requirement::<Scalar>(glwe_size, polynomial_size, fft)?
+            .try_or(level_extraction)?,
+    )
+}
+
+/// Memory optimized version of [`lwe_msb_bit_to_glev_by_trace_with_preprocessing`].
+pub fn lwe_msb_bit_to_glev_by_trace_with_preprocessing_mem_optimized<Scalar>(
     lwe_in: LweCiphertextView<Scalar>,
     mut glev: GlweCiphertextListMutView<Scalar>,
     fourier_bsk: FourierLweBootstrapKeyView,
@@ -236,6 +461,8 @@ pub fn lwe_msb_bit_to_glev_by_trace_with_preprocessing<Scalar>(
     glev_base_log: DecompositionBaseLog,
     glev_level: DecompositionLevelCount,
     log_lut_count: LutCountLog,
+    fft: FftView<'_>,
+    mut stack: PodStack<'_>,
 ) where
     Scalar: UnsignedTorus + CastInto<usize> + CastFrom<u128>,
 {
@@ -249,46 +476,29 @@ pub fn lwe_msb_bit_to_glev_by_trace_with_preprocessing<Scalar>(
 
     let lut_count = 1 << log_lut_count.0;
     for (acc_idx, mut glev_chunk) in glev.chunks_mut(lut_count).enumerate() {
-        let mut accumulator = (0..polynomial_size.0).map(|i| {
-            let k = i % lut_count;
-            let log_scale = Scalar::BITS - (acc_idx * lut_count + k + 1) * glev_base_log.0;
-            (Scalar::ONE).wrapping_neg() << (log_scale - 1)
-        }).collect::<Vec<Scalar>>();
-
-        for a_i in accumulator[0..half_box_size].iter_mut() {
-            *a_i = (*a_i).wrapping_neg();
-        }
-        accumulator.rotate_left(half_box_size);
-
-        let accumulator_plaintext = PlaintextList::from_container(accumulator);
-        let accumulator = allocate_and_trivially_encrypt_new_glwe_ciphertext(
-            glwe_size,
-            &accumulator_plaintext,
-            ciphertext_modulus,
+        let (mut local_accumulator_data, mut stack) = stack.rb_mut().make_aligned_with(
+            glwe_size.0 * polynomial_size.0,
+            CACHELINE_ALIGN,
+            |_| Scalar::ZERO,
         );
-
-        let mut buffers = ComputationBuffers::new();
-        let fft = Fft::new(polynomial_size);
-        let fft = fft.as_view();
-
-        buffers.resize(
-            programmable_bootstrap_lwe_ciphertext_mem_optimized_requirement::<Scalar>(
-                glwe_size,
-                polynomial_size,
-                fft,
-            )
-            .unwrap()
-            .unaligned_bytes_required(),
-        );
-        let stack = buffers.stack();
-
-        let (mut local_accumulator_data, stack) = stack.collect_aligned(CACHELINE_ALIGN, accumulator.as_ref().iter().copied());
         let mut local_accumulator = GlweCiphertextMutView::from_container(
             &mut *local_accumulator_data,
             polynomial_size,
             ciphertext_modulus,
         );
 
+        let mut accumulator_body = local_accumulator.get_mut_body();
+        let accumulator_body = accumulator_body.as_mut();
+        for (i, a_i) in accumulator_body.iter_mut().enumerate() {
+            let k = i % lut_count;
+            let log_scale = Scalar::BITS - (acc_idx * lut_count + k + 1) * glev_base_log.0;
+            *a_i = (Scalar::ONE).wrapping_neg() << (log_scale - 1);
+        }
+        for a_i in accumulator_body[0..half_box_size].iter_mut() {
+            *a_i = (*a_i).wrapping_neg();
+        }
+        accumulator_body.rotate_left(half_box_size);
+
         gen_blind_rotate_local_assign(
             fourier_bsk,
             local_accumulator.as_mut_view(),
@@ -296,11 +506,13 @@ pub fn lwe_msb_bit_to_glev_by_trace_with_preprocessing<Scalar>(
             log_lut_count,
             lwe_in.as_ref(),
             fft,
-            stack,
+            stack.rb_mut(),
         );
 
-        let mut buf_glwe = GlweCiphertext::new(Scalar::ZERO, glwe_size, polynomial_size, ciphertext_modulus);
-        let mut buf_lwe = LweCiphertext::new(Scalar::ZERO, fourier_bsk.output_lwe_dimension().to_lwe_size(), ciphertext_modulus);
+        let (mut buf_glwe_data, stack) = stack.make_aligned_with(glwe_size.0 * polynomial_size.0, CACHELINE_ALIGN, |_| Scalar::ZERO);
+        let (mut buf_lwe_data, mut stack) = stack.make_aligned_with(fourier_bsk.output_lwe_dimension().to_lwe_size().0, CACHELINE_ALIGN, |_| Scalar::ZERO);
+        let mut buf_glwe = GlweCiphertextMutView::from_container(&mut *buf_glwe_data, polynomial_size, ciphertext_modulus);
+        let mut buf_lwe = LweCiphertextMutView::from_container(&mut *buf_lwe_data, ciphertext_modulus);
         for (k, mut glwe) in glev_chunk.iter_mut().enumerate() {
             let cur_level = acc_idx * lut_count + k + 1;
             let log_scale = Scalar::BITS - cur_level * glev_base_log.0;
@@ -312,7 +524,7 @@ pub fn lwe_msb_bit_to_glev_by_trace_with_preprocessing<Scalar>(
             extract_lwe_sample_from_glwe_ciphertext(&buf_glwe, &mut buf_lwe, MonomialDegree(0));
             lwe_preprocessing_assign(&mut buf_lwe, polynomial_size);
             convert_lwe_to_glwe_const(&buf_lwe, &mut glwe);
-            trace_assign(&mut glwe, &auto_keys);
+            trace_partial_assign_mem_optimized(&mut glwe, auto_keys, 1, fft, stack.rb_mut());
         }
     }
 }
@@ -441,6 +653,312 @@ where
 }
 
 
+/// Buffers for circuit bootstrapping one LWE ciphertext: the stack used by
+/// the blind rotation, the traces and the external products, and the GLEV and
+/// GGSW ciphertexts the bit goes through before reaching the Fourier domain.
+struct CbsLane<Scalar: UnsignedTorus> {
+    buffers: ComputationBuffers,
+    glev: GlweCiphertextListOwned<Scalar>,
+    ggsw: GgswCiphertextOwned<Scalar>,
+}
+
+/// Pre-allocated working set for circuit bootstrapping by trace with
+/// preprocessing under one parameter set.
+///
+/// A context holds the FFT plan and, for each of its `lane_count` lanes, the
+/// scratch stack, GLEV and GGSW buffers of one circuit bootstrapping, plus one
+/// Fourier GGSW output per lane. Creating it once and reusing it avoids
+/// allocating these buffers, and the ones of every automorphism inside the
+/// traces, for each bit. Under the `multithread` feature the lanes of
+/// [`CbsContext::circuit_bootstrap_lwe_ciphertext_list`] run in parallel;
+/// callers that bootstrap several lists concurrently hold one context each.
+pub struct CbsContext<Scalar: UnsignedTorus> {
+    fft: Fft,
+    glwe_size: GlweSize,
+    polynomial_size: PolynomialSize,
+    ggsw_base_log: DecompositionBaseLog,
+    ggsw_level: DecompositionLevelCount,
+    log_lut_count: LutCountLog,
+    ciphertext_modulus: CiphertextModulus<Scalar>,
+    lanes: Vec<CbsLane<Scalar>>,
+    fourier_ggsw_list: FourierGgswCiphertextList<Vec<c64>>,
+}
+
+impl<Scalar> CbsContext<Scalar>
+where
+    Scalar: UnsignedTorus + CastInto<usize> + CastFrom<u128>,
+{
+    /// Allocates a context for circuit bootstrapping up to `lane_count` LWE
+    /// ciphertexts at a time with `fourier_bsk` and `auto_keys` into GGSW
+    /// ciphertexts of the given decomposition. Fails if `auto_keys` misses a
+    /// key of the trace or if the scratch space does not fit in memory.
+    pub fn new(
+        fourier_bsk: FourierLweBootstrapKeyView,
+        auto_keys: &HashMap<usize, AutomorphKey<ABox<[c64]>>>,
+        ggsw_base_log: DecompositionBaseLog,
+        ggsw_level: DecompositionLevelCount,
+        log_lut_count: LutCountLog,
+        ciphertext_modulus: CiphertextModulus<Scalar>,
+        lane_count: usize,
+    ) -> Result<Self, Error> {
+        let glwe_size = fourier_bsk.glwe_size();
+        let polynomial_size = fourier_bsk.polynomial_size();
+        check_trace_auto_keys(auto_keys, glwe_size, polynomial_size, 1)?;
+        let fft = Fft::new(polynomial_size);
+
+        let overflow = |_: SizeOverflow| Error::IncompatibleParameters(format!(
+            "the circuit bootstrapping scratch for polynomials of size {} overflows",
+            polynomial_size.0,
+        ));
+        let stack_size = {
+            let fft = fft.as_view();
+            StackReq::try_any_of([
+                lwe_msb_bit_to_glev_by_trace_with_preprocessing_mem_optimized_requirement::<Scalar>(glwe_size, polynomial_size, auto_keys, fft).map_err(overflow)?,
+                switch_scheme_mem_optimized_requirement::<Scalar>(glwe_size, polynomial_size, fft).map_err(overflow)?,
+                convert_standard_ggsw_ciphertext_to_fourier_mem_optimized_requirement(fft).map_err(overflow)?,
+            ])
+            .map_err(overflow)?
+            .unaligned_bytes_required()
+        };
+
+        let lanes = (0..lane_count).map(|_| {
+            let mut buffers = ComputationBuffers::new();
+            buffers.resize(stack_size);
+            CbsLane {
+                buffers,
+                glev: GlweCiphertextList::new(Scalar::ZERO, glwe_size, polynomial_size, GlweCiphertextCount(ggsw_level.0), ciphertext_modulus),
+                ggsw: GgswCiphertext::new(Scalar::ZERO, glwe_size, polynomial_size, ggsw_base_log, ggsw_level, ciphertext_modulus),
+            }
+        }).collect();
+
+        let fourier_ggsw_list = FourierGgswCiphertextList::new(
+            vec![c64::default();
+                lane_count * polynomial_size.to_fourier_polynomial_size().0
+                    * glwe_size.0
+                    * glwe_size.0
+                    * ggsw_level.0
+            ],
+            lane_count,
+            glwe_size,
+            polynomial_size,
+            ggsw_base_log,
+            ggsw_level,
+        );
+
+        Ok(CbsContext {
+            fft,
+            glwe_size,
+            polynomial_size,
+            ggsw_base_log,
+            ggsw_level,
+            log_lut_count,
+            ciphertext_modulus,
+            lanes,
+            fourier_ggsw_list,
+        })
+    }
+
+    pub fn lane_count(&self) -> usize {
+        self.lanes.len()
+    }
+
+    /// Circuit bootstraps `lwe_in` and returns the resulting Fourier GGSW
+    /// ciphertext, which stays valid until the context is used again. Fails if
+    /// `lwe_in` or the keys do not match the context.
+    pub fn circuit_bootstrap_lwe_ciphertext(
+        &mut self,
+        lwe_in: LweCiphertextView<Scalar>,
+        fourier_bsk: FourierLweBootstrapKeyView,
+        auto_keys: &HashMap<usize, AutomorphKey<ABox<[c64]>>>,
+        ss_key: FourierGgswCiphertextListView,
+    ) -> Result<FourierGgswCiphertextView<'_>, Error> {
+        self.check_lane_count(1)?;
+        self.check_inputs(lwe_in.lwe_size(), lwe_in.ciphertext_modulus(), fourier_bsk, auto_keys, ss_key)?;
+
+        let fourier_ggsw = self.fourier_ggsw_list.as_mut_view().into_ggsw_iter().next().unwrap();
+        self.lanes[0].circuit_bootstrap(
+            lwe_in,
+            fourier_ggsw,
+            fourier_bsk,
+            auto_keys,
+            ss_key,
+            self.ggsw_base_log,
+            self.ggsw_level,
+            self.log_lut_count,
+            self.fft.as_view(),
+        );
+
+        Ok(self.fourier_ggsw_list.as_view().into_ggsw_iter().next().unwrap())
+    }
+
+    /// Circuit bootstraps every ciphertext of `input`, one per lane, and
+    /// returns the Fourier GGSW ciphertexts in the same order. They stay valid
+    /// until the context is used again. Fails if `input` holds more
+    /// ciphertexts than the context has lanes, or if it or the keys do not
+    /// match the context.
+    pub fn circuit_bootstrap_lwe_ciphertext_list<InputCont>(
+        &mut self,
+        input: &LweCiphertextList<InputCont>,
+        fourier_bsk: FourierLweBootstrapKeyView,
+        auto_keys: &HashMap<usize, AutomorphKey<ABox<[c64]>>>,
+        ss_key: FourierGgswCiphertextListView,
+    ) -> Result<FourierGgswCiphertextListView<'_>, Error>
+    where
+        Scalar: Sync + Send,
+        InputCont: Container<Element=Scalar>,
+    {
+        let count = input.lwe_ciphertext_count().0;
+        self.check_lane_count(count)?;
+        self.check_inputs(input.lwe_size(), input.ciphertext_modulus(), fourier_bsk, auto_keys, ss_key)?;
+
+        let ggsw_base_log = self.ggsw_base_log;
+        let ggsw_level = self.ggsw_level;
+        let log_lut_count = self.log_lut_count;
+        let fft = self.fft.as_view();
+        let bootstrap = |((lwe_in, lane), fourier_ggsw): ((LweCiphertextView<Scalar>, &mut CbsLane<Scalar>), FourierGgswCiphertextMutView)| {
+            lane.circuit_bootstrap(
+                lwe_in,
+                fourier_ggsw,
+                fourier_bsk,
+                auto_keys,
+                ss_key,
+                ggsw_base_log,
+                ggsw_level,
+                log_lut_count,
+                fft,
+            );
+        };
+
+        let fourier_ggsw_iter = self.fourier_ggsw_list.as_mut_view().into_ggsw_iter();
+        #[cfg(not(feature = "multithread"))]
+        input.iter()
+            .zip(self.lanes.iter_mut())
+            .zip(fourier_ggsw_iter)
+            .for_each(bootstrap);
+        #[cfg(feature = "multithread")]
+        input.par_iter()
+            .zip(self.lanes.par_iter_mut())
+            .zip(fourier_ggsw_iter.collect::<Vec<_>>())
+            .for_each(bootstrap);
+
+        Ok(self.fourier_ggsw_list.as_view().split_at(count).0)
+    }
+
+    /// Circuit bootstraps `lwe_in` into the standard domain GGSW ciphertext
+    /// `ggsw_out`, for callers that keep their GGSW ciphertexts outside the
+    /// Fourier domain. Fails if `ggsw_out` does not have the decomposition of
+    /// the context, or if `lwe_in` or the keys do not match the context.
+    pub fn circuit_bootstrap_lwe_ciphertext_to_standard_ggsw<OutputCont>(
+        &mut self,
+        lwe_in: LweCiphertextView<Scalar>,
+        ggsw_out: &mut GgswCiphertext<OutputCont>,
+        fourier_bsk: FourierLweBootstrapKeyView,
+        auto_keys: &HashMap<usize, AutomorphKey<ABox<[c64]>>>,
+        ss_key: FourierGgswCiphertextListView,
+    ) -> Result<(), Error>
+    where
+        OutputCont: ContainerMut<Element=Scalar>,
+    {
+        self.check_lane_count(1)?;
+        self.check_inputs(lwe_in.lwe_size(), lwe_in.ciphertext_modulus(), fourier_bsk, auto_keys, ss_key)?;
+        if ggsw_out.ciphertext_modulus() != self.ciphertext_modulus {
+            return Err(Error::CiphertextModulusMismatch);
+        }
+        check_dimension("GGSW GLWE size", self.glwe_size.0, ggsw_out.glwe_size().0)?;
+        check_dimension("GGSW polynomial size", self.polynomial_size.0, ggsw_out.polynomial_size().0)?;
+        check_dimension("GGSW base log", self.ggsw_base_log.0, ggsw_out.decomposition_base_log().0)?;
+        check_dimension("GGSW level count", self.ggsw_level.0, ggsw_out.decomposition_level_count().0)?;
+
+        let fft = self.fft.as_view();
+        let lane = &mut self.lanes[0];
+        let mut stack = lane.buffers.stack();
+        lwe_msb_bit_to_glev_by_trace_with_preprocessing_mem_optimized(
+            lwe_in,
+            GlweCiphertextListMutView::from_container(lane.glev.as_mut(), self.glwe_size, self.polynomial_size, self.ciphertext_modulus),
+            fourier_bsk,
+            auto_keys,
+            self.ggsw_base_log,
+            self.ggsw_level,
+            self.log_lut_count,
+            fft,
+            stack.rb_mut(),
+        );
+        switch_scheme_mem_optimized(&lane.glev, ggsw_out, ss_key, fft, stack);
+        Ok(())
+    }
+
+    fn check_lane_count(&self, count: usize) -> Result<(), Error> {
+        if count > self.lanes.len() {
+            return Err(Error::IncompatibleParameters(format!(
+                "{} ciphertexts for a context of {} lanes",
+                count, self.lanes.len(),
+            )));
+        }
+        Ok(())
+    }
+
+    /// Checks an input of the given size and modulus, and the keys, against
+    /// the parameters the context was allocated for.
+    fn check_inputs(
+        &self,
+        lwe_size: LweSize,
+        ciphertext_modulus: CiphertextModulus<Scalar>,
+        fourier_bsk: FourierLweBootstrapKeyView,
+        auto_keys: &HashMap<usize, AutomorphKey<ABox<[c64]>>>,
+        ss_key: FourierGgswCiphertextListView,
+    ) -> Result<(), Error> {
+        if !ciphertext_modulus.is_native_modulus() {
+            return Err(Error::NonNativeModulus);
+        }
+        if ciphertext_modulus != self.ciphertext_modulus {
+            return Err(Error::CiphertextModulusMismatch);
+        }
+        check_dimension("bootstrapping key GLWE size", self.glwe_size.0, fourier_bsk.glwe_size().0)?;
+        check_dimension("bootstrapping key polynomial size", self.polynomial_size.0, fourier_bsk.polynomial_size().0)?;
+        check_dimension("LWE size", fourier_bsk.input_lwe_dimension().to_lwe_size().0, lwe_size.0)?;
+        check_trace_auto_keys(auto_keys, self.glwe_size, self.polynomial_size, 1)?;
+        check_scheme_switching_key(ss_key, self.glwe_size, self.polynomial_size)
+    }
+}
+
+impl<Scalar> CbsLane<Scalar>
+where
+    Scalar: UnsignedTorus + CastInto<usize> + CastFrom<u128>,
+{
+    fn circuit_bootstrap(
+        &mut self,
+        lwe_in: LweCiphertextView<Scalar>,
+        mut fourier_ggsw: FourierGgswCiphertextMutView,
+        fourier_bsk: FourierLweBootstrapKeyView,
+        auto_keys: &HashMap<usize, AutomorphKey<ABox<[c64]>>>,
+        ss_key: FourierGgswCiphertextListView,
+        ggsw_base_log: DecompositionBaseLog,
+        ggsw_level: DecompositionLevelCount,
+        log_lut_count: LutCountLog,
+        fft: FftView<'_>,
+    ) {
+        let glwe_size = self.glev.glwe_size();
+        let polynomial_size = self.glev.polynomial_size();
+        let ciphertext_modulus = self.glev.ciphertext_modulus();
+
+        let mut stack = self.buffers.stack();
+        lwe_msb_bit_to_glev_by_trace_with_preprocessing_mem_optimized(
+            lwe_in,
+            GlweCiphertextListMutView::from_container(self.glev.as_mut(), glwe_size, polynomial_size, ciphertext_modulus),
+            fourier_bsk,
+            auto_keys,
+            ggsw_base_log,
+            ggsw_level,
+            log_lut_count,
+            fft,
+            stack.rb_mut(),
+        );
+        switch_scheme_mem_optimized(&self.glev, &mut self.ggsw, ss_key, fft, stack.rb_mut());
+        convert_standard_ggsw_ciphertext_to_fourier_mem_optimized(&self.ggsw, &mut fourier_ggsw, fft, stack);
+    }
+}
+
+
 pub fn circuit_bootstrap_lwe_ciphertext_by_pksk<Scalar>(
     lwe_in: LweCiphertextView<Scalar>,
     fourier_bsk: FourierLweBootstrapKeyView,
@@ -807,3 +1325,225 @@ where
         lwe_ciphertext_sub_assign(&mut buf, &lwe_extract);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{aes_instances::AES_TIGHT, FftType};
+    use tfhe::core_crypto::seeders::new_seeder;
+
+    const LWE_DIMENSION: LweDimension = LweDimension(16);
+    const GLWE_SIZE: GlweSize = GlweSize(2);
+    const POLYNOMIAL_SIZE: PolynomialSize = PolynomialSize(512);
+    const BASE_LOG: DecompositionBaseLog = DecompositionBaseLog(10);
+    const LEVEL: DecompositionLevelCount = DecompositionLevelCount(2);
+
+    /// Keys of the right shapes; the checks fail before any of them is used.
+    fn keys() -> (
+        FourierLweBootstrapKeyOwned,
+        HashMap<usize, AutomorphKey<ABox<[c64]>>>,
+        FourierGgswCiphertextList<Vec<c64>>,
+    ) {
+        let fourier_bsk = FourierLweBootstrapKey::new(LWE_DIMENSION, GLWE_SIZE, POLYNOMIAL_SIZE, BASE_LOG, LEVEL);
+
+        let mut auto_keys = HashMap::new();
+        for i in 1..=POLYNOMIAL_SIZE.0.ilog2() as usize {
+            let k = POLYNOMIAL_SIZE.0 / (1 << (i - 1)) + 1;
+            auto_keys.insert(k, AutomorphKey::allocate(BASE_LOG, LEVEL, GLWE_SIZE.to_glwe_dimension(), POLYNOMIAL_SIZE, i, FftType::Vanilla));
+        }
+
+        let ss_count = GLWE_SIZE.to_glwe_dimension().0;
+        let ss_key = FourierGgswCiphertextList::new(
+            vec![c64::default();
+                ss_count * POLYNOMIAL_SIZE.to_fourier_polynomial_size().0 * GLWE_SIZE.0 * GLWE_SIZE.0 * LEVEL.0
+            ],
+            ss_count,
+            GLWE_SIZE,
+            POLYNOMIAL_SIZE,
+            BASE_LOG,
+            LEVEL,
+        );
+
+        (fourier_bsk, auto_keys, ss_key)
+    }
+
+    fn context(
+        fourier_bsk: &FourierLweBootstrapKeyOwned,
+        auto_keys: &HashMap<usize, AutomorphKey<ABox<[c64]>>>,
+        ciphertext_modulus: CiphertextModulus<u64>,
+    ) -> CbsContext<u64> {
+        CbsContext::new(fourier_bsk.as_view(), auto_keys, BASE_LOG, LEVEL, LutCountLog(0), ciphertext_modulus, 2).unwrap()
+    }
+
+    #[test]
+    fn more_ciphertexts_than_lanes() {
+        let (fourier_bsk, auto_keys, ss_key) = keys();
+        let mut cbs_context = context(&fourier_bsk, &auto_keys, CiphertextModulus::new_native());
+
+        let input = LweCiphertextList::new(0u64, LWE_DIMENSION.to_lwe_size(), LweCiphertextCount(3), CiphertextModulus::new_native());
+        let result = cbs_context.circuit_bootstrap_lwe_ciphertext_list(&input, fourier_bsk.as_view(), &auto_keys, ss_key.as_view());
+        assert!(matches!(result, Err(Error::IncompatibleParameters(_))));
+    }
+
+    #[test]
+    fn wrong_lwe_size() {
+        let (fourier_bsk, auto_keys, ss_key) = keys();
+        let mut cbs_context = context(&fourier_bsk, &auto_keys, CiphertextModulus::new_native());
+
+        let lwe = LweCiphertext::new(0u64, LweSize(LWE_DIMENSION.0 + 2), CiphertextModulus::new_native());
+        let result = cbs_context.circuit_bootstrap_lwe_ciphertext(lwe.as_view(), fourier_bsk.as_view(), &auto_keys, ss_key.as_view());
+        assert_eq!(
+            result.map(|_| ()),
+            Err(Error::DimensionMismatch { what: "LWE size", expected: LWE_DIMENSION.0 + 1, found: LWE_DIMENSION.0 + 2 }),
+        );
+    }
+
+    #[test]
+    fn missing_automorphism_key() {
+        let (fourier_bsk, mut auto_keys, ss_key) = keys();
+        let mut cbs_context = context(&fourier_bsk, &auto_keys, CiphertextModulus::new_native());
+        auto_keys.remove(&(POLYNOMIAL_SIZE.0 + 1));
+
+        let lwe = LweCiphertext::new(0u64, LWE_DIMENSION.to_lwe_size(), CiphertextModulus::new_native());
+        let result = cbs_context.circuit_bootstrap_lwe_ciphertext(lwe.as_view(), fourier_bsk.as_view(), &auto_keys, ss_key.as_view());
+        assert_eq!(result.map(|_| ()), Err(Error::MissingAutomorphKey(POLYNOMIAL_SIZE.0 + 1)));
+    }
+
+    #[test]
+    fn context_without_automorphism_key() {
+        let (fourier_bsk, mut auto_keys, _) = keys();
+        auto_keys.remove(&(POLYNOMIAL_SIZE.0 + 1));
+
+        let result = CbsContext::new(
+            fourier_bsk.as_view(),
+            &auto_keys,
+            BASE_LOG,
+            LEVEL,
+            LutCountLog(0),
+            CiphertextModulus::<u64>::new_native(),
+            2,
+        );
+        assert_eq!(result.map(|_| ()), Err(Error::MissingAutomorphKey(POLYNOMIAL_SIZE.0 + 1)));
+    }
+
+    #[test]
+    fn wrong_scheme_switching_key() {
+        let (fourier_bsk, auto_keys, _) = keys();
+        let mut cbs_context = context(&fourier_bsk, &auto_keys, CiphertextModulus::new_native());
+        let ss_count = GLWE_SIZE.to_glwe_dimension().0 + 1;
+        let ss_key = FourierGgswCiphertextList::new(
+            vec![c64::default();
+                ss_count * POLYNOMIAL_SIZE.to_fourier_polynomial_size().0 * GLWE_SIZE.0 * GLWE_SIZE.0 * LEVEL.0
+            ],
+            ss_count,
+            GLWE_SIZE,
+            POLYNOMIAL_SIZE,
+            BASE_LOG,
+            LEVEL,
+        );
+
+        let lwe = LweCiphertext::new(0u64, LWE_DIMENSION.to_lwe_size(), CiphertextModulus::new_native());
+        let result = cbs_context.circuit_bootstrap_lwe_ciphertext(lwe.as_view(), fourier_bsk.as_view(), &auto_keys, ss_key.as_view());
+        assert_eq!(
+            result.map(|_| ()),
+            Err(Error::DimensionMismatch {
+                what: "scheme switching key GGSW count",
+                expected: ss_count - 1,
+                found: ss_count,
+            }),
+        );
+    }
+
+    #[test]
+    fn non_native_input_modulus() {
+        let (fourier_bsk, auto_keys, ss_key) = keys();
+        let mut cbs_context = context(&fourier_bsk, &auto_keys, CiphertextModulus::new_native());
+
+        let lwe = LweCiphertext::new(0u64, LWE_DIMENSION.to_lwe_size(), CiphertextModulus::try_new_power_of_2(63).unwrap());
+        let result = cbs_context.circuit_bootstrap_lwe_ciphertext(lwe.as_view(), fourier_bsk.as_view(), &auto_keys, ss_key.as_view());
+        assert_eq!(result.map(|_| ()), Err(Error::NonNativeModulus));
+    }
+
+    #[test]
+    fn input_modulus_differs_from_context() {
+        let (fourier_bsk, auto_keys, ss_key) = keys();
+        let mut cbs_context = context(&fourier_bsk, &auto_keys, CiphertextModulus::try_new_power_of_2(63).unwrap());
+
+        let lwe = LweCiphertext::new(0u64, LWE_DIMENSION.to_lwe_size(), CiphertextModulus::new_native());
+        let result = cbs_context.circuit_bootstrap_lwe_ciphertext(lwe.as_view(), fourier_bsk.as_view(), &auto_keys, ss_key.as_view());
+        assert_eq!(result.map(|_| ()), Err(Error::CiphertextModulusMismatch));
+    }
+
+    #[test]
+    fn standard_ggsw_output_checks() {
+        let (fourier_bsk, auto_keys, ss_key) = keys();
+        let mut cbs_context = context(&fourier_bsk, &auto_keys, CiphertextModulus::new_native());
+        let lwe = LweCiphertext::new(0u64, LWE_DIMENSION.to_lwe_size(), CiphertextModulus::new_native());
+
+        let mut ggsw = GgswCiphertext::new(0u64, GLWE_SIZE, POLYNOMIAL_SIZE, BASE_LOG, DecompositionLevelCount(LEVEL.0 + 1), CiphertextModulus::new_native());
+        let result = cbs_context.circuit_bootstrap_lwe_ciphertext_to_standard_ggsw(lwe.as_view(), &mut ggsw, fourier_bsk.as_view(), &auto_keys, ss_key.as_view());
+        assert_eq!(result, Err(Error::DimensionMismatch { what: "GGSW level count", expected: LEVEL.0, found: LEVEL.0 + 1 }));
+
+        let mut ggsw = GgswCiphertext::new(0u64, GLWE_SIZE, POLYNOMIAL_SIZE, BASE_LOG, LEVEL, CiphertextModulus::try_new_power_of_2(63).unwrap());
+        let result = cbs_context.circuit_bootstrap_lwe_ciphertext_to_standard_ggsw(lwe.as_view(), &mut ggsw, fourier_bsk.as_view(), &auto_keys, ss_key.as_view());
+        assert_eq!(result, Err(Error::CiphertextModulusMismatch));
+    }
+
+    /// Decrypts every row of a scheme switching key and rounds it to the
+    /// scale of its decomposition level.
+    fn decrypt_scheme_switching_key(
+        glwe_sk: &GlweSecretKeyOwned<u64>,
+        ss_key: &GgswCiphertextListOwned<u64>,
+    ) -> Vec<Vec<u64>> {
+        let glwe_size = ss_key.glwe_size();
+        let base_log = ss_key.decomposition_base_log().0;
+        let mut rows = vec![];
+        for ggsw in ss_key.iter() {
+            for (row, glwe) in ggsw.as_glwe_list().iter().enumerate() {
+                let log_scale = u64::BITS as usize - (row / glwe_size.0 + 1) * base_log;
+                let mut decrypted = PlaintextList::new(0u64, PlaintextCount(ss_key.polynomial_size().0));
+                decrypt_glwe_ciphertext(glwe_sk, &glwe, &mut decrypted);
+                rows.push(decrypted.as_ref().iter().map(|pt| pt.wrapping_add(1 << (log_scale - 1)) >> log_scale).collect());
+            }
+        }
+
+        rows
+    }
+
+    #[test]
+    fn decompressed_seeded_scheme_switching_key_decrypts() {
+        let param = &*AES_TIGHT;
+        let mut boxed_seeder = new_seeder();
+        let seeder = boxed_seeder.as_mut();
+        let mut secret_generator = SecretRandomGenerator::<ActivatedRandomGenerator>::new(seeder.seed());
+        let mut encryption_generator =
+            EncryptionRandomGenerator::<ActivatedRandomGenerator>::new(seeder.seed(), seeder);
+
+        let glwe_sk = GlweSecretKey::generate_new_binary(param.glwe_dimension(), param.polynomial_size(), &mut secret_generator);
+        let ss_key = generate_scheme_switching_key(
+            &glwe_sk,
+            param.ss_base_log(),
+            param.ss_level(),
+            param.glwe_modular_std_dev(),
+            param.ciphertext_modulus(),
+            &mut encryption_generator,
+        );
+        let seeded_ss_key = generate_seeded_scheme_switching_key(
+            &glwe_sk,
+            param.ss_base_log(),
+            param.ss_level(),
+            param.glwe_modular_std_dev(),
+            param.ciphertext_modulus(),
+            seeder,
+        );
+        let decompressed_ss_key = seeded_ss_key.decompress_into_ggsw_ciphertext_list();
+        assert_eq!(decompressed_ss_key.ggsw_ciphertext_count(), ss_key.ggsw_ciphertext_count());
+
+        // The seeded key folds the mask terms into the body, so both keys
+        // must decrypt to the same rows
+        assert_eq!(
+            decrypt_scheme_switching_key(&glwe_sk, &decompressed_ss_key),
+            decrypt_scheme_switching_key(&glwe_sk, &ss_key),
+        );
+    }
+}
@@ -6,7 +6,7 @@ use tfhe::core_crypto::{
     algorithms::slice_algorithms::slice_wrapping_opposite_assign,
 };
 use crate::{
-    automorphism::*, keyswitch_glwe_ciphertext, mod_switch::*, utils::*, FourierGlweKeyswitchKey
+    automorphism::*, check_dimension, Error, keyswitch_glwe_ciphertext, mod_switch::*, utils::*, FourierGlweKeyswitchKey
 };
 
 pub fn convert_lwe_to_glwe_const<Scalar, InputCont, OutputCont>(
@@ -167,6 +167,49 @@ pub fn convert_lwes_to_glwe_by_trace_with_preprocessing<Scalar, InputCont, Outpu
 }
 
 
+/// Fallible version of [`convert_lwes_to_glwe_by_trace_with_preprocessing`].
+/// The number of input LWE ciphertexts must be a power of two no larger than
+/// the polynomial size.
+pub fn try_convert_lwes_to_glwe_by_trace_with_preprocessing<Scalar, InputCont, OutputCont>(
+    input: &LweCiphertextList<InputCont>,
+    output: &mut GlweCiphertext<OutputCont>,
+    auto_keys: &HashMap<usize, AutomorphKey<ABox<[c64]>>>,
+) -> Result<(), Error> where
+    Scalar: UnsignedTorus,
+    InputCont: Container<Element=Scalar>,
+    OutputCont: ContainerMut<Element=Scalar>,
+{
+    if input.ciphertext_modulus() != output.ciphertext_modulus() {
+        return Err(Error::CiphertextModulusMismatch);
+    }
+    if !input.ciphertext_modulus().is_native_modulus() {
+        return Err(Error::NonNativeModulus);
+    }
+
+    let glwe_size = output.glwe_size();
+    let polynomial_size = output.polynomial_size();
+    check_dimension(
+        "LWE dimension",
+        glwe_size.to_glwe_dimension().0 * polynomial_size.0,
+        input.lwe_size().to_lwe_dimension().0,
+    )?;
+
+    // Packing uses the keys X -> X^(2^j + 1) for 2^j up to the LWE count and
+    // the partial trace the remaining ones, that is the keys of a full trace
+    let lwe_count = input.lwe_ciphertext_count().0;
+    if !lwe_count.is_power_of_two() || lwe_count > polynomial_size.0 {
+        return Err(Error::IncompatibleParameters(format!(
+            "cannot pack {} LWE ciphertexts into a GLWE ciphertext of polynomial size {}",
+            lwe_count, polynomial_size.0,
+        )));
+    }
+    check_trace_auto_keys(auto_keys, glwe_size, polynomial_size, 1)?;
+
+    convert_lwes_to_glwe_by_trace_with_preprocessing(input, output, auto_keys);
+    Ok(())
+}
+
+
 fn pack_lwes<Scalar, Cont>(
     input: &GlweCiphertextList<Cont>,
     auto_keys: &HashMap<usize, AutomorphKey<ABox<[c64]>>>,
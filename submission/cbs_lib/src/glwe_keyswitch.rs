@@ -217,6 +217,109 @@ pub fn generate_glwe_keyswitch_key<Scalar, InputKeyCont, OutputKeyCont, KSKeyCon
     }
 }
 
+/// A [`GlweKeyswitchKey`] whose masks are replaced by the seed they are drawn
+/// from. The GLWEs of all GLEVs are stored, in the same order, as one
+/// [`SeededGlweCiphertextList`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SeededGlweKeyswitchKey<Scalar: UnsignedInteger> {
+    glwe_list: SeededGlweCiphertextListOwned<Scalar>,
+    input_glwe_dimension: GlweDimension,
+    decomp_base_log: DecompositionBaseLog,
+    decomp_level_count: DecompositionLevelCount,
+}
+
+impl<Scalar: UnsignedTorus> SeededGlweKeyswitchKey<Scalar> {
+    pub fn input_glwe_dimension(&self) -> GlweDimension {
+        self.input_glwe_dimension
+    }
+
+    pub fn output_glwe_dimension(&self) -> GlweDimension {
+        self.glwe_list.glwe_size().to_glwe_dimension()
+    }
+
+    pub fn polynomial_size(&self) -> PolynomialSize {
+        self.glwe_list.polynomial_size()
+    }
+
+    pub fn decomp_base_log(&self) -> DecompositionBaseLog {
+        self.decomp_base_log
+    }
+
+    pub fn decomp_level_count(&self) -> DecompositionLevelCount {
+        self.decomp_level_count
+    }
+
+    pub fn decompress_into_glwe_keyswitch_key(self) -> GlweKeyswitchKeyOwned<Scalar> {
+        let output_glwe_dimension = self.output_glwe_dimension();
+        let polynomial_size = self.polynomial_size();
+        let ciphertext_modulus = self.glwe_list.ciphertext_modulus();
+        let glwe_list = self.glwe_list.decompress_into_glwe_ciphertext_list();
+
+        GlweKeyswitchKey::from_container(
+            glwe_list.into_container(),
+            self.input_glwe_dimension,
+            output_glwe_dimension,
+            polynomial_size,
+            self.decomp_base_log,
+            self.decomp_level_count,
+            ciphertext_modulus,
+        )
+    }
+}
+
+/// Seeded variant of [`allocate_and_generate_new_glwe_keyswitch_key`].
+pub fn allocate_and_generate_new_seeded_glwe_keyswitch_key<Scalar, InputKeyCont, OutputKeyCont, NoiseSeeder>(
+    input_glwe_sk: &GlweSecretKey<InputKeyCont>,
+    output_glwe_sk: &GlweSecretKey<OutputKeyCont>,
+    decomp_base_log: DecompositionBaseLog,
+    decomp_level_count: DecompositionLevelCount,
+    noise_parameters: impl DispersionParameter,
+    ciphertext_modulus: CiphertextModulus::<Scalar>,
+    noise_seeder: &mut NoiseSeeder,
+) -> SeededGlweKeyswitchKey<Scalar>
+where
+    Scalar: UnsignedTorus,
+    InputKeyCont: Container<Element=Scalar>,
+    OutputKeyCont: Container<Element=Scalar>,
+    NoiseSeeder: Seeder + ?Sized,
+{
+    assert_eq!(input_glwe_sk.polynomial_size(), output_glwe_sk.polynomial_size());
+
+    let polynomial_size = input_glwe_sk.polynomial_size();
+    let input_glwe_dimension = input_glwe_sk.glwe_dimension();
+    let glev_size = decomp_level_count.0 * polynomial_size.0;
+
+    // Same plaintexts as encrypt_glev_ciphertext: level j of GLEV i encrypts -S_i scaled by B^-j
+    let mut encoded = PlaintextList::new(Scalar::ZERO, PlaintextCount(input_glwe_dimension.0 * glev_size));
+    for (input_sk_poly, glev_pt) in input_glwe_sk.as_polynomial_list().iter()
+        .zip(encoded.as_mut().chunks_exact_mut(glev_size))
+    {
+        for (k, glwe_pt) in glev_pt.chunks_exact_mut(polynomial_size.0).enumerate() {
+            let log_scale = Scalar::BITS - (k + 1) * decomp_base_log.0;
+            for (pt, sk) in glwe_pt.iter_mut().zip(input_sk_poly.iter()) {
+                *pt = sk.wrapping_neg() << log_scale;
+            }
+        }
+    }
+
+    let mut glwe_list = SeededGlweCiphertextList::new(
+        Scalar::ZERO,
+        output_glwe_sk.glwe_dimension().to_glwe_size(),
+        polynomial_size,
+        GlweCiphertextCount(input_glwe_dimension.0 * decomp_level_count.0),
+        noise_seeder.seed().into(),
+        ciphertext_modulus,
+    );
+    encrypt_seeded_glwe_ciphertext_list(output_glwe_sk, &mut glwe_list, &encoded, noise_parameters, noise_seeder);
+
+    SeededGlweKeyswitchKey {
+        glwe_list,
+        input_glwe_dimension,
+        decomp_base_log,
+        decomp_level_count,
+    }
+}
+
 pub fn standard_keyswitch_glwe_ciphertext<Scalar, KSKeyCont, InputCont, OutputCont>(
     glwe_keyswitch_key: &GlweKeyswitchKey<KSKeyCont>,
     input_glwe_ciphertext: &GlweCiphertext<InputCont>,
@@ -301,3 +404,58 @@ pub fn standard_keyswitch_glwe_ciphertext<Scalar, KSKeyCont, InputCont, OutputCo
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aes_instances::AES_TIGHT;
+    use tfhe::core_crypto::seeders::new_seeder;
+
+    /// Encodes 4-bit messages in the top bits of each coefficient.
+    const MESSAGE_LOG: usize = 4;
+
+    fn decode(plaintext: u64) -> u64 {
+        let rounding = 1 << (u64::BITS as usize - MESSAGE_LOG - 1);
+        plaintext.wrapping_add(rounding) >> (u64::BITS as usize - MESSAGE_LOG)
+    }
+
+    #[test]
+    fn decompressed_seeded_keyswitch_key_keyswitches() {
+        let param = &*AES_TIGHT;
+        let polynomial_size = param.polynomial_size();
+        let ciphertext_modulus = param.ciphertext_modulus();
+        let mut boxed_seeder = new_seeder();
+        let seeder = boxed_seeder.as_mut();
+        let mut secret_generator = SecretRandomGenerator::<ActivatedRandomGenerator>::new(seeder.seed());
+        let mut encryption_generator =
+            EncryptionRandomGenerator::<ActivatedRandomGenerator>::new(seeder.seed(), seeder);
+
+        let input_glwe_sk = GlweSecretKey::generate_new_binary(param.glwe_dimension(), polynomial_size, &mut secret_generator);
+        let output_glwe_sk = GlweSecretKey::generate_new_binary(param.glwe_dimension(), polynomial_size, &mut secret_generator);
+        let seeded_ksk = allocate_and_generate_new_seeded_glwe_keyswitch_key(
+            &input_glwe_sk,
+            &output_glwe_sk,
+            param.auto_base_log(),
+            param.auto_level(),
+            param.glwe_modular_std_dev(),
+            ciphertext_modulus,
+            seeder,
+        );
+        let ksk = seeded_ksk.decompress_into_glwe_keyswitch_key();
+
+        let messages: Vec<u64> = (0..polynomial_size.0).map(|_| rand::random::<u64>() % (1 << MESSAGE_LOG)).collect();
+        let plaintexts = PlaintextList::from_container(
+            messages.iter().map(|m| m << (u64::BITS as usize - MESSAGE_LOG)).collect::<Vec<_>>()
+        );
+        let mut input = GlweCiphertext::new(0u64, param.glwe_dimension().to_glwe_size(), polynomial_size, ciphertext_modulus);
+        encrypt_glwe_ciphertext(&input_glwe_sk, &mut input, &plaintexts, param.glwe_modular_std_dev(), &mut encryption_generator);
+
+        let mut output = GlweCiphertext::new(0u64, param.glwe_dimension().to_glwe_size(), polynomial_size, ciphertext_modulus);
+        standard_keyswitch_glwe_ciphertext(&ksk, &input, &mut output);
+
+        let mut decrypted = PlaintextList::new(0u64, PlaintextCount(polynomial_size.0));
+        decrypt_glwe_ciphertext(&output_glwe_sk, &output, &mut decrypted);
+        let decoded: Vec<u64> = decrypted.as_ref().iter().map(|&pt| decode(pt)).collect();
+        assert_eq!(decoded, messages);
+    }
+}
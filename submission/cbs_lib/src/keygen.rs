@@ -1,6 +1,6 @@
 use tfhe::core_crypto::prelude::*;
 
-use crate::{FftType, FourierGlweKeyswitchKey, FourierGlweKeyswitchKeyOwned, GlweKeyswitchKeyOwned, allocate_and_generate_new_glwe_keyswitch_key, convert_standard_glwe_keyswitch_key_to_fourier};
+use crate::{FftType, FourierGlweKeyswitchKey, FourierGlweKeyswitchKeyOwned, SeededGlweKeyswitchKey, GlweKeyswitchKeyOwned, allocate_and_generate_new_glwe_keyswitch_key, allocate_and_generate_new_seeded_glwe_keyswitch_key, convert_standard_glwe_keyswitch_key_to_fourier};
 
 pub fn keygen_pbs<Scalar: UnsignedTorus, G: ByteRandomGenerator>(
     lwe_dimension: LweDimension,
@@ -240,4 +240,59 @@ pub fn keygen_pbs_with_glwe_ks<Scalar: UnsignedTorus, G: ByteRandomGenerator>(
     );
 
     (lwe_secret_key, glwe_secret_key, lwe_secret_key_after_ks, bootstrap_key, glwe_ksk)
-}
\ No newline at end of file
+}
+
+/// Seeded variant of [`keygen_pbs_with_glwe_ks`]: the bootstrapping key and
+/// the GLWE keyswitching key only keep their bodies and a compression seed.
+pub fn keygen_seeded_pbs_with_glwe_ks<Scalar: UnsignedTorus, G: ByteRandomGenerator, NoiseSeeder: Seeder + ?Sized>(
+    lwe_dimension: LweDimension,
+    glwe_dimension: GlweDimension,
+    polynomial_size: PolynomialSize,
+    lwe_modular_std_dev: impl DispersionParameter,
+    glwe_modular_std_dev: impl DispersionParameter,
+    pbs_base_log: DecompositionBaseLog,
+    pbs_level: DecompositionLevelCount,
+    glwe_ds_base_log: DecompositionBaseLog,
+    glwe_ds_level: DecompositionLevelCount,
+    common_polynomial_size: PolynomialSize,
+    ciphertext_modulus: CiphertextModulus::<Scalar>,
+    secret_generator: &mut SecretRandomGenerator<G>,
+    noise_seeder: &mut NoiseSeeder,
+) -> (
+    LweSecretKey<Vec<Scalar>>,
+    GlweSecretKey<Vec<Scalar>>,
+    LweSecretKey<Vec<Scalar>>,
+    SeededLweBootstrapKeyOwned<Scalar>,
+    SeededGlweKeyswitchKey<Scalar>,
+) {
+    assert_eq!(lwe_dimension.0 % common_polynomial_size.0, 0);
+    assert_eq!((glwe_dimension.0 * polynomial_size.0) % common_polynomial_size.0, 0);
+
+    let lwe_secret_key_after_ks: LweSecretKey<Vec<Scalar>> = LweSecretKey::generate_new_binary(lwe_dimension, secret_generator);
+    let glwe_secret_key: GlweSecretKey<Vec<Scalar>> = GlweSecretKey::generate_new_binary(glwe_dimension, polynomial_size, secret_generator);
+    let lwe_secret_key: LweSecretKey<Vec<Scalar>> = glwe_secret_key.clone().into_lwe_secret_key();
+
+    let bootstrap_key = allocate_and_generate_new_seeded_lwe_bootstrap_key(
+        &lwe_secret_key_after_ks,
+        &glwe_secret_key,
+        pbs_base_log,
+        pbs_level,
+        glwe_modular_std_dev,
+        ciphertext_modulus,
+        noise_seeder,
+    );
+
+    let lwe_secret_key_view = GlweSecretKey::from_container(lwe_secret_key.as_ref(), common_polynomial_size);
+    let lwe_secret_key_after_ks_view = GlweSecretKey::from_container(lwe_secret_key_after_ks.as_ref(), common_polynomial_size);
+    let glwe_ksk = allocate_and_generate_new_seeded_glwe_keyswitch_key(
+        &lwe_secret_key_view,
+        &lwe_secret_key_after_ks_view,
+        glwe_ds_base_log,
+        glwe_ds_level,
+        lwe_modular_std_dev,
+        ciphertext_modulus,
+        noise_seeder,
+    );
+
+    (lwe_secret_key, glwe_secret_key, lwe_secret_key_after_ks, bootstrap_key, glwe_ksk)
+}
@@ -1,4 +1,5 @@
 pub mod utils;
+pub mod error;
 pub mod fourier_poly_mult;
 pub mod mod_switch;
 pub mod keygen;
@@ -21,8 +22,10 @@ pub mod wopbs_params;
 pub mod wopbs_instance;
 pub mod aes_params;
 pub mod aes_instances;
+pub mod aes_noise;
 
 pub use utils::*;
+pub use error::*;
 pub use fourier_poly_mult::*;
 pub use mod_switch::*;
 pub use keygen::*;
@@ -43,3 +46,4 @@ pub use wwlp_cbs_params::*;
 pub use wwlp_cbs_instance::*;
 pub use aes_params::*;
 pub use aes_instances::*;
+pub use aes_noise::*;
@@ -472,9 +472,10 @@ where
 }
 
 /// Evaluate f(x) on x^k, where k is odd
-pub(crate) fn eval_x_k_in_memory<Scalar>(out: &mut PolynomialOwned<Scalar>, poly: PolynomialView<'_, Scalar>, k: usize)
+pub(crate) fn eval_x_k_in_memory<Scalar, OutputCont>(out: &mut Polynomial<OutputCont>, poly: PolynomialView<'_, Scalar>, k: usize)
 where
     Scalar: UnsignedTorus,
+    OutputCont: ContainerMut<Element=Scalar>,
 {
     assert_eq!(k % 2, 1);
     assert!(poly.polynomial_size().0.is_power_of_two());
@@ -1,5 +1,8 @@
+use auto_base_conv::{expand_aes_key, AesKeySize};
 use serde::{Deserialize, Serialize};
 
+use crate::help_fun::get_ctr_blocks;
+
 pub const AES128_SBOX: [u8; 256] = [
     0x63, 0x7C, 0x77, 0x7B, 0xF2, 0x6B, 0x6F, 0xC5, 0x30, 0x01, 0x67, 0x2B, 0xFE, 0xD7, 0xAB, 0x76,
     0xCA, 0x82, 0xC9, 0x7D, 0xFA, 0x59, 0x47, 0xF0, 0xAD, 0xD4, 0xA2, 0xAF, 0x9C, 0xA4, 0x72, 0xC0,
@@ -113,24 +116,19 @@ pub const AES128_INV_SBOX: [u8; 256] = [
 //     0xaf, 0x68, 0x24, 0xa3, 0x1d, 0xe2, 0x3c, 0x0d, 0xa8, 0x0c, 0xb4, 0x56, 0xcb, 0x32, 0x6c, 0xb8,
 // ];
 
-pub const RCON: [u8; 11] = [
-    0x00, 0x01, 0x02, 0x04, 0x08, 0x10, 0x20, 0x40, 0x80, 0x1B, 0x36,
-];
-
 pub const BYTESIZE: usize = 8;
 pub const BLOCKSIZE_IN_BYTE: usize = 16;
 pub const BLOCKSIZE_IN_BIT: usize = 128;
 pub const NUM_COLUMNS: usize = 4;
 pub const NUM_ROWS: usize = 4;
-pub const NUM_ROUNDS: usize = 10;
 
 pub type StateByteArray = [u8; BLOCKSIZE_IN_BYTE];
 pub type StateByteMat = [[u8; NUM_COLUMNS]; NUM_ROWS];
 pub type StateBitArray = [u8; BLOCKSIZE_IN_BIT];
 
-pub struct Aes128Manager {
-    m_key: StateByteArray,
-    rk_mat: [StateByteMat; NUM_ROUNDS + 1],
+pub struct AesManager {
+    key_size: AesKeySize,
+    rk_mat: Vec<StateByteMat>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -143,72 +141,36 @@ pub struct AesCipher {
     pub(crate) data: [u8; BLOCKSIZE_IN_BYTE],
 }
 
-impl Aes128Manager {
-    pub fn new(master_key: &StateByteArray) -> Aes128Manager {
-        let mut key = [0u8; BLOCKSIZE_IN_BYTE];
-        for i in 0..BLOCKSIZE_IN_BYTE {
-            key[i] = master_key[i];
-        }
-        let rk_mat = [[[0u8; NUM_COLUMNS]; NUM_ROWS]; NUM_ROUNDS + 1];
-
-        let mut aes = Aes128Manager {
-            m_key: key,
-            rk_mat: rk_mat,
-        };
-        aes.expand_key();
-
-        aes
+impl AesManager {
+    /// Expands an AES-128, AES-192 or AES-256 key, chosen by its length.
+    pub fn new(master_key: &[u8]) -> Result<AesManager, Box<dyn std::error::Error>> {
+        let key_size = AesKeySize::from_key_len_in_byte(master_key.len()).ok_or_else(|| {
+            format!("AES key must be 16, 24 or 32 bytes long, got {}", master_key.len())
+        })?;
+
+        Ok(AesManager {
+            key_size,
+            rk_mat: expand_aes_key(master_key, key_size),
+        })
     }
 
-    fn expand_key(&mut self) {
-        for col in 0..NUM_COLUMNS {
-            for row in 0..NUM_ROWS {
-                self.rk_mat[0][col][row] = self.m_key[4 * col + row];
-            }
-        }
-
-        for r in 1..=NUM_ROUNDS {
-            let cur_key = self.rk_mat[r - 1];
-            self.rk_mat[r] = cur_key;
-
-            let sub_rot_key = [
-                AES128_SBOX[cur_key[3][1] as usize],
-                AES128_SBOX[cur_key[3][2] as usize],
-                AES128_SBOX[cur_key[3][3] as usize],
-                AES128_SBOX[cur_key[3][0] as usize],
-            ];
-
-            for row in 0..NUM_ROWS {
-                self.rk_mat[r][0][row] ^= sub_rot_key[row];
-            }
-            self.rk_mat[r][0][0] ^= RCON[r];
-
-            for col in 1..NUM_COLUMNS {
-                for row in 0..NUM_ROWS {
-                    self.rk_mat[r][col][row] ^= self.rk_mat[r][col - 1][row];
-                }
-            }
-        }
+    pub fn key_size(&self) -> AesKeySize {
+        self.key_size
     }
 
-    pub fn get_round_keys(&self) -> [StateByteArray; NUM_ROUNDS + 1] {
-        let mut round_keys = [[0u8; BLOCKSIZE_IN_BYTE]; NUM_ROUNDS + 1];
-        for r in 0..=NUM_ROUNDS {
-            for col in 0..NUM_COLUMNS {
-                for row in 0..NUM_ROWS {
-                    round_keys[r][4 * col + row] = self.rk_mat[r][col][row]
-                }
-            }
-        }
+    pub fn num_rounds(&self) -> usize {
+        self.key_size.num_rounds()
+    }
 
-        round_keys
+    pub fn get_round_keys(&self) -> Vec<StateByteArray> {
+        self.rk_mat.iter().map(|rk| byte_mat_to_array(*rk)).collect()
     }
 
     pub fn encrypt_block(&self, message: StateByteArray) -> StateByteArray {
         let mut state = byte_array_to_mat(message);
         self.add_round_key(&mut state, 0);
 
-        for r in 1..NUM_ROUNDS {
+        for r in 1..self.num_rounds() {
             self.sub_bytes(&mut state);
             self.shift_rows(&mut state);
             self.mix_columns(&mut state);
@@ -217,15 +179,25 @@ impl Aes128Manager {
 
         self.sub_bytes(&mut state);
         self.shift_rows(&mut state);
-        self.add_round_key(&mut state, NUM_ROUNDS);
+        self.add_round_key(&mut state, self.num_rounds());
 
         byte_mat_to_array(state)
     }
 
+    /// First `len` bytes of the CTR keystream from `iv`: the encryptions of
+    /// the counter blocks of [`get_ctr_blocks`], one after the other.
+    pub fn ctr_keystream(&self, iv: &StateByteArray, len: usize) -> Vec<u8> {
+        get_ctr_blocks(iv, len.div_ceil(BLOCKSIZE_IN_BYTE))
+            .into_iter()
+            .flat_map(|counter| self.encrypt_block(counter))
+            .take(len)
+            .collect()
+    }
+
     pub fn decrypt_block(&self, ciphertext: StateByteArray) -> StateByteArray {
         let mut state = byte_array_to_mat(ciphertext);
-        self.add_round_key(&mut state, NUM_ROUNDS);
-        for r in (1..NUM_ROUNDS).rev() {
+        self.add_round_key(&mut state, self.num_rounds());
+        for r in (1..self.num_rounds()).rev() {
             self.inv_shift_rows(&mut state);
             self.inv_sub_bytes(&mut state);
             self.add_round_key(&mut state, r);
@@ -237,6 +209,7 @@ impl Aes128Manager {
         byte_mat_to_array(state)
     }
 
+    #[allow(clippy::needless_range_loop)]
     pub fn add_round_key(&self, state: &mut StateByteMat, round: usize) {
         for col in 0..NUM_COLUMNS {
             for row in 0..NUM_ROWS {
@@ -263,7 +236,7 @@ impl Aes128Manager {
     }
 
     pub fn shift_rows(&self, state: &mut StateByteMat) {
-        let buf = state.clone();
+        let buf = *state;
         for row in 0..NUM_ROWS {
             for col in 0..NUM_COLUMNS {
                 state[col][row] = buf[(row + col) % NUM_COLUMNS][row];
@@ -272,7 +245,7 @@ impl Aes128Manager {
     }
 
     pub fn inv_shift_rows(&self, state: &mut StateByteMat) {
-        let buf = state.clone();
+        let buf = *state;
         for row in 0..NUM_ROWS {
             for col in 0..NUM_COLUMNS {
                 state[col][row] = buf[(NUM_COLUMNS + col - row) % NUM_COLUMNS][row];
@@ -281,7 +254,7 @@ impl Aes128Manager {
     }
 
     pub fn mix_columns(&self, state: &mut StateByteMat) {
-        let buf = state.clone();
+        let buf = *state;
         for col in 0..NUM_COLUMNS {
             for row in 0..NUM_ROWS {
                 state[col][row] = mult_by_two(buf[col][row] ^ buf[col][(row + 1) % NUM_ROWS]);
@@ -293,7 +266,7 @@ impl Aes128Manager {
     }
 
     pub fn inv_mix_columns(&self, state: &mut StateByteMat) {
-        let buf = state.clone();
+        let buf = *state;
         for col in 0..NUM_COLUMNS {
             for row in 0..NUM_ROWS {
                 state[col][row] = mult_a_b(buf[col][row], 0x0E);
@@ -334,8 +307,10 @@ impl Aes128Manager {
         keyed_sbox
     }
 
-    // Some problem with
-    pub fn get_10_9_round_lut(
+    /// Keyed LUTs of the first two inverse rounds (rounds Nr and Nr - 1),
+    /// which act on the public ciphertext bytes.
+    #[allow(clippy::needless_range_loop, clippy::type_complexity)]
+    pub fn get_last_two_round_lut(
         &self,
     ) -> (
         [[u8; 256]; 16],
@@ -344,8 +319,8 @@ impl Aes128Manager {
         [[u8; 256]; 16],
     ) {
         let mut keyed_input = [[0u8; 256]; 16];
-        let round_key = self.get_round_keys()[NUM_ROUNDS];
-        // add round 10 key
+        let round_key = self.get_round_keys()[self.num_rounds()];
+        // add last round key
         for (byte_idx, rk) in round_key.iter().enumerate() {
             for x in 0..256 {
                 let x = x as u8;
@@ -376,15 +351,15 @@ impl Aes128Manager {
                 shifted[4 * col + row] = buf[col][row];
             }
         }
-        // //inv sbox and add round 9 keys
+        // //inv sbox and add round Nr - 1 keys
         let mut keyed_sbox = [[0u8; 256]; 16];
-        let round_key = self.get_round_keys()[9];
+        let round_key = self.get_round_keys()[self.num_rounds() - 1];
 
         for (byte_idx, rk) in round_key.iter().enumerate() {
             for i in 0..256 {
                 let x = shifted[byte_idx][i];
                 let mid = AES128_INV_SBOX[x as usize]; //mid value afterinv sbox
-                keyed_sbox[byte_idx][i as usize] = mid ^ rk; // add round key
+                keyed_sbox[byte_idx][i] = mid ^ rk; // add round key
             }
         }
         // // multiples
@@ -395,6 +370,7 @@ impl Aes128Manager {
         (times_14, times_11, times_13, times_9)
     }
 
+    #[allow(clippy::type_complexity)]
     pub fn get_round_lut(
         &self,
         round: usize,
@@ -419,6 +395,36 @@ impl Aes128Manager {
         (times_14, times_11, times_13, times_9)
     }
 
+    /// Forward keyed S-box of round `round` (1 to Nr - 1) together with its
+    /// multiples by 2 and 3, i.e. `SBOX[x ^ rk[round - 1]]` for each byte.
+    #[allow(clippy::type_complexity)]
+    pub fn get_fwd_round_lut(
+        &self,
+        round: usize,
+    ) -> ([[u8; 256]; 16], [[u8; 256]; 16], [[u8; 256]; 16]) {
+        let keyed_sbox = self.get_keyed_sbox(round - 1);
+        let times_2 = get_lut_multiples(&keyed_sbox, 2);
+        let times_3 = get_lut_multiples(&keyed_sbox, 3);
+        (keyed_sbox, times_2, times_3)
+    }
+
+    /// Forward keyed S-box of the last round. The last round key is
+    /// pre-rotated by inv shift rows so it lands on the right byte once the
+    /// server applies shift rows to the S-box output.
+    pub fn get_fwd_last_round_lut(&self) -> [[u8; 256]; 16] {
+        let mut keyed_sbox = self.get_keyed_sbox(self.num_rounds() - 1);
+        let mut rk_last = self.rk_mat[self.num_rounds()];
+        self.inv_shift_rows(&mut rk_last);
+        let rk_last = byte_mat_to_array(rk_last);
+
+        for (lut, rk) in keyed_sbox.iter_mut().zip(rk_last.iter()) {
+            for out in lut.iter_mut() {
+                *out ^= rk;
+            }
+        }
+        keyed_sbox
+    }
+
     pub fn get_0_round_lut(&self) -> [[u8; 256]; 16] {
         let round_key = self.get_round_keys()[0];
         let mut keyed_sbox = [[0u8; 256]; 16];
@@ -489,18 +495,108 @@ pub fn mult_a_b(a: u8, b: u8) -> u8 {
             result ^= a;
         }
         if a & 0x80 != 0 {
-            a = ((a << 1) & 0xFF) ^ 0x1B
+            a = (a << 1) ^ 0x1B
         } else {
             a <<= 1
         }
         b >>= 1;
     }
-    return result;
+    result
 }
 pub fn mult_by_two(a: u8) -> u8 {
     if a & 0x80 != 0 {
-        ((a << 1) & 0xFF) ^ 0x1B
+        (a << 1) ^ 0x1B
     } else {
         a << 1
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aes::cipher::{generic_array::GenericArray, BlockEncrypt, KeyInit};
+
+    fn aes_crate_encrypt<C: KeyInit + BlockEncrypt>(master_key: &[u8], block: StateByteArray) -> StateByteArray {
+        let cipher = C::new_from_slice(master_key).unwrap();
+        let mut block = GenericArray::clone_from_slice(&block);
+        cipher.encrypt_block(&mut block);
+
+        let mut output = [0u8; BLOCKSIZE_IN_BYTE];
+        output.copy_from_slice(&block);
+        output
+    }
+
+    fn master_keys() -> Vec<Vec<u8>> {
+        [16, 24, 32]
+            .into_iter()
+            .map(|key_len| (0..key_len).map(|i| (7 * i + 3) as u8).collect())
+            .collect()
+    }
+
+    fn aes_crate_encrypt_block(manager: &AesManager, master_key: &[u8], block: StateByteArray) -> StateByteArray {
+        match manager.key_size() {
+            AesKeySize::Aes128 => aes_crate_encrypt::<aes::Aes128>(master_key, block),
+            AesKeySize::Aes192 => aes_crate_encrypt::<aes::Aes192>(master_key, block),
+            AesKeySize::Aes256 => aes_crate_encrypt::<aes::Aes256>(master_key, block),
+        }
+    }
+
+    /// Every keyed LUT is derived from the key schedule and the reference
+    /// cipher, so both directions must agree with the `aes` crate.
+    #[test]
+    fn reference_cipher_matches_aes_crate() {
+        let blocks: [StateByteArray; 3] = [
+            [0u8; BLOCKSIZE_IN_BYTE],
+            [0xffu8; BLOCKSIZE_IN_BYTE],
+            std::array::from_fn(|i| (17 * i + 1) as u8),
+        ];
+        for master_key in master_keys() {
+            let manager = AesManager::new(&master_key).unwrap();
+            for block in blocks {
+                let ciphertext = manager.encrypt_block(block);
+                assert_eq!(ciphertext, aes_crate_encrypt_block(&manager, &master_key, block), "{:?}", manager.key_size());
+                assert_eq!(manager.decrypt_block(ciphertext), block, "{:?}", manager.key_size());
+            }
+        }
+    }
+
+    /// Big-endian increment, written out byte by byte.
+    fn increment_counter(counter: &mut StateByteArray) {
+        for byte in counter.iter_mut().rev() {
+            *byte = byte.wrapping_add(1);
+            if *byte != 0 {
+                break;
+            }
+        }
+    }
+
+    #[test]
+    fn ctr_keystream_matches_aes_crate() {
+        // The low 32 bits wrap after two blocks, so the counter carries into byte 11
+        let mut iv = [0u8; BLOCKSIZE_IN_BYTE];
+        iv[..4].copy_from_slice(&[0xf0, 0xf1, 0xf2, 0xf3]);
+        iv[12..].copy_from_slice(&[0xff, 0xff, 0xff, 0xfe]);
+        let num_blocks = 4;
+
+        let mut expected_counters = Vec::with_capacity(num_blocks);
+        let mut counter = iv;
+        for _ in 0..num_blocks {
+            expected_counters.push(counter);
+            increment_counter(&mut counter);
+        }
+        assert_eq!(get_ctr_blocks(&iv, num_blocks), expected_counters);
+
+        for master_key in master_keys() {
+            let manager = AesManager::new(&master_key).unwrap();
+            let expected: Vec<u8> = expected_counters
+                .iter()
+                .flat_map(|&counter| aes_crate_encrypt_block(&manager, &master_key, counter))
+                .collect();
+
+            // The last block is cut short, as for a message that is not a whole number of blocks
+            let len = (num_blocks - 1) * BLOCKSIZE_IN_BYTE + 5;
+            assert_eq!(manager.ctr_keystream(&iv, len), expected[..len], "{:?}", manager.key_size());
+            assert_eq!(manager.ctr_keystream(&iv, num_blocks * BLOCKSIZE_IN_BYTE), expected, "{:?}", manager.key_size());
+        }
+    }
+}
@@ -0,0 +1,15 @@
+use auto_base_conv::{aes_param_by_name, estimate_aes_noise, AesKeySize, AES_PARAM_NAMES};
+
+/// Prints the predicted noise of every registered parameter set, stage by
+/// stage, with the failure probability of an AES-128 decryption.
+pub fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let num_rounds = AesKeySize::Aes128.num_rounds();
+    for name in AES_PARAM_NAMES {
+        let param = aes_param_by_name(name).ok_or_else(|| format!("{} is not registered", name))?;
+        println!("[{}]", name);
+        println!("{}", estimate_aes_noise(param, num_rounds));
+        println!();
+    }
+
+    Ok(())
+}
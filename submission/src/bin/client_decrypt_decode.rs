@@ -1,14 +1,24 @@
 use std::env;
 use std::fs;
+use std::path::Path;
 
+use submission::aes_manager::BYTESIZE;
+use submission::data_struct::ReencryptedResult;
+use submission::compression::CompressedLweList;
+use submission::envelope::{PayloadType, read_artifact, write_artifact};
 use submission::help_fun::decrypt_decode_lwe_list;
-use submission::help_fun::get_size_string;
-use tfhe::core_crypto::prelude::{Container, ContiguousEntityContainer, LweCiphertext, LweCiphertextList, LweSecretKey, UnsignedInteger, decrypt_lwe_ciphertext};
+use submission::help_fun::{get_size_string, select_aes_param, ResultDownload};
+use submission::packing::PackedLweList;
+use tfhe::core_crypto::prelude::{GlweSecretKey, LweCiphertextList, LweSecretKey};
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
 
 pub fn main() -> Result<(), Box<dyn std::error::Error>> {
     let args: Vec<String> = env::args().collect();
     if args.len() < 2 {
-        eprintln!("Usage: {} <size>", args[0]);
+        eprintln!("Usage: {} <size> [--pack_glwe] [--compress] [--aes_reencrypt] [--param <name>]", args[0]);
         std::process::exit(1); 
     }
        let size = args[1].clone();
@@ -17,27 +27,68 @@ pub fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Load secret key
     let secret_keys_dir = format!("{}/secret_keys", io_dir);
     let lwe_sk_path = format!("{}/lwe_sk.bin", secret_keys_dir);
-    let lwe_sk_bytes = fs::read(&lwe_sk_path)?;
-    let lwe_sk: LweSecretKey<Vec<u64>> = bincode::deserialize(&lwe_sk_bytes)?;
+    let param = select_aes_param(&args[2..])?;
+    let (lwe_sk, key_set_id): (LweSecretKey<Vec<u64>>, _) =
+        read_artifact(&lwe_sk_path, PayloadType::LweSecretKey, param, None)?;
 
-    // Load encrypted result from ciphertexts_download
     let ciphertexts_download_dir = format!("{}/ciphertexts_download", io_dir);
-    let result_path = format!("{}/result.bin", ciphertexts_download_dir);
-    let result_bytes = fs::read(&result_path)?;
-    let lwe_ciphertext_list: LweCiphertextList<Vec<u64>> = bincode::deserialize(&result_bytes)?;
 
-    // Decrypt and decode
-    let decrypted_result = decrypt_decode_lwe_list(&lwe_sk, &lwe_ciphertext_list);
+    // With --aes_reencrypt the result comes encrypted under the AES key, and
+    // removing the FHE layer yields a plain AES ciphertext
+    if args[2..].iter().any(|arg| arg == "--aes_reencrypt") {
+        let result_path = format!("{}/result_aes.bin", ciphertexts_download_dir);
+        let (reencrypted, _): (ReencryptedResult, _) =
+            read_artifact(&result_path, PayloadType::ReencryptedResult, param, Some(key_set_id))?;
+
+        let bits = decrypt_decode_lwe_list(&lwe_sk, &reencrypted.ciphertext);
+        let bytes: Vec<u8> = bits
+            .chunks(BYTESIZE)
+            .map(|byte_bits| byte_bits.iter().fold(0u8, |byte, &bit| (byte << 1) | (bit as u8 & 1)))
+            .collect();
+        fs::write(format!("{}/result_aes_ciphertext.hex", io_dir), to_hex(&bytes) + "\n")?;
+
+        let iv_path = format!("{}/result_aes_iv.hex", io_dir);
+        match reencrypted.iv {
+            Some(iv) => fs::write(&iv_path, to_hex(&iv) + "\n")?,
+            None if Path::new(&iv_path).exists() => fs::remove_file(&iv_path)?,
+            None => {}
+        }
+        return Ok(());
+    }
+
+    // Load encrypted result from ciphertexts_download, packed into GLWE
+    // ciphertexts with --pack_glwe or modulus switched with --compress
+    let decrypted_result = match ResultDownload::from_args(&args[2..])? {
+        ResultDownload::PackedGlwe => {
+            let glwe_sk_path = format!("{}/glwe_sk.bin", secret_keys_dir);
+            let (glwe_sk, _): (GlweSecretKey<Vec<u64>>, _) =
+                read_artifact(&glwe_sk_path, PayloadType::GlweSecretKey, param, Some(key_set_id))?;
+            let result_path = format!("{}/result_packed.bin", ciphertexts_download_dir);
+            let (packed, _): (PackedLweList, _) =
+                read_artifact(&result_path, PayloadType::PackedComputeResult, param, Some(key_set_id))?;
+            packed.decrypt_decode(&glwe_sk)?
+        }
+        ResultDownload::Compressed => {
+            let result_path = format!("{}/result_compressed.bin", ciphertexts_download_dir);
+            let (compressed, _): (CompressedLweList, _) =
+                read_artifact(&result_path, PayloadType::CompressedComputeResult, param, Some(key_set_id))?;
+            decrypt_decode_lwe_list(&lwe_sk, &compressed.decompress()?)
+        }
+        ResultDownload::Lwe => {
+            let result_path = format!("{}/result.bin", ciphertexts_download_dir);
+            let (lwe_ciphertext_list, _): (LweCiphertextList<Vec<u64>>, _) =
+                read_artifact(&result_path, PayloadType::ComputeResult, param, Some(key_set_id))?;
+            decrypt_decode_lwe_list(&lwe_sk, &lwe_ciphertext_list)
+        }
+    };
     // intermediate output
     let intermediate_output_path = format!("{}/intermediate", io_dir);
     fs::create_dir_all(&intermediate_output_path)?;
     let output_path = format!("{}/decoded_result.txt", intermediate_output_path);
-    
-    let bytes = bincode::serialize(&decrypted_result)?;
-    fs::write(&output_path, bytes)?;
+    write_artifact(&output_path, PayloadType::DecodedResult, param, key_set_id, &decrypted_result)?;
 
 
 
 
     Ok(())
-}
\ No newline at end of file
+}
@@ -1,14 +1,17 @@
 use std::env;
 use std::fs;
 
+use submission::compression::CompressedLweList;
+use submission::envelope::{PayloadType, read_artifact, write_artifact};
 use submission::help_fun::decrypt_decode_lwe_list;
-use submission::help_fun::get_size_string;
-use tfhe::core_crypto::prelude::{LweCiphertextList, LweSecretKey};
+use submission::help_fun::{get_size_string, select_aes_param, ResultDownload};
+use submission::packing::PackedLweList;
+use tfhe::core_crypto::prelude::{GlweSecretKey, LweCiphertextList, LweSecretKey};
 
 pub fn main() -> Result<(), Box<dyn std::error::Error>> {
     let args: Vec<String> = env::args().collect();
     if args.len() < 2 {
-        eprintln!("Usage: {} <size>", args[0]);
+        eprintln!("Usage: {} <size> [--pack_glwe] [--compress] [--param <name>]", args[0]);
         std::process::exit(1);
     }
 
@@ -18,25 +21,42 @@ pub fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Load secret key
     let secret_keys_dir = format!("{}/secret_keys", io_dir);
     let lwe_sk_path = format!("{}/lwe_sk.bin", secret_keys_dir);
-    let lwe_sk_bytes = fs::read(&lwe_sk_path)?;
-    let lwe_sk: LweSecretKey<Vec<u64>> = bincode::deserialize(&lwe_sk_bytes)?;
+    let param = select_aes_param(&args[2..])?;
+    let (lwe_sk, key_set_id): (LweSecretKey<Vec<u64>>, _) =
+        read_artifact(&lwe_sk_path, PayloadType::LweSecretKey, param, None)?;
 
-    // Load encrypted result from ciphertexts_download
+    // Load encrypted result from ciphertext_aes_download, packed into GLWE
+    // ciphertexts with --pack_glwe or modulus switched with --compress
     let ciphertexts_download_dir = format!("{}/ciphertext_aes_download", io_dir);
-    let result_path = format!("{}/result.bin", ciphertexts_download_dir);
-    let result_bytes = fs::read(&result_path)?;
-    let lwe_ciphertext_list: LweCiphertextList<Vec<u64>> = bincode::deserialize(&result_bytes)?;
-
-    // Decrypt and decode
-    let decrypted_result = decrypt_decode_lwe_list(&lwe_sk, &lwe_ciphertext_list);
+    let decrypted_result = match ResultDownload::from_args(&args[2..])? {
+        ResultDownload::PackedGlwe => {
+            let glwe_sk_path = format!("{}/glwe_sk.bin", secret_keys_dir);
+            let (glwe_sk, _): (GlweSecretKey<Vec<u64>>, _) =
+                read_artifact(&glwe_sk_path, PayloadType::GlweSecretKey, param, Some(key_set_id))?;
+            let result_path = format!("{}/result_packed.bin", ciphertexts_download_dir);
+            let (packed, _): (PackedLweList, _) =
+                read_artifact(&result_path, PayloadType::PackedAesResult, param, Some(key_set_id))?;
+            packed.decrypt_decode(&glwe_sk)?
+        }
+        ResultDownload::Compressed => {
+            let result_path = format!("{}/result_compressed.bin", ciphertexts_download_dir);
+            let (compressed, _): (CompressedLweList, _) =
+                read_artifact(&result_path, PayloadType::CompressedAesResult, param, Some(key_set_id))?;
+            decrypt_decode_lwe_list(&lwe_sk, &compressed.decompress()?)
+        }
+        ResultDownload::Lwe => {
+            let result_path = format!("{}/result.bin", ciphertexts_download_dir);
+            let (lwe_ciphertext_list, _): (LweCiphertextList<Vec<u64>>, _) =
+                read_artifact(&result_path, PayloadType::AesResult, param, Some(key_set_id))?;
+            decrypt_decode_lwe_list(&lwe_sk, &lwe_ciphertext_list)
+        }
+    };
 
     // intermediate output
     let intermediate_output_path = format!("{}/intermediate", io_dir);
     fs::create_dir_all(&intermediate_output_path)?;
     let output_path = format!("{}/decoded_result_aes.txt", intermediate_output_path);
-    
-    let bytes = bincode::serialize(&decrypted_result)?;
-    fs::write(&output_path, bytes)?;
+    write_artifact(&output_path, PayloadType::DecodedResult, param, key_set_id, &decrypted_result)?;
 
     Ok(())
 }
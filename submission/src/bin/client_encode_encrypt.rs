@@ -1,8 +1,8 @@
-use std::{env, fs};
+use std::{env, fs, path::Path};
 
-use auto_base_conv::{AES_TIGHT, AesParam};
-use submission::{aes_manager::Aes128Manager, data_struct::{AllRdKeys, get_0_round_key, get_8_to_1_round_key, get_10_9_round_key}, help_fun::get_size_string};
-use tfhe::core_crypto::{prelude::{ActivatedRandomGenerator, EncryptionRandomGenerator, GlweSecretKey, SecretRandomGenerator}, seeders::new_seeder};
+use auto_base_conv::{AesHalfCBSParam, AesParam, Error};
+use submission::{aes_manager::{AesManager, BYTESIZE}, envelope::{PayloadType, read_artifact, write_artifact}, data_struct::{SeededAllFwdRdKeys, SeededAllHalfCbsRdKeys, SeededAllRdKeys, SeededTransKey, get_0_round_key, get_entry_table_round_key, get_half_cbs_last_two_round_key, get_half_cbs_middle_round_key, get_middle_round_key, get_last_two_round_key, get_fwd_last_round_key, get_fwd_middle_round_key}, help_fun::{get_size_string, read_aes_iv, read_aes_key, select_aes_param, select_half_cbs_param}};
+use tfhe::core_crypto::{prelude::{encrypt_seeded_lwe_ciphertext_list, GlweSecretKey, LweCiphertextCount, PlaintextList, SeededLweCiphertextList, SeededLweCiphertextListOwned, Seeder}, seeders::new_seeder};
 
 
 
@@ -10,21 +10,80 @@ use tfhe::core_crypto::{prelude::{ActivatedRandomGenerator, EncryptionRandomGene
 pub fn gen_transciphering_keys(
     param: &AesParam<u64>,
     glwe_sk: &GlweSecretKey<Vec<u64>>,
-    aes_key: &[u8; 16],
-    encryption_generator: &mut EncryptionRandomGenerator<ActivatedRandomGenerator>,
-) -> AllRdKeys {
-    let aes = Aes128Manager::new(aes_key);
-    AllRdKeys {
-        _10_9_round_key: get_10_9_round_key(param, glwe_sk, &aes, encryption_generator),
-        _8_to_1_round_key: get_8_to_1_round_key(param, glwe_sk, &aes, encryption_generator),
-        _0_round_key: get_0_round_key(param, glwe_sk, &aes, encryption_generator),
-    }
+    aes: &AesManager,
+    noise_seeder: &mut dyn Seeder,
+) -> Result<SeededAllRdKeys, Error> {
+    Ok(SeededAllRdKeys {
+        _last_two_round_key: get_last_two_round_key(param, glwe_sk, aes, noise_seeder)?,
+        _middle_round_key: get_middle_round_key(param, glwe_sk, aes, noise_seeder)?,
+        _0_round_key: get_0_round_key(param, glwe_sk, aes, noise_seeder)?,
+    })
+}
+
+/// Keyed LUTs of the half-CBS pipeline. The first step is encrypted with the
+/// half-CBS decomposition, the other rounds under `half_cbs_param`'s base
+/// parameters.
+pub fn gen_half_cbs_transciphering_keys(
+    half_cbs_param: &AesHalfCBSParam<u64>,
+    glwe_sk: &GlweSecretKey<Vec<u64>>,
+    aes: &AesManager,
+    noise_seeder: &mut dyn Seeder,
+) -> Result<SeededAllHalfCbsRdKeys, Error> {
+    let param = half_cbs_param.base_param();
+    Ok(SeededAllHalfCbsRdKeys {
+        _last_two_round_key: get_half_cbs_last_two_round_key(half_cbs_param, glwe_sk, aes, noise_seeder)?,
+        _entry_table_round_key: get_entry_table_round_key(&param, glwe_sk, aes, noise_seeder),
+        _middle_round_key: get_half_cbs_middle_round_key(&param, glwe_sk, aes, noise_seeder)?,
+        _0_round_key: get_0_round_key(&param, glwe_sk, aes, noise_seeder)?,
+    })
+}
+
+pub fn gen_ctr_transciphering_keys(
+    param: &AesParam<u64>,
+    glwe_sk: &GlweSecretKey<Vec<u64>>,
+    aes: &AesManager,
+    noise_seeder: &mut dyn Seeder,
+) -> Result<SeededAllFwdRdKeys, Error> {
+    Ok(SeededAllFwdRdKeys {
+        _middle_round_key: get_fwd_middle_round_key(param, glwe_sk, aes, noise_seeder)?,
+        _last_round_key: get_fwd_last_round_key(param, glwe_sk, aes, noise_seeder)?,
+    })
+}
+
+/// Encrypts the AES key bit by bit, LSB first within each byte, for the
+/// server-side key schedule.
+pub fn gen_master_key(
+    param: &AesParam<u64>,
+    glwe_sk: &GlweSecretKey<Vec<u64>>,
+    aes_key: &[u8],
+    noise_seeder: &mut dyn Seeder,
+) -> SeededLweCiphertextListOwned<u64> {
+    let lwe_sk = glwe_sk.as_lwe_secret_key();
+    let key_bits = (0..aes_key.len() * BYTESIZE)
+        .map(|i| (((aes_key[i / BYTESIZE] >> (i % BYTESIZE)) & 1) as u64) << 63)
+        .collect::<Vec<u64>>();
+
+    let mut he_key = SeededLweCiphertextList::new(
+        0u64,
+        lwe_sk.lwe_dimension().to_lwe_size(),
+        LweCiphertextCount(aes_key.len() * BYTESIZE),
+        noise_seeder.seed().into(),
+        param.ciphertext_modulus(),
+    );
+    encrypt_seeded_lwe_ciphertext_list(
+        &lwe_sk,
+        &mut he_key,
+        &PlaintextList::from_container(key_bits),
+        param.glwe_modular_std_dev(),
+        noise_seeder,
+    );
+    he_key
 }
 
 pub fn main() -> Result<(), Box<dyn std::error::Error>> {
     let args: Vec<String> = env::args().collect();
     if args.len() < 2 {
-        eprintln!("Usage: {} <size>", args[0]);
+        eprintln!("Usage: {} <size> [--server_key_schedule] [--aes_reencrypt] [--param <name>]", args[0]);
         std::process::exit(1);
     }
 
@@ -32,33 +91,75 @@ pub fn main() -> Result<(), Box<dyn std::error::Error>> {
     let io_dir = "io/".to_owned() + get_size_string(size.parse::<usize>()?);
     let data_dir = "datasets/".to_owned() + get_size_string(size.parse::<usize>()?);
 
-    let aes_key_path = format!("{}/aes_key.hex", data_dir);
-    let hex_string = fs::read_to_string(&aes_key_path)?.trim().to_string();
-
-    let mut aes_key: [u8; 16] = [0u8; 16];
-    for (i, byte) in aes_key.iter_mut().enumerate() {
-        let hex_pair = &hex_string[i * 2..i * 2 + 2];
-        *byte = u8::from_str_radix(hex_pair, 16)?;
-    }
+    // The key length selects AES-128, AES-192 or AES-256
+    let aes_key = read_aes_key(&data_dir)?;
+    let aes = AesManager::new(&aes_key)?;
 
     let secret_keys_dir = format!("{}/secret_keys", io_dir);
     let glwe_sk_path = format!("{}/glwe_sk.bin", secret_keys_dir);
-    let glwe_sk_bytes = fs::read(&glwe_sk_path)?;
-    let glwe_sk: GlweSecretKey<Vec<u64>> = bincode::deserialize(&glwe_sk_bytes)?;
+    let param = select_aes_param(&args[2..])?;
+    let (glwe_sk, key_set_id): (GlweSecretKey<Vec<u64>>, _) =
+        read_artifact(&glwe_sk_path, PayloadType::GlweSecretKey, param, None)?;
 
-    let param = &*AES_TIGHT;
     let mut boxed_seeder = new_seeder();
     let seeder = boxed_seeder.as_mut();
-    let mut encryption_generator =
-        EncryptionRandomGenerator::<ActivatedRandomGenerator>::new(seeder.seed(), seeder);
     
-    let trans_key = gen_transciphering_keys(param, &glwe_sk, &aes_key, &mut encryption_generator);
+    // With --server_key_schedule only the key bits are uploaded, otherwise CTR
+    // instances come with an IV and only need the forward cipher. A half-CBS
+    // parameter set selects the half-CBS pipeline, which only runs ECB
+    let server_key_schedule = args[2..].iter().any(|arg| arg == "--server_key_schedule");
+    let half_cbs_param = select_half_cbs_param(&args[2..])?;
+    let trans_key = match (read_aes_iv(&data_dir)?, half_cbs_param) {
+        (_, Some(_)) if server_key_schedule => {
+            return Err("the half-CBS pipeline needs keyed LUTs, it cannot run with --server_key_schedule".into())
+        }
+        (Some(_), Some(_)) => return Err("the half-CBS pipeline only decrypts ECB datasets".into()),
+        (None, Some(half_cbs_param)) => {
+            SeededTransKey::HalfCbs(gen_half_cbs_transciphering_keys(half_cbs_param, &glwe_sk, &aes, seeder)?)
+        }
+        _ if server_key_schedule => SeededTransKey::MasterKey(gen_master_key(param, &glwe_sk, &aes_key, seeder)),
+        (Some(_), None) => SeededTransKey::Ctr(gen_ctr_transciphering_keys(param, &glwe_sk, &aes, seeder)?),
+        (None, None) => SeededTransKey::Ecb(gen_transciphering_keys(param, &glwe_sk, &aes, seeder)?),
+    };
+
+    // With --aes_reencrypt the server also encrypts the compute result under
+    // the AES key. That runs the forward cipher, so a key holding only the
+    // inverse cipher comes with the forward keyed LUTs of CTR mode
+    let aes_reencrypt = args[2..].iter().any(|arg| arg == "--aes_reencrypt");
+    let reencrypt_key = match trans_key {
+        SeededTransKey::Ecb(_) | SeededTransKey::HalfCbs(_) if aes_reencrypt => {
+            Some(SeededTransKey::Ctr(gen_ctr_transciphering_keys(param, &glwe_sk, &aes, seeder)?))
+        }
+        _ => None,
+    };
+
+    // Trivial GLWEs would hand the AES round keys to the server in the clear
+    for key in std::iter::once(&trans_key).chain(reencrypt_key.as_ref()) {
+        let num_trivial = key.clone().decompress_into_trans_key().count_trivial_accumulators();
+        if num_trivial > 0 {
+            return Err(format!(
+                "refusing to write transciphering key: {} accumulators are trivially encrypted",
+                num_trivial
+            )
+            .into());
+        }
+    }
 
     let ciphertext_upload_dir = format!("{}/ciphertexts_upload", io_dir);
     fs::create_dir_all(&ciphertext_upload_dir)?;
     
     let trans_key_path = format!("{}/trans_key.bin", ciphertext_upload_dir);
-    fs::write(&trans_key_path, bincode::serialize(&trans_key)?)?;
+    write_artifact(&trans_key_path, PayloadType::SeededTransKey, param, key_set_id, &trans_key)?;
+
+    // A key left over from an earlier run would not match this AES key
+    let reencrypt_key_path = format!("{}/reencrypt_key.bin", ciphertext_upload_dir);
+    match &reencrypt_key {
+        Some(reencrypt_key) => {
+            write_artifact(&reencrypt_key_path, PayloadType::SeededTransKey, param, key_set_id, reencrypt_key)?
+        }
+        None if Path::new(&reencrypt_key_path).exists() => fs::remove_file(&reencrypt_key_path)?,
+        None => {}
+    }
     
     println!("Transciphering keys saved to {}", ciphertext_upload_dir);
 
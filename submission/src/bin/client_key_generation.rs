@@ -1,23 +1,26 @@
-use submission::help_fun::get_size_string;
+use submission::envelope::{KeySetId, PayloadType, write_artifact};
+use submission::help_fun::{get_size_string, select_aes_param, select_half_cbs_param};
 use std::{collections::HashMap, env};
 use std::fs;
 
-use aligned_vec::ABox;
-use auto_base_conv::{AES_TIGHT, AesParam, AutomorphKey, AutomorphKeySerializable, GlweKeyswitchKeyOwned, gen_all_auto_keys, generate_scheme_switching_key, keygen_pbs_with_glwe_ks};
-use tfhe::core_crypto::{prelude::{ActivatedRandomGenerator, EncryptionRandomGenerator, GgswCiphertextList, GlweSecretKeyOwned, LweBootstrapKeyOwned, LweSecretKeyOwned, SecretRandomGenerator}, seeders::new_seeder};
-use tfhe::core_crypto::fft_impl::fft64::c64;
+use auto_base_conv::{AesHalfCBSParam, AesParam, SeededGlweKeyswitchKey, SeededSchemeSwitchingKey, gen_all_seeded_auto_keys, generate_seeded_scheme_switching_key, keygen_seeded_pbs_with_glwe_ks};
+use tfhe::core_crypto::{prelude::{ActivatedRandomGenerator, GlweSecretKeyOwned, LweSecretKeyOwned, SecretRandomGenerator, Seeder, SeededLweBootstrapKeyOwned}, seeders::new_seeder};
 
+/// Generates the secret keys and seeded evaluation keys. Only the bodies of
+/// the evaluation keys and their compression seeds are uploaded, the server
+/// expands them in `server_preprocess_dataset`.
+#[allow(clippy::type_complexity)]
 pub fn generate_fhe_keys(
     param: &AesParam<u64>,
     secret_generator: &mut SecretRandomGenerator<ActivatedRandomGenerator>,
-    encryption_generator: &mut EncryptionRandomGenerator<ActivatedRandomGenerator>,
+    noise_seeder: &mut dyn Seeder,
 ) -> (
     LweSecretKeyOwned<u64>,
     GlweSecretKeyOwned<u64>,
-    LweBootstrapKeyOwned<u64>,
-    GlweKeyswitchKeyOwned<u64>,
-    HashMap<usize, AutomorphKey<ABox<[c64]>>>,
-    GgswCiphertextList<Vec<u64>>,
+    SeededLweBootstrapKeyOwned<u64>,
+    SeededGlweKeyswitchKey<u64>,
+    HashMap<usize, SeededGlweKeyswitchKey<u64>>,
+    SeededSchemeSwitchingKey<u64>,
 ) {
     let lwe_dimension = param.lwe_dimension();
     let lwe_modular_std_dev = param.lwe_modular_std_dev();
@@ -29,16 +32,14 @@ pub fn generate_fhe_keys(
     let glwe_ds_base_log = param.glwe_ds_base_log();
     let glwe_ds_level = param.glwe_ds_level();
     let common_polynomial_size = param.common_polynomial_size();
-    let fft_type_ds = param.fft_type_ds();
     let auto_base_log = param.auto_base_log();
     let auto_level = param.auto_level();
-    let fft_type_auto = param.fft_type_auto();
     let ss_base_log = param.ss_base_log();
     let ss_level = param.ss_level();
     let ciphertext_modulus = param.ciphertext_modulus();
 
     // Generate keys
-    let (lwe_sk, glwe_sk, _lwe_sk_after_ks, bsk, ksk) = keygen_pbs_with_glwe_ks(
+    let (lwe_sk, glwe_sk, _lwe_sk_after_ks, bsk, ksk) = keygen_seeded_pbs_with_glwe_ks(
         lwe_dimension,
         glwe_dimension,
         polynomial_size,
@@ -49,60 +50,80 @@ pub fn generate_fhe_keys(
         glwe_ds_base_log,
         glwe_ds_level,
         common_polynomial_size,
-        fft_type_ds,
         ciphertext_modulus,
         secret_generator,
-        encryption_generator,
+        noise_seeder,
     );
-    
-    let ss_key = generate_scheme_switching_key(
+
+    let ss_key = generate_seeded_scheme_switching_key(
         &glwe_sk,
         ss_base_log,
         ss_level,
         glwe_modular_std_dev,
         ciphertext_modulus,
-        encryption_generator,
+        noise_seeder,
     );
-    // let ss_key = ss_key.as_view();
 
-    let auto_keys = gen_all_auto_keys(
+    let auto_keys = gen_all_seeded_auto_keys(
         auto_base_log,
         auto_level,
-        fft_type_auto,
         &glwe_sk,
         glwe_modular_std_dev,
-        encryption_generator,
+        noise_seeder,
     );
 
     (lwe_sk, glwe_sk, bsk, ksk, auto_keys, ss_key)
 }
 
+/// Generates the automorphism and scheme switching keys with which the
+/// half-CBS pipeline converts Lev ciphertexts to GGSW.
+pub fn generate_half_cbs_keys(
+    param: &AesHalfCBSParam<u64>,
+    glwe_sk: &GlweSecretKeyOwned<u64>,
+    noise_seeder: &mut dyn Seeder,
+) -> (
+    HashMap<usize, SeededGlweKeyswitchKey<u64>>,
+    SeededSchemeSwitchingKey<u64>,
+) {
+    let auto_keys = gen_all_seeded_auto_keys(
+        param.half_cbs_auto_base_log(),
+        param.half_cbs_auto_level(),
+        glwe_sk,
+        param.glwe_modular_std_dev(),
+        noise_seeder,
+    );
+
+    let ss_key = generate_seeded_scheme_switching_key(
+        glwe_sk,
+        param.half_cbs_ss_base_log(),
+        param.half_cbs_ss_level(),
+        param.glwe_modular_std_dev(),
+        param.ciphertext_modulus(),
+        noise_seeder,
+    );
+
+    (auto_keys, ss_key)
+}
+
 
 pub fn main() -> Result<(), Box<dyn std::error::Error>> {
     let args: Vec<String> = env::args().collect();
     if args.len() < 2 {
-        eprintln!("Usage: {} <size>", args[0]);
+        eprintln!("Usage: {} <size> [--param <name>]", args[0]);
         std::process::exit(1); 
     }
     let size = args[1].clone();
     let io_dir = "io/".to_owned() + get_size_string(size.parse::<usize>()?);
    
-    let param = &*AES_TIGHT;
+    let param = select_aes_param(&args[2..])?;
     let mut boxed_seeder = new_seeder();
     let seeder = boxed_seeder.as_mut();
     let mut secret_generator =
         SecretRandomGenerator::<ActivatedRandomGenerator>::new(seeder.seed());
-    let mut encryption_generator =
-        EncryptionRandomGenerator::<ActivatedRandomGenerator>::new(seeder.seed(), seeder);
 
-    let fhe_keys =
-        generate_fhe_keys(param, &mut secret_generator, &mut encryption_generator);
+    let fhe_keys = generate_fhe_keys(param, &mut secret_generator, seeder);
     
     let (lwe_sk, glwe_sk, bsk, ksk, auto_keys, ss_key) = fhe_keys;
-    let serialize_auto_keys = auto_keys
-        .into_iter()
-        .map(|(k, v)| (k, v.to_serializable()))
-        .collect::<HashMap<usize, AutomorphKeySerializable>>();
 
     
     // create secret keys directory
@@ -114,8 +135,10 @@ pub fn main() -> Result<(), Box<dyn std::error::Error>> {
     // save secret keys
     let lwe_sk_path = format!("{}/lwe_sk.bin", secret_keys_dir);
     let glwe_sk_path = format!("{}/glwe_sk.bin", secret_keys_dir);
-    fs::write(&lwe_sk_path, bincode::serialize(&lwe_sk)?)?;
-    fs::write(&glwe_sk_path, bincode::serialize(&glwe_sk)?)?;
+    // Every artifact derived from these keys carries the same key set id
+    let key_set_id = KeySetId::generate();
+    write_artifact(&lwe_sk_path, PayloadType::LweSecretKey, param, key_set_id, &lwe_sk)?;
+    write_artifact(&glwe_sk_path, PayloadType::GlweSecretKey, param, key_set_id, &glwe_sk)?;
     
     // save public/evaluation keys
     let bsk_path = format!("{}/bsk.bin", public_keys_dir);
@@ -123,11 +146,19 @@ pub fn main() -> Result<(), Box<dyn std::error::Error>> {
     let auto_keys_path = format!("{}/auto_keys.bin", public_keys_dir);
     let ss_key_path = format!("{}/ss_key.bin", public_keys_dir);
     
-    fs::write(&bsk_path, bincode::serialize(&bsk)?)?;
-    fs::write(&ksk_path, bincode::serialize(&ksk)?)?;
-    fs::write(&auto_keys_path, bincode::serialize(&serialize_auto_keys)?)?;
-    fs::write(&ss_key_path, bincode::serialize(&ss_key)?)?;
-        
+    write_artifact(&bsk_path, PayloadType::SeededBootstrapKey, param, key_set_id, &bsk)?;
+    write_artifact(&ksk_path, PayloadType::SeededGlweKeyswitchKey, param, key_set_id, &ksk)?;
+    write_artifact(&auto_keys_path, PayloadType::SeededAutomorphKeys, param, key_set_id, &auto_keys)?;
+    write_artifact(&ss_key_path, PayloadType::SeededSchemeSwitchKey, param, key_set_id, &ss_key)?;
+
+    if let Some(half_cbs_param) = select_half_cbs_param(&args[2..])? {
+        let (half_cbs_auto_keys, half_cbs_ss_key) = generate_half_cbs_keys(half_cbs_param, &glwe_sk, seeder);
+        let half_cbs_auto_keys_path = format!("{}/half_cbs_auto_keys.bin", public_keys_dir);
+        let half_cbs_ss_key_path = format!("{}/half_cbs_ss_key.bin", public_keys_dir);
+        write_artifact(&half_cbs_auto_keys_path, PayloadType::SeededAutomorphKeys, param, key_set_id, &half_cbs_auto_keys)?;
+        write_artifact(&half_cbs_ss_key_path, PayloadType::SeededSchemeSwitchKey, param, key_set_id, &half_cbs_ss_key)?;
+    }
+    println!("Generated key set {}", key_set_id);
 
     Ok(())
 }
\ No newline at end of file
@@ -1,31 +1,82 @@
 use std::{env, fs};
 
-use submission::help_fun::get_size_string;
+use submission::aes_manager::AesManager;
+use submission::envelope::{PayloadType, read_artifact};
+use submission::help_fun::{get_size_string, parse_hex_blocks, read_aes_key, select_aes_param};
+
+/// Decrypts the AES ciphertext written by `client_decrypt_decode --aes_reencrypt`,
+/// as any holder of the AES key would, into the big-endian result bytes.
+fn decrypt_reencrypted_result(io_dir: &str, data_dir: &str) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let aes = AesManager::new(&read_aes_key(data_dir)?)?;
+    let ciphertext_hex = fs::read_to_string(format!("{}/result_aes_ciphertext.hex", io_dir))?;
+    let ciphertext_hex = ciphertext_hex.trim();
+    let ciphertext = (0..ciphertext_hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(ciphertext_hex.get(i..i + 2).ok_or("odd hex digit count")?, 16).map_err(Into::into))
+        .collect::<Result<Vec<u8>, Box<dyn std::error::Error>>>()?;
+
+    let iv_path = format!("{}/result_aes_iv.hex", io_dir);
+    if std::path::Path::new(&iv_path).exists() {
+        let iv = parse_hex_blocks(&fs::read_to_string(&iv_path)?)?[0];
+        let keystream = aes.ctr_keystream(&iv, ciphertext.len());
+        return Ok(ciphertext.iter().zip(keystream).map(|(c, k)| c ^ k).collect());
+    }
+
+    if ciphertext.is_empty() || !ciphertext.len().is_multiple_of(16) {
+        return Err("the ECB result is not a whole number of blocks".into());
+    }
+    let mut plaintext: Vec<u8> = ciphertext
+        .chunks_exact(16)
+        .flat_map(|block| aes.decrypt_block(std::array::from_fn(|i| block[i])))
+        .collect();
+    let pad = *plaintext.last().unwrap() as usize;
+    if !(1..=16).contains(&pad) || plaintext[plaintext.len() - pad..].iter().any(|&byte| byte as usize != pad) {
+        return Err("the ECB result has invalid PKCS#7 padding, was it decrypted with the right AES key?".into());
+    }
+    plaintext.truncate(plaintext.len() - pad);
+    Ok(plaintext)
+}
 
 pub fn main() -> Result<(), Box<dyn std::error::Error>> {
     let args: Vec<String> = env::args().collect();
     if args.len() < 2 {
-        eprintln!("Usage: {} <size>", args[0]);
+        eprintln!("Usage: {} <size> [--aes_reencrypt] [--param <name>]", args[0]);
         std::process::exit(1);
     }
 
     let size = args[1].clone();
     let io_dir = "io/".to_owned() + get_size_string(size.parse::<usize>()?);
-    let intermediate_output_path = format!("{}/intermediate", io_dir);
-    let input_path = format!("{}/decoded_result.txt", intermediate_output_path);
+    let param = select_aes_param(&args[2..])?;
 
-    let decrypted_result: Vec<u64> = bincode::deserialize(&fs::read(&input_path)?)?;
-    if decrypted_result.len() % 16 != 0 {
-        return Err("decrypted_result length is not a multiple of 16".into());
-    }
-    let mut packed: Vec<u16> = Vec::with_capacity(decrypted_result.len() / 16);
-    for chunk in decrypted_result.chunks(16) {
-        let mut value: u16 = 0;
-        for &bit in chunk {
-            value = (value << 1) | ((bit as u16) & 1);
+    let packed: Vec<u16> = if args[2..].iter().any(|arg| arg == "--aes_reencrypt") {
+        let data_dir = "datasets/".to_owned() + get_size_string(size.parse::<usize>()?);
+        let plaintext = decrypt_reencrypted_result(&io_dir, &data_dir)?;
+        if !plaintext.len().is_multiple_of(2) {
+            return Err("the decrypted result length is not a multiple of 2 bytes".into());
         }
-        packed.push(value);
-    }
+        plaintext
+            .chunks_exact(2)
+            .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+            .collect()
+    } else {
+        let intermediate_output_path = format!("{}/intermediate", io_dir);
+        let input_path = format!("{}/decoded_result.txt", intermediate_output_path);
+
+        let (decrypted_result, _): (Vec<u64>, _) =
+            read_artifact(&input_path, PayloadType::DecodedResult, param, None)?;
+        if !decrypted_result.len().is_multiple_of(16) {
+            return Err("decrypted_result length is not a multiple of 16".into());
+        }
+        let mut packed: Vec<u16> = Vec::with_capacity(decrypted_result.len() / 16);
+        for chunk in decrypted_result.chunks(16) {
+            let mut value: u16 = 0;
+            for &bit in chunk {
+                value = (value << 1) | ((bit as u16) & 1);
+            }
+            packed.push(value);
+        }
+        packed
+    };
 
     let output_path = format!("{}/result.txt", io_dir);
     let mut result_str = packed
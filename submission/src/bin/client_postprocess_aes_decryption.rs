@@ -1,23 +1,26 @@
 use std::{env, fs};
 
-use submission::help_fun::get_size_string;
+use submission::envelope::{PayloadType, read_artifact};
+use submission::help_fun::{get_size_string, select_aes_param};
 
 pub fn main() -> Result<(), Box<dyn std::error::Error>> {
     let args: Vec<String> = env::args().collect();
     if args.len() < 2 {
-        eprintln!("Usage: {} <size>", args[0]);
+        eprintln!("Usage: {} <size> [--param <name>]", args[0]);
         std::process::exit(1); 
     }
     let size = args[1].clone();
     let io_dir = "io/".to_owned() + get_size_string(size.parse::<usize>()?);
+    let param = select_aes_param(&args[2..])?;
     let intermediate_output_path = format!("{}/intermediate", io_dir);
     let input_path = format!("{}/decoded_result_aes.txt", intermediate_output_path);
     let output_path = format!("{}/result_aes.txt", io_dir);
     
     
-    let decrypted_result: Vec<u64> = bincode::deserialize(&fs::read(&input_path)?)?;
+    let (decrypted_result, _): (Vec<u64>, _) =
+        read_artifact(&input_path, PayloadType::DecodedResult, param, None)?;
     // Pack 128 bits into 8 u16 values and save one per line (decimal)
-    if decrypted_result.len() % 16 != 0 {
+    if !decrypted_result.len().is_multiple_of(16) {
         return Err("decrypted_result length is not a multiple of 16".into());
     }
     let mut packed: Vec<u16> = Vec::with_capacity(decrypted_result.len() / 16);
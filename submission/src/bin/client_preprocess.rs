@@ -1,13 +1,16 @@
 use std::env;
 
+use submission::help_fun::select_aes_param;
+
 pub fn main() -> Result<(), Box<dyn std::error::Error>> {
     let args: Vec<String> = env::args().collect();
     if args.len() < 2 {
-        eprintln!("Usage: {} <size>", args[0]);
+        eprintln!("Usage: {} <size> [--param <name>]", args[0]);
         std::process::exit(1); 
     }
     let size = args[1].clone();
-    let io_dir = "io/".to_owned() + &size;
+    let _io_dir = "io/".to_owned() + &size;
+    let _param = select_aes_param(&args[2..])?;
     
     // TODO: Add implementation.
     println!("Implement code in client_preprocess.rs");
@@ -1,39 +1,41 @@
 use std::collections::HashMap;
 use std::env;
 use std::fs;
+use std::sync::{Mutex, PoisonError};
 
-use aes::cipher;
 use aligned_vec::ABox;
+use auto_base_conv::check_lwe_keyswitch_key;
 use auto_base_conv::convert_lwe_to_glwe_const;
-use auto_base_conv::fourier_glev_ciphertext;
-use auto_base_conv::glwe_keyswitch;
-use auto_base_conv::keyswitch_lwe_ciphertext_by_glwe_keyswitch;
-use auto_base_conv::lwe_msb_bit_to_glev_by_trace_with_preprocessing;
-use auto_base_conv::switch_scheme;
-use auto_base_conv::{
-    convert_standard_glwe_keyswitch_key_to_fourier, AutomorphKey, AutomorphKeySerializable,
-    FourierGlweKeyswitchKey, GlweKeyswitchKeyOwned, AES_TIGHT,
-};
-use bincode::de;
+use auto_base_conv::try_keyswitch_lwe_ciphertext_list_by_glwe_keyswitch;
+use auto_base_conv::{AutomorphKey, CbsContext, Error as CbsError, FourierGlweKeyswitchKey};
 use itertools::izip;
-use submission::{
-    aes_manager::{StateByteMat, BLOCKSIZE_IN_BIT, BYTESIZE, NUM_COLUMNS, NUM_ROWS},
-    data_struct::AllRdKeys,
-    help_fun::get_size_string,
-};
-use tfhe::core_crypto::fft_impl::fft128::crypto::ggsw::cmux;
+#[cfg(feature = "multithread")]
+use rayon::prelude::*;
+use std::path::Path;
+
+use submission::aes_manager::BYTESIZE;
+use submission::compression::{CompressedLweList, RESULT_LOG_MODULUS};
+use submission::data_struct::{ReencryptedResult, SeededTransKey};
+use submission::envelope::{PayloadType, read_artifact, write_artifact};
+use submission::fourier_keys::FourierKeyBundle;
+use submission::packing::pack_lwe_list;
+use submission::help_fun::{aes_result_path, get_size_string, read_aes_iv, select_aes_param, ResultDownload};
+use submission::transcipherer::{ServerKeySet, Transcipherer};
 use tfhe::core_crypto::fft_impl::fft64::{
     c64,
-    crypto::{
-        bootstrap::FourierLweBootstrapKeyView,
-        ggsw::{FourierGgswCiphertextListMutView, FourierGgswCiphertextListView},
-    },
+    crypto::{bootstrap::FourierLweBootstrapKeyView, ggsw::FourierGgswCiphertextListView},
 };
 use tfhe::core_crypto::prelude::*;
 
+/// Writes the larger of two 16-bit elements to `output`.
+///
+/// `ggsw_a` and `ggsw_b` are the circuit bootstrapped bits of `lwe_a_list` and
+/// `lwe_b_list`, all MSB first. Each output bit scans the inputs from LSB to MSB
+/// and keeps the bit of whichever input wins at the highest differing position;
+/// equal inputs return the bit of `lwe_a_list`.
 fn max_of_two<Scalar, Cont, MutCont>(
-    input_a: &GgswCiphertextList<Cont>,
-    input_b: &GgswCiphertextList<Cont>,
+    ggsw_a: &[FourierGgswCiphertext<ABox<[c64]>>],
+    ggsw_b: &[FourierGgswCiphertext<ABox<[c64]>>],
     lwe_a_list: &LweCiphertextList<Cont>,
     lwe_b_list: &LweCiphertextList<Cont>,
     output: &mut LweCiphertextList<MutCont>,
@@ -42,11 +44,9 @@ fn max_of_two<Scalar, Cont, MutCont>(
     Cont: Container<Element = Scalar>,
     MutCont: ContainerMut<Element = Scalar>,
 {
-    let glwe_size = input_a.glwe_size();
-    let polynomial_size = input_a.polynomial_size();
-    let ciphertext_modulus = input_a.ciphertext_modulus();
-    let decomposition_base_log = input_a.decomposition_base_log();
-    let decomposition_level_count = input_a.decomposition_level_count();
+    let glwe_size = ggsw_a[0].glwe_size();
+    let polynomial_size = ggsw_a[0].polynomial_size();
+    let ciphertext_modulus = lwe_a_list.ciphertext_modulus();
     let mut glwe_a =
         GlweCiphertext::new(Scalar::ZERO, glwe_size, polynomial_size, ciphertext_modulus);
     let mut glwe_b =
@@ -59,50 +59,344 @@ fn max_of_two<Scalar, Cont, MutCont>(
         GlweCiphertext::new(Scalar::ZERO, glwe_size, polynomial_size, ciphertext_modulus);
     let mut glwe_temp =
         GlweCiphertext::new(Scalar::ZERO, glwe_size, polynomial_size, ciphertext_modulus);
-    let mut fourier_ggsw_a = FourierGgswCiphertext::new(
-        glwe_size,
-        polynomial_size,
-        decomposition_base_log,
-        decomposition_level_count,
-    );
-    let mut fourier_ggsw_b = FourierGgswCiphertext::new(
-        glwe_size,
-        polynomial_size,
-        decomposition_base_log,
-        decomposition_level_count,
-    );
 
     for (lwe_a, lwe_b, mut output_lwe) in
         izip!(lwe_a_list.iter(), lwe_b_list.iter(), output.iter_mut())
     {
         convert_lwe_to_glwe_const(&lwe_a, &mut glwe_b);
         convert_lwe_to_glwe_const(&lwe_b, &mut glwe_a);
-        convert_lwe_to_glwe_const(&lwe_b, &mut glwe_a);
-        for (ggsw_a, ggsw_b) in input_a.iter().rev().zip(input_b.iter().rev()) {
-            convert_standard_ggsw_ciphertext_to_fourier(&ggsw_a, &mut fourier_ggsw_a);
-            convert_standard_ggsw_ciphertext_to_fourier(&ggsw_b, &mut fourier_ggsw_b);
-
+        glwe_e.clone_from(&glwe_b);
+        for (ggsw_a_bit, ggsw_b_bit) in ggsw_a.iter().rev().zip(ggsw_b.iter().rev()) {
             glwe_mid_0.clone_from(&glwe_e);
             glwe_temp.clone_from(&glwe_a);
-            cmux_assign(&mut glwe_mid_0, &mut glwe_temp, &fourier_ggsw_b);
+            cmux_assign(&mut glwe_mid_0, &mut glwe_temp, ggsw_b_bit);
 
             glwe_mid_1.clone_from(&glwe_b);
             glwe_temp.clone_from(&glwe_e);
-            cmux_assign(&mut glwe_mid_1, &mut glwe_temp, &fourier_ggsw_b);
+            cmux_assign(&mut glwe_mid_1, &mut glwe_temp, ggsw_b_bit);
 
             glwe_e.clone_from(&glwe_mid_0);
-            cmux_assign(&mut glwe_e, &mut glwe_mid_1, &fourier_ggsw_a);
+            cmux_assign(&mut glwe_e, &mut glwe_mid_1, ggsw_a_bit);
         }
         extract_lwe_sample_from_glwe_ciphertext(&glwe_e, &mut output_lwe, MonomialDegree(0));
     }
 }
+
+const BITS_PER_ELEMENT: usize = 16;
+
+/// Mini-workload evaluated on the transciphered elements, as selected by the
+/// harness `--mini_workload` flag.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum MiniWorkload {
+    Max,
+    InnerProduct,
+}
+
+impl MiniWorkload {
+    fn from_args(args: &[String]) -> Result<Self, Box<dyn std::error::Error>> {
+        match args.iter().position(|arg| arg == "--mini_workload") {
+            None => Ok(MiniWorkload::Max),
+            Some(pos) => match args.get(pos + 1).map(String::as_str) {
+                Some("0") => Ok(MiniWorkload::Max),
+                Some("1") => Ok(MiniWorkload::InnerProduct),
+                _ => Err("--mini_workload expects 0 (max) or 1 (inner product)".into()),
+            },
+        }
+    }
+}
+
+/// Keys needed to circuit bootstrap a big LWE bit into a Fourier GGSW.
+struct CbsKeys<'a> {
+    fourier_glwe_ksk: &'a FourierGlweKeyswitchKey<ABox<[c64]>>,
+    fourier_bsk: FourierLweBootstrapKeyView<'a>,
+    auto_keys: &'a HashMap<usize, AutomorphKey<ABox<[c64]>>>,
+    ss_key: FourierGgswCiphertextListView<'a>,
+    base_log: DecompositionBaseLog,
+    level: DecompositionLevelCount,
+    log_lut_count: LutCountLog,
+    /// Idle contexts. A bootstrap takes one, allocating it only when all are
+    /// in use by concurrent bootstraps, and puts it back when done, so the
+    /// contexts are reused for the whole workload.
+    contexts: Mutex<Vec<CbsContext<u64>>>,
+}
+
+impl CbsKeys<'_> {
+    /// Circuit bootstraps every bit of `lwe_list`, up to `BITS_PER_ELEMENT` in
+    /// parallel with the `multithread` feature.
+    fn circuit_bootstrap_list<Cont>(
+        &self,
+        lwe_list: &LweCiphertextList<Cont>,
+    ) -> Result<Vec<FourierGgswCiphertext<ABox<[c64]>>>, CbsError>
+    where
+        Cont: Container<Element = u64>,
+    {
+        let mut lwe_ks_list = LweCiphertextList::new(
+            0u64,
+            self.fourier_bsk.input_lwe_dimension().to_lwe_size(),
+            lwe_list.lwe_ciphertext_count(),
+            lwe_list.ciphertext_modulus(),
+        );
+        try_keyswitch_lwe_ciphertext_list_by_glwe_keyswitch(lwe_list, &mut lwe_ks_list, self.fourier_glwe_ksk)?;
+
+        let idle_context = self.contexts.lock().unwrap_or_else(PoisonError::into_inner).pop();
+        let mut cbs_context = match idle_context {
+            Some(cbs_context) => cbs_context,
+            None => CbsContext::new(
+                self.fourier_bsk,
+                self.auto_keys,
+                self.base_log,
+                self.level,
+                self.log_lut_count,
+                lwe_list.ciphertext_modulus(),
+                BITS_PER_ELEMENT,
+            )?,
+        };
+
+        let mut fourier_ggsws = Vec::with_capacity(lwe_list.lwe_ciphertext_count().0);
+        let result = lwe_ks_list.chunks(cbs_context.lane_count()).try_for_each(|lwe_bits| -> Result<(), CbsError> {
+            let fourier_ggsw_list = cbs_context.circuit_bootstrap_lwe_ciphertext_list(&lwe_bits, self.fourier_bsk, self.auto_keys, self.ss_key)?;
+            for fourier_ggsw in fourier_ggsw_list.into_ggsw_iter() {
+                let mut owned = FourierGgswCiphertext::new(
+                    self.fourier_bsk.glwe_size(),
+                    self.fourier_bsk.polynomial_size(),
+                    self.base_log,
+                    self.level,
+                );
+                owned.as_mut_view().data().copy_from_slice(fourier_ggsw.data());
+                fourier_ggsws.push(owned);
+            }
+            Ok(())
+        });
+        self.contexts.lock().unwrap_or_else(PoisonError::into_inner).push(cbs_context);
+
+        result.map(|()| fourier_ggsws)
+    }
+}
+
+/// Returns `ggsw_bit ? if_true : if_false`, leaving both inputs untouched.
+fn cmux(
+    ggsw_bit: &FourierGgswCiphertext<ABox<[c64]>>,
+    if_false: &GlweCiphertextOwned<u64>,
+    if_true: &GlweCiphertextOwned<u64>,
+) -> GlweCiphertextOwned<u64> {
+    let mut output = if_false.clone();
+    let mut temp = if_true.clone();
+    cmux_assign(&mut output, &mut temp, ggsw_bit);
+    output
+}
+
+/// Returns the bitwise NOT of the GLWE constant `glwe`.
+fn glwe_not(glwe: &GlweCiphertextOwned<u64>) -> GlweCiphertextOwned<u64> {
+    let mut output = glwe.clone();
+    glwe_ciphertext_plaintext_add_assign(&mut output, Plaintext(1u64 << 63));
+    output
+}
+
+/// Full adder on the circuit bootstrapped bits `a` and `b` and the GLWE
+/// constant `c`, returning `(a ^ b ^ c, maj(a, b, c))`.
+///
+/// Both outputs are CMUX trees on `ggsw_a` and `ggsw_b`, so no bootstrapping
+/// is needed and the noise of `c` only grows by a few external products.
+fn full_adder(
+    ggsw_a: &FourierGgswCiphertext<ABox<[c64]>>,
+    ggsw_b: &FourierGgswCiphertext<ABox<[c64]>>,
+    glwe_c: &GlweCiphertextOwned<u64>,
+    glwe_zero: &GlweCiphertextOwned<u64>,
+    glwe_one: &GlweCiphertextOwned<u64>,
+) -> (GlweCiphertextOwned<u64>, GlweCiphertextOwned<u64>) {
+    // sum = b ? !(a ^ c) : a ^ c
+    let glwe_a_xor_c = cmux(ggsw_a, glwe_c, &glwe_not(glwe_c));
+    let glwe_sum = cmux(ggsw_b, &glwe_a_xor_c, &glwe_not(&glwe_a_xor_c));
+
+    // carry = b ? (a | c) : (a & c)
+    let glwe_a_or_c = cmux(ggsw_a, glwe_c, glwe_one);
+    let glwe_a_and_c = cmux(ggsw_a, glwe_zero, glwe_c);
+    let glwe_carry = cmux(ggsw_b, &glwe_a_and_c, &glwe_a_or_c);
+
+    (glwe_sum, glwe_carry)
+}
+
+/// Adds `input_a * input_b mod 2^16` to `acc`. All three lists hold 16 bits MSB first.
+///
+/// The product is accumulated row by row as `acc = a_i ? acc + (b << i) : acc`.
+/// The bits of `a` and `b` are circuit bootstrapped once, and each row circuit
+/// bootstraps the accumulator bits it carries into, so that every full adder
+/// is a CMUX tree on bootstrapped bits. The lowest bit of a row has no carry
+/// in and is updated linearly as `acc_i ^ (a_i & b_0)`. The accumulator bits
+/// are all CMUX outputs, so they need no refresh between calls.
+fn multiply_accumulate<Cont, MutCont>(
+    input_a: &LweCiphertextList<Cont>,
+    input_b: &LweCiphertextList<Cont>,
+    acc: &mut LweCiphertextList<MutCont>,
+    cbs_keys: &CbsKeys,
+) -> Result<(), CbsError>
+where
+    Cont: Container<Element = u64>,
+    MutCont: ContainerMut<Element = u64>,
+{
+    let glwe_size = cbs_keys.fourier_bsk.glwe_size();
+    let polynomial_size = cbs_keys.fourier_bsk.polynomial_size();
+    let ciphertext_modulus = acc.ciphertext_modulus();
+    let lwe_size = acc.lwe_size();
+
+    // Index i of these vectors holds the bit of weight 2^i.
+    let mut ggsw_a = cbs_keys.circuit_bootstrap_list(input_a)?;
+    ggsw_a.reverse();
+    let mut ggsw_b = cbs_keys.circuit_bootstrap_list(input_b)?;
+    ggsw_b.reverse();
+    let mut acc_bits: Vec<LweCiphertextOwned<u64>> = acc
+        .iter()
+        .rev()
+        .map(|lwe_bit| LweCiphertext::from_container(lwe_bit.as_ref().to_vec(), ciphertext_modulus))
+        .collect();
+
+    let glwe_zero = GlweCiphertext::new(0u64, glwe_size, polynomial_size, ciphertext_modulus);
+    let glwe_one = glwe_not(&glwe_zero);
+    let mut glwe_acc = glwe_zero.clone();
+    let mut lwe_partial = LweCiphertext::new(0u64, lwe_size, ciphertext_modulus);
+    for (i, ggsw_a_i) in ggsw_a.iter().enumerate() {
+        // acc_i ^= a_i & b_0, carrying a_i & b_0 & acc_i
+        convert_lwe_to_glwe_const(&acc_bits[i], &mut glwe_acc);
+        let glwe_partial = cmux(ggsw_a_i, &glwe_zero, &cmux(&ggsw_b[0], &glwe_zero, &glwe_one));
+        let mut glwe_carry = cmux(ggsw_a_i, &glwe_zero, &cmux(&ggsw_b[0], &glwe_zero, &glwe_acc));
+        extract_lwe_sample_from_glwe_ciphertext(&glwe_partial, &mut lwe_partial, MonomialDegree(0));
+        lwe_ciphertext_add_assign(&mut acc_bits[i], &lwe_partial);
+
+        if i + 1 == BITS_PER_ELEMENT {
+            break;
+        }
+        let upper_bits: Vec<u64> = acc_bits[i + 1..]
+            .iter()
+            .flat_map(|lwe_bit| lwe_bit.as_ref().iter().copied())
+            .collect();
+        let upper_bits = LweCiphertextList::from_container(upper_bits, lwe_size, ciphertext_modulus);
+        let ggsw_acc = cbs_keys.circuit_bootstrap_list(&upper_bits)?;
+
+        for (j, ggsw_acc_j) in (i + 1..BITS_PER_ELEMENT).zip(ggsw_acc.iter()) {
+            let (glwe_sum, glwe_next_carry) =
+                full_adder(ggsw_acc_j, &ggsw_b[j - i], &glwe_carry, &glwe_zero, &glwe_one);
+            // acc_j <- a_i ? acc_j + b_{j - i} + carry : acc_j
+            let glwe_acc_j = cmux(ggsw_acc_j, &glwe_zero, &glwe_one);
+            let glwe_acc_j = cmux(ggsw_a_i, &glwe_acc_j, &glwe_sum);
+            extract_lwe_sample_from_glwe_ciphertext(&glwe_acc_j, &mut acc_bits[j], MonomialDegree(0));
+            glwe_carry = glwe_next_carry;
+        }
+    }
+
+    for (acc_bit, mut output_bit) in acc_bits.iter().zip(acc.iter_mut().rev()) {
+        output_bit.as_mut().copy_from_slice(acc_bit.as_ref());
+    }
+    Ok(())
+}
+
+/// Inner product modulo 2^16 of the first half of the 16-bit elements with the second half.
+fn inner_product<Cont>(
+    lwe_ciphertext_list: &LweCiphertextList<Cont>,
+    cbs_keys: &CbsKeys,
+) -> Result<LweCiphertextListOwned<u64>, CbsError>
+where
+    Cont: Container<Element = u64>,
+{
+    let ciphertext_modulus = lwe_ciphertext_list.ciphertext_modulus();
+    let lwe_size = lwe_ciphertext_list.lwe_size();
+
+    let elements: Vec<LweCiphertextListView<u64>> =
+        lwe_ciphertext_list.chunks_exact(BITS_PER_ELEMENT).collect();
+    let (first_half, second_half) = elements.split_at(elements.len() / 2);
+
+    let mut acc = LweCiphertextList::new(
+        0u64,
+        lwe_size,
+        LweCiphertextCount(BITS_PER_ELEMENT),
+        ciphertext_modulus,
+    );
+    for (input_a, input_b) in first_half.iter().zip(second_half.iter()) {
+        multiply_accumulate(input_a, input_b, &mut acc, cbs_keys)?;
+    }
+
+    Ok(acc)
+}
+
+/// Maximum of all the 16-bit elements, reduced pairwise in a balanced tournament.
+///
+/// Each round circuit bootstraps the elements that take part in a comparison and
+/// halves their number, so the CBS depth is `ceil(log2(n))` instead of `n - 1`. An
+/// odd element out is carried over to the next round untouched.
+fn tournament_max<Cont>(
+    lwe_ciphertext_list: &LweCiphertextList<Cont>,
+    cbs_keys: &CbsKeys,
+) -> Result<LweCiphertextListOwned<u64>, CbsError>
+where
+    Cont: Container<Element = u64>,
+{
+    let ciphertext_modulus = lwe_ciphertext_list.ciphertext_modulus();
+    let lwe_size = lwe_ciphertext_list.lwe_size();
+
+    let mut elements: Vec<LweCiphertextListOwned<u64>> = lwe_ciphertext_list
+        .chunks_exact(BITS_PER_ELEMENT)
+        .map(|chunk| LweCiphertextList::from_container(chunk.as_ref().to_vec(), lwe_size, ciphertext_modulus))
+        .collect();
+
+    let max_of_pair = |pair: &[LweCiphertextListOwned<u64>]| -> Result<LweCiphertextListOwned<u64>, CbsError> {
+        match pair {
+            [lwe_a_list, lwe_b_list] => {
+                let ggsw_a = cbs_keys.circuit_bootstrap_list(lwe_a_list)?;
+                let ggsw_b = cbs_keys.circuit_bootstrap_list(lwe_b_list)?;
+
+                let mut output = LweCiphertextList::new(
+                    0u64,
+                    lwe_size,
+                    LweCiphertextCount(BITS_PER_ELEMENT),
+                    ciphertext_modulus,
+                );
+                max_of_two(&ggsw_a, &ggsw_b, lwe_a_list, lwe_b_list, &mut output);
+                Ok(output)
+            }
+            [lwe_list] => Ok(lwe_list.clone()),
+            _ => unreachable!(),
+        }
+    };
+
+    while elements.len() > 1 {
+        #[cfg(not(feature = "multithread"))]
+        let pairs = elements.chunks(2);
+        #[cfg(feature = "multithread")]
+        let pairs = elements.par_chunks(2);
+
+        elements = pairs.map(max_of_pair).collect::<Result<_, _>>()?;
+    }
+
+    Ok(elements.pop().unwrap())
+}
+
+/// Appends PKCS#7 padding to the result bytes as trivial encryptions, so that
+/// they fill whole AES blocks.
+fn pkcs7_pad(lwe_ciphertext_list: &LweCiphertextList<Vec<u64>>) -> LweCiphertextListOwned<u64> {
+    const BLOCKSIZE_IN_BYTE: usize = 16;
+    let num_bytes = lwe_ciphertext_list.lwe_ciphertext_count().0 / BYTESIZE;
+    let pad = (BLOCKSIZE_IN_BYTE - num_bytes % BLOCKSIZE_IN_BYTE) as u8;
+
+    let lwe_size = lwe_ciphertext_list.lwe_size();
+    let mut container = lwe_ciphertext_list.as_ref().to_vec();
+    container.resize((num_bytes + pad as usize) * BYTESIZE * lwe_size.0, 0);
+    let mut padded =
+        LweCiphertextList::from_container(container, lwe_size, lwe_ciphertext_list.ciphertext_modulus());
+    for (i, mut lwe) in padded.iter_mut().skip(num_bytes * BYTESIZE).enumerate() {
+        if (pad >> (BYTESIZE - 1 - i % BYTESIZE)) & 1 == 1 {
+            lwe_ciphertext_plaintext_add_assign(&mut lwe, Plaintext(1u64 << 63));
+        }
+    }
+    padded
+}
+
 pub fn main() -> Result<(), Box<dyn std::error::Error>> {
     let args: Vec<String> = env::args().collect();
     if args.len() < 2 {
-        eprintln!("Usage: {} <size>", args[0]);
+        eprintln!("Usage: {} <size> [--mini_workload <0|1>] [--pack_glwe] [--compress] [--aes_reencrypt] [--param <name>]", args[0]);
         std::process::exit(1);
     }
     let size = args[1].clone();
+    let workload = MiniWorkload::from_args(&args[2..])?;
     let io_dir = "io/".to_owned() + get_size_string(size.parse::<usize>()?);
 
     let target_dir = format!("{}/ciphertexts_download", io_dir);
@@ -111,249 +405,268 @@ pub fn main() -> Result<(), Box<dyn std::error::Error>> {
     fs::create_dir_all(&target_dir)?;
 
     // load params
-    let param = &*AES_TIGHT;
-    let glwe_size = param.glwe_dimension().to_glwe_size();
-    let polynomial_size = param.polynomial_size();
-    let ks_base_log = param.glwe_ds_base_log();
-    let ks_level = param.glwe_ds_level();
+    let param = select_aes_param(&args[2..])?;
     let base_log = param.cbs_base_log();
     let level = param.cbs_level();
-    let ciphertext_modulus = param.ciphertext_modulus();
     let log_lut_count = param.log_lut_count();
 
-    // Load encrypted result from aes_ciphertexts_download
-    let ciphertexts_download_dir = format!("{}/ciphertext_aes_download", io_dir);
-    let aes_result_path = format!("{}/result.bin", ciphertexts_download_dir);
-    let aes_result_bytes = fs::read(&aes_result_path)?;
-    let lwe_ciphertext_list: LweCiphertextList<Vec<u64>> = bincode::deserialize(&aes_result_bytes)?;
-
-    // Load computation keys
-    let public_keys_dir = format!("{}/public_keys", io_dir);
-
-    let bsk_bytes = fs::read(format!("{}/bsk.bin", public_keys_dir))?;
-    let ksk_bytes = fs::read(format!("{}/ksk.bin", public_keys_dir))?;
-    let auto_keys_bytes = fs::read(format!("{}/auto_keys.bin", public_keys_dir))?;
-    let ss_key_bytes = fs::read(format!("{}/ss_key.bin", public_keys_dir))?;
-
-    // Deserialize keys
-    let bsk: LweBootstrapKeyOwned<u64> = bincode::deserialize(&bsk_bytes)?;
-    let ksk: GlweKeyswitchKeyOwned<u64> = bincode::deserialize(&ksk_bytes)?;
-    let ss_key: GgswCiphertextListOwned<u64> = bincode::deserialize(&ss_key_bytes)?;
-    let auto_keys_serialize: HashMap<usize, AutomorphKeySerializable> =
-        bincode::deserialize(&auto_keys_bytes)?;
-
-    let param = &*AES_TIGHT;
-
-    // Convert serializable automorph keys back to standard form
-    let auto_keys: HashMap<usize, AutomorphKey<ABox<[c64]>>> = auto_keys_serialize
-        .into_iter()
-        .map(|(k, v)| (k, AutomorphKey::from_serializable(v, param.fft_type_auto())))
-        .collect();
-
-    // Convert keys to Fourier domain
-    let mut fourier_glwe_ksk = FourierGlweKeyswitchKey::new(
-        ksk.input_glwe_dimension().to_glwe_size(),
-        ksk.output_glwe_dimension().to_glwe_size(),
-        ksk.polynomial_size(),
-        ksk.decomp_base_log(),
-        ksk.decomp_level_count(),
-        param.fft_type_ds(),
-    );
-    convert_standard_glwe_keyswitch_key_to_fourier(&ksk, &mut fourier_glwe_ksk);
-
-    let mut fourier_bsk = FourierLweBootstrapKey::new(
-        bsk.input_lwe_dimension(),
-        bsk.glwe_size(),
-        bsk.polynomial_size(),
-        bsk.decomposition_base_log(),
-        bsk.decomposition_level_count(),
-    );
-    convert_standard_lwe_bootstrap_key_to_fourier(&bsk, &mut fourier_bsk);
-    let fourier_bsk = fourier_bsk.as_view();
-
-    let mut fourier_ss_key = FourierGgswCiphertextList::new(
-        vec![
-            c64::default();
-            ss_key.glwe_size().to_glwe_dimension().0
-                * ss_key.polynomial_size().to_fourier_polynomial_size().0
-                * ss_key.glwe_size().0
-                * ss_key.glwe_size().0
-                * ss_key.decomposition_level_count().0
-        ],
-        ss_key.glwe_size().to_glwe_dimension().0,
-        ss_key.glwe_size(),
-        ss_key.polynomial_size(),
-        ss_key.decomposition_base_log(),
-        ss_key.decomposition_level_count(),
-    );
-    let mut lwe_buffer = LweCiphertext::new(
-        0,
-        bsk.input_lwe_dimension().to_lwe_size(),
-        ciphertext_modulus,
-    );
-    for (mut fourier_ggsw, ggsw) in fourier_ss_key
-        .as_mut_view()
-        .into_ggsw_iter()
-        .zip(ss_key.iter())
-    {
-        convert_standard_ggsw_ciphertext_to_fourier(&ggsw, &mut fourier_ggsw);
-    }
-    let fourier_ss_key = fourier_ss_key.as_view();
+    // Load the encrypted result of server_encrypted_aes_decryption, which was
+    // run with the same download form
+    let download = ResultDownload::from_args(&args[2..])?;
+    let (lwe_ciphertext_list, key_set_id): (LweCiphertextList<Vec<u64>>, _) =
+        read_artifact(&aes_result_path(&io_dir, download), PayloadType::AesResult, param, None)?;
+
+    // Load the Fourier evaluation keys built by server_preprocess_dataset,
+    // which must come from the same key set
+    let bundle_path = format!("{}/server_keys/fourier_keys.bin", io_dir);
+    let (bundle, _): (FourierKeyBundle, _) =
+        read_artifact(&bundle_path, PayloadType::FourierKeyBundle, param, Some(key_set_id))?;
+    let keys = ServerKeySet::from_bundle(bundle, param)?;
+    let fourier_bsk = keys.fourier_bsk.as_view();
+    check_lwe_keyswitch_key(
+        &keys.fourier_glwe_ksk,
+        lwe_ciphertext_list.lwe_size(),
+        fourier_bsk.input_lwe_dimension().to_lwe_size(),
+    )?;
 
     let total_bits = lwe_ciphertext_list.lwe_ciphertext_count().0;
-    if total_bits % 16 != 0 {
-        return Err("lwe_ciphertext_list length is not a multiple of 16".into());
+    if total_bits == 0 || !total_bits.is_multiple_of(BITS_PER_ELEMENT) {
+        return Err("lwe_ciphertext_list length is not a positive multiple of 16".into());
     }
-    let num_chunks = total_bits / 16;
-    if num_chunks != 8 {
-        return Err("expected 8 chunks of 16 bits".into());
+    let num_chunks = total_bits / BITS_PER_ELEMENT;
+
+    let cbs_keys = CbsKeys {
+        fourier_glwe_ksk: &keys.fourier_glwe_ksk,
+        fourier_bsk,
+        auto_keys: &keys.auto_keys,
+        ss_key: keys.ss_key.as_view(),
+        base_log,
+        level,
+        log_lut_count,
+        contexts: Mutex::new(Vec::new()),
+    };
+    let result = match workload {
+        MiniWorkload::InnerProduct => {
+            if !num_chunks.is_multiple_of(2) {
+                return Err("inner product expects an even number of 16-bit elements".into());
+            }
+            inner_product(&lwe_ciphertext_list, &cbs_keys)?
+        }
+        MiniWorkload::Max => tournament_max(&lwe_ciphertext_list, &cbs_keys)?,
+    };
+
+    // Save final result, in the form the client downloads. The result is a
+    // single 16-bit element, packed into one GLWE ciphertext with --pack_glwe
+    match download {
+        ResultDownload::Lwe => {
+            let final_result_path = format!("{}/result.bin", target_dir);
+            write_artifact(&final_result_path, PayloadType::ComputeResult, param, key_set_id, &result)?;
+        }
+        ResultDownload::PackedGlwe => {
+            let packed = pack_lwe_list(
+                &result,
+                BITS_PER_ELEMENT,
+                fourier_bsk.glwe_size(),
+                fourier_bsk.polynomial_size(),
+                &keys.auto_keys,
+            )?;
+            let packed_path = format!("{}/result_packed.bin", target_dir);
+            write_artifact(&packed_path, PayloadType::PackedComputeResult, param, key_set_id, &packed)?;
+        }
+        ResultDownload::Compressed => {
+            let compressed = CompressedLweList::compress(&result, RESULT_LOG_MODULUS)?;
+            let compressed_path = format!("{}/result_compressed.bin", target_dir);
+            write_artifact(&compressed_path, PayloadType::CompressedComputeResult, param, key_set_id, &compressed)?;
+        }
     }
 
-    let mut ggsw_chunks: Vec<GgswCiphertextList<Vec<u64>>> = Vec::with_capacity(num_chunks);
-    for input_chunk in lwe_ciphertext_list.chunks_exact(16) {
-        let mut vec_glev = vec![
-            GlweCiphertextList::new(
-                0,
-                glwe_size,
-                polynomial_size,
-                GlweCiphertextCount(level.0),
-                ciphertext_modulus,
-            );
-            16
-        ];
-        for (input_bit, glev) in input_chunk.iter().zip(vec_glev.iter_mut()) {
-            let glev_mut_view = GlweCiphertextListMutView::from_container(
-                glev.as_mut(),
-                glwe_size,
-                polynomial_size,
-                ciphertext_modulus,
-            );
-
-            keyswitch_lwe_ciphertext_by_glwe_keyswitch(
-                &input_bit.as_view(),
-                &mut lwe_buffer,
-                &fourier_glwe_ksk,
-            );
-
-            lwe_msb_bit_to_glev_by_trace_with_preprocessing(
-                lwe_buffer.as_view(),
-                glev_mut_view,
-                fourier_bsk,
-                &auto_keys,
-                base_log,
-                level,
-                log_lut_count,
-            );
+    // Encrypt the result under the AES key as well, for consumers that only
+    // hold that key: CTR with a fresh IV for CTR datasets, ECB otherwise
+    if args[2..].iter().any(|arg| arg == "--aes_reencrypt") {
+        let upload_dir = format!("{}/ciphertexts_upload", io_dir);
+        // client_encode_encrypt writes reencrypt_key.bin only when trans_key.bin
+        // lacks the forward cipher
+        let reencrypt_key_path = format!("{}/reencrypt_key.bin", upload_dir);
+        let trans_key_path = if Path::new(&reencrypt_key_path).exists() {
+            reencrypt_key_path
+        } else {
+            format!("{}/trans_key.bin", upload_dir)
+        };
+        println!("Re-encrypting the result with the AES key from {}", trans_key_path);
+        let (trans_key, _): (SeededTransKey, _) =
+            read_artifact(&trans_key_path, PayloadType::SeededTransKey, param, Some(key_set_id))?;
+        let transcipherer = Transcipherer::new(*param, keys, trans_key.decompress_into_trans_key())?;
+        if !transcipherer.supports_ctr() {
+            return Err("--aes_reencrypt needs the forward cipher; rerun client_encode_encrypt \
+                with --aes_reencrypt"
+                .into());
         }
 
-        let mut ggsw_bit_list = GgswCiphertextList::new(
-            0,
-            glwe_size,
-            polynomial_size,
-            base_log,
-            level,
-            GgswCiphertextCount(16),
-            ciphertext_modulus,
+        let data_dir = "datasets/".to_owned() + get_size_string(size.parse::<usize>()?);
+        let reencrypted = match read_aes_iv(&data_dir)? {
+            Some(_) => {
+                let iv: [u8; 16] = rand::random();
+                ReencryptedResult { iv: Some(iv), ciphertext: transcipherer.encrypt_ctr(&result, &iv)? }
+            }
+            None => ReencryptedResult { iv: None, ciphertext: transcipherer.encrypt_blocks(&pkcs7_pad(&result))? },
+        };
+        let reencrypted_path = format!("{}/result_aes.bin", target_dir);
+        write_artifact(&reencrypted_path, PayloadType::ReencryptedResult, param, key_set_id, &reencrypted)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use auto_base_conv::{
+        decompress_seeded_auto_keys, gen_all_seeded_auto_keys, generate_seeded_scheme_switching_key,
+        keygen_seeded_pbs_with_glwe_ks, AesParam, AES_TIGHT,
+    };
+    use tfhe::core_crypto::seeders::new_seeder;
+
+    /// Runs the client key generation and the server preprocessing for
+    /// `param`, returning the LWE secret key and the server keys.
+    fn server_key_set(param: &AesParam<u64>, noise_seeder: &mut dyn Seeder) -> (LweSecretKeyOwned<u64>, ServerKeySet) {
+        let mut secret_generator = SecretRandomGenerator::<ActivatedRandomGenerator>::new(noise_seeder.seed());
+        let (lwe_sk, glwe_sk, _, bsk, ksk) = keygen_seeded_pbs_with_glwe_ks(
+            param.lwe_dimension(),
+            param.glwe_dimension(),
+            param.polynomial_size(),
+            param.lwe_modular_std_dev(),
+            param.glwe_modular_std_dev(),
+            param.pbs_base_log(),
+            param.pbs_level(),
+            param.glwe_ds_base_log(),
+            param.glwe_ds_level(),
+            param.common_polynomial_size(),
+            param.ciphertext_modulus(),
+            &mut secret_generator,
+            noise_seeder,
         );
-        for (mut ggsw, glev) in ggsw_bit_list.iter_mut().zip(vec_glev.iter()) {
-            switch_scheme(&glev, &mut ggsw, fourier_ss_key);
-        }
+        let ss_key = generate_seeded_scheme_switching_key(
+            &glwe_sk,
+            param.ss_base_log(),
+            param.ss_level(),
+            param.glwe_modular_std_dev(),
+            param.ciphertext_modulus(),
+            noise_seeder,
+        );
+        let auto_keys = gen_all_seeded_auto_keys(
+            param.auto_base_log(),
+            param.auto_level(),
+            &glwe_sk,
+            param.glwe_modular_std_dev(),
+            noise_seeder,
+        );
+
+        let auto_keys = decompress_seeded_auto_keys(auto_keys, param.fft_type_auto())
+            .into_iter()
+            .map(|(k, v)| (k, v.to_serializable()))
+            .collect();
+        let bundle = FourierKeyBundle::from_standard_keys(
+            param,
+            &bsk.decompress_into_lwe_bootstrap_key(),
+            &ksk.decompress_into_glwe_keyswitch_key(),
+            &ss_key.decompress_into_ggsw_ciphertext_list(),
+            auto_keys,
+        );
+        (lwe_sk, ServerKeySet::from_bundle(bundle, param).unwrap())
+    }
 
-        ggsw_chunks.push(ggsw_bit_list);
+    fn cbs_keys<'a>(param: &AesParam<u64>, keys: &'a ServerKeySet) -> CbsKeys<'a> {
+        CbsKeys {
+            fourier_glwe_ksk: &keys.fourier_glwe_ksk,
+            fourier_bsk: keys.fourier_bsk.as_view(),
+            auto_keys: &keys.auto_keys,
+            ss_key: keys.ss_key.as_view(),
+            base_log: param.cbs_base_log(),
+            level: param.cbs_level(),
+            log_lut_count: param.log_lut_count(),
+            contexts: Mutex::new(Vec::new()),
+        }
     }
 
-    let mut lwe_chunks: Vec<LweCiphertextList<Vec<u64>>> = Vec::new();
-    for lwe_chunk in lwe_ciphertext_list.chunks_exact(16) {
-        let mut list = LweCiphertextList::new(
+    /// Encrypts the bits of `elements`, MSB first, as the transciphering outputs them.
+    fn encrypt_elements(
+        param: &AesParam<u64>,
+        lwe_sk: &LweSecretKeyOwned<u64>,
+        elements: &[u16],
+        noise_seeder: &mut dyn Seeder,
+    ) -> LweCiphertextListOwned<u64> {
+        let mut encryption_generator =
+            EncryptionRandomGenerator::<ActivatedRandomGenerator>::new(noise_seeder.seed(), noise_seeder);
+        let plaintexts: Vec<u64> = elements
+            .iter()
+            .flat_map(|element| (0..BITS_PER_ELEMENT).rev().map(move |i| ((*element as u64 >> i) & 1) << 63))
+            .collect();
+        let mut lwe_list = LweCiphertextList::new(
             0u64,
-            lwe_ciphertext_list.lwe_size(),
-            LweCiphertextCount(16),
-            ciphertext_modulus,
+            lwe_sk.lwe_dimension().to_lwe_size(),
+            LweCiphertextCount(plaintexts.len()),
+            param.ciphertext_modulus(),
+        );
+        encrypt_lwe_ciphertext_list(
+            lwe_sk,
+            &mut lwe_list,
+            &PlaintextList::from_container(plaintexts),
+            param.glwe_modular_std_dev(),
+            &mut encryption_generator,
         );
-        list.as_mut().clone_from_slice(lwe_chunk.as_ref());
-        lwe_chunks.push(list);
+        lwe_list
     }
 
-    let mut mid_lwe_list = LweCiphertextList::new(
-        0u64,
-        lwe_ciphertext_list.lwe_size(),
-        LweCiphertextCount(16),
-        ciphertext_modulus,
-    );
-
-    let mut mid_lwe_list_2 = mid_lwe_list.clone();
+    fn decrypt_element(lwe_sk: &LweSecretKeyOwned<u64>, lwe_list: &LweCiphertextListOwned<u64>) -> u16 {
+        assert_eq!(lwe_list.lwe_ciphertext_count().0, BITS_PER_ELEMENT);
+        lwe_list.iter().fold(0u16, |element, lwe_bit| {
+            let plaintext = decrypt_lwe_ciphertext(lwe_sk, &lwe_bit).0;
+            (element << 1) | (plaintext.wrapping_add(1 << 62) >> 63) as u16
+        })
+    }
 
-    let mut mid_ggsw_list = GgswCiphertextList::new(
-        0,
-        glwe_size,
-        polynomial_size,
-        base_log,
-        level,
-        GgswCiphertextCount(16),
-        ciphertext_modulus,
-    );
+    #[test]
+    fn inner_product_matches_plaintext() {
+        let param = &*AES_TIGHT;
+        let mut boxed_seeder = new_seeder();
+        let seeder = boxed_seeder.as_mut();
+        let (lwe_sk, keys) = server_key_set(param, seeder);
+        let cbs_keys = cbs_keys(param, &keys);
+
+        // 0xFFFF * 0xFFFF carries through every bit of every row
+        let elements = [0xFFFF, 0x1234, 0xFFFF, 0xABCD];
+        let expected = 0xFFFFu16.wrapping_mul(0xFFFF).wrapping_add(0x1234u16.wrapping_mul(0xABCD));
+
+        let lwe_list = encrypt_elements(param, &lwe_sk, &elements, seeder);
+        let result = inner_product(&lwe_list, &cbs_keys).unwrap();
+        assert_eq!(decrypt_element(&lwe_sk, &result), expected);
+    }
 
-    max_of_two(
-        &ggsw_chunks[0],
-        &ggsw_chunks[1],
-        &lwe_chunks[0],
-        &lwe_chunks[1],
-        &mut mid_lwe_list_2,
-    );
-    for i in 2_usize..8 {
-        let mut vec_glev = vec![
-            GlweCiphertextList::new(
-                0,
-                glwe_size,
-                polynomial_size,
-                GlweCiphertextCount(level.0),
-                ciphertext_modulus,
-            );
-            16
-        ];
-        mid_lwe_list.clone_from(&mid_lwe_list_2);
-        for (input_bit, glev) in mid_lwe_list.iter().zip(vec_glev.iter_mut()) {
-            let glev_mut_view = GlweCiphertextListMutView::from_container(
-                glev.as_mut(),
-                glwe_size,
-                polynomial_size,
-                ciphertext_modulus,
-            );
-
-            keyswitch_lwe_ciphertext_by_glwe_keyswitch(
-                &input_bit.as_view(),
-                &mut lwe_buffer,
-                &fourier_glwe_ksk,
-            );
-
-            lwe_msb_bit_to_glev_by_trace_with_preprocessing(
-                lwe_buffer.as_view(),
-                glev_mut_view,
-                fourier_bsk,
-                &auto_keys,
-                base_log,
-                level,
-                log_lut_count,
-            );
-        }
+    fn check_tournament_max(elements: &[u16]) {
+        let param = &*AES_TIGHT;
+        let mut boxed_seeder = new_seeder();
+        let seeder = boxed_seeder.as_mut();
+        let (lwe_sk, keys) = server_key_set(param, seeder);
+        let cbs_keys = cbs_keys(param, &keys);
 
-        for (mut ggsw, glev) in mid_ggsw_list.iter_mut().zip(vec_glev.iter()) {
-            switch_scheme(&glev, &mut ggsw, fourier_ss_key);
-        }
+        let lwe_list = encrypt_elements(param, &lwe_sk, elements, seeder);
+        let result = tournament_max(&lwe_list, &cbs_keys).unwrap();
+        assert_eq!(decrypt_element(&lwe_sk, &result), *elements.iter().max().unwrap(), "{:04x?}", elements);
+    }
 
-        max_of_two(
-            &mid_ggsw_list,
-            &ggsw_chunks[i],
-            &mid_lwe_list,
-            &lwe_chunks[i],
-            &mut mid_lwe_list_2,
-        );
+    #[test]
+    fn tournament_max_of_odd_count() {
+        // The odd element out is the maximum, and it is 0xFFFF
+        check_tournament_max(&[0x1234, 0x8000, 0x7FFF, 0x0001, 0xFFFF]);
     }
 
-    // Save final result
-    let final_result_path = format!("{}/result.bin", target_dir);
-    let final_result_bytes = bincode::serialize(&mid_lwe_list_2)?;
-    fs::write(&final_result_path, final_result_bytes)?;
+    #[test]
+    fn tournament_max_of_single_element() {
+        check_tournament_max(&[0xBEEF]);
+    }
 
-    Ok(())
+    #[test]
+    fn tournament_max_with_tied_maximum() {
+        check_tournament_max(&[0x00FF, 0xA5A5, 0x0F0F, 0xA5A5, 0xA5A4, 0x5A5A]);
+    }
 }
@@ -1,15 +1,66 @@
+use std::collections::HashMap;
 use std::env;
+use std::fs;
 
-use submission::help_fun::get_size_string;
+use auto_base_conv::{
+    decompress_seeded_auto_keys, SeededGlweKeyswitchKey, SeededSchemeSwitchingKey,
+};
+use submission::envelope::{PayloadType, read_artifact, write_artifact};
+use submission::fourier_keys::FourierKeyBundle;
+use submission::help_fun::{get_size_string, select_aes_param, select_half_cbs_param};
+use tfhe::core_crypto::prelude::SeededLweBootstrapKeyOwned;
 
 pub fn main() -> Result<(), Box<dyn std::error::Error>> {
     let args: Vec<String> = env::args().collect();
     if args.len() < 2 {
-        eprintln!("Usage: {} <size>", args[0]);
+        eprintln!("Usage: {} <size> [--param <name>]", args[0]);
         std::process::exit(1); 
     }
     let size = args[1].clone();
     let io_dir = "io/".to_owned() + get_size_string(size.parse::<usize>()?);
 
+    let param = select_aes_param(&args[2..])?;
+    let public_keys_dir = format!("{}/public_keys", io_dir);
+    let (bsk, key_set_id): (SeededLweBootstrapKeyOwned<u64>, _) =
+        read_artifact(&format!("{}/bsk.bin", public_keys_dir), PayloadType::SeededBootstrapKey, param, None)?;
+    let key_set = Some(key_set_id);
+    let (ksk, _): (SeededGlweKeyswitchKey<u64>, _) =
+        read_artifact(&format!("{}/ksk.bin", public_keys_dir), PayloadType::SeededGlweKeyswitchKey, param, key_set)?;
+    let (ss_key, _): (SeededSchemeSwitchingKey<u64>, _) =
+        read_artifact(&format!("{}/ss_key.bin", public_keys_dir), PayloadType::SeededSchemeSwitchKey, param, key_set)?;
+    let (auto_keys, _): (HashMap<usize, SeededGlweKeyswitchKey<u64>>, _) =
+        read_artifact(&format!("{}/auto_keys.bin", public_keys_dir), PayloadType::SeededAutomorphKeys, param, key_set)?;
+
+    // Regenerate the masks from their seeds
+    let bsk = bsk.decompress_into_lwe_bootstrap_key();
+    let ksk = ksk.decompress_into_glwe_keyswitch_key();
+    let ss_key = ss_key.decompress_into_ggsw_ciphertext_list();
+    let auto_keys = decompress_seeded_auto_keys(auto_keys, param.fft_type_auto())
+        .into_iter()
+        .map(|(k, v)| (k, v.to_serializable()))
+        .collect();
+
+    // Convert the evaluation keys to the Fourier domain once for both servers
+    let mut bundle = FourierKeyBundle::from_standard_keys(param, &bsk, &ksk, &ss_key, auto_keys);
+
+    if let Some(half_cbs_param) = select_half_cbs_param(&args[2..])? {
+        let (ss_key, _): (SeededSchemeSwitchingKey<u64>, _) =
+            read_artifact(&format!("{}/half_cbs_ss_key.bin", public_keys_dir), PayloadType::SeededSchemeSwitchKey, param, key_set)?;
+        let (auto_keys, _): (HashMap<usize, SeededGlweKeyswitchKey<u64>>, _) =
+            read_artifact(&format!("{}/half_cbs_auto_keys.bin", public_keys_dir), PayloadType::SeededAutomorphKeys, param, key_set)?;
+
+        let ss_key = ss_key.decompress_into_ggsw_ciphertext_list();
+        let auto_keys = decompress_seeded_auto_keys(auto_keys, half_cbs_param.half_cbs_fft_type_auto())
+            .into_iter()
+            .map(|(k, v)| (k, v.to_serializable()))
+            .collect();
+        bundle = bundle.with_half_cbs_keys(&ss_key, auto_keys);
+    }
+
+    let server_keys_dir = format!("{}/server_keys", io_dir);
+    fs::create_dir_all(&server_keys_dir)?;
+    let bundle_path = format!("{}/fourier_keys.bin", server_keys_dir);
+    write_artifact(&bundle_path, PayloadType::FourierKeyBundle, param, key_set_id, &bundle)?;
+
     Ok(())
-}
\ No newline at end of file
+}
@@ -0,0 +1,154 @@
+//! Compact download form of result LWE ciphertexts. Each ciphertext only
+//! carries one bit at `2^63`, so the server switches it to a small
+//! power-of-two modulus and writes the remaining high bits of every
+//! coefficient back to back.
+
+use std::error::Error;
+
+use auto_base_conv::lwe_ciphertext_mod_switch_from_native_to_non_native_power_of_two;
+use serde::{Deserialize, Serialize};
+use tfhe::core_crypto::prelude::*;
+
+/// Modulus of the compressed results, as a power of two. Switching to it adds
+/// an error of standard deviation about `2^48 * sqrt(n / 24)` for an LWE
+/// dimension `n`, far below the `2^62` that a bit tolerates, and cuts the size
+/// of a ciphertext by 4.
+pub const RESULT_LOG_MODULUS: usize = 16;
+
+/// LWE ciphertexts switched to the modulus `2^log_modulus`, with the
+/// `log_modulus` bits of every coefficient packed into 64-bit words.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct CompressedLweList {
+    pub lwe_size: usize,
+    pub lwe_count: usize,
+    pub log_modulus: usize,
+    pub data: Vec<u64>,
+}
+
+impl CompressedLweList {
+    /// Switches every ciphertext of `lwe_list` to the modulus
+    /// `2^log_modulus`, rounding to the nearest multiple, and packs them.
+    pub fn compress(lwe_list: &LweCiphertextList<Vec<u64>>, log_modulus: usize) -> Result<Self, Box<dyn Error>> {
+        if !(1..u64::BITS as usize).contains(&log_modulus) {
+            return Err(format!("cannot compress to a modulus of 2^{}", log_modulus).into());
+        }
+        let lwe_size = lwe_list.lwe_size();
+        let small_modulus = CiphertextModulus::<u64>::try_new_power_of_2(log_modulus)
+            .map_err(|_| format!("cannot compress to a modulus of 2^{}", log_modulus))?;
+        let half_step = small_modulus.get_power_of_two_scaling_to_native_torus() / 2;
+        let shift = u64::BITS as usize - log_modulus;
+
+        let num_coefs = lwe_size.0 * lwe_list.lwe_ciphertext_count().0;
+        let mut data = vec![0u64; (num_coefs * log_modulus).div_ceil(u64::BITS as usize)];
+        let mut rounded = LweCiphertext::new(0u64, lwe_size, lwe_list.ciphertext_modulus());
+        let mut switched = LweCiphertext::new(0u64, lwe_size, small_modulus);
+        for (lwe_idx, lwe) in lwe_list.iter().enumerate() {
+            // The switch truncates, so shift by half a step first to round
+            for (dst, src) in rounded.as_mut().iter_mut().zip(lwe.as_ref()) {
+                *dst = src.wrapping_add(half_step);
+            }
+            lwe_ciphertext_mod_switch_from_native_to_non_native_power_of_two(&rounded, &mut switched);
+
+            for (coef_idx, &coef) in switched.as_ref().iter().enumerate() {
+                let bit_pos = (lwe_idx * lwe_size.0 + coef_idx) * log_modulus;
+                let (word, offset) = (bit_pos / u64::BITS as usize, bit_pos % u64::BITS as usize);
+                let value = coef >> shift;
+                data[word] |= value << offset;
+                if offset + log_modulus > u64::BITS as usize {
+                    data[word + 1] |= value >> (u64::BITS as usize - offset);
+                }
+            }
+        }
+
+        Ok(CompressedLweList {
+            lwe_size: lwe_size.0,
+            lwe_count: lwe_list.lwe_ciphertext_count().0,
+            log_modulus,
+            data,
+        })
+    }
+
+    /// Unpacks the ciphertexts back to the native modulus, which keeps the
+    /// switching error but decrypts like the original ones.
+    pub fn decompress(&self) -> Result<LweCiphertextListOwned<u64>, Box<dyn Error>> {
+        let num_coefs = self.lwe_size * self.lwe_count;
+        if !(1..u64::BITS as usize).contains(&self.log_modulus)
+            || self.lwe_size == 0
+            || self.data.len() != (num_coefs * self.log_modulus).div_ceil(u64::BITS as usize)
+        {
+            return Err(format!(
+                "a compressed list of {} LWE ciphertexts of size {} at {} bits per coefficient \
+                does not fit {} words",
+                self.lwe_count,
+                self.lwe_size,
+                self.log_modulus,
+                self.data.len()
+            )
+            .into());
+        }
+
+        let shift = u64::BITS as usize - self.log_modulus;
+        let mask = (1u64 << self.log_modulus) - 1;
+        let container = (0..num_coefs)
+            .map(|coef_idx| {
+                let bit_pos = coef_idx * self.log_modulus;
+                let (word, offset) = (bit_pos / u64::BITS as usize, bit_pos % u64::BITS as usize);
+                let mut value = self.data[word] >> offset;
+                if offset + self.log_modulus > u64::BITS as usize {
+                    value |= self.data[word + 1] << (u64::BITS as usize - offset);
+                }
+                (value & mask) << shift
+            })
+            .collect();
+
+        Ok(LweCiphertextList::from_container(
+            container,
+            LweSize(self.lwe_size),
+            CiphertextModulus::new_native(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::help_fun::decrypt_decode_lwe_list;
+    use auto_base_conv::AES_TIGHT;
+    use tfhe::core_crypto::seeders::new_seeder;
+
+    #[test]
+    fn compress_round_trip() {
+        let param = *AES_TIGHT;
+        let lwe_dimension = LweDimension(param.glwe_dimension().0 * param.polynomial_size().0);
+        let mut boxed_seeder = new_seeder();
+        let seeder = boxed_seeder.as_mut();
+        let mut secret_generator = SecretRandomGenerator::<ActivatedRandomGenerator>::new(seeder.seed());
+        let mut encryption_generator =
+            EncryptionRandomGenerator::<ActivatedRandomGenerator>::new(seeder.seed(), seeder);
+
+        let lwe_sk = LweSecretKey::generate_new_binary(lwe_dimension, &mut secret_generator);
+        let bits: Vec<u64> = (0..100).map(|_| rand::random::<u64>() & 1).collect();
+        let plaintexts = PlaintextList::from_container(bits.iter().map(|bit| bit << 63).collect::<Vec<_>>());
+        let mut lwe_list = LweCiphertextList::new(
+            0u64,
+            lwe_dimension.to_lwe_size(),
+            LweCiphertextCount(bits.len()),
+            param.ciphertext_modulus(),
+        );
+        encrypt_lwe_ciphertext_list(&lwe_sk, &mut lwe_list, &plaintexts, param.glwe_modular_std_dev(), &mut encryption_generator);
+
+        // 21 bits per coefficient makes coefficients straddle two words
+        for log_modulus in [RESULT_LOG_MODULUS, 21] {
+            let compressed = CompressedLweList::compress(&lwe_list, log_modulus).unwrap();
+            let num_coefs = lwe_dimension.to_lwe_size().0 * bits.len();
+            assert_eq!(compressed.data.len(), (num_coefs * log_modulus).div_ceil(u64::BITS as usize));
+
+            let decompressed = compressed.decompress().unwrap();
+            assert_eq!(decompressed.lwe_ciphertext_count(), lwe_list.lwe_ciphertext_count());
+            assert_eq!(decrypt_decode_lwe_list(&lwe_sk, &decompressed), bits);
+        }
+
+        assert!(CompressedLweList::compress(&lwe_list, 0).is_err());
+        assert!(CompressedLweList::compress(&lwe_list, u64::BITS as usize).is_err());
+    }
+}
@@ -1,270 +1,480 @@
-use auto_base_conv::{AesParam, generate_vec_keyed_lut_accumulator};
+use std::ops::Range;
+
+use auto_base_conv::{AesHalfCBSParam, AesParam, Error, generate_vec_seeded_keyed_lut_accumulator, generate_vec_seeded_keyed_lut_entry_table, generate_vec_seeded_keyed_lut_glev, generate_vec_seeded_packed_keyed_lut_accumulator};
 use serde::{Deserialize, Serialize};
-use tfhe::core_crypto::prelude::{ActivatedRandomGenerator, CastInto, EncryptionRandomGenerator, GlweCiphertext, GlweCiphertextList, GlweSecretKey, PlaintextList, allocate_and_trivially_encrypt_new_glwe_ciphertext};
+use tfhe::core_crypto::prelude::{Container, ContiguousEntityContainer, GlweCiphertext, GlweCiphertextList, GlweCiphertextListOwned, GlweSecretKey, LweCiphertextList, SeededGlweCiphertextListOwned, SeededLweCiphertextListOwned, Seeder};
 
-use crate::aes_manager::{Aes128Manager, BYTESIZE};
+use crate::aes_manager::{AesManager, BYTESIZE};
 
 
 
 /////////////////////// Data Structures ///////////////////////
+/// Keyed LUTs for the inverse cipher (ECB mode). The number of middle rounds
+/// follows the key size: Nr - 2 of them, stored from round 1 up. Each byte of
+/// a middle round packs its ×9, ×11, ×13 and ×14 keyed LUTs, in that order,
+/// into one set of accumulators.
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct AllRdKeys {
-    pub _10_9_round_key: (
+    #[allow(clippy::type_complexity)]
+    pub _last_two_round_key: (
         Vec<GlweCiphertextList<Vec<u64>>>,
         Vec<GlweCiphertextList<Vec<u64>>>,
         Vec<GlweCiphertextList<Vec<u64>>>,
         Vec<GlweCiphertextList<Vec<u64>>>,
     ),
 
-    pub _8_to_1_round_key: Vec<(
-        Vec<Vec<GlweCiphertext<Vec<u64>>>>,
-        Vec<Vec<GlweCiphertext<Vec<u64>>>>,
-        Vec<Vec<GlweCiphertext<Vec<u64>>>>,
-        Vec<Vec<GlweCiphertext<Vec<u64>>>>,
+    pub _middle_round_key: Vec<Vec<GlweCiphertextList<Vec<u64>>>>,
+    pub _0_round_key: Vec<GlweCiphertextList<Vec<u64>>>,
+}
+
+/// Keyed LUTs for evaluating forward AES on public counter blocks (CTR mode),
+/// with one entry per round 1 to Nr - 1 in `_middle_round_key`.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct AllFwdRdKeys {
+    #[allow(clippy::type_complexity)]
+    pub _middle_round_key: Vec<(
+        Vec<GlweCiphertextList<Vec<u64>>>,
+        Vec<GlweCiphertextList<Vec<u64>>>,
+        Vec<GlweCiphertextList<Vec<u64>>>,
     )>,
-    pub _0_round_key: Vec<Vec<GlweCiphertext<Vec<u64>>>>,
+    pub _last_round_key: Vec<GlweCiphertextList<Vec<u64>>>,
 }
 
-///////////////////////////// local helper functions /////////////////////////////
+/// Keyed LUTs of the half-CBS pipeline (ECB mode).
+///
+/// The first step looks up GLev encryptions of the ×9, ×11, ×13 and ×14 LUTs,
+/// indexed by level then byte, so that its output is a Lev state that converts
+/// to GGSW without a circuit bootstrap. Round Nr - 2 is then one entry table
+/// per byte, evaluated on those GGSWs. The rounds below are laid out as in
+/// [`AllRdKeys`], with Nr - 3 middle rounds, and are circuit bootstrapped as
+/// in the patched WWLp CBS pipeline: only the Nr - 1 to Nr - 2 transition
+/// keeps the state in Lev form.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct AllHalfCbsRdKeys {
+    #[allow(clippy::type_complexity)]
+    pub _last_two_round_key: (
+        Vec<Vec<GlweCiphertextList<Vec<u64>>>>,
+        Vec<Vec<GlweCiphertextList<Vec<u64>>>>,
+        Vec<Vec<GlweCiphertextList<Vec<u64>>>>,
+        Vec<Vec<GlweCiphertextList<Vec<u64>>>>,
+    ),
+    pub _entry_table_round_key: Vec<GlweCiphertextList<Vec<u64>>>,
 
-pub fn get_10_9_round_key(
-    param: &AesParam<u64>,
-    glwe_sk: &GlweSecretKey<Vec<u64>>,
-    aes: &Aes128Manager,
-    encryption_generator: &mut EncryptionRandomGenerator<ActivatedRandomGenerator>,
-) -> (
-    Vec<GlweCiphertextList<Vec<u64>>>,
-    Vec<GlweCiphertextList<Vec<u64>>>,
-    Vec<GlweCiphertextList<Vec<u64>>>,
-    Vec<GlweCiphertextList<Vec<u64>>>,
-) {
-    let (times_14, times_11, times_13, times_9) = aes.get_10_9_round_lut();
-    let glwe_modular_std_dev = param.glwe_modular_std_dev();
-    let ciphertext_modulus = param.ciphertext_modulus();
-    let round_10_9_he_lut_times14 = generate_vec_keyed_lut_accumulator(
-        times_14,
-        u64::BITS as usize - 1,
-        &glwe_sk,
-        glwe_modular_std_dev,
-        ciphertext_modulus,
-        encryption_generator,
-    );
-    let round_10_9_he_lut_times11 = generate_vec_keyed_lut_accumulator(
-        times_11,
-        u64::BITS as usize - 1,
-        &glwe_sk,
-        glwe_modular_std_dev,
-        ciphertext_modulus,
-        encryption_generator,
-    );
-    let round_10_9_he_lut_times13 = generate_vec_keyed_lut_accumulator(
-        times_13,
-        u64::BITS as usize - 1,
-        &glwe_sk,
-        glwe_modular_std_dev,
-        ciphertext_modulus,
-        encryption_generator,
-    );
-    let round_10_9_he_lut_times9 = generate_vec_keyed_lut_accumulator(
-        times_9,
-        u64::BITS as usize - 1,
-        &glwe_sk,
-        glwe_modular_std_dev,
-        ciphertext_modulus,
-        encryption_generator,
-    );
-    (
-        round_10_9_he_lut_times9,
-        round_10_9_he_lut_times11,
-        round_10_9_he_lut_times13,
-        round_10_9_he_lut_times14,
-    )
+    pub _middle_round_key: Vec<Vec<GlweCiphertextList<Vec<u64>>>>,
+    pub _0_round_key: Vec<GlweCiphertextList<Vec<u64>>>,
 }
 
-pub fn get_8_to_1_round_key(
-    param: &AesParam<u64>,
-    glwe_sk: &GlweSecretKey<Vec<u64>>,
-    aes: &Aes128Manager,
-    encryption_generator: &mut EncryptionRandomGenerator<ActivatedRandomGenerator>,
-) -> Vec<(
-    Vec<Vec<GlweCiphertext<Vec<u64>>>>,
-    Vec<Vec<GlweCiphertext<Vec<u64>>>>,
-    Vec<Vec<GlweCiphertext<Vec<u64>>>>,
-    Vec<Vec<GlweCiphertext<Vec<u64>>>>,
-)> {
-    let mut all_rk: Vec<(
-        Vec<Vec<GlweCiphertext<Vec<u64>>>>,
-        Vec<Vec<GlweCiphertext<Vec<u64>>>>,
-        Vec<Vec<GlweCiphertext<Vec<u64>>>>,
-        Vec<Vec<GlweCiphertext<Vec<u64>>>>,
-    )> = Vec::new();
-    for round in 1..=8 {
-        let (he_lut_times9, he_lut_times11, he_lut_times13, he_lut_times14) =
-            get_round_keys(param, aes, round);
-        all_rk.push((
-            he_lut_times9,
-            he_lut_times11,
-            he_lut_times13,
-            he_lut_times14,
-        ));
-    }
+/// Transciphering key uploaded by the client, one variant per AES mode.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum TransKey {
+    Ecb(AllRdKeys),
+    Ctr(AllFwdRdKeys),
+    /// The 128, 192 or 256 AES key bits alone, encrypted under the GLWE key
+    /// seen as an LWE key and laid out like the HE state. The server expands
+    /// it itself and serves both ECB and CTR from it.
+    MasterKey(LweCiphertextList<Vec<u64>>),
+    /// ECB through the half-CBS pipeline.
+    HalfCbs(AllHalfCbsRdKeys),
+}
 
-    all_rk
+/// Compute result encrypted under the AES key by the server, still under FHE.
+/// The bits are MSB first within each byte. `iv` is set for CTR mode; in
+/// ECB mode the plaintext was padded with PKCS#7 to a whole number of blocks.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ReencryptedResult {
+    pub iv: Option<[u8; 16]>,
+    pub ciphertext: LweCiphertextList<Vec<u64>>,
 }
-fn get_round_keys(
-    param: &AesParam<u64>,
-    aes: &Aes128Manager,
-    round: usize,
-) -> (
-    Vec<Vec<GlweCiphertext<Vec<u64>>>>,
-    Vec<Vec<GlweCiphertext<Vec<u64>>>>,
-    Vec<Vec<GlweCiphertext<Vec<u64>>>>,
-    Vec<Vec<GlweCiphertext<Vec<u64>>>>,
-) {
-    //16 * 2 accumulator
-    let (times_14, times_11, times_13, times_9) = aes.get_round_lut(round);
-    let mut he_lut_times14: Vec<Vec<GlweCiphertext<Vec<u64>>>> = Vec::new();
-    let mut he_lut_times11: Vec<Vec<GlweCiphertext<Vec<u64>>>> = Vec::new();
-    let mut he_lut_times13: Vec<Vec<GlweCiphertext<Vec<u64>>>> = Vec::new();
-    let mut he_lut_times9: Vec<Vec<GlweCiphertext<Vec<u64>>>> = Vec::new();
-    let num_accumulator = 2;
-    let log_scale = 63;
-    let num_par_lut = 4;
-
-    for lut_9 in times_9.iter() {
-        let mut temp_vec: Vec<GlweCiphertext<Vec<u64>>> = Vec::new();
-        for acc_idx in 0..num_accumulator {
-            let accumulator = (0..param.polynomial_size().0)
-                .map(|i| {
-                    let lut_idx = acc_idx * num_par_lut + i / (1 << BYTESIZE);
-                    (((lut_9[i % (1 << BYTESIZE)] & (1 << lut_idx)) as usize)
-                        << (log_scale - lut_idx))
-                        .cast_into()
-                })
-                .collect::<Vec<u64>>();
-            let accumulator_plaintext = PlaintextList::from_container(accumulator);
-            let accumulator: GlweCiphertext<Vec<u64>> =
-                allocate_and_trivially_encrypt_new_glwe_ciphertext(
-                    param.glwe_dimension().to_glwe_size(),
-                    &accumulator_plaintext,
-                    param.ciphertext_modulus(),
-                );
-            temp_vec.push(accumulator);
+
+/// Upload form of [`AllRdKeys`]. Each byte's accumulators are one seeded GLWE
+/// list, so only their bodies and a seed travel to the server.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SeededAllRdKeys {
+    #[allow(clippy::type_complexity)]
+    pub _last_two_round_key: (
+        Vec<SeededGlweCiphertextListOwned<u64>>,
+        Vec<SeededGlweCiphertextListOwned<u64>>,
+        Vec<SeededGlweCiphertextListOwned<u64>>,
+        Vec<SeededGlweCiphertextListOwned<u64>>,
+    ),
+
+    pub _middle_round_key: Vec<Vec<SeededGlweCiphertextListOwned<u64>>>,
+    pub _0_round_key: Vec<SeededGlweCiphertextListOwned<u64>>,
+}
+
+/// Upload form of [`AllFwdRdKeys`].
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SeededAllFwdRdKeys {
+    #[allow(clippy::type_complexity)]
+    pub _middle_round_key: Vec<(
+        Vec<SeededGlweCiphertextListOwned<u64>>,
+        Vec<SeededGlweCiphertextListOwned<u64>>,
+        Vec<SeededGlweCiphertextListOwned<u64>>,
+    )>,
+    pub _last_round_key: Vec<SeededGlweCiphertextListOwned<u64>>,
+}
+
+/// Upload form of [`AllHalfCbsRdKeys`].
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SeededAllHalfCbsRdKeys {
+    #[allow(clippy::type_complexity)]
+    pub _last_two_round_key: (
+        Vec<Vec<SeededGlweCiphertextListOwned<u64>>>,
+        Vec<Vec<SeededGlweCiphertextListOwned<u64>>>,
+        Vec<Vec<SeededGlweCiphertextListOwned<u64>>>,
+        Vec<Vec<SeededGlweCiphertextListOwned<u64>>>,
+    ),
+    pub _entry_table_round_key: Vec<SeededGlweCiphertextListOwned<u64>>,
+
+    pub _middle_round_key: Vec<Vec<SeededGlweCiphertextListOwned<u64>>>,
+    pub _0_round_key: Vec<SeededGlweCiphertextListOwned<u64>>,
+}
+
+/// Upload form of [`TransKey`], expanded by the server with
+/// [`SeededTransKey::decompress_into_trans_key`].
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum SeededTransKey {
+    Ecb(SeededAllRdKeys),
+    Ctr(SeededAllFwdRdKeys),
+    MasterKey(SeededLweCiphertextListOwned<u64>),
+    HalfCbs(SeededAllHalfCbsRdKeys),
+}
+
+impl SeededAllRdKeys {
+    pub fn decompress_into_all_rd_keys(self) -> AllRdKeys {
+        let (times_9, times_11, times_13, times_14) = self._last_two_round_key;
+        AllRdKeys {
+            _last_two_round_key: (
+                decompress_lists(times_9),
+                decompress_lists(times_11),
+                decompress_lists(times_13),
+                decompress_lists(times_14),
+            ),
+            _middle_round_key: self
+                ._middle_round_key
+                .into_iter()
+                .map(decompress_lists)
+                .collect(),
+            _0_round_key: decompress_lists(self._0_round_key),
         }
-        he_lut_times9.push(temp_vec);
     }
+}
 
-    for lut_11 in times_11.iter() {
-        let mut temp_vec: Vec<GlweCiphertext<Vec<u64>>> = Vec::new();
-        for acc_idx in 0..num_accumulator {
-            let accumulator = (0..param.polynomial_size().0)
-                .map(|i| {
-                    let lut_idx = acc_idx * num_par_lut + i / (1 << BYTESIZE);
-                    (((lut_11[i % (1 << BYTESIZE)] & (1 << lut_idx)) as usize)
-                        << (log_scale - lut_idx))
-                        .cast_into()
+impl SeededAllFwdRdKeys {
+    pub fn decompress_into_all_fwd_rd_keys(self) -> AllFwdRdKeys {
+        AllFwdRdKeys {
+            _middle_round_key: self
+                ._middle_round_key
+                .into_iter()
+                .map(|(times_1, times_2, times_3)| {
+                    (
+                        decompress_lists(times_1),
+                        decompress_lists(times_2),
+                        decompress_lists(times_3),
+                    )
                 })
-                .collect::<Vec<u64>>();
-            let accumulator_plaintext = PlaintextList::from_container(accumulator);
-            let accumulator: GlweCiphertext<Vec<u64>> =
-                allocate_and_trivially_encrypt_new_glwe_ciphertext(
-                    param.glwe_dimension().to_glwe_size(),
-                    &accumulator_plaintext,
-                    param.ciphertext_modulus(),
-                );
-            temp_vec.push(accumulator);
+                .collect(),
+            _last_round_key: decompress_lists(self._last_round_key),
         }
-        he_lut_times11.push(temp_vec);
     }
+}
 
-    for lut_13 in times_13.iter() {
-        let mut temp_vec: Vec<GlweCiphertext<Vec<u64>>> = Vec::new();
-        for acc_idx in 0..num_accumulator {
-            let accumulator = (0..param.polynomial_size().0)
-                .map(|i| {
-                    let lut_idx = acc_idx * num_par_lut + i / (1 << BYTESIZE);
-                    (((lut_13[i % (1 << BYTESIZE)] & (1 << lut_idx)) as usize)
-                        << (log_scale - lut_idx))
-                        .cast_into()
-                })
-                .collect::<Vec<u64>>();
-            let accumulator_plaintext = PlaintextList::from_container(accumulator);
-            let accumulator: GlweCiphertext<Vec<u64>> =
-                allocate_and_trivially_encrypt_new_glwe_ciphertext(
-                    param.glwe_dimension().to_glwe_size(),
-                    &accumulator_plaintext,
-                    param.ciphertext_modulus(),
-                );
-            temp_vec.push(accumulator);
+impl SeededAllHalfCbsRdKeys {
+    pub fn decompress_into_all_half_cbs_rd_keys(self) -> AllHalfCbsRdKeys {
+        let (times_9, times_11, times_13, times_14) = self._last_two_round_key;
+        let decompress_glev = |glev: Vec<Vec<SeededGlweCiphertextListOwned<u64>>>| {
+            glev.into_iter().map(decompress_lists).collect()
+        };
+        AllHalfCbsRdKeys {
+            _last_two_round_key: (
+                decompress_glev(times_9),
+                decompress_glev(times_11),
+                decompress_glev(times_13),
+                decompress_glev(times_14),
+            ),
+            _entry_table_round_key: decompress_lists(self._entry_table_round_key),
+            _middle_round_key: self
+                ._middle_round_key
+                .into_iter()
+                .map(decompress_lists)
+                .collect(),
+            _0_round_key: decompress_lists(self._0_round_key),
         }
-        he_lut_times13.push(temp_vec);
     }
+}
 
-    for lut_14 in times_14.iter() {
-        let mut temp_vec: Vec<GlweCiphertext<Vec<u64>>> = Vec::new();
-        for acc_idx in 0..num_accumulator {
-            let accumulator = (0..param.polynomial_size().0)
-                .map(|i| {
-                    let lut_idx = acc_idx * num_par_lut + i / (1 << BYTESIZE);
-                    (((lut_14[i % (1 << BYTESIZE)] & (1 << lut_idx)) as usize)
-                        << (log_scale - lut_idx))
-                        .cast_into()
-                })
-                .collect::<Vec<u64>>();
-            let accumulator_plaintext = PlaintextList::from_container(accumulator);
-            let accumulator: GlweCiphertext<Vec<u64>> =
-                allocate_and_trivially_encrypt_new_glwe_ciphertext(
-                    param.glwe_dimension().to_glwe_size(),
-                    &accumulator_plaintext,
-                    param.ciphertext_modulus(),
-                );
-            temp_vec.push(accumulator);
+impl SeededTransKey {
+    /// Regenerates the masks from their seeds.
+    pub fn decompress_into_trans_key(self) -> TransKey {
+        match self {
+            SeededTransKey::Ecb(all_rd_key) => TransKey::Ecb(all_rd_key.decompress_into_all_rd_keys()),
+            SeededTransKey::Ctr(all_fwd_rd_key) => {
+                TransKey::Ctr(all_fwd_rd_key.decompress_into_all_fwd_rd_keys())
+            }
+            SeededTransKey::MasterKey(he_key) => {
+                TransKey::MasterKey(he_key.decompress_into_lwe_ciphertext_list())
+            }
+            SeededTransKey::HalfCbs(all_rd_key) => {
+                TransKey::HalfCbs(all_rd_key.decompress_into_all_half_cbs_rd_keys())
+            }
+        }
+    }
+}
+
+fn decompress_lists(luts: Vec<SeededGlweCiphertextListOwned<u64>>) -> Vec<GlweCiphertextListOwned<u64>> {
+    luts.into_iter()
+        .map(|acc_list| acc_list.decompress_into_glwe_ciphertext_list())
+        .collect()
+}
+
+impl AllRdKeys {
+    /// Number of accumulators sent as trivial GLWEs, i.e. with an all-zero mask.
+    pub fn count_trivial_accumulators(&self) -> usize {
+        let (times_9, times_11, times_13, times_14) = &self._last_two_round_key;
+        let mut count = [times_9, times_11, times_13, times_14]
+            .into_iter()
+            .map(|luts| count_trivial_in_lists(luts))
+            .sum::<usize>();
+
+        for packed_luts in self._middle_round_key.iter() {
+            count += count_trivial_in_lists(packed_luts);
+        }
+        count + count_trivial_in_lists(&self._0_round_key)
+    }
+}
+
+impl AllFwdRdKeys {
+    /// Number of accumulators sent as trivial GLWEs, i.e. with an all-zero mask.
+    pub fn count_trivial_accumulators(&self) -> usize {
+        let mut count = count_trivial_in_lists(&self._last_round_key);
+        for (times_1, times_2, times_3) in self._middle_round_key.iter() {
+            count += [times_1, times_2, times_3]
+                .into_iter()
+                .map(|luts| count_trivial_in_lists(luts))
+                .sum::<usize>();
+        }
+        count
+    }
+}
+
+impl AllHalfCbsRdKeys {
+    /// Number of accumulators and table entries sent as trivial GLWEs.
+    pub fn count_trivial_accumulators(&self) -> usize {
+        let (times_9, times_11, times_13, times_14) = &self._last_two_round_key;
+        let mut count = [times_9, times_11, times_13, times_14]
+            .into_iter()
+            .flatten()
+            .map(|luts| count_trivial_in_lists(luts))
+            .sum::<usize>();
+        count += count_trivial_in_lists(&self._entry_table_round_key);
+
+        for packed_luts in self._middle_round_key.iter() {
+            count += count_trivial_in_lists(packed_luts);
         }
-        he_lut_times14.push(temp_vec);
+        count + count_trivial_in_lists(&self._0_round_key)
     }
+}
+
+impl TransKey {
+    pub fn count_trivial_accumulators(&self) -> usize {
+        match self {
+            TransKey::Ecb(all_rd_key) => all_rd_key.count_trivial_accumulators(),
+            TransKey::Ctr(all_fwd_rd_key) => all_fwd_rd_key.count_trivial_accumulators(),
+            TransKey::MasterKey(he_key) => he_key
+                .iter()
+                .filter(|lwe| lwe.get_mask().as_ref().iter().all(|&a| a == 0))
+                .count(),
+            TransKey::HalfCbs(all_rd_key) => all_rd_key.count_trivial_accumulators(),
+        }
+    }
+}
+
+fn is_trivial_glwe<C: Container<Element = u64>>(glwe: &GlweCiphertext<C>) -> bool {
+    glwe.get_mask().as_ref().iter().all(|&a| a == 0)
+}
+
+fn count_trivial_in_lists(luts: &[GlweCiphertextList<Vec<u64>>]) -> usize {
+    luts.iter()
+        .flat_map(|acc_list| acc_list.iter())
+        .filter(is_trivial_glwe)
+        .count()
+}
+
+///////////////////////////// local helper functions /////////////////////////////
+
+#[allow(clippy::type_complexity)]
+pub fn get_last_two_round_key(
+    param: &AesParam<u64>,
+    glwe_sk: &GlweSecretKey<Vec<u64>>,
+    aes: &AesManager,
+    noise_seeder: &mut dyn Seeder,
+) -> Result<(
+    Vec<SeededGlweCiphertextListOwned<u64>>,
+    Vec<SeededGlweCiphertextListOwned<u64>>,
+    Vec<SeededGlweCiphertextListOwned<u64>>,
+    Vec<SeededGlweCiphertextListOwned<u64>>,
+), Error> {
+    let (times_14, times_11, times_13, times_9) = aes.get_last_two_round_lut();
+    let last_two_he_lut_times14 = encrypt_keyed_lut(param, glwe_sk, times_14, noise_seeder)?;
+    let last_two_he_lut_times11 = encrypt_keyed_lut(param, glwe_sk, times_11, noise_seeder)?;
+    let last_two_he_lut_times13 = encrypt_keyed_lut(param, glwe_sk, times_13, noise_seeder)?;
+    let last_two_he_lut_times9 = encrypt_keyed_lut(param, glwe_sk, times_9, noise_seeder)?;
+    Ok((
+        last_two_he_lut_times9,
+        last_two_he_lut_times11,
+        last_two_he_lut_times13,
+        last_two_he_lut_times14,
+    ))
+}
+
+pub fn get_middle_round_key(
+    param: &AesParam<u64>,
+    glwe_sk: &GlweSecretKey<Vec<u64>>,
+    aes: &AesManager,
+    noise_seeder: &mut dyn Seeder,
+) -> Result<Vec<Vec<SeededGlweCiphertextListOwned<u64>>>, Error> {
+    get_packed_round_key(param, glwe_sk, aes, 1..aes.num_rounds() - 1, noise_seeder)
+}
+
+/// Packed ×9, ×11, ×13 and ×14 keyed LUTs of each round in `rounds`.
+fn get_packed_round_key(
+    param: &AesParam<u64>,
+    glwe_sk: &GlweSecretKey<Vec<u64>>,
+    aes: &AesManager,
+    rounds: Range<usize>,
+    noise_seeder: &mut dyn Seeder,
+) -> Result<Vec<Vec<SeededGlweCiphertextListOwned<u64>>>, Error> {
+    rounds
+        .map(|round| {
+            let (times_14, times_11, times_13, times_9) = aes.get_round_lut(round);
+            generate_vec_seeded_packed_keyed_lut_accumulator(
+                &[times_9, times_11, times_13, times_14],
+                u64::BITS as usize - 1,
+                glwe_sk,
+                param.glwe_modular_std_dev(),
+                param.ciphertext_modulus(),
+                noise_seeder,
+            )
+        })
+        .collect()
+}
 
-    (
-        he_lut_times9,
-        he_lut_times11,
-        he_lut_times13,
-        he_lut_times14,
+/// GLev counterpart of [`get_last_two_round_key`], with the decomposition of
+/// the half-CBS GGSWs.
+#[allow(clippy::type_complexity)]
+pub fn get_half_cbs_last_two_round_key(
+    param: &AesHalfCBSParam<u64>,
+    glwe_sk: &GlweSecretKey<Vec<u64>>,
+    aes: &AesManager,
+    noise_seeder: &mut dyn Seeder,
+) -> Result<(
+    Vec<Vec<SeededGlweCiphertextListOwned<u64>>>,
+    Vec<Vec<SeededGlweCiphertextListOwned<u64>>>,
+    Vec<Vec<SeededGlweCiphertextListOwned<u64>>>,
+    Vec<Vec<SeededGlweCiphertextListOwned<u64>>>,
+), Error> {
+    let (times_14, times_11, times_13, times_9) = aes.get_last_two_round_lut();
+    let mut encrypt_glev = |keyed_lut| {
+        generate_vec_seeded_keyed_lut_glev(
+            keyed_lut,
+            param.half_cbs_base_log(),
+            param.half_cbs_level(),
+            glwe_sk,
+            param.glwe_modular_std_dev(),
+            param.ciphertext_modulus(),
+            noise_seeder,
+        )
+    };
+    let times_14 = encrypt_glev(times_14)?;
+    let times_11 = encrypt_glev(times_11)?;
+    let times_13 = encrypt_glev(times_13)?;
+    let times_9 = encrypt_glev(times_9)?;
+    Ok((times_9, times_11, times_13, times_14))
+}
+
+/// Entry tables of the ×9, ×11, ×13 and ×14 keyed LUTs of round Nr - 2, in
+/// that order, for the half-CBS pipeline.
+pub fn get_entry_table_round_key(
+    param: &AesParam<u64>,
+    glwe_sk: &GlweSecretKey<Vec<u64>>,
+    aes: &AesManager,
+    noise_seeder: &mut dyn Seeder,
+) -> Vec<SeededGlweCiphertextListOwned<u64>> {
+    let (times_14, times_11, times_13, times_9) = aes.get_round_lut(aes.num_rounds() - 2);
+    generate_vec_seeded_keyed_lut_entry_table(
+        &[times_9, times_11, times_13, times_14],
+        glwe_sk,
+        param.glwe_modular_std_dev(),
+        param.ciphertext_modulus(),
+        noise_seeder,
     )
 }
 
+/// Middle rounds left to the patched WWLp CBS in the half-CBS pipeline, from
+/// round 1 up to Nr - 3.
+pub fn get_half_cbs_middle_round_key(
+    param: &AesParam<u64>,
+    glwe_sk: &GlweSecretKey<Vec<u64>>,
+    aes: &AesManager,
+    noise_seeder: &mut dyn Seeder,
+) -> Result<Vec<Vec<SeededGlweCiphertextListOwned<u64>>>, Error> {
+    get_packed_round_key(param, glwe_sk, aes, 1..aes.num_rounds() - 2, noise_seeder)
+}
+
 pub fn get_0_round_key(
     param: &AesParam<u64>,
     glwe_sk: &GlweSecretKey<Vec<u64>>,
-    aes: &Aes128Manager,
-    encryption_generator: &mut EncryptionRandomGenerator<ActivatedRandomGenerator>,
-) -> Vec<Vec<GlweCiphertext<Vec<u64>>>> {
-    let plain_lut = aes.get_0_round_lut();
-    let mut he_lut: Vec<Vec<GlweCiphertext<Vec<u64>>>> = Vec::new();
-    let num_accumulator = 2;
-    let log_scale = 63;
-    let num_par_lut = 4;
-
-    for lut_1 in plain_lut.iter() {
-        let mut temp_vec: Vec<GlweCiphertext<Vec<u64>>> = Vec::new();
-        for acc_idx in 0..num_accumulator {
-            let accumulator = (0..param.polynomial_size().0)
-                .map(|i| {
-                    let lut_idx = acc_idx * num_par_lut + i / (1 << BYTESIZE);
-                    (((lut_1[i % (1 << BYTESIZE)] & (1 << lut_idx)) as usize)
-                        << (log_scale - lut_idx))
-                        .cast_into()
-                })
-                .collect::<Vec<u64>>();
-            let accumulator_plaintext = PlaintextList::from_container(accumulator);
-            let accumulator: GlweCiphertext<Vec<u64>> =
-                allocate_and_trivially_encrypt_new_glwe_ciphertext(
-                    param.glwe_dimension().to_glwe_size(),
-                    &accumulator_plaintext,
-                    param.ciphertext_modulus(),
-                );
-            temp_vec.push(accumulator);
-        }
-        he_lut.push(temp_vec);
-    }
-    he_lut
+    aes: &AesManager,
+    noise_seeder: &mut dyn Seeder,
+) -> Result<Vec<SeededGlweCiphertextListOwned<u64>>, Error> {
+    encrypt_keyed_lut(param, glwe_sk, aes.get_0_round_lut(), noise_seeder)
+}
+
+/// Encrypts one keyed LUT per state byte as seeded accumulator lists.
+fn encrypt_keyed_lut(
+    param: &AesParam<u64>,
+    glwe_sk: &GlweSecretKey<Vec<u64>>,
+    keyed_lut: [[u8; 1 << BYTESIZE]; 16],
+    noise_seeder: &mut dyn Seeder,
+) -> Result<Vec<SeededGlweCiphertextListOwned<u64>>, Error> {
+    generate_vec_seeded_keyed_lut_accumulator(
+        keyed_lut,
+        u64::BITS as usize - 1,
+        glwe_sk,
+        param.glwe_modular_std_dev(),
+        param.ciphertext_modulus(),
+        noise_seeder,
+    )
 }
 
+#[allow(clippy::type_complexity)]
+pub fn get_fwd_middle_round_key(
+    param: &AesParam<u64>,
+    glwe_sk: &GlweSecretKey<Vec<u64>>,
+    aes: &AesManager,
+    noise_seeder: &mut dyn Seeder,
+) -> Result<Vec<(
+    Vec<SeededGlweCiphertextListOwned<u64>>,
+    Vec<SeededGlweCiphertextListOwned<u64>>,
+    Vec<SeededGlweCiphertextListOwned<u64>>,
+)>, Error> {
+    (1..aes.num_rounds())
+        .map(|round| {
+            let (times_1, times_2, times_3) = aes.get_fwd_round_lut(round);
+            Ok((
+                encrypt_keyed_lut(param, glwe_sk, times_1, noise_seeder)?,
+                encrypt_keyed_lut(param, glwe_sk, times_2, noise_seeder)?,
+                encrypt_keyed_lut(param, glwe_sk, times_3, noise_seeder)?,
+            ))
+        })
+        .collect()
+}
+
+pub fn get_fwd_last_round_key(
+    param: &AesParam<u64>,
+    glwe_sk: &GlweSecretKey<Vec<u64>>,
+    aes: &AesManager,
+    noise_seeder: &mut dyn Seeder,
+) -> Result<Vec<SeededGlweCiphertextListOwned<u64>>, Error> {
+    encrypt_keyed_lut(param, glwe_sk, aes.get_fwd_last_round_lut(), noise_seeder)
+}
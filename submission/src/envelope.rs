@@ -0,0 +1,269 @@
+//! Versioned envelope wrapped around every bincode artifact the binaries
+//! exchange, so that a key or ciphertext produced by another parameter set,
+//! build or key generation run is rejected on load instead of failing deep
+//! inside the homomorphic pipeline.
+//!
+//! An artifact is a bincode-encoded [`EnvelopeHeader`] followed by the
+//! bincode-encoded payload. The header starts with [`MAGIC`] and the format
+//! version, which are checked before the rest of the header is decoded.
+
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io::{BufReader, Read};
+
+use auto_base_conv::{aes_half_cbs_param_by_name, aes_param_by_name, AesHalfCBSParam, AesParam, AES_PARAM_NAMES};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+pub const MAGIC: [u8; 8] = *b"FHETRCPH";
+pub const FORMAT_VERSION: u16 = 4;
+
+/// Kind of payload carried by an artifact.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PayloadType {
+    LweSecretKey,
+    GlweSecretKey,
+    BootstrapKey,
+    GlweKeyswitchKey,
+    AutomorphKeys,
+    SchemeSwitchKey,
+    TransKey,
+    AesResult,
+    ComputeResult,
+    DecodedResult,
+    FourierKeyBundle,
+    SeededBootstrapKey,
+    SeededGlweKeyswitchKey,
+    SeededAutomorphKeys,
+    SeededSchemeSwitchKey,
+    SeededTransKey,
+    ReencryptedResult,
+    PackedAesResult,
+    PackedComputeResult,
+    CompressedAesResult,
+    CompressedComputeResult,
+}
+
+/// Random identifier drawn by `client_key_generation` and stamped on every
+/// artifact derived from that key set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeySetId([u8; 16]);
+
+impl KeySetId {
+    /// Draws a random version 4 UUID.
+    pub fn generate() -> Self {
+        let mut bytes: [u8; 16] = rand::random();
+        bytes[6] = (bytes[6] & 0x0f) | 0x40;
+        bytes[8] = (bytes[8] & 0x3f) | 0x80;
+        KeySetId(bytes)
+    }
+}
+
+impl fmt::Display for KeySetId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, byte) in self.0.iter().enumerate() {
+            if matches!(i, 4 | 6 | 8 | 10) {
+                write!(f, "-")?;
+            }
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EnvelopeHeader {
+    pub magic: [u8; 8],
+    pub format_version: u16,
+    /// Version of the `submission` crate that wrote the artifact, only used in
+    /// error messages.
+    pub writer_version: String,
+    pub param_fingerprint: u64,
+    /// Registry name of the parameter set, `unregistered` for a set built by
+    /// hand.
+    pub param_name: String,
+    pub key_set_id: KeySetId,
+    pub payload_type: PayloadType,
+}
+
+/// Every field of the parameter set, in a fixed order.
+fn canonical_fields(param: &AesParam<u64>) -> String {
+    format!(
+        "{:?}|{:?}|{:?}|{:?}|{:?}|{:?}|{:?}|{:?}|{:?}|{:?}|{:?}|{:?}|{:?}|{:?}|{:?}|{:?}|{:?}|{:?}|{:?}|{:?}",
+        param.lwe_dimension(),
+        param.lwe_modular_std_dev().0.to_bits(),
+        param.polynomial_size(),
+        param.glwe_dimension(),
+        param.glwe_modular_std_dev().0.to_bits(),
+        param.pbs_base_log(),
+        param.pbs_level(),
+        param.glwe_ds_base_log(),
+        param.glwe_ds_level(),
+        param.common_polynomial_size(),
+        param.fft_type_ds(),
+        param.auto_base_log(),
+        param.auto_level(),
+        param.fft_type_auto(),
+        param.ss_base_log(),
+        param.ss_level(),
+        param.cbs_base_log(),
+        param.cbs_level(),
+        param.log_lut_count(),
+        param.ciphertext_modulus(),
+    )
+}
+
+/// Half-CBS set registered under [`AES_PARAM_NAMES`] whose base fields are
+/// those of `param`.
+fn registered_half_cbs_param(param: &AesParam<u64>) -> Option<&'static AesHalfCBSParam<u64>> {
+    let canonical = canonical_fields(param);
+    AES_PARAM_NAMES
+        .into_iter()
+        .filter_map(aes_half_cbs_param_by_name)
+        .find(|half_cbs_param| canonical_fields(&half_cbs_param.base_param()) == canonical)
+}
+
+/// FNV-1a hash of every field of the parameter set, including the half-CBS
+/// fields when `param` is the base of a half-CBS set. Two sets share a
+/// fingerprint only if the keys of one can be used with the other.
+pub fn param_fingerprint(param: &AesParam<u64>) -> u64 {
+    let mut canonical = canonical_fields(param);
+    if let Some(half_cbs_param) = registered_half_cbs_param(param) {
+        canonical += &format!(
+            "|{:?}|{:?}|{:?}|{:?}|{:?}|{:?}|{:?}",
+            half_cbs_param.half_cbs_auto_base_log(),
+            half_cbs_param.half_cbs_auto_level(),
+            half_cbs_param.half_cbs_fft_type_auto(),
+            half_cbs_param.half_cbs_ss_base_log(),
+            half_cbs_param.half_cbs_ss_level(),
+            half_cbs_param.half_cbs_base_log(),
+            half_cbs_param.half_cbs_level(),
+        );
+    }
+
+    fnv1a(canonical.as_bytes())
+}
+
+/// 64-bit FNV-1a hash of `bytes`.
+pub(crate) fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325_u64, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+/// Name under which `param` is registered in [`AES_PARAM_NAMES`].
+pub fn param_name(param: &AesParam<u64>) -> &'static str {
+    let fingerprint = param_fingerprint(param);
+    AES_PARAM_NAMES
+        .into_iter()
+        .find(|name| aes_param_by_name(name).is_some_and(|p| param_fingerprint(p) == fingerprint))
+        .unwrap_or("unregistered")
+}
+
+/// Writes `payload` to `path` behind an envelope header.
+pub fn write_artifact<T: Serialize + ?Sized>(
+    path: &str,
+    payload_type: PayloadType,
+    param: &AesParam<u64>,
+    key_set_id: KeySetId,
+    payload: &T,
+) -> Result<(), Box<dyn Error>> {
+    let header = EnvelopeHeader {
+        magic: MAGIC,
+        format_version: FORMAT_VERSION,
+        writer_version: env!("CARGO_PKG_VERSION").to_owned(),
+        param_fingerprint: param_fingerprint(param),
+        param_name: param_name(param).to_owned(),
+        key_set_id,
+        payload_type,
+    };
+
+    let mut bytes = bincode::serialize(&header)?;
+    bincode::serialize_into(&mut bytes, payload)?;
+    fs::write(path, bytes)?;
+    Ok(())
+}
+
+/// Reads an artifact written by [`write_artifact`], checking that it holds a
+/// `payload_type` produced under `param`. When `expected_key_set_id` is given
+/// the artifact must also come from that key set. Returns the payload together
+/// with the key set id found in the header.
+///
+/// The file is decoded as it is read, so loading a key never holds both its
+/// encoded and decoded forms in memory.
+pub fn read_artifact<T: DeserializeOwned>(
+    path: &str,
+    payload_type: PayloadType,
+    param: &AesParam<u64>,
+    expected_key_set_id: Option<KeySetId>,
+) -> Result<(T, KeySetId), Box<dyn Error>> {
+    let file = fs::File::open(path).map_err(|e| format!("{}: {}", path, e))?;
+    let mut file = BufReader::new(file);
+
+    // Magic and format version, checked before the rest of the header is decoded
+    let mut prefix = Vec::new();
+    (&mut file)
+        .take((MAGIC.len() + 2) as u64)
+        .read_to_end(&mut prefix)
+        .map_err(|e| format!("{}: {}", path, e))?;
+
+    if !prefix.starts_with(&MAGIC) {
+        return Err(format!(
+            "{}: not an enveloped artifact (bad magic), it was probably written by an older build; \
+             regenerate it with the current binaries",
+            path
+        )
+        .into());
+    }
+    let (_, format_version): ([u8; 8], u16) = bincode::deserialize(&prefix)
+        .map_err(|e| format!("{}: truncated envelope header: {}", path, e))?;
+    if format_version != FORMAT_VERSION {
+        return Err(format!(
+            "{}: envelope format version {} is not supported, this build reads version {}",
+            path, format_version, FORMAT_VERSION
+        )
+        .into());
+    }
+
+    let mut reader = prefix.as_slice().chain(file);
+    let header: EnvelopeHeader = bincode::deserialize_from(&mut reader)
+        .map_err(|e| format!("{}: corrupted envelope header: {}", path, e))?;
+
+    if header.payload_type != payload_type {
+        return Err(format!(
+            "{}: expected a {:?} artifact, found a {:?}",
+            path, payload_type, header.payload_type
+        )
+        .into());
+    }
+    let fingerprint = param_fingerprint(param);
+    if header.param_name != param_name(param) {
+        return Err(format!(
+            "{}: written for parameter set {}, but this run selected {}; pass --param {} or set AES_PARAM={}",
+            path, header.param_name, param_name(param), header.param_name, header.param_name
+        )
+        .into());
+    }
+    if header.param_fingerprint != fingerprint {
+        return Err(format!(
+            "{}: written for parameter set {} {:016x} (by version {}), but this build defines it as {:016x}",
+            path, header.param_name, header.param_fingerprint, header.writer_version, fingerprint
+        )
+        .into());
+    }
+    if let Some(expected) = expected_key_set_id {
+        if header.key_set_id != expected {
+            return Err(format!(
+                "{}: belongs to key set {}, but the other keys belong to key set {}",
+                path, header.key_set_id, expected
+            )
+            .into());
+        }
+    }
+
+    let payload = bincode::deserialize_from(reader)
+        .map_err(|e| format!("{}: corrupted {:?} payload: {}", path, payload_type, e))?;
+    Ok((payload, header.key_set_id))
+}
+
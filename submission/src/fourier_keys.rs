@@ -0,0 +1,463 @@
+//! Evaluation keys converted to the Fourier domain once by
+//! `server_preprocess_dataset`, so that the server binaries skip the FFT of
+//! the bootstrapping, keyswitching and scheme switching keys on every run.
+//! The half-CBS pipeline adds its own automorphism and scheme switching keys.
+//!
+//! The Fourier coefficients are stored as the bit patterns of their real and
+//! imaginary parts, as `AutomorphKeySerializable` does, rather than through
+//! tfhe's serde impls which go back to the standard domain. Those bits depend
+//! on the FFT plan of the build that converted the keys, so the bundle records
+//! a fingerprint of it and refuses to load under another plan.
+
+use std::collections::HashMap;
+use std::error::Error;
+
+use aligned_vec::ABox;
+use auto_base_conv::{
+    convert_standard_glwe_keyswitch_key_to_fourier, AesHalfCBSParam, AesParam, AutomorphKey,
+    AutomorphKeySerializable, FourierGlweKeyswitchKey, GlweKeyswitchKeyOwned,
+};
+use serde::{Deserialize, Serialize};
+use tfhe::core_crypto::fft_impl::fft64::c64;
+use tfhe::core_crypto::prelude::*;
+
+use crate::envelope::fnv1a;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct FourierBootstrapKeyData {
+    data: Vec<u64>,
+    input_lwe_dimension: LweDimension,
+    glwe_size: GlweSize,
+    polynomial_size: PolynomialSize,
+    decomp_base_log: DecompositionBaseLog,
+    decomp_level_count: DecompositionLevelCount,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct FourierGlweKeyswitchKeyData {
+    data: Vec<u64>,
+    input_glwe_size: GlweSize,
+    output_glwe_size: GlweSize,
+    polynomial_size: PolynomialSize,
+    decomp_base_log: DecompositionBaseLog,
+    decomp_level_count: DecompositionLevelCount,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct FourierGgswListData {
+    data: Vec<u64>,
+    count: usize,
+    glwe_size: GlweSize,
+    polynomial_size: PolynomialSize,
+    decomp_base_log: DecompositionBaseLog,
+    decomp_level_count: DecompositionLevelCount,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct FourierHalfCbsKeyData {
+    ss_key: FourierGgswListData,
+    auto_keys: HashMap<usize, AutomorphKeySerializable>,
+}
+
+/// All server evaluation keys in the Fourier domain.
+///
+/// The keys are persisted as raw `c64` bits, which depend on the FFT plan, so
+/// the bundle carries the [`fft_fingerprint`] of the build that wrote it and
+/// only loads in a build with the same one.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FourierKeyBundle {
+    fft_fingerprint: u64,
+    bsk: FourierBootstrapKeyData,
+    glwe_ksk: FourierGlweKeyswitchKeyData,
+    ss_key: FourierGgswListData,
+    auto_keys: HashMap<usize, AutomorphKeySerializable>,
+    half_cbs: Option<FourierHalfCbsKeyData>,
+}
+
+/// Half-CBS keys rebuilt by [`FourierKeyBundle::take_half_cbs_keys`]: the
+/// scheme switching key and the automorphism keys.
+pub type FourierHalfCbsKeys = (
+    FourierGgswCiphertextList<Vec<c64>>,
+    HashMap<usize, AutomorphKey<ABox<[c64]>>>,
+);
+
+/// Hash of the Fourier transforms of a fixed GGSW ciphertext at each of
+/// `polynomial_sizes`, which identifies the FFT plans tfhe uses for them in
+/// this build.
+fn fft_fingerprint(polynomial_sizes: &[PolynomialSize]) -> u64 {
+    let glwe_size = GlweSize(2);
+    let decomp_base_log = DecompositionBaseLog(1);
+    let decomp_level_count = DecompositionLevelCount(1);
+
+    let mut bytes = Vec::new();
+    for &polynomial_size in polynomial_sizes {
+        let mut ggsw = GgswCiphertext::new(
+            0u64,
+            glwe_size,
+            polynomial_size,
+            decomp_base_log,
+            decomp_level_count,
+            CiphertextModulus::new_native(),
+        );
+        for (i, x) in ggsw.as_mut().iter_mut().enumerate() {
+            *x = (i as u64 + 1).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+        }
+        let mut fourier_ggsw = FourierGgswCiphertext::new(glwe_size, polynomial_size, decomp_base_log, decomp_level_count);
+        convert_standard_ggsw_ciphertext_to_fourier(&ggsw, &mut fourier_ggsw);
+        bytes.extend(c64_to_bits(fourier_ggsw.as_view().data()).iter().flat_map(|bits| bits.to_le_bytes()));
+    }
+    fnv1a(&bytes)
+}
+
+fn c64_to_bits(data: &[c64]) -> Vec<u64> {
+    data.iter()
+        .flat_map(|c| [c.re.to_bits(), c.im.to_bits()])
+        .collect()
+}
+
+/// Takes `bits` by value so that each key's bit patterns are freed as soon as
+/// the key is rebuilt, rather than once the whole bundle is.
+fn fill_c64_from_bits(
+    output: &mut [c64],
+    bits: Vec<u64>,
+    key_name: &str,
+) -> Result<(), Box<dyn Error>> {
+    if bits.len() != 2 * output.len() {
+        return Err(format!(
+            "Fourier {} holds {} coefficients, expected {}",
+            key_name,
+            bits.len() / 2,
+            output.len()
+        )
+        .into());
+    }
+    for (c, re_im) in output.iter_mut().zip(bits.chunks_exact(2)) {
+        *c = c64 {
+            re: f64::from_bits(re_im[0]),
+            im: f64::from_bits(re_im[1]),
+        };
+    }
+    Ok(())
+}
+
+impl FourierKeyBundle {
+    /// Converts the standard-domain keys uploaded by the client.
+    pub fn from_standard_keys(
+        param: &AesParam<u64>,
+        bsk: &LweBootstrapKeyOwned<u64>,
+        ksk: &GlweKeyswitchKeyOwned<u64>,
+        ss_key: &GgswCiphertextListOwned<u64>,
+        auto_keys: HashMap<usize, AutomorphKeySerializable>,
+    ) -> Self {
+        let mut fourier_bsk = FourierLweBootstrapKey::new(
+            bsk.input_lwe_dimension(),
+            bsk.glwe_size(),
+            bsk.polynomial_size(),
+            bsk.decomposition_base_log(),
+            bsk.decomposition_level_count(),
+        );
+        convert_standard_lwe_bootstrap_key_to_fourier(bsk, &mut fourier_bsk);
+
+        let mut fourier_glwe_ksk = FourierGlweKeyswitchKey::new(
+            ksk.input_glwe_dimension().to_glwe_size(),
+            ksk.output_glwe_dimension().to_glwe_size(),
+            ksk.polynomial_size(),
+            ksk.decomp_base_log(),
+            ksk.decomp_level_count(),
+            param.fft_type_ds(),
+        );
+        convert_standard_glwe_keyswitch_key_to_fourier(ksk, &mut fourier_glwe_ksk);
+
+        FourierKeyBundle {
+            fft_fingerprint: fft_fingerprint(&[bsk.polynomial_size(), ksk.polynomial_size()]),
+            bsk: FourierBootstrapKeyData {
+                data: c64_to_bits(fourier_bsk.as_view().data()),
+                input_lwe_dimension: bsk.input_lwe_dimension(),
+                glwe_size: bsk.glwe_size(),
+                polynomial_size: bsk.polynomial_size(),
+                decomp_base_log: bsk.decomposition_base_log(),
+                decomp_level_count: bsk.decomposition_level_count(),
+            },
+            glwe_ksk: FourierGlweKeyswitchKeyData {
+                data: c64_to_bits(fourier_glwe_ksk.as_fourier_glev_ciphertext_list().as_ref()),
+                input_glwe_size: fourier_glwe_ksk.input_glwe_size(),
+                output_glwe_size: fourier_glwe_ksk.output_glwe_size(),
+                polynomial_size: fourier_glwe_ksk.polynomial_size(),
+                decomp_base_log: fourier_glwe_ksk.decomp_base_log(),
+                decomp_level_count: fourier_glwe_ksk.decomp_level_count(),
+            },
+            ss_key: FourierGgswListData::from_standard(ss_key),
+            auto_keys,
+            half_cbs: None,
+        }
+    }
+
+    /// Checks that the keys were converted with the FFT plan of this build.
+    fn check_fft(&self) -> Result<(), Box<dyn Error>> {
+        let fingerprint = fft_fingerprint(&[self.bsk.polynomial_size, self.glwe_ksk.polynomial_size]);
+        if self.fft_fingerprint != fingerprint {
+            return Err(format!(
+                "the Fourier key bundle was converted with FFT {:016x}, but this build uses FFT {:016x}; \
+                rerun server_preprocess_dataset with this build",
+                self.fft_fingerprint, fingerprint
+            )
+            .into());
+        }
+        Ok(())
+    }
+
+    /// Adds the keys of the half-CBS pipeline, generated with the `half_cbs_*`
+    /// decompositions.
+    pub fn with_half_cbs_keys(
+        mut self,
+        ss_key: &GgswCiphertextListOwned<u64>,
+        auto_keys: HashMap<usize, AutomorphKeySerializable>,
+    ) -> Self {
+        self.half_cbs = Some(FourierHalfCbsKeyData {
+            ss_key: FourierGgswListData::from_standard(ss_key),
+            auto_keys,
+        });
+        self
+    }
+
+    /// Moves the half-CBS keys out of the bundle, `None` if it was built
+    /// without them.
+    pub fn take_half_cbs_keys(
+        &mut self,
+        param: &AesHalfCBSParam<u64>,
+    ) -> Result<Option<FourierHalfCbsKeys>, Box<dyn Error>> {
+        self.check_fft()?;
+        let Some(half_cbs) = self.half_cbs.take() else {
+            return Ok(None);
+        };
+        let ss_key = half_cbs.ss_key.into_fourier("half-CBS scheme switching key")?;
+        let auto_keys = half_cbs
+            .auto_keys
+            .into_iter()
+            .map(|(k, v)| (k, AutomorphKey::from_serializable(v, param.half_cbs_fft_type_auto())))
+            .collect();
+
+        Ok(Some((ss_key, auto_keys)))
+    }
+
+    /// Rebuilds the Fourier keys used by the server binaries: the
+    /// bootstrapping key, the GLWE keyswitching key, the scheme switching key
+    /// and the automorphism keys.
+    #[allow(clippy::type_complexity)]
+    pub fn into_fourier_keys(
+        self,
+        param: &AesParam<u64>,
+    ) -> Result<
+        (
+            FourierLweBootstrapKeyOwned,
+            FourierGlweKeyswitchKey<ABox<[c64]>>,
+            FourierGgswCiphertextList<Vec<c64>>,
+            HashMap<usize, AutomorphKey<ABox<[c64]>>>,
+        ),
+        Box<dyn Error>,
+    > {
+        self.check_fft()?;
+        let mut fourier_bsk = FourierLweBootstrapKey::new(
+            self.bsk.input_lwe_dimension,
+            self.bsk.glwe_size,
+            self.bsk.polynomial_size,
+            self.bsk.decomp_base_log,
+            self.bsk.decomp_level_count,
+        );
+        fill_c64_from_bits(fourier_bsk.as_mut_view().data(), self.bsk.data, "bootstrapping key")?;
+
+        let mut fourier_glwe_ksk = FourierGlweKeyswitchKey::new(
+            self.glwe_ksk.input_glwe_size,
+            self.glwe_ksk.output_glwe_size,
+            self.glwe_ksk.polynomial_size,
+            self.glwe_ksk.decomp_base_log,
+            self.glwe_ksk.decomp_level_count,
+            param.fft_type_ds(),
+        );
+        fill_c64_from_bits(
+            fourier_glwe_ksk.as_mut_fourier_glev_ciphertext_list().as_mut(),
+            self.glwe_ksk.data,
+            "GLWE keyswitching key",
+        )?;
+
+        let fourier_ss_key = self.ss_key.into_fourier("scheme switching key")?;
+
+        let auto_keys = self
+            .auto_keys
+            .into_iter()
+            .map(|(k, v)| (k, AutomorphKey::from_serializable(v, param.fft_type_auto())))
+            .collect();
+
+        Ok((fourier_bsk, fourier_glwe_ksk, fourier_ss_key, auto_keys))
+    }
+}
+
+impl FourierGgswListData {
+    fn from_standard(ggsw_list: &GgswCiphertextListOwned<u64>) -> Self {
+        let mut fourier_ggsw_list = new_fourier_ggsw_list(
+            ggsw_list.ggsw_ciphertext_count().0,
+            ggsw_list.glwe_size(),
+            ggsw_list.polynomial_size(),
+            ggsw_list.decomposition_base_log(),
+            ggsw_list.decomposition_level_count(),
+        );
+        for (mut fourier_ggsw, ggsw) in fourier_ggsw_list
+            .as_mut_view()
+            .into_ggsw_iter()
+            .zip(ggsw_list.iter())
+        {
+            convert_standard_ggsw_ciphertext_to_fourier(&ggsw, &mut fourier_ggsw);
+        }
+
+        FourierGgswListData {
+            data: c64_to_bits(fourier_ggsw_list.as_view().data()),
+            count: fourier_ggsw_list.count(),
+            glwe_size: fourier_ggsw_list.glwe_size(),
+            polynomial_size: fourier_ggsw_list.polynomial_size(),
+            decomp_base_log: fourier_ggsw_list.decomposition_base_log(),
+            decomp_level_count: fourier_ggsw_list.decomposition_level_count(),
+        }
+    }
+
+    fn into_fourier(self, key_name: &str) -> Result<FourierGgswCiphertextList<Vec<c64>>, Box<dyn Error>> {
+        let mut fourier_ggsw_list = new_fourier_ggsw_list(
+            self.count,
+            self.glwe_size,
+            self.polynomial_size,
+            self.decomp_base_log,
+            self.decomp_level_count,
+        );
+        fill_c64_from_bits(fourier_ggsw_list.as_mut_view().data(), self.data, key_name)?;
+        Ok(fourier_ggsw_list)
+    }
+}
+
+pub(crate) fn new_fourier_ggsw_list(
+    count: usize,
+    glwe_size: GlweSize,
+    polynomial_size: PolynomialSize,
+    decomp_base_log: DecompositionBaseLog,
+    decomp_level_count: DecompositionLevelCount,
+) -> FourierGgswCiphertextList<Vec<c64>> {
+    FourierGgswCiphertextList::new(
+        vec![
+            c64::default();
+            count
+                * polynomial_size.to_fourier_polynomial_size().0
+                * glwe_size.0
+                * glwe_size.0
+                * decomp_level_count.0
+        ],
+        count,
+        glwe_size,
+        polynomial_size,
+        decomp_base_log,
+        decomp_level_count,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use auto_base_conv::{AES_HALF_CBS, AES_TIGHT};
+
+    const GLWE_DIMENSION: GlweDimension = GlweDimension(1);
+    const POLYNOMIAL_SIZE: PolynomialSize = PolynomialSize(512);
+    const BASE_LOG: DecompositionBaseLog = DecompositionBaseLog(10);
+    const LEVEL: DecompositionLevelCount = DecompositionLevelCount(2);
+
+    /// Fills `data` with arbitrary but reproducible torus elements, so that
+    /// every Fourier coefficient differs from its neighbours.
+    fn fill_pattern(data: &mut [u64]) {
+        for (i, x) in data.iter_mut().enumerate() {
+            *x = (i as u64 + 1).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+        }
+    }
+
+    #[test]
+    fn bundle_round_trip() {
+        let param = &*AES_TIGHT;
+        let ciphertext_modulus = CiphertextModulus::new_native();
+        let glwe_size = GLWE_DIMENSION.to_glwe_size();
+
+        let mut bsk = LweBootstrapKey::new(0u64, glwe_size, POLYNOMIAL_SIZE, BASE_LOG, LEVEL, LweDimension(4), ciphertext_modulus);
+        fill_pattern(bsk.as_mut());
+        let mut ksk = GlweKeyswitchKeyOwned::new(0u64, GLWE_DIMENSION, GLWE_DIMENSION, POLYNOMIAL_SIZE, BASE_LOG, LEVEL, ciphertext_modulus);
+        fill_pattern(ksk.as_mut());
+        let mut ss_key = GgswCiphertextList::new(
+            0u64,
+            glwe_size,
+            POLYNOMIAL_SIZE,
+            BASE_LOG,
+            LEVEL,
+            GgswCiphertextCount(GLWE_DIMENSION.0),
+            ciphertext_modulus,
+        );
+        fill_pattern(ss_key.as_mut());
+
+        let bundle = FourierKeyBundle::from_standard_keys(param, &bsk, &ksk, &ss_key, HashMap::new());
+        let bundle: FourierKeyBundle = bincode::deserialize(&bincode::serialize(&bundle).unwrap()).unwrap();
+        let (fourier_bsk, fourier_glwe_ksk, fourier_ss_key, auto_keys) = bundle.into_fourier_keys(param).unwrap();
+
+        let mut expected_bsk = FourierLweBootstrapKey::new(
+            bsk.input_lwe_dimension(),
+            glwe_size,
+            POLYNOMIAL_SIZE,
+            BASE_LOG,
+            LEVEL,
+        );
+        convert_standard_lwe_bootstrap_key_to_fourier(&bsk, &mut expected_bsk);
+        assert_eq!(fourier_bsk.input_lwe_dimension(), expected_bsk.input_lwe_dimension());
+        assert!(fourier_bsk.as_view().data() == expected_bsk.as_view().data());
+
+        let mut expected_ksk = FourierGlweKeyswitchKey::new(glwe_size, glwe_size, POLYNOMIAL_SIZE, BASE_LOG, LEVEL, param.fft_type_ds());
+        convert_standard_glwe_keyswitch_key_to_fourier(&ksk, &mut expected_ksk);
+        assert!(
+            fourier_glwe_ksk.as_fourier_glev_ciphertext_list().as_ref()
+                == expected_ksk.as_fourier_glev_ciphertext_list().as_ref()
+        );
+
+        let mut expected_ss_key = new_fourier_ggsw_list(GLWE_DIMENSION.0, glwe_size, POLYNOMIAL_SIZE, BASE_LOG, LEVEL);
+        for (mut fourier_ggsw, ggsw) in expected_ss_key.as_mut_view().into_ggsw_iter().zip(ss_key.iter()) {
+            convert_standard_ggsw_ciphertext_to_fourier(&ggsw, &mut fourier_ggsw);
+        }
+        assert_eq!(fourier_ss_key.count(), GLWE_DIMENSION.0);
+        assert!(fourier_ss_key.as_view().data() == expected_ss_key.as_view().data());
+
+        assert!(auto_keys.is_empty());
+    }
+
+    #[test]
+    fn bundle_rejects_other_fft() {
+        let param = &*AES_TIGHT;
+        let ciphertext_modulus = CiphertextModulus::new_native();
+        let glwe_size = GLWE_DIMENSION.to_glwe_size();
+        let bsk = LweBootstrapKey::new(0u64, glwe_size, POLYNOMIAL_SIZE, BASE_LOG, LEVEL, LweDimension(4), ciphertext_modulus);
+        let ksk = GlweKeyswitchKeyOwned::new(0u64, GLWE_DIMENSION, GLWE_DIMENSION, POLYNOMIAL_SIZE, BASE_LOG, LEVEL, ciphertext_modulus);
+        let ss_key = GgswCiphertextList::new(
+            0u64,
+            glwe_size,
+            POLYNOMIAL_SIZE,
+            BASE_LOG,
+            LEVEL,
+            GgswCiphertextCount(GLWE_DIMENSION.0),
+            ciphertext_modulus,
+        );
+
+        let mut bundle = FourierKeyBundle::from_standard_keys(param, &bsk, &ksk, &ss_key, HashMap::new());
+        bundle.fft_fingerprint ^= 1;
+        assert!(bundle.clone().into_fourier_keys(param).is_err());
+        assert!(bundle.take_half_cbs_keys(&AES_HALF_CBS).is_err());
+    }
+
+    #[test]
+    fn fill_c64_from_bits_rejects_length_mismatch() {
+        let mut output = vec![c64::default(); 4];
+        assert!(fill_c64_from_bits(&mut output, vec![0u64; 7], "test key").is_err());
+        assert!(fill_c64_from_bits(&mut output, vec![0u64; 10], "test key").is_err());
+
+        let bits = c64_to_bits(&[c64 { re: 1.5, im: -0.25 }; 4]);
+        fill_c64_from_bits(&mut output, bits, "test key").unwrap();
+        assert!(output.iter().all(|c| c.re == 1.5 && c.im == -0.25));
+    }
+}
@@ -1,5 +1,9 @@
+use auto_base_conv::{aes_half_cbs_param_by_name, aes_param_by_name, AesHalfCBSParam, AesParam, AES_PARAM_NAMES};
 use tfhe::core_crypto::prelude::{Container, ContiguousEntityContainer, LweCiphertext, LweCiphertextList, LweSecretKey, UnsignedInteger, decrypt_lwe_ciphertext};
 
+/// Environment variable selecting the parameter set when `--param` is not given.
+pub const AES_PARAM_ENV: &str = "AES_PARAM";
+
 pub fn get_size_string(position: usize) -> &'static str {
     match position {
         0 => "toy",
@@ -9,6 +13,145 @@ pub fn get_size_string(position: usize) -> &'static str {
     }
 }
 
+/// Name looked up by [`select_aes_param`] and [`select_half_cbs_param`].
+fn selected_param_name(args: &[String]) -> Result<String, Box<dyn std::error::Error>> {
+    match args.iter().position(|arg| arg == "--param") {
+        Some(pos) => Ok(args
+            .get(pos + 1)
+            .cloned()
+            .ok_or_else(|| format!("--param expects one of {}", AES_PARAM_NAMES.join(", ")))?),
+        None => Ok(std::env::var(AES_PARAM_ENV).unwrap_or_else(|_| AES_PARAM_NAMES[0].to_owned())),
+    }
+}
+
+/// Selects the parameter set named by `--param <name>` in `args`, else by the
+/// `AES_PARAM` environment variable, else the first of [`AES_PARAM_NAMES`].
+/// Every binary of a run must select the same set, which the artifact
+/// envelopes enforce.
+pub fn select_aes_param(args: &[String]) -> Result<&'static AesParam<u64>, Box<dyn std::error::Error>> {
+    let name = selected_param_name(args)?;
+
+    aes_param_by_name(&name).ok_or_else(|| {
+        format!(
+            "unknown parameter set {:?}, expected one of {}",
+            name,
+            AES_PARAM_NAMES.join(", ")
+        )
+        .into()
+    })
+}
+
+/// Half-CBS fields of the selected parameter set, `Some` only when the run
+/// uses the half-CBS pipeline.
+pub fn select_half_cbs_param(args: &[String]) -> Result<Option<&'static AesHalfCBSParam<u64>>, Box<dyn std::error::Error>> {
+    Ok(aes_half_cbs_param_by_name(&selected_param_name(args)?))
+}
+
+/// Form in which the servers hand out their results. Every binary of a run
+/// must select the same one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ResultDownload {
+    /// `result.bin`, the LWE ciphertexts themselves.
+    Lwe,
+    /// `result_packed.bin`, selected by `--pack_glwe`.
+    PackedGlwe,
+    /// `result_compressed.bin`, selected by `--compress`.
+    Compressed,
+}
+
+impl ResultDownload {
+    pub fn from_args(args: &[String]) -> Result<Self, Box<dyn std::error::Error>> {
+        let pack_glwe = args.iter().any(|arg| arg == "--pack_glwe");
+        let compress = args.iter().any(|arg| arg == "--compress");
+        match (pack_glwe, compress) {
+            (true, true) => Err("--pack_glwe and --compress select different downloads, pass only one".into()),
+            (true, false) => Ok(ResultDownload::PackedGlwe),
+            (false, true) => Ok(ResultDownload::Compressed),
+            (false, false) => Ok(ResultDownload::Lwe),
+        }
+    }
+}
+
+/// Where `server_encrypted_aes_decryption` leaves the LWE result for
+/// `server_encrypted_compute`: the download directory when the client
+/// downloads it as is, a server-side directory otherwise.
+pub fn aes_result_path(io_dir: &str, download: ResultDownload) -> String {
+    match download {
+        ResultDownload::Lwe => format!("{}/ciphertext_aes_download/result.bin", io_dir),
+        ResultDownload::PackedGlwe | ResultDownload::Compressed => {
+            format!("{}/server_intermediate/aes_result.bin", io_dir)
+        }
+    }
+}
+
+/// Parses a hex string into consecutive 16-byte AES blocks.
+pub fn parse_hex_blocks(hex_string: &str) -> Result<Vec<[u8; 16]>, Box<dyn std::error::Error>> {
+    let hex_string = hex_string.trim();
+    if hex_string.is_empty() || !hex_string.len().is_multiple_of(32) {
+        return Err(format!(
+            "hex input of length {} is not a whole number of 16-byte blocks",
+            hex_string.len()
+        )
+        .into());
+    }
+
+    let mut blocks = Vec::with_capacity(hex_string.len() / 32);
+    for block_hex in hex_string.as_bytes().chunks_exact(32) {
+        let mut block = [0u8; 16];
+        for (byte, hex_pair) in block.iter_mut().zip(block_hex.chunks_exact(2)) {
+            *byte = u8::from_str_radix(std::str::from_utf8(hex_pair)?, 16)?;
+        }
+        blocks.push(block);
+    }
+    Ok(blocks)
+}
+
+/// Reads `aes_key.hex` from the dataset directory. The key may be 16, 24 or
+/// 32 bytes long, for AES-128, AES-192 and AES-256 respectively.
+pub fn read_aes_key(data_dir: &str) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let key_path = format!("{}/aes_key.hex", data_dir);
+    let hex_string = std::fs::read_to_string(&key_path)?;
+    let hex_string = hex_string.trim();
+    if !matches!(hex_string.len(), 32 | 48 | 64) {
+        return Err(format!(
+            "{} holds {} hex digits, expected 32, 48 or 64",
+            key_path,
+            hex_string.len()
+        )
+        .into());
+    }
+
+    hex_string
+        .as_bytes()
+        .chunks_exact(2)
+        .map(|hex_pair| Ok(u8::from_str_radix(std::str::from_utf8(hex_pair)?, 16)?))
+        .collect()
+}
+
+/// Reads `aes_iv.hex` from the dataset directory. The harness only writes it
+/// for instances encrypted in CTR mode, so `None` means ECB.
+pub fn read_aes_iv(data_dir: &str) -> Result<Option<[u8; 16]>, Box<dyn std::error::Error>> {
+    let iv_path = format!("{}/aes_iv.hex", data_dir);
+    if !std::path::Path::new(&iv_path).exists() {
+        return Ok(None);
+    }
+
+    let blocks = parse_hex_blocks(&std::fs::read_to_string(&iv_path)?)?;
+    if blocks.len() != 1 {
+        return Err(format!("{} must hold exactly one 16-byte IV", iv_path).into());
+    }
+    Ok(Some(blocks[0]))
+}
+
+/// Counter blocks `iv, iv + 1, ...`, with the IV read as a big-endian 128-bit
+/// integer as in the harness.
+pub fn get_ctr_blocks(iv: &[u8; 16], num_blocks: usize) -> Vec<[u8; 16]> {
+    let iv = u128::from_be_bytes(*iv);
+    (0..num_blocks)
+        .map(|i| iv.wrapping_add(i as u128).to_be_bytes())
+        .collect()
+}
+
 pub fn decrypt_decode_lwe_list(
     lwe_sk: &LweSecretKey<Vec<u64>>,
     ciphertext: &LweCiphertextList<Vec<u64>>,
@@ -16,7 +159,7 @@ pub fn decrypt_decode_lwe_list(
     let delta = 1_u64 << 63;
     let mut result: Vec<u64> = Vec::new();
     for c in ciphertext.iter() {
-        result.push(decrypt(&lwe_sk, &c, delta));
+        result.push(decrypt(lwe_sk, &c, delta));
     }
     result
 }
@@ -35,9 +178,8 @@ where
         .ciphertext_modulus()
         .get_power_of_two_scaling_to_native_torus();
 
-    let decrypted = decrypt_lwe_ciphertext(&lwe_sk, &lwe_ctxt).0;
+    let decrypted = decrypt_lwe_ciphertext(lwe_sk, lwe_ctxt).0;
     let decrypted = decrypted * scaling;
     let rounding = (decrypted & (delta >> 1)) << 1;
-    let decoded = (decrypted.wrapping_add(rounding)) / delta;
-    return decoded;
+    (decrypted.wrapping_add(rounding)) / delta
 }
\ No newline at end of file
@@ -1,3 +1,9 @@
 pub mod data_struct;
 pub mod aes_manager;
-pub mod help_fun;
\ No newline at end of file
+pub mod compression;
+pub mod envelope;
+pub mod fourier_keys;
+pub mod help_fun;
+pub mod noise_report;
+pub mod packing;
+pub mod transcipherer;
@@ -0,0 +1,136 @@
+//! Empirical noise measurement of the ECB transciphering, for test machines
+//! that hold the client secret key and the AES key. [`NoiseProbe`] decrypts
+//! the homomorphic state after every AES round, compares it with the rounds
+//! of [`AesRef`] and accumulates the absolute errors into a [`NoiseReport`].
+
+use std::collections::BTreeMap;
+use std::error::Error;
+
+use auto_base_conv::{byte_mat_to_bit_array, get_val_and_bit_and_abs_err, AesRef, StateByteMat};
+use serde::Serialize;
+use tfhe::core_crypto::prelude::*;
+
+/// Noise of the state after one AES round, over every bit of every block.
+/// Errors are absolute errors on the 64-bit torus, given in bits, i.e. as
+/// log2 of the error.
+#[derive(Debug, Clone, Serialize)]
+pub struct RoundNoise {
+    /// Index of the round key just added, from Nr - 1 down to 0.
+    pub round: usize,
+    pub num_bits: usize,
+    /// Bits that do not decode to their expected value.
+    pub wrong_bits: usize,
+    pub max_err_bits: f64,
+    pub mean_err_bits: f64,
+    pub std_dev_err_bits: f64,
+}
+
+/// Per-round noise report, written as JSON by the measurement mode of
+/// `server_encrypted_aes_decryption`.
+#[derive(Debug, Clone, Serialize)]
+pub struct NoiseReport {
+    pub num_rounds: usize,
+    pub num_blocks: usize,
+    /// Rounds in evaluation order.
+    pub rounds: Vec<RoundNoise>,
+}
+
+impl NoiseReport {
+    pub fn to_json(&self) -> Result<String, Box<dyn Error>> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+}
+
+/// Decrypts intermediate transciphering states with the large LWE secret key,
+/// the key of the bootstrapping output.
+pub struct NoiseProbe<'a> {
+    lwe_sk: &'a LweSecretKey<Vec<u64>>,
+    aes: AesRef,
+    num_blocks: usize,
+    // Absolute errors and wrong bit count of each round, keyed by round.
+    errors: BTreeMap<usize, (Vec<u64>, usize)>,
+    expected: Vec<StateByteMat>,
+}
+
+impl<'a> NoiseProbe<'a> {
+    pub fn new(lwe_sk: &'a LweSecretKey<Vec<u64>>, aes_key: &[u8]) -> Result<Self, Box<dyn Error>> {
+        if !matches!(aes_key.len(), 16 | 24 | 32) {
+            return Err(format!("an AES key is 16, 24 or 32 bytes long, got {}", aes_key.len()).into());
+        }
+        Ok(NoiseProbe {
+            lwe_sk,
+            aes: AesRef::new(aes_key),
+            num_blocks: 0,
+            errors: BTreeMap::new(),
+            expected: Vec::new(),
+        })
+    }
+
+    pub fn num_rounds(&self) -> usize {
+        self.aes.num_rounds()
+    }
+
+    /// Starts a new block. The homomorphic state of the middle rounds is
+    /// already InvShiftRows'ed for the next round, so the reference states
+    /// are too.
+    pub fn begin_block(&mut self, ciphertext: &[u8; 16]) {
+        self.expected = self.aes.decrypt_round_states(*ciphertext);
+        for state in &mut self.expected[1..] {
+            self.aes.inv_shift_rows(state);
+        }
+        self.num_blocks += 1;
+    }
+
+    /// Records the state after the round of key `round`, with the bits of
+    /// each byte LSB first.
+    pub fn record<C: Container<Element = u64>>(&mut self, round: usize, he_state: &LweCiphertextList<C>) {
+        let expected_bits = byte_mat_to_bit_array(self.expected[round]);
+        let (errors, wrong_bits) = self.errors.entry(round).or_default();
+        for (he_bit, expected_bit) in he_state.iter().zip(expected_bits.iter()) {
+            let (decoded, _, abs_err) =
+                get_val_and_bit_and_abs_err(self.lwe_sk, &he_bit, *expected_bit as u64, 1u64 << 63);
+            errors.push(abs_err);
+            if decoded != *expected_bit as u64 {
+                *wrong_bits += 1;
+            }
+        }
+    }
+
+    pub fn into_report(self) -> NoiseReport {
+        let rounds = self
+            .errors
+            .into_iter()
+            .rev()
+            .map(|(round, (errors, wrong_bits))| {
+                let num_bits = errors.len();
+                let max = errors.iter().copied().max().unwrap_or(0) as f64;
+                let mean = errors.iter().map(|&err| err as f64).sum::<f64>() / num_bits as f64;
+                let variance = errors
+                    .iter()
+                    .map(|&err| (err as f64 - mean).powi(2))
+                    .sum::<f64>()
+                    / num_bits as f64;
+                RoundNoise {
+                    round,
+                    num_bits,
+                    wrong_bits,
+                    max_err_bits: log2_err(max),
+                    mean_err_bits: log2_err(mean),
+                    std_dev_err_bits: log2_err(variance.sqrt()),
+                }
+            })
+            .collect();
+
+        NoiseReport {
+            num_rounds: self.aes.num_rounds(),
+            num_blocks: self.num_blocks,
+            rounds,
+        }
+    }
+}
+
+// An error below one unit of the torus counts as 0 bits rather than -inf,
+// which JSON cannot represent.
+fn log2_err(err: f64) -> f64 {
+    err.max(1.0).log2()
+}
@@ -0,0 +1,203 @@
+//! GLWE packing of result bits before download. The server packs groups of
+//! LWE ciphertexts into single GLWE ciphertexts with the automorphism keys
+//! it already holds for circuit bootstrapping, and the client reads the bits
+//! back from the coefficients of the decrypted polynomials.
+//!
+//! A GLWE ciphertext of dimension k and polynomial size N replaces
+//! `lwes_per_glwe` LWE ciphertexts of dimension k·N, so packing 128 bits
+//! shrinks them by a factor of about `128 / (k + 1)`.
+
+use std::collections::HashMap;
+use std::error::Error;
+
+use aligned_vec::ABox;
+use auto_base_conv::{try_convert_lwes_to_glwe_by_trace_with_preprocessing, AutomorphKey};
+use serde::{Deserialize, Serialize};
+use tfhe::core_crypto::fft_impl::fft64::c64;
+use tfhe::core_crypto::prelude::*;
+#[cfg(feature = "multithread")]
+use rayon::prelude::*;
+
+/// LWE ciphertexts of single bits packed by [`pack_lwe_list`]. Bit `j` of a
+/// group sits at coefficient `j * N / lwes_per_glwe` of its GLWE ciphertext.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PackedLweList {
+    pub glwes: GlweCiphertextList<Vec<u64>>,
+    pub lwes_per_glwe: usize,
+    /// Number of packed LWE ciphertexts; the last group is padded with
+    /// encryptions of zero.
+    pub lwe_count: usize,
+}
+
+/// Packs `lwe_list` into GLWE ciphertexts of `lwes_per_glwe` bits each, a
+/// power of two no larger than the polynomial size of `glwe_size`.
+pub fn pack_lwe_list(
+    lwe_list: &LweCiphertextList<Vec<u64>>,
+    lwes_per_glwe: usize,
+    glwe_size: GlweSize,
+    polynomial_size: PolynomialSize,
+    auto_keys: &HashMap<usize, AutomorphKey<ABox<[c64]>>>,
+) -> Result<PackedLweList, Box<dyn Error>> {
+    if !lwes_per_glwe.is_power_of_two() || lwes_per_glwe > polynomial_size.0 {
+        return Err(format!(
+            "cannot pack {} LWE ciphertexts per GLWE ciphertext of polynomial size {}, \
+            it must be a power of two dividing it",
+            lwes_per_glwe, polynomial_size.0
+        )
+        .into());
+    }
+
+    let lwe_size = lwe_list.lwe_size();
+    let ciphertext_modulus = lwe_list.ciphertext_modulus();
+    let lwe_count = lwe_list.lwe_ciphertext_count().0;
+
+    let glwe_count = lwe_count.div_ceil(lwes_per_glwe);
+    let mut container = lwe_list.as_ref().to_vec();
+    container.resize(glwe_count * lwes_per_glwe * lwe_size.0, 0);
+    let padded = LweCiphertextList::from_container(container, lwe_size, ciphertext_modulus);
+
+    let mut glwes = GlweCiphertextList::new(
+        0u64,
+        glwe_size,
+        polynomial_size,
+        GlweCiphertextCount(glwe_count),
+        ciphertext_modulus,
+    );
+
+    let pack_group = |(lwe_group, mut glwe): (LweCiphertextListView<u64>, GlweCiphertextMutView<u64>)| {
+        try_convert_lwes_to_glwe_by_trace_with_preprocessing(&lwe_group, &mut glwe, auto_keys)
+    };
+    #[cfg(not(feature = "multithread"))]
+    padded
+        .chunks_exact(lwes_per_glwe)
+        .zip(glwes.iter_mut())
+        .try_for_each(pack_group)?;
+    #[cfg(feature = "multithread")]
+    padded
+        .par_chunks_exact(lwes_per_glwe)
+        .zip(glwes.par_iter_mut())
+        .try_for_each(pack_group)?;
+
+    Ok(PackedLweList { glwes, lwes_per_glwe, lwe_count })
+}
+
+impl PackedLweList {
+    /// Decrypts every packed bit, in the order of the LWE ciphertexts given
+    /// to [`pack_lwe_list`].
+    pub fn decrypt_decode(&self, glwe_sk: &GlweSecretKey<Vec<u64>>) -> Result<Vec<u64>, Box<dyn Error>> {
+        let polynomial_size = self.glwes.polynomial_size();
+        if glwe_sk.polynomial_size() != polynomial_size
+            || glwe_sk.glwe_dimension().to_glwe_size() != self.glwes.glwe_size()
+        {
+            return Err("the packed result does not match the GLWE secret key".into());
+        }
+        if !self.lwes_per_glwe.is_power_of_two()
+            || self.lwes_per_glwe > polynomial_size.0
+            || self.lwe_count > self.lwes_per_glwe * self.glwes.glwe_ciphertext_count().0
+        {
+            return Err(format!(
+                "a packed result of {} bits in groups of {} does not fit its GLWE ciphertexts",
+                self.lwe_count, self.lwes_per_glwe
+            )
+            .into());
+        }
+
+        let stride = polynomial_size.0 / self.lwes_per_glwe;
+        let mut plaintext_list = PlaintextList::new(0u64, PlaintextCount(polynomial_size.0));
+        let mut bits = Vec::with_capacity(self.lwe_count);
+        for glwe in self.glwes.iter() {
+            decrypt_glwe_ciphertext(glwe_sk, &glwe, &mut plaintext_list);
+            bits.extend(
+                plaintext_list
+                    .as_ref()
+                    .iter()
+                    .step_by(stride)
+                    .map(|&coef| (coef.wrapping_add(1 << 62) >> 63) & 1),
+            );
+        }
+        bits.truncate(self.lwe_count);
+        Ok(bits)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aes_manager::BLOCKSIZE_IN_BIT;
+    use auto_base_conv::{gen_all_auto_keys, AES_TIGHT};
+    use tfhe::core_crypto::seeders::new_seeder;
+
+    #[test]
+    fn packed_block_decrypts_at_stride() {
+        let param = *AES_TIGHT;
+        let polynomial_size = param.polynomial_size();
+        let mut boxed_seeder = new_seeder();
+        let seeder = boxed_seeder.as_mut();
+        let mut secret_generator = SecretRandomGenerator::<ActivatedRandomGenerator>::new(seeder.seed());
+        let mut encryption_generator =
+            EncryptionRandomGenerator::<ActivatedRandomGenerator>::new(seeder.seed(), seeder);
+
+        let glwe_sk = GlweSecretKey::generate_new_binary(param.glwe_dimension(), polynomial_size, &mut secret_generator);
+        let lwe_sk = glwe_sk.as_lwe_secret_key();
+        let auto_keys = gen_all_auto_keys(
+            param.auto_base_log(),
+            param.auto_level(),
+            param.fft_type_auto(),
+            &glwe_sk,
+            param.glwe_modular_std_dev(),
+            &mut encryption_generator,
+        );
+
+        let bits: Vec<u64> = (0..BLOCKSIZE_IN_BIT).map(|_| rand::random::<u64>() & 1).collect();
+        let plaintexts = PlaintextList::from_container(bits.iter().map(|bit| bit << 63).collect::<Vec<_>>());
+        let mut lwe_list = LweCiphertextList::new(
+            0u64,
+            lwe_sk.lwe_dimension().to_lwe_size(),
+            LweCiphertextCount(bits.len()),
+            param.ciphertext_modulus(),
+        );
+        encrypt_lwe_ciphertext_list(&lwe_sk, &mut lwe_list, &plaintexts, param.glwe_modular_std_dev(), &mut encryption_generator);
+
+        let packed = pack_lwe_list(
+            &lwe_list,
+            BLOCKSIZE_IN_BIT,
+            param.glwe_dimension().to_glwe_size(),
+            polynomial_size,
+            &auto_keys,
+        )
+        .unwrap();
+        assert_eq!(packed.glwes.glwe_ciphertext_count(), GlweCiphertextCount(1));
+
+        // Bit j sits at coefficient j * N / 128
+        let stride = polynomial_size.0 / BLOCKSIZE_IN_BIT;
+        let mut plaintext_list = PlaintextList::new(0u64, PlaintextCount(polynomial_size.0));
+        decrypt_glwe_ciphertext(&glwe_sk, &packed.glwes.get(0), &mut plaintext_list);
+        for (j, &bit) in bits.iter().enumerate() {
+            let coef = plaintext_list.as_ref()[j * stride];
+            assert_eq!((coef.wrapping_add(1 << 62) >> 63) & 1, bit, "bit {}", j);
+        }
+
+        assert_eq!(packed.decrypt_decode(&glwe_sk).unwrap(), bits);
+    }
+
+    #[test]
+    fn pack_rejects_bad_group_size() {
+        let param = *AES_TIGHT;
+        let polynomial_size = param.polynomial_size();
+        let glwe_size = param.glwe_dimension().to_glwe_size();
+        let lwe_list = LweCiphertextList::new(
+            0u64,
+            LweSize(param.glwe_dimension().0 * polynomial_size.0 + 1),
+            LweCiphertextCount(BLOCKSIZE_IN_BIT),
+            param.ciphertext_modulus(),
+        );
+
+        for lwes_per_glwe in [0, 3, 96, 2 * polynomial_size.0] {
+            assert!(
+                pack_lwe_list(&lwe_list, lwes_per_glwe, glwe_size, polynomial_size, &HashMap::new()).is_err(),
+                "{}",
+                lwes_per_glwe
+            );
+        }
+    }
+}
@@ -0,0 +1,1772 @@
+//! Library-level AES transciphering. [`ServerKeySet`] holds the server
+//! evaluation keys in the Fourier domain and [`Transcipherer`] turns AES
+//! ciphertext blocks into LWE encryptions of their plaintext bits, MSB first
+//! within each byte, using the transciphering key uploaded by the client.
+//!
+//! Besides the patched WWLp CBS pipeline, ECB blocks can go through the
+//! half-CBS pipeline: the first step outputs a Lev state that the half-CBS
+//! keys convert to GGSW with an automorphism trace and a scheme switch, and
+//! those GGSWs evaluate round Nr - 2 on entry tables without bootstrapping
+//! the state. Only this one transition runs on half-CBS; the rounds below
+//! bootstrap the state with the patched WWLp CBS. Keeping the state in Lev
+//! form through every middle round is not implemented, so a latency or key
+//! size comparison against the patched WWLp CBS pipeline measures the saving
+//! of a single round.
+//!
+//! The same keys also run the cipher the other way: [`Transcipherer::encrypt_blocks`]
+//! and [`Transcipherer::encrypt_ctr`] encrypt LWE-encrypted plaintext bits
+//! under the AES key, so that a result can be handed back as AES ciphertext.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::sync::{Mutex, MutexGuard, OnceLock, PoisonError};
+
+use aligned_vec::ABox;
+use auto_base_conv::{
+    blind_rotate_keyed_sbox, blind_rotate_keyed_sboxes, byte_array_to_mat, byte_mat_to_array,
+    convert_lev_state_to_ggsw, evaluate_lut_entry_table_on_xored_bytes,
+    get_he_state_byte, get_he_state_byte_mut,
+    he_add_round_key,
+    he_eval_byte_luts_by_patched_wwlp_cbs, he_eval_packed_keyed_byte_luts_by_patched_wwlp_cbs,
+    he_key_expansion_by_patched_wwlp_cbs, he_mix_columns_precomp, he_shift_rows,
+    known_rotate_keyed_lut, try_keyswitch_lwe_ciphertext_list_by_glwe_keyswitch,
+    known_rotate_keyed_lut_for_half_cbs, lwe_ciphertext_list_add_assign,
+    mult_a_b, new_byte_cbs_workers, AesHalfCBSParam, AesParam, AutomorphKey,
+    ByteCbsWorker, Error as CbsError, FourierGlweKeyswitchKey, LutEntryTableScratch,
+    AES128_INV_SBOX, AES128_INV_SBOX_MULT_BY_9, AES128_INV_SBOX_MULT_BY_B,
+    AES128_INV_SBOX_MULT_BY_D, AES128_INV_SBOX_MULT_BY_E, AES128_SBOX, AES128_SBOX_MULT_BY_2,
+    AES128_SBOX_MULT_BY_3,
+};
+use tfhe::core_crypto::fft_impl::fft64::{
+    c64,
+    crypto::{
+        bootstrap::FourierLweBootstrapKeyView,
+        ggsw::{FourierGgswCiphertextListView, FourierGgswCiphertextMutView},
+    },
+};
+use tfhe::core_crypto::prelude::*;
+#[cfg(feature = "multithread")]
+use rayon::prelude::*;
+
+use crate::{
+    aes_manager::{StateByteMat, BLOCKSIZE_IN_BIT, BYTESIZE, NUM_COLUMNS, NUM_ROWS},
+    data_struct::{AllFwdRdKeys, AllHalfCbsRdKeys, AllRdKeys, TransKey},
+    fourier_keys::{new_fourier_ggsw_list, FourierKeyBundle},
+    help_fun::get_ctr_blocks,
+    noise_report::NoiseProbe,
+};
+
+/// Server evaluation keys in the Fourier domain.
+pub struct ServerKeySet {
+    pub fourier_bsk: FourierLweBootstrapKeyOwned,
+    pub fourier_glwe_ksk: FourierGlweKeyswitchKey<ABox<[c64]>>,
+    pub auto_keys: HashMap<usize, AutomorphKey<ABox<[c64]>>>,
+    pub ss_key: FourierGgswCiphertextList<Vec<c64>>,
+    /// Only present for a half-CBS parameter set.
+    pub half_cbs: Option<HalfCbsKeySet>,
+}
+
+/// Keys converting the Lev state of the half-CBS pipeline to GGSW, with the
+/// parameter set they were checked against.
+pub struct HalfCbsKeySet {
+    pub param: AesHalfCBSParam<u64>,
+    pub auto_keys: HashMap<usize, AutomorphKey<ABox<[c64]>>>,
+    pub ss_key: FourierGgswCiphertextList<Vec<c64>>,
+}
+
+impl ServerKeySet {
+    /// Rebuilds the keys of a bundle written by `server_preprocess_dataset`
+    /// and checks that they were generated for `param`.
+    pub fn from_bundle(bundle: FourierKeyBundle, param: &AesParam<u64>) -> Result<Self, Box<dyn Error>> {
+        let (fourier_bsk, fourier_glwe_ksk, ss_key, auto_keys) = bundle.into_fourier_keys(param)?;
+        param.check_evaluation_keys(fourier_bsk.as_view(), &fourier_glwe_ksk, &auto_keys, ss_key.as_view())?;
+
+        Ok(ServerKeySet {
+            fourier_bsk,
+            fourier_glwe_ksk,
+            auto_keys,
+            ss_key,
+            half_cbs: None,
+        })
+    }
+
+    /// Like [`from_bundle`](Self::from_bundle), also rebuilding the half-CBS
+    /// keys that `server_preprocess_dataset` adds for a half-CBS parameter set.
+    pub fn from_half_cbs_bundle(mut bundle: FourierKeyBundle, param: &AesHalfCBSParam<u64>) -> Result<Self, Box<dyn Error>> {
+        let (ss_key, auto_keys) = bundle.take_half_cbs_keys(param)?.ok_or(
+            "the Fourier key bundle holds no half-CBS keys; rerun server_preprocess_dataset \
+            with the half-CBS parameter set",
+        )?;
+        param.check_half_cbs_keys(&auto_keys, ss_key.as_view())?;
+
+        let mut keys = Self::from_bundle(bundle, &param.base_param())?;
+        keys.half_cbs = Some(HalfCbsKeySet { param: *param, auto_keys, ss_key });
+        Ok(keys)
+    }
+}
+
+/// Round key material the transciphering runs on.
+enum CipherKey {
+    /// Keyed LUTs of the inverse cipher, ECB only.
+    Ecb(AllRdKeys),
+    /// Keyed LUTs of the forward cipher, CTR only.
+    Ctr(AllFwdRdKeys),
+    /// Round keys expanded from an encrypted master key, both modes. The
+    /// InvMixColumns of the round keys needed by ECB is computed on first use.
+    RoundKeys {
+        round_keys: Vec<LweCiphertextListOwned<u64>>,
+        inv_mixed_round_keys: OnceLock<Vec<LweCiphertextListOwned<u64>>>,
+    },
+    /// GLev and entry table keyed LUTs of the half-CBS pipeline, ECB only.
+    HalfCbs(AllHalfCbsRdKeys),
+}
+
+/// Most LUTs evaluated on one byte at once: the ×9, ×11, ×13 and ×14 LUTs of
+/// the inverse cipher.
+const MAX_LUT_COUNT: usize = 4;
+
+/// Transciphers AES ciphertexts with the server keys and a transciphering
+/// key it owns. All the methods borrow the keys, so a single instance can
+/// serve any number of requests; the circuit bootstrapping scratch is
+/// allocated once and requests take turns on it.
+pub struct Transcipherer {
+    param: AesParam<u64>,
+    keys: ServerKeySet,
+    cipher_key: CipherKey,
+    cbs_workers: Mutex<Vec<ByteCbsWorker<u64>>>,
+}
+
+impl Transcipherer {
+    /// An encrypted master key is expanded into round keys here, once.
+    pub fn new(param: AesParam<u64>, keys: ServerKeySet, trans_key: TransKey) -> Result<Self, Box<dyn Error>> {
+        let mut cbs_workers = new_byte_cbs_workers(
+            keys.fourier_bsk.as_view(),
+            &keys.auto_keys,
+            param.cbs_base_log(),
+            param.cbs_level(),
+            param.log_lut_count(),
+            param.ciphertext_modulus(),
+            MAX_LUT_COUNT,
+        )?;
+        let cipher_key = match trans_key {
+            TransKey::Ecb(all_rd_key) => CipherKey::Ecb(all_rd_key),
+            TransKey::Ctr(all_fwd_rd_key) => CipherKey::Ctr(all_fwd_rd_key),
+            TransKey::MasterKey(he_key) => CipherKey::RoundKeys {
+                round_keys: he_key_expansion_by_patched_wwlp_cbs(
+                    &he_key,
+                    &keys.fourier_glwe_ksk,
+                    keys.fourier_bsk.as_view(),
+                    &keys.auto_keys,
+                    keys.ss_key.as_view(),
+                    &mut cbs_workers,
+                )?,
+                inv_mixed_round_keys: OnceLock::new(),
+            },
+            TransKey::HalfCbs(all_rd_key) => CipherKey::HalfCbs(all_rd_key),
+        };
+
+        Ok(Transcipherer {
+            param,
+            keys,
+            cipher_key,
+            cbs_workers: Mutex::new(cbs_workers),
+        })
+    }
+
+    pub fn param(&self) -> &AesParam<u64> {
+        &self.param
+    }
+
+    pub fn keys(&self) -> &ServerKeySet {
+        &self.keys
+    }
+
+    /// Whether the transciphering key can decrypt ECB ciphertexts.
+    pub fn supports_ecb(&self) -> bool {
+        !matches!(self.cipher_key, CipherKey::Ctr(_))
+    }
+
+    /// Whether the transciphering key can decrypt CTR ciphertexts.
+    pub fn supports_ctr(&self) -> bool {
+        !matches!(self.cipher_key, CipherKey::Ecb(_) | CipherKey::HalfCbs(_))
+    }
+
+    /// Transciphers a single ECB block into 128 LWE ciphertexts.
+    pub fn transcipher_block(&self, ciphertext: &[u8; 16]) -> Result<LweCiphertextListOwned<u64>, Box<dyn Error>> {
+        self.transcipher_blocks(std::slice::from_ref(ciphertext))
+    }
+
+    /// Transciphers a sequence of ECB blocks, block `i` occupying bits
+    /// `128 * i..128 * (i + 1)` of the returned list.
+    pub fn transcipher_blocks(&self, ciphertexts: &[[u8; 16]]) -> Result<LweCiphertextListOwned<u64>, Box<dyn Error>> {
+        let keys = &self.keys;
+        let mut cbs_workers = self.cbs_workers();
+        match &self.cipher_key {
+            CipherKey::Ecb(all_rd_key) => aes_to_lwe_trasnciphering_blocks(
+                ciphertexts,
+                &self.param,
+                all_rd_key,
+                keys.fourier_bsk.as_view(),
+                &keys.fourier_glwe_ksk,
+                &keys.auto_keys,
+                keys.ss_key.as_view(),
+                &mut cbs_workers,
+            ),
+            CipherKey::Ctr(_) => Err("the transciphering key only holds forward round keys, \
+                which need an IV to run in CTR mode".into()),
+            CipherKey::RoundKeys { round_keys, inv_mixed_round_keys } => {
+                let inv_mixed_round_keys = match inv_mixed_round_keys.get() {
+                    Some(inv_mixed) => inv_mixed,
+                    None => {
+                        let inv_mixed = he_inv_mix_round_keys(
+                            round_keys,
+                            &self.param,
+                            keys.fourier_bsk.as_view(),
+                            &keys.fourier_glwe_ksk,
+                            &keys.auto_keys,
+                            keys.ss_key.as_view(),
+                            &mut cbs_workers,
+                        )?;
+                        inv_mixed_round_keys.get_or_init(|| inv_mixed)
+                    }
+                };
+                aes_round_keys_to_lwe_trasnciphering_blocks(
+                    ciphertexts,
+                    &self.param,
+                    round_keys,
+                    inv_mixed_round_keys,
+                    keys.fourier_bsk.as_view(),
+                    &keys.fourier_glwe_ksk,
+                    &keys.auto_keys,
+                    keys.ss_key.as_view(),
+                    &mut cbs_workers,
+                )
+            }
+            CipherKey::HalfCbs(all_rd_key) => aes_half_cbs_to_lwe_trasnciphering_blocks(
+                ciphertexts,
+                &self.param,
+                all_rd_key,
+                keys.fourier_bsk.as_view(),
+                &keys.fourier_glwe_ksk,
+                &keys.auto_keys,
+                keys.ss_key.as_view(),
+                &mut cbs_workers,
+                self.half_cbs_keys()?,
+            ),
+        }
+    }
+
+    /// Each request already runs on every worker, so requests take turns.
+    fn cbs_workers(&self) -> MutexGuard<'_, Vec<ByteCbsWorker<u64>>> {
+        self.cbs_workers.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn half_cbs_keys(&self) -> Result<&HalfCbsKeySet, Box<dyn Error>> {
+        self.keys.half_cbs.as_ref().ok_or_else(|| {
+            "the transciphering key runs the half-CBS pipeline, but the server keys \
+            were loaded without half-CBS keys"
+                .into()
+        })
+    }
+
+    /// Transciphers ECB blocks like [`transcipher_blocks`](Self::transcipher_blocks)
+    /// while `probe` decrypts the state after every round. Only the keyed LUTs
+    /// of the inverse cipher are supported, through either pipeline.
+    pub fn transcipher_blocks_with_probe(
+        &self,
+        ciphertexts: &[[u8; 16]],
+        probe: &mut NoiseProbe,
+    ) -> Result<LweCiphertextListOwned<u64>, Box<dyn Error>> {
+        let num_rounds = match &self.cipher_key {
+            CipherKey::Ecb(all_rd_key) => all_rd_key._middle_round_key.len() + 2,
+            CipherKey::HalfCbs(all_rd_key) => all_rd_key._middle_round_key.len() + 3,
+            _ => return Err("noise measurement needs a transciphering key of ECB keyed LUTs".into()),
+        };
+        if probe.num_rounds() != num_rounds {
+            return Err(format!(
+                "the AES key has {} rounds but the transciphering key {}",
+                probe.num_rounds(),
+                num_rounds
+            )
+            .into());
+        }
+
+        let keys = &self.keys;
+        let mut cbs_workers = self.cbs_workers();
+        let mut he_blocks = LweCiphertextList::new(
+            0u64,
+            keys.fourier_bsk.output_lwe_dimension().to_lwe_size(),
+            LweCiphertextCount(BLOCKSIZE_IN_BIT * ciphertexts.len()),
+            self.param.ciphertext_modulus(),
+        );
+        for (ciphertext, mut he_block) in ciphertexts
+            .iter()
+            .zip(he_blocks.chunks_exact_mut(BLOCKSIZE_IN_BIT))
+        {
+            probe.begin_block(ciphertext);
+            let on_round = |round, he_state: &LweCiphertextList<Vec<u64>>| probe.record(round, he_state);
+            let he_state = match &self.cipher_key {
+                CipherKey::Ecb(all_rd_key) => aes_to_lwe_trasnciphering(
+                    ciphertext,
+                    &self.param,
+                    all_rd_key,
+                    keys.fourier_bsk.as_view(),
+                    &keys.fourier_glwe_ksk,
+                    &keys.auto_keys,
+                    keys.ss_key.as_view(),
+                    &mut cbs_workers,
+                    on_round,
+                )?,
+                CipherKey::HalfCbs(all_rd_key) => aes_half_cbs_to_lwe_trasnciphering(
+                    ciphertext,
+                    &self.param,
+                    all_rd_key,
+                    keys.fourier_bsk.as_view(),
+                    &keys.fourier_glwe_ksk,
+                    &keys.auto_keys,
+                    keys.ss_key.as_view(),
+                    &mut cbs_workers,
+                    self.half_cbs_keys()?,
+                    on_round,
+                )?,
+                CipherKey::Ctr(_) | CipherKey::RoundKeys { .. } => unreachable!("rejected above"),
+            };
+            he_block.as_mut().clone_from_slice(he_state.as_ref());
+        }
+        Ok(he_blocks)
+    }
+
+    /// Transciphers CTR blocks, block `i` being XORed with the encryption of
+    /// the counter `iv + i`. The output layout is that of
+    /// [`transcipher_blocks`](Self::transcipher_blocks).
+    pub fn transcipher_ctr(
+        &self,
+        ciphertexts: &[[u8; 16]],
+        iv: &[u8; 16],
+    ) -> Result<LweCiphertextListOwned<u64>, Box<dyn Error>> {
+        let keys = &self.keys;
+        let mut cbs_workers = self.cbs_workers();
+        match &self.cipher_key {
+            CipherKey::Ecb(_) | CipherKey::HalfCbs(_) => Err("the transciphering key only holds inverse round keys, \
+                which cannot run in CTR mode".into()),
+            CipherKey::Ctr(all_fwd_rd_key) => aes_ctr_to_lwe_trasnciphering_blocks(
+                ciphertexts,
+                iv,
+                &self.param,
+                all_fwd_rd_key,
+                keys.fourier_bsk.as_view(),
+                &keys.fourier_glwe_ksk,
+                &keys.auto_keys,
+                keys.ss_key.as_view(),
+                &mut cbs_workers,
+            ),
+            CipherKey::RoundKeys { round_keys, .. } => aes_ctr_round_keys_to_lwe_trasnciphering_blocks(
+                ciphertexts,
+                iv,
+                &self.param,
+                round_keys,
+                keys.fourier_bsk.as_view(),
+                &keys.fourier_glwe_ksk,
+                &keys.auto_keys,
+                keys.ss_key.as_view(),
+                &mut cbs_workers,
+            ),
+        }
+    }
+
+    /// Encrypts LWE-encrypted plaintext blocks under the AES key in ECB mode,
+    /// the reverse of [`transcipher_blocks`](Self::transcipher_blocks). The
+    /// input and output bits are laid out like its output. This runs the
+    /// forward cipher, so it needs a transciphering key that supports CTR.
+    pub fn encrypt_blocks(
+        &self,
+        he_blocks: &LweCiphertextList<Vec<u64>>,
+    ) -> Result<LweCiphertextListOwned<u64>, Box<dyn Error>> {
+        self.check_plaintext_bits(he_blocks)?;
+        if !he_blocks.lwe_ciphertext_count().0.is_multiple_of(BLOCKSIZE_IN_BIT) {
+            return Err(format!(
+                "ECB encryption needs whole blocks, got {} bits",
+                he_blocks.lwe_ciphertext_count().0
+            )
+            .into());
+        }
+
+        let mut he_output = he_blocks.clone();
+        for mut he_block in he_output.chunks_exact_mut(BLOCKSIZE_IN_BIT) {
+            let mut he_state = LweCiphertextList::from_container(
+                he_block.as_ref().to_vec(),
+                he_block.lwe_size(),
+                he_block.ciphertext_modulus(),
+            );
+            reverse_bits_in_bytes(&mut he_state);
+            self.he_fwd_cipher(&mut he_state)?;
+            reverse_bits_in_bytes(&mut he_state);
+            he_block.as_mut().copy_from_slice(he_state.as_ref());
+        }
+        Ok(he_output)
+    }
+
+    /// Encrypts LWE-encrypted plaintext bytes under the AES key in CTR mode,
+    /// XORing block `i` with the encryption of the counter `iv + i`. The last
+    /// block may be partial, and the bits are laid out as in
+    /// [`encrypt_blocks`](Self::encrypt_blocks).
+    pub fn encrypt_ctr(
+        &self,
+        he_bytes: &LweCiphertextList<Vec<u64>>,
+        iv: &[u8; 16],
+    ) -> Result<LweCiphertextListOwned<u64>, Box<dyn Error>> {
+        self.check_plaintext_bits(he_bytes)?;
+        let num_bits = he_bytes.lwe_ciphertext_count().0;
+        if !num_bits.is_multiple_of(BYTESIZE) {
+            return Err(format!("CTR encryption needs whole bytes, got {} bits", num_bits).into());
+        }
+
+        let mut he_output = he_bytes.clone();
+        let counters = get_ctr_blocks(iv, num_bits.div_ceil(BLOCKSIZE_IN_BIT));
+        for (counter, mut he_block) in counters.iter().zip(he_output.chunks_mut(BLOCKSIZE_IN_BIT)) {
+            let mut keystream = self.he_keystream(counter)?;
+            reverse_bits_in_bytes(&mut keystream);
+            lwe_ciphertext_list_add_assign(&mut he_block, keystream.as_view());
+        }
+        Ok(he_output)
+    }
+
+    fn check_plaintext_bits(&self, he_bits: &LweCiphertextList<Vec<u64>>) -> Result<(), Box<dyn Error>> {
+        let lwe_size = self.keys.fourier_bsk.output_lwe_dimension().to_lwe_size();
+        if he_bits.lwe_size() != lwe_size {
+            return Err(format!(
+                "the plaintext bits are LWEs of size {}, expected {}",
+                he_bits.lwe_size().0,
+                lwe_size.0
+            )
+            .into());
+        }
+        Ok(())
+    }
+
+    /// Forward cipher on a state holding bits LSB first within each byte.
+    fn he_fwd_cipher(&self, he_state: &mut LweCiphertextList<Vec<u64>>) -> Result<(), Box<dyn Error>> {
+        let keys = &self.keys;
+        let mut cbs_workers = self.cbs_workers();
+        match &self.cipher_key {
+            CipherKey::Ecb(_) | CipherKey::HalfCbs(_) => {
+                return Err("the transciphering key only holds inverse round keys, \
+                    which cannot run the forward cipher"
+                    .into())
+            }
+            CipherKey::Ctr(all_fwd_rd_key) => he_fwd_rounds_by_patched_wwlp_cbs(
+                he_state,
+                &all_fwd_rd_key._middle_round_key,
+                &all_fwd_rd_key._last_round_key,
+                &self.param,
+                keys.fourier_bsk.as_view(),
+                &keys.fourier_glwe_ksk,
+                &keys.auto_keys,
+                keys.ss_key.as_view(),
+                &mut cbs_workers,
+            )?,
+            CipherKey::RoundKeys { round_keys, .. } => {
+                he_add_round_key(he_state, &round_keys[0]);
+                he_fwd_rounds_by_round_keys(
+                    he_state,
+                    round_keys,
+                    &self.param,
+                    keys.fourier_bsk.as_view(),
+                    &keys.fourier_glwe_ksk,
+                    &keys.auto_keys,
+                    keys.ss_key.as_view(),
+                    &mut cbs_workers,
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Encryption of a public counter block, LSB first within each byte.
+    fn he_keystream(&self, counter: &[u8; 16]) -> Result<LweCiphertextListOwned<u64>, Box<dyn Error>> {
+        let keys = &self.keys;
+        let mut cbs_workers = self.cbs_workers();
+        match &self.cipher_key {
+            CipherKey::Ecb(_) | CipherKey::HalfCbs(_) => Err("the transciphering key only holds inverse round keys, \
+                which cannot run the forward cipher".into()),
+            CipherKey::Ctr(all_fwd_rd_key) => aes_ctr_keystream_trasnciphering(
+                counter,
+                &self.param,
+                all_fwd_rd_key,
+                keys.fourier_bsk.as_view(),
+                &keys.fourier_glwe_ksk,
+                &keys.auto_keys,
+                keys.ss_key.as_view(),
+                &mut cbs_workers,
+            ),
+            CipherKey::RoundKeys { round_keys, .. } => aes_round_keys_keystream_trasnciphering(
+                counter,
+                &self.param,
+                round_keys,
+                keys.fourier_bsk.as_view(),
+                &keys.fourier_glwe_ksk,
+                &keys.auto_keys,
+                keys.ss_key.as_view(),
+                &mut cbs_workers,
+            ),
+        }
+    }
+}
+
+/// Transciphers a sequence of AES blocks and concatenates the results, so that
+/// block `i` occupies bits `128 * i..128 * (i + 1)` of the returned list.
+#[allow(clippy::too_many_arguments)]
+fn aes_to_lwe_trasnciphering_blocks<KSKeyCont>(
+    ciphertexts: &[[u8; 16]],
+    parms: &AesParam<u64>,
+    all_rd_key: &AllRdKeys,
+    fft_bsk: FourierLweBootstrapKeyView,
+    fft_ksk: &FourierGlweKeyswitchKey<KSKeyCont>,
+    auto_key: &HashMap<usize, AutomorphKey<ABox<[c64]>>>,
+    ss_key: FourierGgswCiphertextListView,
+    cbs_workers: &mut [ByteCbsWorker<u64>],
+) -> Result<LweCiphertextList<Vec<u64>>, Box<dyn Error>>
+where
+    KSKeyCont: Container<Element = c64> + Sync,
+{
+    let mut he_blocks = LweCiphertextList::new(
+        0u64,
+        fft_bsk.output_lwe_dimension().to_lwe_size(),
+        LweCiphertextCount(BLOCKSIZE_IN_BIT * ciphertexts.len()),
+        parms.ciphertext_modulus(),
+    );
+
+    for (ciphertext, mut he_block) in ciphertexts
+        .iter()
+        .zip(he_blocks.chunks_exact_mut(BLOCKSIZE_IN_BIT))
+    {
+        let he_state = aes_to_lwe_trasnciphering(
+            ciphertext, parms, all_rd_key, fft_bsk, fft_ksk, auto_key, ss_key, cbs_workers, |_, _| {},
+        )?;
+        he_block.as_mut().clone_from_slice(he_state.as_ref());
+    }
+    Ok(he_blocks)
+}
+
+/// `on_round` is handed the state after each round, with the index of the
+/// round key just added, from Nr - 1 down to 0.
+#[allow(clippy::too_many_arguments)]
+fn aes_to_lwe_trasnciphering<KSKeyCont, F>(
+    ciphertext: &[u8; 16],
+    parms: &AesParam<u64>,
+    all_rd_key: &AllRdKeys,
+    fft_bsk: FourierLweBootstrapKeyView,
+    fft_ksk: &FourierGlweKeyswitchKey<KSKeyCont>,
+    auto_key: &HashMap<usize, AutomorphKey<ABox<[c64]>>>,
+    ss_key: FourierGgswCiphertextListView,
+    cbs_workers: &mut [ByteCbsWorker<u64>],
+    mut on_round: F,
+) -> Result<LweCiphertextList<Vec<u64>>, Box<dyn Error>>
+where
+    KSKeyCont: Container<Element = c64> + Sync,
+    F: FnMut(usize, &LweCiphertextList<Vec<u64>>),
+{
+    let fft_bsk_lwe_size = fft_bsk.output_lwe_dimension().to_lwe_size();
+    let ciphertext_modulus = parms.ciphertext_modulus();
+
+    let rd_key_last_two = &all_rd_key._last_two_round_key;
+    let rd_key_middle = &all_rd_key._middle_round_key;
+    let rd_key_0 = &all_rd_key._0_round_key;
+    // prepare containers
+    let mut he_state = LweCiphertextList::new(
+        0u64,
+        fft_bsk_lwe_size,
+        LweCiphertextCount(BLOCKSIZE_IN_BIT),
+        ciphertext_modulus,
+    );
+
+    let mut he_state_times_9 = LweCiphertextList::new(
+        0u64,
+        fft_bsk_lwe_size,
+        LweCiphertextCount(BLOCKSIZE_IN_BIT),
+        ciphertext_modulus,
+    );
+
+    let mut he_state_times_11 = LweCiphertextList::new(
+        0u64,
+        fft_bsk_lwe_size,
+        LweCiphertextCount(BLOCKSIZE_IN_BIT),
+        ciphertext_modulus,
+    );
+
+    let mut he_state_times_13 = LweCiphertextList::new(
+        0u64,
+        fft_bsk_lwe_size,
+        LweCiphertextCount(BLOCKSIZE_IN_BIT),
+        ciphertext_modulus,
+    );
+
+    let mut he_state_times_14 = LweCiphertextList::new(
+        0u64,
+        fft_bsk_lwe_size,
+        LweCiphertextCount(BLOCKSIZE_IN_BIT),
+        ciphertext_modulus,
+    );
+
+    let mut temp = byte_array_to_mat(*ciphertext);
+    inv_shift_rows(&mut temp);
+    let ciphertext = byte_mat_to_array(temp);
+    let (
+        round_10_9_he_lut_cipher_times_9,
+        round_10_9_he_lut_cipher_times_11,
+        round_10_9_he_lut_cipher_times_13,
+        round_10_9_he_lut_cipher_times_14,
+    ) = rd_key_last_two;
+
+    known_rotate_keyed_lut(
+        ciphertext,
+        round_10_9_he_lut_cipher_times_9,
+        &mut he_state_times_9,
+    )?;
+    known_rotate_keyed_lut(
+        ciphertext,
+        round_10_9_he_lut_cipher_times_11,
+        &mut he_state_times_11,
+    )?;
+    known_rotate_keyed_lut(
+        ciphertext,
+        round_10_9_he_lut_cipher_times_13,
+        &mut he_state_times_13,
+    )?;
+    known_rotate_keyed_lut(
+        ciphertext,
+        round_10_9_he_lut_cipher_times_14,
+        &mut he_state_times_14,
+    )?;
+
+    he_inv_mix_columns_precomp(
+        &mut he_state,
+        &he_state_times_9,
+        &he_state_times_11,
+        &he_state_times_13,
+        &he_state_times_14,
+    );
+
+    he_inv_shift_rows(&mut he_state);
+    on_round(rd_key_middle.len() + 1, &he_state);
+
+    he_inv_rounds_by_patched_wwlp_cbs(
+        &mut he_state,
+        &mut he_state_times_9,
+        &mut he_state_times_11,
+        &mut he_state_times_13,
+        &mut he_state_times_14,
+        rd_key_middle,
+        rd_key_0,
+        parms,
+        fft_bsk,
+        fft_ksk,
+        auto_key,
+        ss_key,
+        cbs_workers,
+        on_round,
+    )?;
+    Ok(he_state)
+}
+
+/// Rounds `rd_key_middle.len()` down to 0 of the inverse cipher, on a state
+/// the rounds above were already removed from, followed by the reordering of
+/// the output bits. The ×9, ×11, ×13 and ×14 lists are scratch space.
+#[allow(clippy::too_many_arguments)]
+fn he_inv_rounds_by_patched_wwlp_cbs<KSKeyCont, F>(
+    he_state: &mut LweCiphertextList<Vec<u64>>,
+    he_state_times_9: &mut LweCiphertextList<Vec<u64>>,
+    he_state_times_11: &mut LweCiphertextList<Vec<u64>>,
+    he_state_times_13: &mut LweCiphertextList<Vec<u64>>,
+    he_state_times_14: &mut LweCiphertextList<Vec<u64>>,
+    rd_key_middle: &[Vec<GlweCiphertextList<Vec<u64>>>],
+    rd_key_0: &[GlweCiphertextList<Vec<u64>>],
+    parms: &AesParam<u64>,
+    fft_bsk: FourierLweBootstrapKeyView,
+    fft_ksk: &FourierGlweKeyswitchKey<KSKeyCont>,
+    auto_key: &HashMap<usize, AutomorphKey<ABox<[c64]>>>,
+    ss_key: FourierGgswCiphertextListView,
+    cbs_workers: &mut [ByteCbsWorker<u64>],
+    mut on_round: F,
+) -> Result<(), Box<dyn Error>>
+where
+    KSKeyCont: Container<Element = c64> + Sync,
+    F: FnMut(usize, &LweCiphertextList<Vec<u64>>),
+{
+    let mut he_state_ks = LweCiphertextList::new(
+        0u64,
+        parms.lwe_dimension().to_lwe_size(),
+        LweCiphertextCount(BLOCKSIZE_IN_BIT),
+        parms.ciphertext_modulus(),
+    );
+
+    // Nr-2,...,2,1
+    for round in (1..=rd_key_middle.len()).rev() {
+        try_keyswitch_lwe_ciphertext_list_by_glwe_keyswitch(he_state, &mut he_state_ks, fft_ksk)?;
+        // rd_key_middle[i] packs the ×9, ×11, ×13 and ×14 keyed LUTs of each byte
+        he_eval_packed_keyed_byte_luts_by_patched_wwlp_cbs(
+            &he_state_ks,
+            &mut [
+                he_state_times_9.as_mut_view(),
+                he_state_times_11.as_mut_view(),
+                he_state_times_13.as_mut_view(),
+                he_state_times_14.as_mut_view(),
+            ],
+            &rd_key_middle[round - 1], //序号从0开始,但轮数从1开始
+            fft_bsk,
+            auto_key,
+            ss_key,
+            cbs_workers,
+        )?;
+
+        he_inv_mix_columns_precomp(
+            he_state,
+            he_state_times_9,
+            he_state_times_11,
+            he_state_times_13,
+            he_state_times_14,
+        );
+
+        he_inv_shift_rows(he_state);
+        on_round(round, he_state);
+    }
+
+    // 最后一轮，只有查表
+    try_keyswitch_lwe_ciphertext_list_by_glwe_keyswitch(he_state, &mut he_state_ks, fft_ksk)?;
+
+    he_eval_packed_keyed_byte_luts_by_patched_wwlp_cbs(
+        &he_state_ks,
+        std::slice::from_mut(he_state),
+        rd_key_0,
+        fft_bsk,
+        auto_key,
+        ss_key,
+        cbs_workers,
+    )?;
+    on_round(0, he_state);
+
+    reverse_bits_in_bytes(he_state);
+    Ok(())
+}
+
+/// Half-CBS counterpart of `aes_to_lwe_trasnciphering_blocks`.
+#[allow(clippy::too_many_arguments)]
+fn aes_half_cbs_to_lwe_trasnciphering_blocks<KSKeyCont>(
+    ciphertexts: &[[u8; 16]],
+    parms: &AesParam<u64>,
+    all_rd_key: &AllHalfCbsRdKeys,
+    fft_bsk: FourierLweBootstrapKeyView,
+    fft_ksk: &FourierGlweKeyswitchKey<KSKeyCont>,
+    auto_key: &HashMap<usize, AutomorphKey<ABox<[c64]>>>,
+    ss_key: FourierGgswCiphertextListView,
+    cbs_workers: &mut [ByteCbsWorker<u64>],
+    half_cbs_keys: &HalfCbsKeySet,
+) -> Result<LweCiphertextList<Vec<u64>>, Box<dyn Error>>
+where
+    KSKeyCont: Container<Element = c64> + Sync,
+{
+    let mut he_blocks = LweCiphertextList::new(
+        0u64,
+        fft_bsk.output_lwe_dimension().to_lwe_size(),
+        LweCiphertextCount(BLOCKSIZE_IN_BIT * ciphertexts.len()),
+        parms.ciphertext_modulus(),
+    );
+
+    for (ciphertext, mut he_block) in ciphertexts
+        .iter()
+        .zip(he_blocks.chunks_exact_mut(BLOCKSIZE_IN_BIT))
+    {
+        let he_state = aes_half_cbs_to_lwe_trasnciphering(
+            ciphertext, parms, all_rd_key, fft_bsk, fft_ksk, auto_key, ss_key, cbs_workers, half_cbs_keys, |_, _| {},
+        )?;
+        he_block.as_mut().clone_from_slice(he_state.as_ref());
+    }
+    Ok(he_blocks)
+}
+
+/// Half-CBS pipeline. The first step looks up GLev keyed LUTs, so each term of
+/// the InvMixColumns of round Nr - 1 comes out as a Lev ciphertext per bit.
+/// Those 512 Lev ciphertexts become GGSWs through the half-CBS keys and drive
+/// the entry tables of round Nr - 2, which evaluate the keyed S-box directly
+/// on the XOR of the four terms of each state byte. The rounds below run as in
+/// `aes_to_lwe_trasnciphering`. `on_round` sees the same rounds as there.
+///
+/// The half-CBS covers the Nr - 1 to Nr - 2 transition only. A Lev state
+/// comes out of GLev keyed LUTs rotated by known ciphertext bytes, which only
+/// the first step has; the entry tables output one LWE ciphertext per bit,
+/// so from round Nr - 3 down there is no Lev state to hand to
+/// `convert_lev_state_to_ggsw` and the state goes through the full CBS.
+/// Carrying the Lev state further would need entry tables encrypted once per
+/// half-CBS level, which neither the client keys nor `AllHalfCbsRdKeys` hold.
+#[allow(clippy::too_many_arguments)]
+fn aes_half_cbs_to_lwe_trasnciphering<KSKeyCont, F>(
+    ciphertext: &[u8; 16],
+    parms: &AesParam<u64>,
+    all_rd_key: &AllHalfCbsRdKeys,
+    fft_bsk: FourierLweBootstrapKeyView,
+    fft_ksk: &FourierGlweKeyswitchKey<KSKeyCont>,
+    auto_key: &HashMap<usize, AutomorphKey<ABox<[c64]>>>,
+    ss_key: FourierGgswCiphertextListView,
+    cbs_workers: &mut [ByteCbsWorker<u64>],
+    half_cbs_keys: &HalfCbsKeySet,
+    mut on_round: F,
+) -> Result<LweCiphertextList<Vec<u64>>, Box<dyn Error>>
+where
+    KSKeyCont: Container<Element = c64> + Sync,
+    F: FnMut(usize, &LweCiphertextList<Vec<u64>>),
+{
+    let fft_bsk_lwe_size = fft_bsk.output_lwe_dimension().to_lwe_size();
+    let ciphertext_modulus = parms.ciphertext_modulus();
+    let half_cbs_base_log = half_cbs_keys.param.half_cbs_base_log();
+    let half_cbs_level = half_cbs_keys.param.half_cbs_level();
+
+    let rd_key_middle = &all_rd_key._middle_round_key;
+    let num_rounds = rd_key_middle.len() + 3;
+
+    let new_he_state = || LweCiphertextList::new(
+        0u64,
+        fft_bsk_lwe_size,
+        LweCiphertextCount(BLOCKSIZE_IN_BIT),
+        ciphertext_modulus,
+    );
+    let mut he_state = new_he_state();
+    let mut he_state_times_9 = new_he_state();
+    let mut he_state_times_11 = new_he_state();
+    let mut he_state_times_13 = new_he_state();
+    let mut he_state_times_14 = new_he_state();
+
+    let mut temp = byte_array_to_mat(*ciphertext);
+    inv_shift_rows(&mut temp);
+    let ciphertext = byte_mat_to_array(temp);
+    let (
+        round_10_9_he_glev_cipher_times_9,
+        round_10_9_he_glev_cipher_times_11,
+        round_10_9_he_glev_cipher_times_13,
+        round_10_9_he_glev_cipher_times_14,
+    ) = &all_rd_key._last_two_round_key;
+
+    let new_lev_state = || vec![
+        LweCiphertextList::new(0u64, fft_bsk_lwe_size, LweCiphertextCount(half_cbs_level.0), ciphertext_modulus);
+        BLOCKSIZE_IN_BIT
+    ];
+    // ×9, ×11, ×13 and ×14 terms one after the other, as the GGSWs are indexed
+    let mut lev_state = Vec::with_capacity(4 * BLOCKSIZE_IN_BIT);
+    for glev_keyed_lut in [
+        round_10_9_he_glev_cipher_times_9,
+        round_10_9_he_glev_cipher_times_11,
+        round_10_9_he_glev_cipher_times_13,
+        round_10_9_he_glev_cipher_times_14,
+    ] {
+        let mut lev_state_times = new_lev_state();
+        known_rotate_keyed_lut_for_half_cbs(ciphertext, glev_keyed_lut, &mut lev_state_times)?;
+        lev_state.extend(lev_state_times);
+    }
+
+    // The first level is scaled by 2^(64 - base_log). Bringing it to 2^63
+    // turns the sum of the terms into their XOR, which gives the state of
+    // round Nr - 1 for `on_round`, the pipeline itself only uses the GGSWs
+    for (he_state_times, lev_state_times) in [
+        &mut he_state_times_9,
+        &mut he_state_times_11,
+        &mut he_state_times_13,
+        &mut he_state_times_14,
+    ]
+    .into_iter()
+    .zip(lev_state.chunks_exact(BLOCKSIZE_IN_BIT))
+    {
+        for (mut lwe, lev) in he_state_times.iter_mut().zip(lev_state_times.iter()) {
+            lwe.as_mut().clone_from_slice(lev.get(0).as_ref());
+            lwe_ciphertext_cleartext_mul_assign(&mut lwe, Cleartext(1u64 << (half_cbs_base_log.0 - 1)));
+        }
+    }
+    he_inv_mix_columns_precomp(
+        &mut he_state,
+        &he_state_times_9,
+        &he_state_times_11,
+        &he_state_times_13,
+        &he_state_times_14,
+    );
+    he_inv_shift_rows(&mut he_state);
+    on_round(num_rounds - 1, &he_state);
+
+    let fourier_ggsw_state = he_lev_state_to_fourier_ggsw_by_half_cbs(
+        &lev_state,
+        fft_bsk.glwe_size(),
+        fft_bsk.polynomial_size(),
+        half_cbs_keys,
+    )?;
+    he_inv_keyed_sbox_8_to_32_by_entry_table(
+        &fourier_ggsw_state,
+        &mut he_state_times_9,
+        &mut he_state_times_11,
+        &mut he_state_times_13,
+        &mut he_state_times_14,
+        &all_rd_key._entry_table_round_key,
+    );
+    he_inv_mix_columns_precomp(
+        &mut he_state,
+        &he_state_times_9,
+        &he_state_times_11,
+        &he_state_times_13,
+        &he_state_times_14,
+    );
+    he_inv_shift_rows(&mut he_state);
+    on_round(num_rounds - 2, &he_state);
+
+    he_inv_rounds_by_patched_wwlp_cbs(
+        &mut he_state,
+        &mut he_state_times_9,
+        &mut he_state_times_11,
+        &mut he_state_times_13,
+        &mut he_state_times_14,
+        rd_key_middle,
+        &all_rd_key._0_round_key,
+        parms,
+        fft_bsk,
+        fft_ksk,
+        auto_key,
+        ss_key,
+        cbs_workers,
+        on_round,
+    )?;
+    Ok(he_state)
+}
+
+/// Converts every Lev ciphertext of `lev_state` to a Fourier GGSW with the
+/// half-CBS decomposition.
+fn he_lev_state_to_fourier_ggsw_by_half_cbs(
+    lev_state: &[LweCiphertextList<Vec<u64>>],
+    glwe_size: GlweSize,
+    polynomial_size: PolynomialSize,
+    half_cbs_keys: &HalfCbsKeySet,
+) -> Result<FourierGgswCiphertextList<Vec<c64>>, Box<dyn Error>> {
+    let ggsw_base_log = half_cbs_keys.param.half_cbs_base_log();
+    let ggsw_level = half_cbs_keys.param.half_cbs_level();
+    let ciphertext_modulus = half_cbs_keys.param.ciphertext_modulus();
+
+    let mut fourier_ggsw_state = new_fourier_ggsw_list(
+        lev_state.len(),
+        glwe_size,
+        polynomial_size,
+        ggsw_base_log,
+        ggsw_level,
+    );
+    let lev_to_ggsw = |(lev, mut fourier_ggsw): (&LweCiphertextList<Vec<u64>>, FourierGgswCiphertextMutView)| -> Result<(), CbsError> {
+        let mut ggsw = GgswCiphertextList::new(
+            0u64,
+            glwe_size,
+            polynomial_size,
+            ggsw_base_log,
+            ggsw_level,
+            GgswCiphertextCount(1),
+            ciphertext_modulus,
+        );
+        convert_lev_state_to_ggsw(
+            std::slice::from_ref(lev),
+            &mut ggsw,
+            &half_cbs_keys.auto_keys,
+            half_cbs_keys.ss_key.as_view(),
+        )?;
+        convert_standard_ggsw_ciphertext_to_fourier(&ggsw.get(0), &mut fourier_ggsw);
+        Ok(())
+    };
+
+    #[cfg(not(feature = "multithread"))]
+    lev_state
+        .iter()
+        .zip(fourier_ggsw_state.as_mut_view().into_ggsw_iter())
+        .try_for_each(lev_to_ggsw)?;
+    #[cfg(feature = "multithread")]
+    lev_state
+        .par_iter()
+        .zip(fourier_ggsw_state.as_mut_view().into_ggsw_iter().collect::<Vec<_>>())
+        .try_for_each(lev_to_ggsw)?;
+    Ok(fourier_ggsw_state)
+}
+
+/// One byte of the ×9, ×11, ×13 and ×14 output states, nested as zipping the
+/// four chunk iterators yields them.
+type ByteMultiples<'a> = (
+    (
+        (LweCiphertextListMutView<'a, u64>, LweCiphertextListMutView<'a, u64>),
+        LweCiphertextListMutView<'a, u64>,
+    ),
+    LweCiphertextListMutView<'a, u64>,
+);
+
+/// Keyed S-box of round Nr - 2 in the half-CBS pipeline. The input of state
+/// byte `i` is the XOR of four terms of the InvMixColumns of round Nr - 1,
+/// picked out of `fourier_ggsw_state` (×9, ×11, ×13 and ×14 terms one after
+/// the other) as `he_inv_mix_columns_precomp` and `he_inv_shift_rows` would.
+/// `entry_tables[i]` holds the ×9, ×11, ×13 and ×14 keyed LUTs of byte `i`, in
+/// that order.
+fn he_inv_keyed_sbox_8_to_32_by_entry_table(
+    fourier_ggsw_state: &FourierGgswCiphertextList<Vec<c64>>,
+    he_state_output_mult_by_9: &mut LweCiphertextList<Vec<u64>>,
+    he_state_output_mult_by_11: &mut LweCiphertextList<Vec<u64>>,
+    he_state_output_mult_by_13: &mut LweCiphertextList<Vec<u64>>,
+    he_state_output_mult_by_14: &mut LweCiphertextList<Vec<u64>>,
+    entry_tables: &[GlweCiphertextList<Vec<u64>>],
+) {
+    let fourier_ggsw_byte = |term: usize, row: usize, col: usize| {
+        let ggsw_idx = term * BLOCKSIZE_IN_BIT + (NUM_ROWS * col + row) * BYTESIZE;
+        fourier_ggsw_state.as_view().split_at(ggsw_idx).1.split_at(BYTESIZE).0
+    };
+    // Terms of the InvMixColumns, in the order of the GGSW state
+    let (times_9, times_11, times_13, times_14) = (0, 1, 2, 3);
+
+    let ciphertext_modulus = he_state_output_mult_by_9.ciphertext_modulus();
+    let new_scratch = || {
+        LutEntryTableScratch::new(
+            fourier_ggsw_state.glwe_size(),
+            fourier_ggsw_state.polynomial_size(),
+            ciphertext_modulus,
+        )
+    };
+    let eval_byte = |scratch: &mut LutEntryTableScratch<u64>, (
+        i,
+        (
+            ((mut output_mult_by_9, mut output_mult_by_11), mut output_mult_by_13),
+            mut output_mult_by_14,
+        ),
+    ): (usize, ByteMultiples<'_>)| {
+        let (row, col) = (i % NUM_ROWS, i / NUM_ROWS);
+        let mix_col = (col + NUM_COLUMNS - row) % NUM_COLUMNS;
+        evaluate_lut_entry_table_on_xored_bytes(
+            &[
+                fourier_ggsw_byte(times_14, row, mix_col),
+                fourier_ggsw_byte(times_11, (row + 1) % NUM_ROWS, mix_col),
+                fourier_ggsw_byte(times_13, (row + 2) % NUM_ROWS, mix_col),
+                fourier_ggsw_byte(times_9, (row + 3) % NUM_ROWS, mix_col),
+            ],
+            &entry_tables[i],
+            &mut [
+                output_mult_by_9.as_mut_view(),
+                output_mult_by_11.as_mut_view(),
+                output_mult_by_13.as_mut_view(),
+                output_mult_by_14.as_mut_view(),
+            ],
+            scratch,
+        );
+    };
+
+    #[cfg(not(feature = "multithread"))]
+    {
+        let mut scratch = new_scratch();
+        he_state_output_mult_by_9
+            .chunks_exact_mut(BYTESIZE)
+            .zip(he_state_output_mult_by_11.chunks_exact_mut(BYTESIZE))
+            .zip(he_state_output_mult_by_13.chunks_exact_mut(BYTESIZE))
+            .zip(he_state_output_mult_by_14.chunks_exact_mut(BYTESIZE))
+            .enumerate()
+            .for_each(|byte| eval_byte(&mut scratch, byte));
+    }
+    #[cfg(feature = "multithread")]
+    he_state_output_mult_by_9
+        .par_chunks_exact_mut(BYTESIZE)
+        .zip(he_state_output_mult_by_11.par_chunks_exact_mut(BYTESIZE))
+        .zip(he_state_output_mult_by_13.par_chunks_exact_mut(BYTESIZE))
+        .zip(he_state_output_mult_by_14.par_chunks_exact_mut(BYTESIZE))
+        .enumerate()
+        .for_each_init(new_scratch, eval_byte);
+}
+
+/// CTR-mode counterpart of `aes_to_lwe_trasnciphering_blocks`: block `i` is
+/// XORed with the encryption of the counter `iv + i`.
+#[allow(clippy::too_many_arguments)]
+fn aes_ctr_to_lwe_trasnciphering_blocks<KSKeyCont>(
+    ciphertexts: &[[u8; 16]],
+    iv: &[u8; 16],
+    parms: &AesParam<u64>,
+    all_fwd_rd_key: &AllFwdRdKeys,
+    fft_bsk: FourierLweBootstrapKeyView,
+    fft_ksk: &FourierGlweKeyswitchKey<KSKeyCont>,
+    auto_key: &HashMap<usize, AutomorphKey<ABox<[c64]>>>,
+    ss_key: FourierGgswCiphertextListView,
+    cbs_workers: &mut [ByteCbsWorker<u64>],
+) -> Result<LweCiphertextList<Vec<u64>>, Box<dyn Error>>
+where
+    KSKeyCont: Container<Element = c64> + Sync,
+{
+    let counters = get_ctr_blocks(iv, ciphertexts.len());
+    let mut he_blocks = LweCiphertextList::new(
+        0u64,
+        fft_bsk.output_lwe_dimension().to_lwe_size(),
+        LweCiphertextCount(BLOCKSIZE_IN_BIT * ciphertexts.len()),
+        parms.ciphertext_modulus(),
+    );
+
+    for ((ciphertext, counter), mut he_block) in ciphertexts
+        .iter()
+        .zip(counters.iter())
+        .zip(he_blocks.chunks_exact_mut(BLOCKSIZE_IN_BIT))
+    {
+        let mut he_state = aes_ctr_keystream_trasnciphering(
+            counter,
+            parms,
+            all_fwd_rd_key,
+            fft_bsk,
+            fft_ksk,
+            auto_key,
+            ss_key,
+            cbs_workers,
+        )?;
+        he_xor_known_bytes(&mut he_state, ciphertext);
+        reverse_bits_in_bytes(&mut he_state);
+        he_block.as_mut().clone_from_slice(he_state.as_ref());
+    }
+    Ok(he_blocks)
+}
+
+/// Encrypts a public counter block under the encrypted AES key. The output
+/// bits are LSB first within each byte, like the rest of the HE state.
+#[allow(clippy::too_many_arguments)]
+fn aes_ctr_keystream_trasnciphering<KSKeyCont>(
+    counter: &[u8; 16],
+    parms: &AesParam<u64>,
+    all_fwd_rd_key: &AllFwdRdKeys,
+    fft_bsk: FourierLweBootstrapKeyView,
+    fft_ksk: &FourierGlweKeyswitchKey<KSKeyCont>,
+    auto_key: &HashMap<usize, AutomorphKey<ABox<[c64]>>>,
+    ss_key: FourierGgswCiphertextListView,
+    cbs_workers: &mut [ByteCbsWorker<u64>],
+) -> Result<LweCiphertextList<Vec<u64>>, Box<dyn Error>>
+where
+    KSKeyCont: Container<Element = c64> + Sync,
+{
+    let fft_bsk_lwe_size = fft_bsk.output_lwe_dimension().to_lwe_size();
+    let ciphertext_modulus = parms.ciphertext_modulus();
+
+    let (rd_key_1, rd_key_2_to_last) = all_fwd_rd_key
+        ._middle_round_key
+        .split_first()
+        .ok_or("the transciphering key holds no forward middle round keys")?;
+    // prepare containers
+    let mut he_state = LweCiphertextList::new(
+        0u64,
+        fft_bsk_lwe_size,
+        LweCiphertextCount(BLOCKSIZE_IN_BIT),
+        ciphertext_modulus,
+    );
+    let mut he_state_mult_by_2 = he_state.clone();
+    let mut he_state_mult_by_3 = he_state.clone();
+
+    // first round: the counter is public, so the keyed S-box is a known rotation
+    let (he_lut_times1, he_lut_times2, he_lut_times3) = rd_key_1;
+    known_rotate_keyed_lut(*counter, he_lut_times1, &mut he_state)?;
+    known_rotate_keyed_lut(*counter, he_lut_times2, &mut he_state_mult_by_2)?;
+    known_rotate_keyed_lut(*counter, he_lut_times3, &mut he_state_mult_by_3)?;
+
+    he_shift_rows(&mut he_state);
+    he_shift_rows(&mut he_state_mult_by_2);
+    he_shift_rows(&mut he_state_mult_by_3);
+    he_mix_columns_precomp(&mut he_state, &he_state_mult_by_2, &he_state_mult_by_3);
+
+    he_fwd_rounds_by_patched_wwlp_cbs(
+        &mut he_state,
+        rd_key_2_to_last,
+        &all_fwd_rd_key._last_round_key,
+        parms,
+        fft_bsk,
+        fft_ksk,
+        auto_key,
+        ss_key,
+        cbs_workers,
+    )?;
+
+    Ok(he_state)
+}
+
+/// Forward rounds of the keyed LUT cipher on an encrypted state, LSB first
+/// within each byte: one round per entry of `rd_keys`, then the last round.
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn he_fwd_rounds_by_patched_wwlp_cbs<KSKeyCont>(
+    he_state: &mut LweCiphertextList<Vec<u64>>,
+    rd_keys: &[(
+        Vec<GlweCiphertextList<Vec<u64>>>,
+        Vec<GlweCiphertextList<Vec<u64>>>,
+        Vec<GlweCiphertextList<Vec<u64>>>,
+    )],
+    rd_key_last: &[GlweCiphertextList<Vec<u64>>],
+    parms: &AesParam<u64>,
+    fft_bsk: FourierLweBootstrapKeyView,
+    fft_ksk: &FourierGlweKeyswitchKey<KSKeyCont>,
+    auto_key: &HashMap<usize, AutomorphKey<ABox<[c64]>>>,
+    ss_key: FourierGgswCiphertextListView,
+    cbs_workers: &mut [ByteCbsWorker<u64>],
+) -> Result<(), Box<dyn Error>>
+where
+    KSKeyCont: Container<Element = c64> + Sync,
+{
+    let ciphertext_modulus = parms.ciphertext_modulus();
+    let mut he_state_mult_by_2 = he_state.clone();
+    let mut he_state_mult_by_3 = he_state.clone();
+
+    let mut he_state_ks = LweCiphertextList::new(
+        0u64,
+        parms.lwe_dimension().to_lwe_size(),
+        LweCiphertextCount(BLOCKSIZE_IN_BIT),
+        ciphertext_modulus,
+    );
+
+    for (he_lut_times1, he_lut_times2, he_lut_times3) in rd_keys {
+        try_keyswitch_lwe_ciphertext_list_by_glwe_keyswitch(he_state, &mut he_state_ks, fft_ksk)?;
+        blind_rotate_keyed_sboxes(
+            &he_state_ks,
+            he_lut_times1,
+            he_lut_times2,
+            he_lut_times3,
+            he_state,
+            &mut he_state_mult_by_2,
+            &mut he_state_mult_by_3,
+            fft_bsk,
+            auto_key,
+            ss_key,
+            cbs_workers,
+        )?;
+
+        he_shift_rows(he_state);
+        he_shift_rows(&mut he_state_mult_by_2);
+        he_shift_rows(&mut he_state_mult_by_3);
+        he_mix_columns_precomp(he_state, &he_state_mult_by_2, &he_state_mult_by_3);
+    }
+
+    // last round, the last round key is already folded into the LUT
+    try_keyswitch_lwe_ciphertext_list_by_glwe_keyswitch(he_state, &mut he_state_ks, fft_ksk)?;
+    blind_rotate_keyed_sbox(&he_state_ks, rd_key_last, he_state, fft_bsk, auto_key, ss_key, cbs_workers)?;
+    he_shift_rows(he_state);
+    Ok(())
+}
+
+/// ECB transciphering from round keys expanded on the server. This is the
+/// plain inverse cipher: unkeyed inverse S-box LUTs followed by
+/// `he_add_round_key`. `inv_mixed_round_keys` holds round keys 1 to Nr - 1
+/// with InvMixColumns already applied, see `he_inv_mix_round_keys`, so that
+/// it can still be folded into the S-box LUTs.
+#[allow(clippy::too_many_arguments)]
+fn aes_round_keys_to_lwe_trasnciphering_blocks<KSKeyCont>(
+    ciphertexts: &[[u8; 16]],
+    parms: &AesParam<u64>,
+    round_keys: &[LweCiphertextList<Vec<u64>>],
+    inv_mixed_round_keys: &[LweCiphertextList<Vec<u64>>],
+    fft_bsk: FourierLweBootstrapKeyView,
+    fft_ksk: &FourierGlweKeyswitchKey<KSKeyCont>,
+    auto_key: &HashMap<usize, AutomorphKey<ABox<[c64]>>>,
+    ss_key: FourierGgswCiphertextListView,
+    cbs_workers: &mut [ByteCbsWorker<u64>],
+) -> Result<LweCiphertextList<Vec<u64>>, Box<dyn Error>>
+where
+    KSKeyCont: Container<Element = c64> + Sync,
+{
+    let mut he_blocks = LweCiphertextList::new(
+        0u64,
+        fft_bsk.output_lwe_dimension().to_lwe_size(),
+        LweCiphertextCount(BLOCKSIZE_IN_BIT * ciphertexts.len()),
+        parms.ciphertext_modulus(),
+    );
+
+    for (ciphertext, mut he_block) in ciphertexts
+        .iter()
+        .zip(he_blocks.chunks_exact_mut(BLOCKSIZE_IN_BIT))
+    {
+        let he_state = aes_round_keys_to_lwe_trasnciphering(
+            ciphertext,
+            parms,
+            round_keys,
+            inv_mixed_round_keys,
+            fft_bsk,
+            fft_ksk,
+            auto_key,
+            ss_key,
+            cbs_workers,
+        )?;
+        he_block.as_mut().clone_from_slice(he_state.as_ref());
+    }
+    Ok(he_blocks)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn aes_round_keys_to_lwe_trasnciphering<KSKeyCont>(
+    ciphertext: &[u8; 16],
+    parms: &AesParam<u64>,
+    round_keys: &[LweCiphertextList<Vec<u64>>],
+    inv_mixed_round_keys: &[LweCiphertextList<Vec<u64>>],
+    fft_bsk: FourierLweBootstrapKeyView,
+    fft_ksk: &FourierGlweKeyswitchKey<KSKeyCont>,
+    auto_key: &HashMap<usize, AutomorphKey<ABox<[c64]>>>,
+    ss_key: FourierGgswCiphertextListView,
+    cbs_workers: &mut [ByteCbsWorker<u64>],
+) -> Result<LweCiphertextList<Vec<u64>>, Box<dyn Error>>
+where
+    KSKeyCont: Container<Element = c64> + Sync,
+{
+    let mut he_state_ks = LweCiphertextList::new(
+        0u64,
+        parms.lwe_dimension().to_lwe_size(),
+        LweCiphertextCount(BLOCKSIZE_IN_BIT),
+        parms.ciphertext_modulus(),
+    );
+
+    let num_rounds = round_keys.len() - 1;
+    let mut he_state = round_keys[num_rounds].clone();
+    he_xor_known_bytes(&mut he_state, ciphertext);
+    // times 9, 11, 13, 14 of the inverse S-box output
+    let mut he_state_mults = vec![he_state.clone(); 4];
+
+    // Nr-1,...,2,1
+    for round in (1..num_rounds).rev() {
+        he_inv_shift_rows(&mut he_state);
+        try_keyswitch_lwe_ciphertext_list_by_glwe_keyswitch(&he_state, &mut he_state_ks, fft_ksk)?;
+        he_eval_byte_luts_by_patched_wwlp_cbs(
+            &he_state_ks,
+            &mut he_state_mults,
+            &[
+                &AES128_INV_SBOX_MULT_BY_9,
+                &AES128_INV_SBOX_MULT_BY_B,
+                &AES128_INV_SBOX_MULT_BY_D,
+                &AES128_INV_SBOX_MULT_BY_E,
+            ],
+            fft_bsk,
+            auto_key,
+            ss_key,
+            cbs_workers,
+        )?;
+        he_inv_mix_columns_precomp(
+            &mut he_state,
+            &he_state_mults[0],
+            &he_state_mults[1],
+            &he_state_mults[2],
+            &he_state_mults[3],
+        );
+        he_add_round_key(&mut he_state, &inv_mixed_round_keys[round - 1]);
+    }
+
+    // last round, no InvMixColumns
+    he_inv_shift_rows(&mut he_state);
+    try_keyswitch_lwe_ciphertext_list_by_glwe_keyswitch(&he_state, &mut he_state_ks, fft_ksk)?;
+    he_eval_byte_luts_by_patched_wwlp_cbs(
+        &he_state_ks,
+        std::slice::from_mut(&mut he_state),
+        &[&AES128_INV_SBOX],
+        fft_bsk,
+        auto_key,
+        ss_key,
+        cbs_workers,
+    )?;
+    he_add_round_key(&mut he_state, &round_keys[0]);
+
+    reverse_bits_in_bytes(&mut he_state);
+    Ok(he_state)
+}
+
+/// CTR transciphering from round keys expanded on the server: the forward
+/// cipher with unkeyed S-box LUTs followed by `he_add_round_key`.
+#[allow(clippy::too_many_arguments)]
+fn aes_ctr_round_keys_to_lwe_trasnciphering_blocks<KSKeyCont>(
+    ciphertexts: &[[u8; 16]],
+    iv: &[u8; 16],
+    parms: &AesParam<u64>,
+    round_keys: &[LweCiphertextList<Vec<u64>>],
+    fft_bsk: FourierLweBootstrapKeyView,
+    fft_ksk: &FourierGlweKeyswitchKey<KSKeyCont>,
+    auto_key: &HashMap<usize, AutomorphKey<ABox<[c64]>>>,
+    ss_key: FourierGgswCiphertextListView,
+    cbs_workers: &mut [ByteCbsWorker<u64>],
+) -> Result<LweCiphertextList<Vec<u64>>, Box<dyn Error>>
+where
+    KSKeyCont: Container<Element = c64> + Sync,
+{
+    let counters = get_ctr_blocks(iv, ciphertexts.len());
+    let mut he_blocks = LweCiphertextList::new(
+        0u64,
+        fft_bsk.output_lwe_dimension().to_lwe_size(),
+        LweCiphertextCount(BLOCKSIZE_IN_BIT * ciphertexts.len()),
+        parms.ciphertext_modulus(),
+    );
+
+    for ((ciphertext, counter), mut he_block) in ciphertexts
+        .iter()
+        .zip(counters.iter())
+        .zip(he_blocks.chunks_exact_mut(BLOCKSIZE_IN_BIT))
+    {
+        let mut he_state = aes_round_keys_keystream_trasnciphering(
+            counter, parms, round_keys, fft_bsk, fft_ksk, auto_key, ss_key, cbs_workers,
+        )?;
+        he_xor_known_bytes(&mut he_state, ciphertext);
+        reverse_bits_in_bytes(&mut he_state);
+        he_block.as_mut().clone_from_slice(he_state.as_ref());
+    }
+    Ok(he_blocks)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn aes_round_keys_keystream_trasnciphering<KSKeyCont>(
+    counter: &[u8; 16],
+    parms: &AesParam<u64>,
+    round_keys: &[LweCiphertextList<Vec<u64>>],
+    fft_bsk: FourierLweBootstrapKeyView,
+    fft_ksk: &FourierGlweKeyswitchKey<KSKeyCont>,
+    auto_key: &HashMap<usize, AutomorphKey<ABox<[c64]>>>,
+    ss_key: FourierGgswCiphertextListView,
+    cbs_workers: &mut [ByteCbsWorker<u64>],
+) -> Result<LweCiphertextList<Vec<u64>>, Box<dyn Error>>
+where
+    KSKeyCont: Container<Element = c64> + Sync,
+{
+    let mut he_state = round_keys[0].clone();
+    he_xor_known_bytes(&mut he_state, counter);
+    he_fwd_rounds_by_round_keys(&mut he_state, round_keys, parms, fft_bsk, fft_ksk, auto_key, ss_key, cbs_workers)?;
+
+    Ok(he_state)
+}
+
+/// Rounds 1 to Nr of the forward cipher from round keys, on an encrypted
+/// state to which round key 0 was already added.
+#[allow(clippy::too_many_arguments)]
+fn he_fwd_rounds_by_round_keys<KSKeyCont>(
+    he_state: &mut LweCiphertextList<Vec<u64>>,
+    round_keys: &[LweCiphertextList<Vec<u64>>],
+    parms: &AesParam<u64>,
+    fft_bsk: FourierLweBootstrapKeyView,
+    fft_ksk: &FourierGlweKeyswitchKey<KSKeyCont>,
+    auto_key: &HashMap<usize, AutomorphKey<ABox<[c64]>>>,
+    ss_key: FourierGgswCiphertextListView,
+    cbs_workers: &mut [ByteCbsWorker<u64>],
+) -> Result<(), Box<dyn Error>>
+where
+    KSKeyCont: Container<Element = c64> + Sync,
+{
+    let mut he_state_ks = LweCiphertextList::new(
+        0u64,
+        parms.lwe_dimension().to_lwe_size(),
+        LweCiphertextCount(BLOCKSIZE_IN_BIT),
+        parms.ciphertext_modulus(),
+    );
+
+    // times 1, 2, 3 of the S-box output
+    let mut he_state_mults = vec![he_state.clone(); 3];
+
+    // 1,2,...,9
+    let num_rounds = round_keys.len() - 1;
+    for round_key in &round_keys[1..num_rounds] {
+        try_keyswitch_lwe_ciphertext_list_by_glwe_keyswitch(he_state, &mut he_state_ks, fft_ksk)?;
+        he_eval_byte_luts_by_patched_wwlp_cbs(
+            &he_state_ks,
+            &mut he_state_mults,
+            &[&AES128_SBOX, &AES128_SBOX_MULT_BY_2, &AES128_SBOX_MULT_BY_3],
+            fft_bsk,
+            auto_key,
+            ss_key,
+            cbs_workers,
+        )?;
+        for he_state_mult in he_state_mults.iter_mut() {
+            he_shift_rows(he_state_mult);
+        }
+        he_state.as_mut().clone_from_slice(he_state_mults[0].as_ref());
+        he_mix_columns_precomp(he_state, &he_state_mults[1], &he_state_mults[2]);
+        he_add_round_key(he_state, round_key);
+    }
+
+    // last round, no MixColumns
+    try_keyswitch_lwe_ciphertext_list_by_glwe_keyswitch(he_state, &mut he_state_ks, fft_ksk)?;
+    he_eval_byte_luts_by_patched_wwlp_cbs(
+        &he_state_ks,
+        std::slice::from_mut(he_state),
+        &[&AES128_SBOX],
+        fft_bsk,
+        auto_key,
+        ss_key,
+        cbs_workers,
+    )?;
+    he_shift_rows(he_state);
+    he_add_round_key(he_state, &round_keys[num_rounds]);
+    Ok(())
+}
+
+///////////////////////////// local helper functions /////////////////////////////
+
+/// InvMixColumns of round keys 1 to Nr - 1, evaluated like the cipher rounds from
+/// multiplication LUTs. The sum of the four products is bootstrapped once more
+/// through an identity LUT, so each cipher round only adds a fresh ciphertext.
+fn he_inv_mix_round_keys<KSKeyCont>(
+    round_keys: &[LweCiphertextList<Vec<u64>>],
+    parms: &AesParam<u64>,
+    fft_bsk: FourierLweBootstrapKeyView,
+    fft_ksk: &FourierGlweKeyswitchKey<KSKeyCont>,
+    auto_key: &HashMap<usize, AutomorphKey<ABox<[c64]>>>,
+    ss_key: FourierGgswCiphertextListView,
+    cbs_workers: &mut [ByteCbsWorker<u64>],
+) -> Result<Vec<LweCiphertextList<Vec<u64>>>, Box<dyn Error>>
+where
+    KSKeyCont: Container<Element = c64> + Sync,
+{
+    let mult_lut = |b: u8| -> [u8; 256] { std::array::from_fn(|x| mult_a_b(x as u8, b)) };
+    let (times_9, times_11, times_13, times_14) =
+        (mult_lut(9), mult_lut(11), mult_lut(13), mult_lut(14));
+    let identity = mult_lut(1);
+
+    let mut round_key_ks = LweCiphertextList::new(
+        0u64,
+        parms.lwe_dimension().to_lwe_size(),
+        LweCiphertextCount(BLOCKSIZE_IN_BIT),
+        parms.ciphertext_modulus(),
+    );
+    let mut round_key_mults = vec![round_keys[0].clone(); 4];
+
+    round_keys[1..round_keys.len() - 1]
+        .iter()
+        .map(|round_key| -> Result<_, Box<dyn Error>> {
+            try_keyswitch_lwe_ciphertext_list_by_glwe_keyswitch(round_key, &mut round_key_ks, fft_ksk)?;
+            he_eval_byte_luts_by_patched_wwlp_cbs(
+                &round_key_ks,
+                &mut round_key_mults,
+                &[&times_9, &times_11, &times_13, &times_14],
+                fft_bsk,
+                auto_key,
+                ss_key,
+                cbs_workers,
+            )?;
+            let mut inv_mixed_round_key = round_key.clone();
+            he_inv_mix_columns_precomp(
+                &mut inv_mixed_round_key,
+                &round_key_mults[0],
+                &round_key_mults[1],
+                &round_key_mults[2],
+                &round_key_mults[3],
+            );
+
+            try_keyswitch_lwe_ciphertext_list_by_glwe_keyswitch(
+                &inv_mixed_round_key,
+                &mut round_key_ks,
+                fft_ksk,
+            )?;
+            he_eval_byte_luts_by_patched_wwlp_cbs(
+                &round_key_ks,
+                std::slice::from_mut(&mut inv_mixed_round_key),
+                &[&identity],
+                fft_bsk,
+                auto_key,
+                ss_key,
+                cbs_workers,
+            )?;
+            Ok(inv_mixed_round_key)
+        })
+        .collect()
+}
+
+/// XOR with a known bit: encryptions of `b` become encryptions of `1 - b`.
+fn he_xor_known_bytes(he_state: &mut LweCiphertextList<Vec<u64>>, bytes: &[u8; 16]) {
+    for (i, mut lwe) in he_state.iter_mut().enumerate() {
+        if (bytes[i / BYTESIZE] >> (i % BYTESIZE)) & 1 == 1 {
+            lwe_ciphertext_plaintext_add_assign(&mut lwe, Plaintext(1u64 << 63));
+        }
+    }
+}
+
+/// Reorders each byte of the HE state from LSB first to MSB first, which is
+/// the order the client postprocess packs bits in.
+fn reverse_bits_in_bytes(he_state: &mut LweCiphertextList<Vec<u64>>) {
+    for mut chunk in he_state.chunks_exact_mut(BYTESIZE) {
+        let tmp: Vec<Vec<u64>> = chunk.iter().map(|ct| ct.as_ref().to_vec()).collect();
+
+        for i in 0..BYTESIZE {
+            let src = &tmp[BYTESIZE - 1 - i];
+            chunk.get_mut(i).as_mut().clone_from_slice(src.as_ref());
+        }
+    }
+}
+
+fn he_inv_mix_columns_precomp<Scalar, Cont, ContMut>(
+    he_state: &mut LweCiphertextList<ContMut>,
+    he_state_mult_by_9: &LweCiphertextList<Cont>,
+    he_state_mult_by_11: &LweCiphertextList<Cont>,
+    he_state_mult_by_13: &LweCiphertextList<Cont>,
+    he_state_mult_by_14: &LweCiphertextList<Cont>,
+) where
+    Scalar: UnsignedInteger,
+    Cont: Container<Element = Scalar>,
+    ContMut: ContainerMut<Element = Scalar>,
+{
+    let mut buf = LweCiphertextList::new(
+        Scalar::ZERO,
+        he_state.lwe_size(),
+        LweCiphertextCount(BLOCKSIZE_IN_BIT),
+        he_state.ciphertext_modulus(),
+    );
+    buf.as_mut().clone_from_slice(he_state.as_ref());
+
+    for row in 0..NUM_ROWS {
+        for col in 0..NUM_COLUMNS {
+            let mut tmp = LweCiphertextList::new(
+                Scalar::ZERO,
+                he_state.lwe_size(),
+                LweCiphertextCount(BYTESIZE),
+                he_state.ciphertext_modulus(),
+            );
+
+            tmp.as_mut()
+                .clone_from_slice(get_he_state_byte(he_state_mult_by_14, row, col).as_ref());
+            lwe_ciphertext_list_add_assign(
+                &mut tmp,
+                get_he_state_byte(he_state_mult_by_11, (row + 1) % 4, col),
+            );
+            lwe_ciphertext_list_add_assign(
+                &mut tmp,
+                get_he_state_byte(he_state_mult_by_13, (row + 2) % 4, col),
+            );
+            lwe_ciphertext_list_add_assign(
+                &mut tmp,
+                get_he_state_byte(he_state_mult_by_9, (row + 3) % 4, col),
+            );
+
+            get_he_state_byte_mut(he_state, row, col)
+                .as_mut()
+                .clone_from_slice(tmp.as_ref());
+        }
+    }
+}
+
+fn he_inv_shift_rows<Scalar, Cont>(he_state: &mut LweCiphertextList<Cont>)
+where
+    Scalar: UnsignedInteger,
+    Cont: ContainerMut<Element = Scalar>,
+{
+    let mut buf = LweCiphertextList::new(
+        Scalar::ZERO,
+        he_state.lwe_size(),
+        LweCiphertextCount(BLOCKSIZE_IN_BIT),
+        he_state.ciphertext_modulus(),
+    );
+    buf.as_mut().clone_from_slice(he_state.as_ref());
+
+    for row in 1..4 {
+        for col in 0..4 {
+            let mut dst = get_he_state_byte_mut(he_state, row, col);
+            let src = get_he_state_byte(&buf, row, (4 - row + col) % 4);
+            dst.as_mut().clone_from_slice(src.as_ref());
+        }
+    }
+}
+
+fn inv_shift_rows(state: &mut StateByteMat) {
+    let buf = *state;
+    for row in 0..NUM_ROWS {
+        for col in 0..NUM_COLUMNS {
+            state[col][row] = buf[(NUM_COLUMNS + col - row) % NUM_COLUMNS][row];
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aes_manager::{AesManager, BLOCKSIZE_IN_BYTE};
+    use crate::data_struct::{get_fwd_last_round_key, get_fwd_middle_round_key, SeededAllFwdRdKeys};
+    use auto_base_conv::{
+        decompress_seeded_auto_keys, gen_all_seeded_auto_keys, generate_seeded_scheme_switching_key,
+        keygen_seeded_pbs_with_glwe_ks, AES_TIGHT,
+    };
+    use tfhe::core_crypto::seeders::new_seeder;
+
+    /// Runs the client key generation and the server preprocessing for
+    /// `param`, returning the secret keys and the server keys.
+    fn server_key_set(
+        param: &AesParam<u64>,
+        noise_seeder: &mut dyn Seeder,
+    ) -> (LweSecretKeyOwned<u64>, GlweSecretKeyOwned<u64>, ServerKeySet) {
+        let mut secret_generator = SecretRandomGenerator::<ActivatedRandomGenerator>::new(noise_seeder.seed());
+        let (lwe_sk, glwe_sk, _, bsk, ksk) = keygen_seeded_pbs_with_glwe_ks(
+            param.lwe_dimension(),
+            param.glwe_dimension(),
+            param.polynomial_size(),
+            param.lwe_modular_std_dev(),
+            param.glwe_modular_std_dev(),
+            param.pbs_base_log(),
+            param.pbs_level(),
+            param.glwe_ds_base_log(),
+            param.glwe_ds_level(),
+            param.common_polynomial_size(),
+            param.ciphertext_modulus(),
+            &mut secret_generator,
+            noise_seeder,
+        );
+        let ss_key = generate_seeded_scheme_switching_key(
+            &glwe_sk,
+            param.ss_base_log(),
+            param.ss_level(),
+            param.glwe_modular_std_dev(),
+            param.ciphertext_modulus(),
+            noise_seeder,
+        );
+        let auto_keys = gen_all_seeded_auto_keys(
+            param.auto_base_log(),
+            param.auto_level(),
+            &glwe_sk,
+            param.glwe_modular_std_dev(),
+            noise_seeder,
+        );
+
+        let auto_keys = decompress_seeded_auto_keys(auto_keys, param.fft_type_auto())
+            .into_iter()
+            .map(|(k, v)| (k, v.to_serializable()))
+            .collect();
+        let bundle = FourierKeyBundle::from_standard_keys(
+            param,
+            &bsk.decompress_into_lwe_bootstrap_key(),
+            &ksk.decompress_into_glwe_keyswitch_key(),
+            &ss_key.decompress_into_ggsw_ciphertext_list(),
+            auto_keys,
+        );
+        let keys = ServerKeySet::from_bundle(bundle, param).unwrap();
+        (lwe_sk, glwe_sk, keys)
+    }
+
+    /// Decrypts bits laid out MSB first within each byte back into bytes.
+    fn decrypt_bytes(lwe_sk: &LweSecretKeyOwned<u64>, he_bits: &LweCiphertextList<Vec<u64>>) -> Vec<u8> {
+        let bits: Vec<u8> = he_bits
+            .iter()
+            .map(|he_bit| {
+                let plaintext = decrypt_lwe_ciphertext(lwe_sk, &he_bit).0;
+                (plaintext.wrapping_add(1 << 62) >> 63) as u8
+            })
+            .collect();
+        bits.chunks_exact(BYTESIZE)
+            .map(|byte_bits| byte_bits.iter().fold(0u8, |byte, bit| (byte << 1) | bit))
+            .collect()
+    }
+
+    #[test]
+    fn transcipher_ctr_decrypts_to_plaintext() {
+        let param = &*AES_TIGHT;
+        let mut boxed_seeder = new_seeder();
+        let seeder = boxed_seeder.as_mut();
+        let (lwe_sk, glwe_sk, keys) = server_key_set(param, seeder);
+
+        let aes_key: Vec<u8> = (0..16).map(|i| (7 * i + 3) as u8).collect();
+        let aes = AesManager::new(&aes_key).unwrap();
+        let trans_key = SeededAllFwdRdKeys {
+            _middle_round_key: get_fwd_middle_round_key(param, &glwe_sk, &aes, seeder).unwrap(),
+            _last_round_key: get_fwd_last_round_key(param, &glwe_sk, &aes, seeder).unwrap(),
+        };
+        let transcipherer =
+            Transcipherer::new(*param, keys, TransKey::Ctr(trans_key.decompress_into_all_fwd_rd_keys())).unwrap();
+        assert!(transcipherer.supports_ctr());
+
+        // The second counter carries out of the last byte of the IV
+        let mut iv = [0u8; BLOCKSIZE_IN_BYTE];
+        iv[BLOCKSIZE_IN_BYTE - 1] = 0xff;
+        let plaintext: Vec<u8> = (0..2 * BLOCKSIZE_IN_BYTE).map(|i| (31 * i + 5) as u8).collect();
+        let keystream = aes.ctr_keystream(&iv, plaintext.len());
+        let ciphertexts: Vec<[u8; 16]> = plaintext
+            .iter()
+            .zip(keystream.iter())
+            .map(|(p, k)| p ^ k)
+            .collect::<Vec<_>>()
+            .chunks_exact(BLOCKSIZE_IN_BYTE)
+            .map(|block| std::array::from_fn(|i| block[i]))
+            .collect();
+
+        let he_bits = transcipherer.transcipher_ctr(&ciphertexts, &iv).unwrap();
+        assert_eq!(decrypt_bytes(&lwe_sk, &he_bits), plaintext);
+    }
+}